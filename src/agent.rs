@@ -1,32 +1,93 @@
 use anyhow::Result;
+use futures::StreamExt;
+use std::future::Future;
+use std::pin::Pin;
 use std::sync::{Arc, Weak};
-use tracing::info;
+use tracing::{debug, info, warn};
 
-use teloxide::Bot;
-
-use crate::config::Config;
-use crate::llm::{ChatMessage, FunctionDefinition, LlmClient, ToolDefinition};
+use crate::config::{Config, ToolPolicy};
+use crate::context;
+use crate::facts;
+use crate::llm::{ChatMessage, FunctionDefinition, LlmProvider, LlmResponse, ToolDefinition};
 use crate::mcp::McpManager;
-use crate::memory::MemoryStore;
-use crate::platform::IncomingMessage;
-use crate::scheduler::reminders::ScheduledTaskStore;
+use crate::memory::knowledge::{KnowledgeEntry, KnowledgeScope};
+use crate::memory::usage::{UsagePeriod, UsageScope, UsageTotals};
+use crate::memory::{KeywordMatch, MemoryStore};
+use crate::platform::{IncomingMessage, PendingMemoryClears};
+use crate::scheduler::reminders::{ScheduledTask, ScheduledTaskStore};
 use crate::scheduler::Scheduler;
 use crate::skills::SkillRegistry;
 use crate::tools;
 
 /// A request dispatched from a fire closure to the background job runner.
+/// The reply goes to `incoming.chat_id` on `incoming.platform`.
 pub struct ScheduledJobRequest {
     pub incoming: IncomingMessage,
-    pub bot: Arc<Bot>,
     pub task_id: String,
     pub is_recurring: bool,
     pub task_store: ScheduledTaskStore,
 }
 
+/// Progress notifications emitted while a message is being processed,
+/// so platforms can render partial output before the loop finishes.
+#[derive(Debug, Clone)]
+pub enum AgentEvent {
+    /// A piece of assistant text streamed from the LLM
+    TextDelta(String),
+    /// The assistant decided to call a tool; text streamed so far was a preamble
+    ToolCall {
+        name: String,
+        /// Raw JSON arguments as sent by the model
+        arguments: String,
+    },
+    /// A tool finished; `output` is what the model will see
+    ToolResult { name: String, output: String },
+    /// A tool call under the `ask` policy is waiting for the user's decision
+    ApprovalRequest(ApprovalRequest),
+}
+
+/// A pending tool call that needs the user's approval.
+/// The agent loop is suspended until `respond` is called or the request times out.
+#[derive(Debug, Clone)]
+pub struct ApprovalRequest {
+    /// Unique id, e.g. for matching button presses to requests
+    pub id: String,
+    pub tool: String,
+    /// Pretty-printed JSON arguments
+    pub arguments: String,
+    reply: Arc<std::sync::Mutex<Option<tokio::sync::oneshot::Sender<bool>>>>,
+}
+
+impl ApprovalRequest {
+    fn new(
+        tool: &str,
+        arguments: &serde_json::Value,
+    ) -> (Self, tokio::sync::oneshot::Receiver<bool>) {
+        let (tx, rx) = tokio::sync::oneshot::channel();
+        let request = Self {
+            id: uuid::Uuid::new_v4().to_string(),
+            tool: tool.to_string(),
+            arguments: serde_json::to_string_pretty(arguments).unwrap_or_default(),
+            reply: Arc::new(std::sync::Mutex::new(Some(tx))),
+        };
+        (request, rx)
+    }
+
+    /// Deliver the user's decision. Only the first call has any effect.
+    pub fn respond(&self, approved: bool) {
+        if let Some(tx) = self.reply.lock().unwrap().take() {
+            let _ = tx.send(approved);
+        }
+    }
+}
+
+/// Channel end used by platforms to receive `AgentEvent`s.
+pub type AgentEventSender = tokio::sync::mpsc::UnboundedSender<AgentEvent>;
+
 /// The core agent that processes messages through LLM + tools.
 /// Platform-agnostic — receives IncomingMessage, returns response text.
 pub struct Agent {
-    pub llm: LlmClient,
+    pub llm: Arc<dyn LlmProvider>,
     pub config: Config,
     pub mcp: McpManager,
     pub memory: MemoryStore,
@@ -34,11 +95,11 @@ pub struct Agent {
     // Fields used by scheduling / job closures
     pub task_store: ScheduledTaskStore,
     pub scheduler: Arc<Scheduler>,
-    pub bot: Arc<Bot>,
-    #[allow(dead_code)]
     pub self_weak: Weak<Agent>,
     /// Sender for dispatching scheduled job work to the background runner.
     pub job_tx: tokio::sync::mpsc::UnboundedSender<ScheduledJobRequest>,
+    /// `/memory clear` requests waiting for confirmation
+    pub memory_clears: PendingMemoryClears,
 }
 
 impl Agent {
@@ -50,11 +111,10 @@ impl Agent {
         skills: SkillRegistry,
         task_store: ScheduledTaskStore,
         scheduler: Arc<Scheduler>,
-        bot: Arc<Bot>,
         self_weak: Weak<Agent>,
         job_tx: tokio::sync::mpsc::UnboundedSender<ScheduledJobRequest>,
     ) -> Self {
-        let llm = LlmClient::new(config.openrouter.clone());
+        let llm = crate::llm::build_from_config(&config);
         Self {
             llm,
             config,
@@ -63,9 +123,9 @@ impl Agent {
             skills: tokio::sync::RwLock::new(skills),
             task_store,
             scheduler,
-            bot,
             self_weak,
             job_tx,
+            memory_clears: PendingMemoryClears::default(),
         }
     }
 
@@ -95,15 +155,52 @@ impl Agent {
 
     /// Process an incoming message and return the response text
     pub async fn process_message(&self, incoming: &IncomingMessage) -> Result<String> {
+        self.process_message_with_events(incoming, None, false)
+            .await
+    }
+
+    /// Process an incoming message, reporting tool activity and approval requests
+    /// to `events`. With `stream` set, LLM output is streamed as `TextDelta` events.
+    /// With `events` set to None this behaves exactly like `process_message`.
+    pub async fn process_message_with_events(
+        &self,
+        incoming: &IncomingMessage,
+        events: Option<&AgentEventSender>,
+        stream: bool,
+    ) -> Result<String> {
+        self.run(incoming, events, stream, None).await
+    }
+
+    /// Process the prompt of a scheduled task; LLM usage is attributed to `task_id`.
+    pub async fn process_scheduled_message(
+        &self,
+        incoming: &IncomingMessage,
+        task_id: &str,
+    ) -> Result<String> {
+        self.run(incoming, None, false, Some(task_id)).await
+    }
+
+    async fn run(
+        &self,
+        incoming: &IncomingMessage,
+        events: Option<&AgentEventSender>,
+        stream: bool,
+        task_id: Option<&str>,
+    ) -> Result<String> {
         let platform = &incoming.platform;
         let user_id = &incoming.user_id;
-        let chat_id = &incoming.chat_id;
 
-        // Get or create persistent conversation
+        // Get or create persistent conversation (shared by the chat in group chats)
         let conversation_id = self
             .memory
-            .get_or_create_conversation(platform, user_id)
+            .get_or_create_conversation(platform, &incoming.conversation_owner())
             .await?;
+        let scope = UsageScope {
+            platform: platform.clone(),
+            user_id: user_id.clone(),
+            conversation_id: Some(conversation_id.clone()),
+            task_id: task_id.map(str::to_string),
+        };
 
         // Load existing messages from memory
         let mut messages = self.memory.load_messages(&conversation_id).await?;
@@ -114,12 +211,7 @@ impl Agent {
         //   (DB keeps the historical system message intact).
         let current_system_prompt = self.build_system_prompt().await;
         if messages.is_empty() {
-            let system_msg = ChatMessage {
-                role: "system".to_string(),
-                content: Some(current_system_prompt),
-                tool_calls: None,
-                tool_call_id: None,
-            };
+            let system_msg = ChatMessage::new("system", current_system_prompt);
             self.memory
                 .save_message(&conversation_id, &system_msg)
                 .await?;
@@ -133,18 +225,24 @@ impl Agent {
             }
         }
 
-        // Add user message
-        let user_msg = ChatMessage {
-            role: "user".to_string(),
-            content: Some(incoming.text.clone()),
-            tool_calls: None,
-            tool_call_id: None,
-        };
+        // Add user message (attachments become notes, extracted text and images)
+        let user_msg = crate::media::user_message(
+            &self.config.media,
+            &self.config.sandbox.allowed_directory,
+            incoming,
+        )
+        .await;
         self.memory
             .save_message(&conversation_id, &user_msg)
             .await?;
+        let user_text = user_msg.content.clone().unwrap_or_default();
         messages.push(user_msg);
 
+        // Relevant long-term memories, looked up once per turn
+        let recalled = self
+            .recall_memories(incoming, &conversation_id, &user_text)
+            .await;
+
         // Gather all tool definitions
         let mut all_tools: Vec<ToolDefinition> = tools::builtin_tool_definitions();
         all_tools.extend(self.mcp.tool_definitions());
@@ -155,7 +253,38 @@ impl Agent {
         // Agentic loop — keep calling LLM until we get a non-tool response
         let max_iterations = self.config.max_iterations();
         for iteration in 0..max_iterations {
-            let response = self.llm.chat(&messages, &all_tools).await?;
+            if let Some(notice) = self.check_daily_budget(&scope).await? {
+                return Ok(notice);
+            }
+            let request_messages = self
+                .build_context_window(
+                    &scope,
+                    &conversation_id,
+                    &messages,
+                    &all_tools,
+                    recalled.as_deref(),
+                )
+                .await?;
+            let response = match events.filter(|_| stream) {
+                Some(tx) => {
+                    let tx = tx.clone();
+                    let on_delta = move |text: &str| {
+                        if text.is_empty() {
+                            return;
+                        }
+                        let _ = tx.send(AgentEvent::TextDelta(text.to_string()));
+                    };
+                    self.llm
+                        .chat_stream(&request_messages, &all_tools, &on_delta)
+                        .await?
+                }
+                None => self.llm.chat(&request_messages, &all_tools).await?,
+            };
+            self.record_usage(&scope, &response, &request_messages, &all_tools)
+                .await;
+            let model = response.model;
+            let response = response.message;
+            debug!("Response produced by model '{}'", model);
 
             if let Some(tool_calls) = &response.tool_calls {
                 if !tool_calls.is_empty() {
@@ -167,31 +296,20 @@ impl Agent {
 
                     // Save assistant message with tool calls
                     self.memory
-                        .save_message(&conversation_id, &response)
+                        .save_message_with_model(&conversation_id, &response, Some(&model))
                         .await?;
                     messages.push(response.clone());
 
-                    // Execute each tool call
-                    for tool_call in tool_calls {
-                        let arguments: serde_json::Value =
-                            serde_json::from_str(&tool_call.function.arguments)
-                                .unwrap_or(serde_json::Value::Object(serde_json::Map::new()));
-
-                        let tool_result = self
-                            .execute_tool(&tool_call.function.name, &arguments, user_id, chat_id)
-                            .await;
-
-                        info!(
-                            "Tool '{}' result length: {} chars",
-                            tool_call.function.name,
-                            tool_result.len()
-                        );
+                    let results = self.execute_tool_calls(tool_calls, incoming, events).await;
 
+                    // Save results in the order the model requested them
+                    for (tool_call, tool_result) in tool_calls.iter().zip(results) {
                         let tool_msg = ChatMessage {
                             role: "tool".to_string(),
                             content: Some(tool_result),
                             tool_calls: None,
                             tool_call_id: Some(tool_call.id.clone()),
+                            images: Vec::new(),
                         };
                         self.memory
                             .save_message(&conversation_id, &tool_msg)
@@ -206,8 +324,17 @@ impl Agent {
             // Final response — no tool calls
             let content = response.content.clone().unwrap_or_default();
             self.memory
-                .save_message(&conversation_id, &response)
+                .save_message_with_model(&conversation_id, &response, Some(&model))
                 .await?;
+            if task_id.is_none()
+                && self
+                    .memory
+                    .conversation_title(&conversation_id)
+                    .await?
+                    .is_none()
+            {
+                self.spawn_title_generation(scope, conversation_id, user_text, content.clone());
+            }
 
             return Ok(content);
         }
@@ -215,6 +342,518 @@ impl Agent {
         Ok("I've reached the maximum number of tool call iterations. Please try rephrasing your request.".to_string())
     }
 
+    /// Execute the tool calls of one assistant turn, running independent calls
+    /// concurrently (up to `[agent] max_parallel_tools`). Results are returned in
+    /// the same order as `tool_calls`.
+    async fn execute_tool_calls(
+        &self,
+        tool_calls: &[crate::llm::ToolCall],
+        origin: &IncomingMessage,
+        events: Option<&AgentEventSender>,
+    ) -> Vec<String> {
+        let arguments: Vec<serde_json::Value> = tool_calls
+            .iter()
+            .map(|call| {
+                serde_json::from_str(&call.function.arguments)
+                    .unwrap_or(serde_json::Value::Object(serde_json::Map::new()))
+            })
+            .collect();
+        let classes: Vec<tools::ToolConcurrency> = tool_calls
+            .iter()
+            .zip(&arguments)
+            .map(|(call, args)| tools::tool_concurrency(&call.function.name, args))
+            .collect();
+
+        let mut results = vec![String::new(); tool_calls.len()];
+        for lanes in plan_tool_lanes(&classes) {
+            // Calls within a lane run sequentially; lanes run concurrently.
+            let lane_futures = lanes.into_iter().map(|lane| {
+                let arguments = &arguments;
+                async move {
+                    let mut outputs = Vec::with_capacity(lane.len());
+                    for index in lane {
+                        let name = &tool_calls[index].function.name;
+                        if let Some(refusal) =
+                            self.authorize_tool(name, &arguments[index], events).await
+                        {
+                            info!("Tool '{}' not run: {}", name, refusal);
+                            outputs.push((index, refusal));
+                            continue;
+                        }
+                        if let Some(tx) = events {
+                            let _ = tx.send(AgentEvent::ToolCall {
+                                name: name.clone(),
+                                arguments: tool_calls[index].function.arguments.clone(),
+                            });
+                        }
+                        let result = self.execute_tool(name, &arguments[index], origin).await;
+                        let result = tools::cap_tool_output(
+                            name,
+                            &tool_calls[index].id,
+                            &arguments[index],
+                            result,
+                            self.config.tool_output.limit_for(name),
+                            &self.config.sandbox.allowed_directory,
+                            &self.config.tool_output.spill_dir,
+                        )
+                        .await;
+                        info!("Tool '{}' result length: {} chars", name, result.len());
+                        if let Some(tx) = events {
+                            let _ = tx.send(AgentEvent::ToolResult {
+                                name: name.clone(),
+                                output: result.clone(),
+                            });
+                        }
+                        outputs.push((index, result));
+                    }
+                    outputs
+                }
+            });
+            let outputs: Vec<Vec<(usize, String)>> = futures::stream::iter(lane_futures)
+                .buffer_unordered(self.config.max_parallel_tools())
+                .collect()
+                .await;
+            for (index, result) in outputs.into_iter().flatten() {
+                results[index] = result;
+            }
+        }
+        results
+    }
+
+    /// Apply the `[approval]` policy to a tool call. Returns the text to hand back
+    /// to the model instead of a tool result when the call must not run.
+    async fn authorize_tool(
+        &self,
+        name: &str,
+        arguments: &serde_json::Value,
+        events: Option<&AgentEventSender>,
+    ) -> Option<String> {
+        match self.config.approval.policy_for(name) {
+            ToolPolicy::Allow => None,
+            ToolPolicy::Deny => Some(format!(
+                "Tool '{}' is disabled by the approval policy and was not run.",
+                name
+            )),
+            ToolPolicy::Ask => {
+                let unavailable = format!(
+                    "Tool '{}' requires the user's approval, but approval cannot be requested here. The tool was not run.",
+                    name
+                );
+                let Some(tx) = events else {
+                    return Some(unavailable);
+                };
+                let (request, decision) = ApprovalRequest::new(name, arguments);
+                if tx.send(AgentEvent::ApprovalRequest(request)).is_err() {
+                    return Some(unavailable);
+                }
+                let timeout = std::time::Duration::from_secs(self.config.approval.timeout_secs);
+                match tokio::time::timeout(timeout, decision).await {
+                    Ok(Ok(true)) => None,
+                    Ok(Ok(false)) => Some(format!(
+                        "The user denied the call to '{}'. The tool was not run.",
+                        name
+                    )),
+                    Ok(Err(_)) => Some(format!(
+                        "The approval request for '{}' was dismissed. The tool was not run.",
+                        name
+                    )),
+                    Err(_) => Some(format!(
+                        "The user did not approve '{}' within {} seconds. The tool was not run.",
+                        name, self.config.approval.timeout_secs
+                    )),
+                }
+            }
+        }
+    }
+
+    /// Build the message list for the next LLM request within the token budget.
+    ///
+    /// The system prompt and recent turns are kept verbatim. When the history no
+    /// longer fits, the oldest turns are folded into the conversation's rolling
+    /// summary (persisted in `conversations`), which is appended to the system prompt.
+    /// The `recalled` memories are sent as notes just before the latest user message.
+    async fn build_context_window(
+        &self,
+        scope: &UsageScope,
+        conversation_id: &str,
+        messages: &[ChatMessage],
+        tools: &[ToolDefinition],
+        recalled: Option<&str>,
+    ) -> Result<Vec<ChatMessage>> {
+        let system = messages.iter().find(|m| m.role == "system");
+        let body: Vec<&ChatMessage> = messages.iter().filter(|m| m.role != "system").collect();
+
+        let state = self
+            .memory
+            .load_conversation_summary(conversation_id)
+            .await?;
+        let already_folded = state.summarized_count.min(body.len());
+        let recent: Vec<ChatMessage> = body[already_folded..]
+            .iter()
+            .map(|m| (*m).clone())
+            .collect();
+        let mut summary = state.summary;
+
+        let fixed_tokens = context::estimate_tools_tokens(tools)
+            + system.map(context::estimate_message_tokens).unwrap_or(0)
+            + summary
+                .as_deref()
+                .map(context::estimate_tokens)
+                .unwrap_or(0)
+            + recalled.map(context::estimate_tokens).unwrap_or(0)
+            + self.config.openrouter.max_tokens as usize;
+        let budget = self
+            .config
+            .context_window_tokens()
+            .saturating_sub(fixed_tokens);
+
+        // Fold down to half the budget so we don't summarize again on every turn.
+        let split = context::split_point(&recent, budget, budget / 2);
+        if split > 0 {
+            info!(
+                "Context over budget ({} tokens): folding {} message(s) into summary",
+                budget, split
+            );
+            match self
+                .summarize(scope, summary.as_deref(), &recent[..split])
+                .await
+            {
+                Ok(new_summary) => {
+                    self.memory
+                        .save_conversation_summary(
+                            conversation_id,
+                            &new_summary,
+                            already_folded + split,
+                        )
+                        .await?;
+                    summary = Some(new_summary);
+                }
+                Err(e) => {
+                    // Still drop the old turns for this request; retry summarizing next time.
+                    warn!(
+                        "Failed to summarize conversation, truncating instead: {:#}",
+                        e
+                    );
+                }
+            }
+        }
+
+        let mut window = Vec::with_capacity(recent.len() - split + 2);
+        if let Some(system) = system {
+            let mut system = system.clone();
+            if let Some(summary) = summary.as_deref() {
+                let content = system.content.get_or_insert_with(String::new);
+                content.push_str("\n\n# Summary of earlier conversation\n\n");
+                content.push_str(summary);
+            }
+            window.push(system);
+        }
+        window.extend_from_slice(&recent[split..]);
+        // Recalled memories go in their own user-role message just before the
+        // user's, never in the system prompt
+        if let Some(recalled) = recalled {
+            let at = window
+                .iter()
+                .rposition(|m| m.role == "user")
+                .unwrap_or(window.len());
+            window.insert(at, ChatMessage::user(recalled));
+        }
+        Ok(window)
+    }
+
+    /// Knowledge and snippets of other conversations relevant to `text`, rendered
+    /// as notes for the prompt, when `[memory.auto_recall]` is enabled. Searches in
+    /// the same scope as the memory tools. Failures only skip the step.
+    async fn recall_memories(
+        &self,
+        incoming: &IncomingMessage,
+        conversation_id: &str,
+        text: &str,
+    ) -> Option<String> {
+        let config = &self.config.memory.auto_recall;
+        if !config.enabled || text.trim().is_empty() {
+            return None;
+        }
+        let owner = incoming.conversation_owner();
+        let mut candidates = Vec::new();
+
+        let scope = KnowledgeScope::user(&incoming.platform, &owner);
+        match self
+            .memory
+            .search_knowledge_scored(scope, text, KeywordMatch::AnyWord, config.max_knowledge)
+            .await
+        {
+            Ok(entries) => candidates.extend(entries.into_iter().map(|(entry, relevance)| {
+                context::RecalledMemory {
+                    label: format!("fact {}/{}", entry.category, entry.key),
+                    text: entry.value,
+                    relevance,
+                }
+            })),
+            Err(e) => warn!("Auto-recall knowledge search failed: {:#}", e),
+        }
+
+        if config.max_messages > 0 {
+            // Tool results and system prompts are not worth recalling; ask for
+            // extra hits to make up for them
+            match self
+                .memory
+                .search_messages_scored(
+                    &incoming.platform,
+                    &owner,
+                    text,
+                    KeywordMatch::AnyWord,
+                    config.max_messages * 3,
+                    Some(conversation_id),
+                )
+                .await
+            {
+                Ok(found) => candidates.extend(
+                    found
+                        .into_iter()
+                        .filter(|(message, _)| {
+                            matches!(message.role.as_str(), "user" | "assistant")
+                        })
+                        .filter_map(|(message, relevance)| {
+                            Some(context::RecalledMemory {
+                                label: format!("earlier message ({})", message.role),
+                                text: context::clip(
+                                    message.content.as_deref()?,
+                                    context::RECALLED_MESSAGE_CHARS,
+                                ),
+                                relevance,
+                            })
+                        })
+                        .take(config.max_messages),
+                ),
+                Err(e) => warn!("Auto-recall message search failed: {:#}", e),
+            }
+        }
+
+        for memory in &candidates {
+            debug!(
+                "Auto-recall candidate [{}] relevance {:.3}: {}",
+                memory.label, memory.relevance, memory.text
+            );
+        }
+        let considered = candidates.len();
+        let selected =
+            context::select_memories(candidates, config.min_relevance, config.max_tokens);
+        if considered > 0 {
+            info!(
+                "Auto-recall for {} user {}: injected {} of {} memories{}{}",
+                incoming.platform,
+                owner,
+                selected.len(),
+                considered,
+                if selected.is_empty() { "" } else { ": " },
+                selected
+                    .iter()
+                    .map(|m| format!("[{}] ({:.2})", m.label, m.relevance))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            );
+        }
+        context::render_memories(&selected)
+    }
+
+    /// Ask the LLM to merge `previous` with a transcript of `messages` into a new summary.
+    async fn summarize(
+        &self,
+        scope: &UsageScope,
+        previous: Option<&str>,
+        messages: &[ChatMessage],
+    ) -> Result<String> {
+        let mut request = String::new();
+        if let Some(previous) = previous {
+            request.push_str(&format!("Previous summary:\n{}\n\n", previous));
+        }
+        request.push_str(&format!(
+            "New transcript excerpt:\n{}",
+            context::render_transcript(messages)
+        ));
+
+        let prompt = [
+            ChatMessage::new("system", context::SUMMARY_PROMPT),
+            ChatMessage::user(request),
+        ];
+        let response = self.llm.chat(&prompt, &[]).await?;
+        self.record_usage(scope, &response, &prompt, &[]).await;
+        response
+            .message
+            .content
+            .filter(|c| !c.trim().is_empty())
+            .ok_or_else(|| anyhow::anyhow!("Summarization returned no content"))
+    }
+
+    /// Name an untitled conversation after its first exchange, in the background
+    /// so the reply is not held up. Falls back to the user's first message.
+    fn spawn_title_generation(
+        &self,
+        scope: UsageScope,
+        conversation_id: String,
+        user_text: String,
+        reply: String,
+    ) {
+        let Some(agent) = self.self_weak.upgrade() else {
+            return;
+        };
+        tokio::spawn(async move {
+            let title = match agent.generate_title(&scope, &user_text, &reply).await {
+                Ok(title) => Some(title),
+                Err(e) => {
+                    warn!("Failed to generate conversation title: {:#}", e);
+                    context::short_title(&user_text)
+                }
+            };
+            let Some(title) = title else {
+                return;
+            };
+            match agent
+                .memory
+                .set_conversation_title(&conversation_id, &title, true)
+                .await
+            {
+                Ok(true) => debug!("Conversation {} titled '{}'", conversation_id, title),
+                Ok(false) => {}
+                Err(e) => warn!("Failed to save conversation title: {:#}", e),
+            }
+        });
+    }
+
+    async fn generate_title(
+        &self,
+        scope: &UsageScope,
+        user_text: &str,
+        reply: &str,
+    ) -> Result<String> {
+        let exchange = [
+            ChatMessage::user(user_text),
+            ChatMessage::new("assistant", reply),
+        ];
+        let prompt = [
+            ChatMessage::new("system", context::TITLE_PROMPT),
+            ChatMessage::user(context::render_transcript(&exchange)),
+        ];
+        let response = self.llm.chat(&prompt, &[]).await?;
+        self.record_usage(scope, &response, &prompt, &[]).await;
+        response
+            .message
+            .content
+            .as_deref()
+            .and_then(context::short_title)
+            .ok_or_else(|| anyhow::anyhow!("Title generation returned no content"))
+    }
+
+    /// Ask the LLM which durable facts `messages` reveal beyond the `known` ones.
+    /// Returns the raw reply, to be read with `facts::parse_facts`.
+    pub async fn fact_extraction(
+        &self,
+        scope: &UsageScope,
+        known: &[KnowledgeEntry],
+        messages: &[ChatMessage],
+    ) -> Result<String> {
+        let prompt = [
+            ChatMessage::new("system", facts::FACT_EXTRACTION_PROMPT),
+            ChatMessage::user(facts::extraction_request(
+                known,
+                &context::render_transcript(messages),
+            )),
+        ];
+        let response = self.llm.chat(&prompt, &[]).await?;
+        self.record_usage(scope, &response, &prompt, &[]).await;
+        response
+            .message
+            .content
+            .ok_or_else(|| anyhow::anyhow!("Fact extraction returned no content"))
+    }
+
+    /// Returns a notice for the user when they have used up their daily budget.
+    async fn check_daily_budget(&self, scope: &UsageScope) -> Result<Option<String>> {
+        let budget = self.config.daily_budget(&scope.user_id);
+        if budget.daily_token_budget.is_none() && budget.daily_cost_budget.is_none() {
+            return Ok(None);
+        }
+        let today = self
+            .memory
+            .usage_today(&scope.platform, &scope.user_id)
+            .await?;
+        if !today.exceeds(&budget) {
+            return Ok(None);
+        }
+        warn!(
+            "User {} is over their daily budget ({} tokens, ${:.4} today)",
+            scope.user_id,
+            today.total_tokens(),
+            today.cost
+        );
+        Ok(Some(
+            "You've reached your daily usage budget. It resets at midnight UTC — see /usage for details."
+                .to_string(),
+        ))
+    }
+
+    /// Persist usage for one LLM call. Providers that don't report usage are
+    /// recorded with an estimate so budgets still apply.
+    async fn record_usage(
+        &self,
+        scope: &UsageScope,
+        response: &LlmResponse,
+        request: &[ChatMessage],
+        tools: &[ToolDefinition],
+    ) {
+        let usage = response.usage.unwrap_or_else(|| crate::llm::Usage {
+            prompt_tokens: (context::estimate_messages_tokens(request)
+                + context::estimate_tools_tokens(tools)) as u64,
+            completion_tokens: context::estimate_message_tokens(&response.message) as u64,
+            cost: None,
+        });
+        if let Err(e) = self
+            .memory
+            .record_usage(scope, &response.model, &usage)
+            .await
+        {
+            warn!("Failed to record LLM usage: {:#}", e);
+        }
+    }
+
+    /// Closure run by the scheduler when `task` fires. It hands the task's prompt
+    /// to the background runner (via a channel, so the closure stays `Send` without
+    /// requiring the agent loop to be), which replies on the task's platform.
+    fn scheduled_job_trigger(
+        &self,
+        task: &ScheduledTask,
+    ) -> impl Fn() -> Pin<Box<dyn Future<Output = ()> + Send>> + Send + Sync + 'static {
+        let job_tx = self.job_tx.clone();
+        let task_store = self.task_store.clone();
+        let task_id = task.id.clone();
+        let is_recurring = task.trigger_type == "recurring";
+        let incoming = IncomingMessage {
+            platform: task.platform.clone(),
+            user_id: task.user_id.clone(),
+            chat_id: task.chat_id.clone(),
+            user_name: String::new(),
+            text: task.prompt.clone(),
+            attachments: Vec::new(),
+            is_group: task.is_group,
+            thread_id: task.thread_id.clone(),
+        };
+        move || {
+            let req = ScheduledJobRequest {
+                incoming: incoming.clone(),
+                task_id: task_id.clone(),
+                is_recurring,
+                task_store: task_store.clone(),
+            };
+            let tx = job_tx.clone();
+            Box::pin(async move {
+                if let Err(e) = tx.send(req) {
+                    tracing::error!("Failed to dispatch scheduled job: {}", e);
+                }
+            })
+        }
+    }
+
     /// Re-register all active scheduled tasks from the DB into the scheduler.
     /// Called once at startup after the agent is constructed.
     pub async fn restore_scheduled_tasks(&self) {
@@ -228,46 +867,7 @@ impl Agent {
 
         let count = tasks.len();
         for task in tasks {
-            // Build the same fire closure as in schedule_task handler
-            let job_tx = self.job_tx.clone();
-            let bot_clone = Arc::clone(&self.bot);
-            let tid = task.id.clone();
-            let uid = task.user_id.clone();
-            let cid = task.chat_id.clone();
-            let prompt_cap = task.prompt.clone();
-            let is_recurring = task.trigger_type == "recurring";
-            let store_clone = self.task_store.clone();
-
-            let fire = move || {
-                let tx = job_tx.clone();
-                let bot = bot_clone.clone();
-                let store = store_clone.clone();
-                let tid = tid.clone();
-                let uid = uid.clone();
-                let cid = cid.clone();
-                let prompt = prompt_cap.clone();
-                let recurring = is_recurring;
-                Box::pin(async move {
-                    let incoming = crate::platform::IncomingMessage {
-                        platform: "telegram".to_string(),
-                        user_id: uid,
-                        chat_id: cid,
-                        user_name: String::new(),
-                        text: prompt,
-                    };
-                    let req = ScheduledJobRequest {
-                        incoming,
-                        bot,
-                        task_id: tid,
-                        is_recurring: recurring,
-                        task_store: store,
-                    };
-                    if let Err(e) = tx.send(req) {
-                        tracing::error!("Failed to dispatch restored scheduled job: {}", e);
-                    }
-                })
-                    as std::pin::Pin<Box<dyn std::future::Future<Output = ()> + Send>>
-            };
+            let fire = self.scheduled_job_trigger(&task);
 
             // Register with the right scheduler method based on trigger_type
             let sched_result = if task.trigger_type == "one_shot" {
@@ -329,11 +929,78 @@ impl Agent {
         }
     }
 
-    /// Clear conversation history for a user
+    /// Archive the user's current conversation; the next message starts a new one
     pub async fn clear_conversation(&self, platform: &str, user_id: &str) -> Result<()> {
         self.memory.clear_conversation(platform, user_id).await
     }
 
+    /// Human-readable usage report for the `/usage` command:
+    /// today against the daily budget, then daily, monthly and per-model breakdowns.
+    pub async fn usage_report(&self, platform: &str, user_id: &str) -> Result<String> {
+        fn line(label: &str, totals: &UsageTotals) -> String {
+            format!(
+                "  {}: {} tokens ({} in / {} out), {} call(s), ${:.4}\n",
+                label,
+                totals.total_tokens(),
+                totals.prompt_tokens,
+                totals.completion_tokens,
+                totals.calls,
+                totals.cost
+            )
+        }
+
+        let today = self.memory.usage_today(platform, user_id).await?;
+        let daily = self
+            .memory
+            .usage_by_period(platform, user_id, UsagePeriod::Day, 7)
+            .await?;
+        let monthly = self
+            .memory
+            .usage_by_period(platform, user_id, UsagePeriod::Month, 6)
+            .await?;
+        let by_model = self
+            .memory
+            .usage_by_model_this_month(platform, user_id)
+            .await?;
+
+        let mut report = String::from("Usage (UTC)\n\n");
+        report.push_str(&line("Today", &today));
+        let budget = self.config.daily_budget(user_id);
+        if let Some(limit) = budget.daily_token_budget {
+            report.push_str(&format!(
+                "  Daily token budget: {} / {}\n",
+                today.total_tokens(),
+                limit
+            ));
+        }
+        if let Some(limit) = budget.daily_cost_budget {
+            report.push_str(&format!(
+                "  Daily cost budget: ${:.4} / ${:.2}\n",
+                today.cost, limit
+            ));
+        }
+
+        if daily.is_empty() {
+            report.push_str("\nNo LLM usage recorded yet.");
+            return Ok(report);
+        }
+        report.push_str("\nLast 7 active days:\n");
+        for (day, totals) in &daily {
+            report.push_str(&line(day, totals));
+        }
+        report.push_str("\nBy month:\n");
+        for (month, totals) in &monthly {
+            report.push_str(&line(month, totals));
+        }
+        if !by_model.is_empty() {
+            report.push_str("\nBy model (this month):\n");
+            for (model, totals) in &by_model {
+                report.push_str(&line(model, totals));
+            }
+        }
+        Ok(report)
+    }
+
     /// Get all tool definitions for display
     pub fn all_tool_definitions(&self) -> Vec<ToolDefinition> {
         let mut all_tools = tools::builtin_tool_definitions();
@@ -359,7 +1026,8 @@ impl Agent {
                         "properties": {
                             "category": { "type": "string", "description": "Category (e.g., 'user_preference', 'fact', 'project')" },
                             "key": { "type": "string", "description": "Short identifier for this knowledge" },
-                            "value": { "type": "string", "description": "The knowledge to remember" }
+                            "value": { "type": "string", "description": "The knowledge to remember" },
+                            "shared": { "type": "boolean", "description": "Store it for every user instead of only this one. Only when an admin explicitly asks to share it." }
                         },
                         "required": ["category", "key", "value"]
                     }),
@@ -380,6 +1048,35 @@ impl Agent {
                     }),
                 },
             },
+            ToolDefinition {
+                tool_type: "function".to_string(),
+                function: FunctionDefinition {
+                    name: "forget".to_string(),
+                    description: "Delete a piece of remembered knowledge, e.g. when the user asks you to forget it or it is no longer true.".to_string(),
+                    parameters: json!({
+                        "type": "object",
+                        "properties": {
+                            "category": { "type": "string", "description": "Category of the entry" },
+                            "key": { "type": "string", "description": "Key of the entry to delete" },
+                            "shared": { "type": "boolean", "description": "Delete the entry shared by every user rather than this user's own (admins only)" }
+                        },
+                        "required": ["category", "key"]
+                    }),
+                },
+            },
+            ToolDefinition {
+                tool_type: "function".to_string(),
+                function: FunctionDefinition {
+                    name: "list_knowledge".to_string(),
+                    description: "List remembered knowledge. Without a category, lists the categories and their number of entries; with one, lists every entry in it.".to_string(),
+                    parameters: json!({
+                        "type": "object",
+                        "properties": {
+                            "category": { "type": "string", "description": "Category to list (omit to list categories)" }
+                        }
+                    }),
+                },
+            },
             ToolDefinition {
                 tool_type: "function".to_string(),
                 function: FunctionDefinition {
@@ -510,20 +1207,39 @@ impl Agent {
         ]
     }
 
-    /// Execute a tool call by routing to the right handler
+    /// Execute a tool call by routing to the right handler.
+    /// `origin` is the message that led to the call (who asked, and where).
     async fn execute_tool(
         &self,
         name: &str,
         arguments: &serde_json::Value,
-        user_id: &str,
-        chat_id: &str,
+        origin: &IncomingMessage,
     ) -> String {
+        let user_id = origin.user_id.as_str();
+        // Memory is kept per conversation owner: the user, or the group chat
+        let owner = origin.conversation_owner();
+        let scope = KnowledgeScope::user(&origin.platform, &owner);
+        let shared = arguments["shared"].as_bool().unwrap_or(false);
+        let write_scope = if shared {
+            KnowledgeScope::SHARED
+        } else {
+            scope
+        };
+        if shared && matches!(name, "remember" | "forget") && !self.config.memory.is_admin(user_id)
+        {
+            return "Only an admin can change shared knowledge. Store it for this user instead."
+                .to_string();
+        }
         match name {
             "remember" => {
                 let category = arguments["category"].as_str().unwrap_or("general");
                 let key = arguments["key"].as_str().unwrap_or("");
                 let value = arguments["value"].as_str().unwrap_or("");
-                match self.memory.remember(category, key, value, None).await {
+                match self
+                    .memory
+                    .remember(write_scope, category, key, value, None)
+                    .await
+                {
                     Ok(()) => format!("Remembered: [{}] {} = {}", category, key, value),
                     Err(e) => format!("Failed to remember: {}", e),
                 }
@@ -531,12 +1247,48 @@ impl Agent {
             "recall" => {
                 let category = arguments["category"].as_str().unwrap_or("general");
                 let key = arguments["key"].as_str().unwrap_or("");
-                match self.memory.recall(category, key).await {
+                match self.memory.recall(scope, category, key).await {
                     Ok(Some(value)) => value,
                     Ok(None) => format!("No knowledge found for [{}] {}", category, key),
                     Err(e) => format!("Failed to recall: {}", e),
                 }
             }
+            "forget" => {
+                let category = arguments["category"].as_str().unwrap_or("general");
+                let key = arguments["key"].as_str().unwrap_or("");
+                match self.memory.forget(write_scope, category, key).await {
+                    Ok(true) => format!("Forgot: [{}] {}", category, key),
+                    Ok(false) => format!("No knowledge found for [{}] {}", category, key),
+                    Err(e) => format!("Failed to forget: {}", e),
+                }
+            }
+            "list_knowledge" => match arguments["category"].as_str() {
+                Some(category) => match self.memory.list_knowledge(scope, category).await {
+                    Ok(entries) if entries.is_empty() => {
+                        format!("No knowledge in category '{}'", category)
+                    }
+                    Ok(entries) => entries
+                        .iter()
+                        .map(|entry| {
+                            let shared = if entry.shared { " (shared)" } else { "" };
+                            format!("{} = {}{}", entry.key, entry.value, shared)
+                        })
+                        .collect::<Vec<_>>()
+                        .join("\n"),
+                    Err(e) => format!("Failed to list knowledge: {}", e),
+                },
+                None => match self.memory.knowledge_categories(scope).await {
+                    Ok(categories) if categories.is_empty() => {
+                        "No knowledge stored yet.".to_string()
+                    }
+                    Ok(categories) => categories
+                        .iter()
+                        .map(|(category, count)| format!("{} ({} entries)", category, count))
+                        .collect::<Vec<_>>()
+                        .join("\n"),
+                    Err(e) => format!("Failed to list knowledge: {}", e),
+                },
+            },
             "search_memory" => {
                 let query = arguments["query"].as_str().unwrap_or("");
                 let limit = arguments["limit"].as_u64().unwrap_or(5) as usize;
@@ -544,7 +1296,11 @@ impl Agent {
                 let mut results = Vec::new();
 
                 // Search conversations (hybrid vector + FTS5)
-                if let Ok(msgs) = self.memory.search_messages(query, limit).await {
+                if let Ok(msgs) = self
+                    .memory
+                    .search_messages(&origin.platform, &owner, query, limit)
+                    .await
+                {
                     for msg in msgs {
                         if let Some(content) = &msg.content {
                             results.push(format!("[{}]: {}", msg.role, content));
@@ -553,7 +1309,7 @@ impl Agent {
                 }
 
                 // Search knowledge (hybrid vector + FTS5)
-                if let Ok(entries) = self.memory.search_knowledge(query, limit).await {
+                if let Ok(entries) = self.memory.search_knowledge(scope, query, limit).await {
                     for entry in entries {
                         results.push(format!(
                             "[knowledge:{}] {} = {}",
@@ -613,69 +1369,31 @@ impl Agent {
                     id: task_id.clone(),
                     scheduler_job_id: None,
                     user_id: user_id.to_string(),
-                    chat_id: chat_id.to_string(),
-                    platform: "telegram".to_string(),
+                    chat_id: origin.chat_id.clone(),
+                    platform: origin.platform.clone(),
                     trigger_type: trigger_type.clone(),
                     trigger_value: trigger_value.clone(),
-                    prompt: prompt_text.clone(),
+                    prompt: prompt_text,
                     description: description.clone(),
                     status: "active".to_string(),
                     created_at: now,
                     next_run_at: Some(next_run_at),
+                    is_group: origin.is_group,
+                    thread_id: origin.thread_id.clone(),
                 };
                 if let Err(e) = self.task_store.create(&task).await {
                     return format!("Failed to save task: {}", e);
                 }
 
-                // Build closure captures — fire closure dispatches to background runner
-                // via a channel so it can be `Send` without requiring process_message to be Send.
-                let job_tx = self.job_tx.clone();
-                let bot_clone = Arc::clone(&self.bot);
-                let store_clone = self.task_store.clone();
-                let tid = task_id.clone();
-                let uid = user_id.to_string();
-                let cid = chat_id.to_string();
-                let prompt_cap = prompt_text.clone();
-                let desc_cap = description.clone();
-                let is_recurring = trigger_type == "recurring";
-                let tv = trigger_value.clone();
-
-                let fire = move || {
-                    let tx = job_tx.clone();
-                    let bot = bot_clone.clone();
-                    let store = store_clone.clone();
-                    let tid = tid.clone();
-                    let uid = uid.clone();
-                    let cid = cid.clone();
-                    let prompt = prompt_cap.clone();
-                    let recurring = is_recurring;
-                    Box::pin(async move {
-                        let incoming = crate::platform::IncomingMessage {
-                            platform: "telegram".to_string(),
-                            user_id: uid,
-                            chat_id: cid,
-                            user_name: String::new(),
-                            text: prompt,
-                        };
-                        let req = ScheduledJobRequest {
-                            incoming,
-                            bot,
-                            task_id: tid,
-                            is_recurring: recurring,
-                            task_store: store,
-                        };
-                        if let Err(e) = tx.send(req) {
-                            tracing::error!("Failed to dispatch scheduled job: {}", e);
-                        }
-                    })
-                        as std::pin::Pin<Box<dyn std::future::Future<Output = ()> + Send>>
-                };
+                let fire = self.scheduled_job_trigger(&task);
 
                 // Register with scheduler
                 let sched_result = if let Some(d) = delay {
-                    self.scheduler.add_one_shot_job(d, &desc_cap, fire).await
+                    self.scheduler.add_one_shot_job(d, &description, fire).await
                 } else {
-                    self.scheduler.add_cron_job(&tv, &desc_cap, fire).await
+                    self.scheduler
+                        .add_cron_job(&trigger_value, &description, fire)
+                        .await
                 };
 
                 match sched_result {
@@ -801,20 +1519,46 @@ impl Agent {
                 Ok(result) => result,
                 Err(e) => format!("MCP tool error: {}", e),
             },
-            _ => {
-                match tools::execute_builtin_tool(
-                    name,
-                    arguments,
-                    &self.config.sandbox.allowed_directory,
-                )
-                .await
-                {
-                    Ok(result) => result,
-                    Err(e) => format!("Tool error: {}", e),
+            _ => match tools::execute_builtin_tool(name, arguments, &self.config.sandbox).await {
+                Ok(result) => result,
+                Err(e) => format!("Tool error: {}", e),
+            },
+        }
+    }
+}
+
+/// Group tool calls into stages that run one after another. Each stage holds
+/// lanes of call indices: lanes run concurrently, calls within a lane in order.
+/// Keyed calls share a lane with earlier calls on the same key, and exclusive
+/// calls get a stage of their own.
+fn plan_tool_lanes(classes: &[tools::ToolConcurrency]) -> Vec<Vec<Vec<usize>>> {
+    let mut stages: Vec<Vec<Vec<usize>>> = Vec::new();
+    let mut lanes: Vec<Vec<usize>> = Vec::new();
+    let mut keyed: std::collections::HashMap<&str, usize> = std::collections::HashMap::new();
+
+    for (index, class) in classes.iter().enumerate() {
+        match class {
+            tools::ToolConcurrency::Parallel => lanes.push(vec![index]),
+            tools::ToolConcurrency::Keyed(key) => match keyed.get(key.as_str()) {
+                Some(&lane) => lanes[lane].push(index),
+                None => {
+                    keyed.insert(key, lanes.len());
+                    lanes.push(vec![index]);
+                }
+            },
+            tools::ToolConcurrency::Exclusive => {
+                if !lanes.is_empty() {
+                    stages.push(std::mem::take(&mut lanes));
+                    keyed.clear();
                 }
+                stages.push(vec![vec![index]]);
             }
         }
     }
+    if !lanes.is_empty() {
+        stages.push(lanes);
+    }
+    stages
 }
 
 /// Parse an ISO 8601 datetime string and return the Duration until it fires.
@@ -866,22 +1610,6 @@ fn validate_cron_expr(expr: &str) -> anyhow::Result<()> {
     Ok(())
 }
 
-/// Split a long response string into chunks of at most `max_len` characters.
-pub fn split_response_chunks(text: &str, max_len: usize) -> Vec<String> {
-    if text.is_empty() {
-        return vec![];
-    }
-    let mut chunks = Vec::new();
-    let mut start = 0;
-    let chars: Vec<char> = text.chars().collect();
-    while start < chars.len() {
-        let end = (start + max_len).min(chars.len());
-        chunks.push(chars[start..end].iter().collect());
-        start = end;
-    }
-    chunks
-}
-
 /// Validate skill directory name: lowercase letters, numbers, hyphens, 1–64 chars.
 fn validate_skill_name(name: &str) -> Result<(), String> {
     if name.is_empty() {
@@ -919,8 +1647,592 @@ fn validate_skill_path(path: &str) -> Result<(), String> {
 }
 
 #[cfg(test)]
-mod tests {
+pub(crate) mod tests {
     use super::*;
+    use crate::llm::mock::MockProvider;
+
+    /// Build an agent backed by `MockProvider` with an in-memory database and
+    /// a sandbox directory that is removed when dropped.
+    pub(crate) async fn test_agent(
+        replies: Vec<ChatMessage>,
+    ) -> (Agent, Arc<MockProvider>, tempfile::TempDir) {
+        let sandbox = tempfile::tempdir().unwrap();
+        let config: Config = toml::from_str(&format!(
+            "[telegram]\nbot_token = \"test\"\nallowed_user_ids = [1]\n\n\
+             [sandbox]\nallowed_directory = \"{}\"\n",
+            sandbox.path().display()
+        ))
+        .unwrap();
+
+        let memory = MemoryStore::open_in_memory().unwrap();
+        let task_store = ScheduledTaskStore::new(memory.connection());
+        let scheduler = Arc::new(Scheduler::new().await.unwrap());
+        let (job_tx, _job_rx) = tokio::sync::mpsc::unbounded_channel();
+        let mut agent = Agent::new(
+            config,
+            McpManager::new(),
+            memory,
+            SkillRegistry::new(),
+            task_store,
+            scheduler,
+            Weak::new(),
+            job_tx,
+        );
+        let mock = Arc::new(MockProvider::new(replies));
+        agent.llm = mock.clone();
+        (agent, mock, sandbox)
+    }
+
+    fn incoming(text: &str) -> IncomingMessage {
+        IncomingMessage {
+            platform: "telegram".to_string(),
+            user_id: "1".to_string(),
+            chat_id: "1".to_string(),
+            user_name: "Tester".to_string(),
+            text: text.to_string(),
+            attachments: Vec::new(),
+            is_group: false,
+            thread_id: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_process_message_runs_tool_then_answers() {
+        let (agent, mock, _sandbox) = test_agent(vec![
+            MockProvider::tool_call("call_1", "list_files", serde_json::json!({})),
+            MockProvider::text("Nothing here."),
+        ])
+        .await;
+
+        let response = agent
+            .process_message(&incoming("list files"))
+            .await
+            .unwrap();
+        assert_eq!(response, "Nothing here.");
+
+        let requests = mock.requests();
+        assert_eq!(requests.len(), 2);
+        let tool_msg = requests[1].last().unwrap();
+        assert_eq!(tool_msg.role, "tool");
+        assert_eq!(tool_msg.tool_call_id.as_deref(), Some("call_1"));
+        assert_eq!(tool_msg.content.as_deref(), Some("Directory is empty"));
+    }
+
+    #[tokio::test]
+    async fn test_group_members_share_the_chat_conversation() {
+        let (agent, mock, _sandbox) = test_agent(vec![
+            MockProvider::text("Noted."),
+            MockProvider::text("Tuesday."),
+        ])
+        .await;
+        let mut from_ana = incoming("the release is on Tuesday");
+        from_ana.is_group = true;
+        from_ana.chat_id = "-100".to_string();
+        from_ana.user_name = "Ana".to_string();
+        let mut from_ben = from_ana.clone();
+        from_ben.user_id = "2".to_string();
+        from_ben.user_name = "Ben".to_string();
+        from_ben.text = "when is the release?".to_string();
+
+        agent.process_message(&from_ana).await.unwrap();
+        agent.process_message(&from_ben).await.unwrap();
+
+        let contents: Vec<_> = mock.requests()[1]
+            .iter()
+            .filter(|m| m.role != "system")
+            .filter_map(|m| m.content.clone())
+            .collect();
+        assert_eq!(
+            contents,
+            [
+                "[Ana] the release is on Tuesday",
+                "Noted.",
+                "[Ben] when is the release?"
+            ]
+        );
+        // The speakers' private conversations are untouched
+        assert!(agent
+            .memory
+            .list_conversations("telegram", "1")
+            .await
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn test_plan_tool_lanes() {
+        use tools::ToolConcurrency::{Exclusive, Keyed, Parallel};
+        let key = |k: &str| Keyed(k.to_string());
+        let plan = plan_tool_lanes(&[
+            Parallel,
+            key("file:a"),
+            Parallel,
+            key("file:a"),
+            key("file:b"),
+            Exclusive,
+            Parallel,
+            key("file:a"),
+        ]);
+        assert_eq!(
+            plan,
+            vec![
+                vec![vec![0], vec![1, 3], vec![2], vec![4]],
+                vec![vec![5]],
+                vec![vec![6], vec![7]],
+            ]
+        );
+        assert!(plan_tool_lanes(&[]).is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_parallel_tool_results_keep_request_order() {
+        let (agent, mock, _sandbox) = test_agent(vec![
+            MockProvider::tool_calls(vec![
+                (
+                    "call_1",
+                    "write_file",
+                    serde_json::json!({ "path": "notes.txt", "content": "v1" }),
+                ),
+                ("call_2", "list_files", serde_json::json!({})),
+                (
+                    "call_3",
+                    "read_file",
+                    serde_json::json!({ "path": "./notes.txt" }),
+                ),
+            ]),
+            MockProvider::text("Done."),
+        ])
+        .await;
+
+        let response = agent.process_message(&incoming("go")).await.unwrap();
+        assert_eq!(response, "Done.");
+
+        let requests = mock.requests();
+        let tool_msgs: Vec<&ChatMessage> =
+            requests[1].iter().filter(|m| m.role == "tool").collect();
+        let ids: Vec<&str> = tool_msgs
+            .iter()
+            .map(|m| m.tool_call_id.as_deref().unwrap())
+            .collect();
+        assert_eq!(ids, vec!["call_1", "call_2", "call_3"]);
+        // The read is keyed to the same file, so it observes the earlier write
+        assert_eq!(tool_msgs[2].content.as_deref(), Some("v1"));
+    }
+
+    #[tokio::test]
+    async fn test_forget_and_list_knowledge_tools() {
+        let (agent, _mock, _sandbox) = test_agent(vec![]).await;
+        agent
+            .memory
+            .remember(
+                KnowledgeScope::user("telegram", "1"),
+                "food",
+                "favourite",
+                "ramen",
+                None,
+            )
+            .await
+            .unwrap();
+        let origin = incoming("hi");
+        let run = |name: &'static str, arguments: serde_json::Value| {
+            let agent = &agent;
+            let origin = &origin;
+            async move { agent.execute_tool(name, &arguments, origin).await }
+        };
+
+        assert_eq!(
+            run("list_knowledge", serde_json::json!({})).await,
+            "food (1 entries)"
+        );
+        assert_eq!(
+            run("list_knowledge", serde_json::json!({ "category": "food" })).await,
+            "favourite = ramen"
+        );
+        assert_eq!(
+            run(
+                "forget",
+                serde_json::json!({ "category": "food", "key": "favourite" })
+            )
+            .await,
+            "Forgot: [food] favourite"
+        );
+        assert_eq!(
+            run("list_knowledge", serde_json::json!({})).await,
+            "No knowledge stored yet."
+        );
+    }
+
+    #[tokio::test]
+    async fn test_only_admins_change_shared_knowledge() {
+        let (mut agent, _mock, _sandbox) = test_agent(vec![]).await;
+        agent
+            .memory
+            .remember(KnowledgeScope::SHARED, "office", "wifi", "guest-net", None)
+            .await
+            .unwrap();
+        let shared_write = serde_json::json!({
+            "category": "office", "key": "wifi", "value": "evil-net", "shared": true
+        });
+        let shared_delete =
+            serde_json::json!({ "category": "office", "key": "wifi", "shared": true });
+        let origin = incoming("hi");
+
+        for (tool, arguments) in [("remember", &shared_write), ("forget", &shared_delete)] {
+            let result = agent.execute_tool(tool, arguments, &origin).await;
+            assert!(result.contains("Only an admin"), "{}", result);
+        }
+        let scope = KnowledgeScope::user("telegram", "2");
+        assert_eq!(
+            agent.memory.recall(scope, "office", "wifi").await.unwrap(),
+            Some("guest-net".to_string())
+        );
+
+        agent.config.memory.admin_user_ids = vec!["1".to_string()];
+        let result = agent.execute_tool("remember", &shared_write, &origin).await;
+        assert_eq!(result, "Remembered: [office] wifi = evil-net");
+        assert_eq!(
+            agent.memory.recall(scope, "office", "wifi").await.unwrap(),
+            Some("evil-net".to_string())
+        );
+    }
+
+    #[tokio::test]
+    async fn test_group_task_runs_in_the_group_conversation() {
+        let (mut agent, _mock, _sandbox) =
+            test_agent(vec![MockProvider::text("Standup in 5 minutes!")]).await;
+        let (job_tx, mut job_rx) = tokio::sync::mpsc::unbounded_channel();
+        agent.job_tx = job_tx;
+        let mut origin = incoming("remind us");
+        origin.chat_id = "-100".to_string();
+        origin.is_group = true;
+        origin.thread_id = Some("7".to_string());
+
+        let arguments = serde_json::json!({
+            "trigger_type": "recurring", "trigger_value": "0 0 9 * * *",
+            "prompt": "Post the standup reminder", "description": "standup"
+        });
+        let result = agent
+            .execute_tool("schedule_task", &arguments, &origin)
+            .await;
+        assert!(result.contains("scheduled"), "{}", result);
+
+        let task = agent.task_store.list_all_active().await.unwrap().remove(0);
+        (agent.scheduled_job_trigger(&task))().await;
+        let request = job_rx.recv().await.unwrap();
+        assert_eq!(request.incoming.conversation_owner(), "chat:-100:7");
+        assert_eq!(request.incoming.user_id, "1");
+
+        agent
+            .process_scheduled_message(&request.incoming, &request.task_id)
+            .await
+            .unwrap();
+        let conversation = agent
+            .memory
+            .get_or_create_conversation("telegram", "chat:-100:7")
+            .await
+            .unwrap();
+        let messages = agent.memory.load_messages(&conversation).await.unwrap();
+        let prompt = messages.iter().find(|m| m.role == "user").unwrap();
+        assert_eq!(prompt.content.as_deref(), Some("Post the standup reminder"));
+    }
+
+    #[tokio::test]
+    async fn test_auto_recall_injects_relevant_memories() {
+        let (mut agent, mock, _sandbox) = test_agent(vec![
+            MockProvider::text("Noted."),
+            MockProvider::text("No peanuts, then."),
+            MockProvider::text("Hello!"),
+        ])
+        .await;
+        agent.config.memory.auto_recall.enabled = true;
+        // Without embeddings relevance is the keyword score, which stays
+        // modest in a store this small
+        agent.config.memory.auto_recall.min_relevance = 0.4;
+        let user = KnowledgeScope::user("telegram", "1");
+        // Some unrelated history: only words rare in the store score well
+        for (key, value) in [
+            ("city", "lives in Lisbon"),
+            ("pet", "has a beagle called Rex"),
+            ("job", "works night shifts as a nurse"),
+            ("sport", "plays tennis on Sundays"),
+            ("music", "listens to jazz while cooking"),
+        ] {
+            agent
+                .memory
+                .remember(user, "profile", key, value, None)
+                .await
+                .unwrap();
+        }
+        let earlier = agent
+            .memory
+            .new_conversation("telegram", "1", None)
+            .await
+            .unwrap();
+        for text in [
+            "Remind me to water the plants",
+            "Will it rain in Lisbon tomorrow?",
+            "Find a tennis court near the office",
+            "Call mum on Friday evening",
+        ] {
+            let message = ChatMessage::user(text);
+            agent.memory.save_message(&earlier, &message).await.unwrap();
+        }
+        agent
+            .memory
+            .remember(user, "health", "allergy", "allergic to peanuts", None)
+            .await
+            .unwrap();
+        agent
+            .memory
+            .remember(
+                KnowledgeScope::user("telegram", "2"),
+                "health",
+                "allergy",
+                "allergic to peanuts and shellfish",
+                None,
+            )
+            .await
+            .unwrap();
+        agent
+            .process_message(&incoming("I booked the Thai place for dinner"))
+            .await
+            .unwrap();
+        agent
+            .memory
+            .new_conversation("telegram", "1", None)
+            .await
+            .unwrap();
+
+        agent
+            .process_message(&incoming("Any peanuts in the Thai dinner?"))
+            .await
+            .unwrap();
+        let request = &mock.requests()[1];
+        assert_eq!(request.len(), 3);
+        // The memories are quoted as notes before the message, not in the system prompt
+        let system = request[0].content.clone().unwrap();
+        assert!(!system.contains("peanuts"), "{}", system);
+        assert_eq!(request[1].role, "user");
+        let notes = request[1].content.clone().unwrap();
+        assert!(notes.starts_with("<memory_notes>"), "{}", notes);
+        assert!(notes.contains("- [fact health/allergy] allergic to peanuts\n"));
+        assert!(!notes.contains("shellfish"), "other users' facts leaked");
+        assert!(notes.contains("- [earlier message (user)] I booked the Thai place for dinner"));
+        assert_eq!(
+            request[2].content.as_deref(),
+            Some("Any peanuts in the Thai dinner?")
+        );
+
+        // Nothing relevant: no notes
+        agent.process_message(&incoming("hi")).await.unwrap();
+        let request = &mock.requests()[2];
+        assert!(!request.iter().any(|m| m
+            .content
+            .as_deref()
+            .unwrap_or("")
+            .contains("<memory_notes>")));
+    }
+
+    #[tokio::test]
+    async fn test_fact_extraction_stores_new_and_changed_facts() {
+        let (mut agent, mock, _sandbox) = test_agent(vec![
+            MockProvider::text("Noted."),
+            MockProvider::text(
+                "```json\n[{\"category\": \"home\", \"key\": \"city\", \"value\": \"Lisbon\"},\
+                 {\"category\": \"pet\", \"key\": \"dog\", \"value\": \"Rex, a beagle\"},\
+                 {\"category\": \"pet\", \"key\": \"cat\", \"value\": \"Has a cat called Mia\"}]\n```",
+            ),
+        ])
+        .await;
+        agent.config.memory.fact_extraction.idle_minutes = 0;
+        let user = KnowledgeScope::user("telegram", "1");
+        agent
+            .memory
+            .remember(user, "home", "city", "Porto", None)
+            .await
+            .unwrap();
+        agent
+            .memory
+            .remember(user, "pets", "dog", "rex a beagle", None)
+            .await
+            .unwrap();
+        agent
+            .process_message(&incoming("We moved to Lisbon with Rex and our cat Mia"))
+            .await
+            .unwrap();
+        let config = agent.config.memory.fact_extraction.clone();
+
+        crate::scheduler::tasks::extract_facts(&agent.memory, &agent, &config).await;
+        let request = mock.requests()[1][1].content.clone().unwrap();
+        assert!(request.contains("- home/city: Porto"), "{}", request);
+        assert!(request.contains("We moved to Lisbon"));
+        let city = agent
+            .memory
+            .knowledge_entry(user, "home", "city")
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(city.value, "Lisbon");
+        let conversation = agent
+            .memory
+            .get_or_create_conversation("telegram", "1")
+            .await
+            .unwrap();
+        let cat = agent
+            .memory
+            .knowledge_entry(user, "pet", "cat")
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(cat.source, Some(format!("conversation:{}", conversation)));
+        // Rex was already known under another category
+        assert!(agent
+            .memory
+            .knowledge_entry(user, "pet", "dog")
+            .await
+            .unwrap()
+            .is_none());
+
+        // Reviewed turns are not sent again
+        crate::scheduler::tasks::extract_facts(&agent.memory, &agent, &config).await;
+        assert_eq!(mock.requests().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_approval_policy_gates_tool_calls() {
+        let (mut agent, mock, _sandbox) = test_agent(vec![
+            MockProvider::tool_calls(vec![
+                ("call_1", "list_files", serde_json::json!({})),
+                (
+                    "call_2",
+                    "execute_command",
+                    serde_json::json!({ "command": "ls" }),
+                ),
+            ]),
+            MockProvider::text("ok"),
+            MockProvider::tool_call("call_3", "list_files", serde_json::json!({})),
+            MockProvider::text("ok"),
+        ])
+        .await;
+        agent
+            .config
+            .approval
+            .tools
+            .insert("list_files".to_string(), ToolPolicy::Ask);
+        agent
+            .config
+            .approval
+            .tools
+            .insert("execute_command".to_string(), ToolPolicy::Deny);
+
+        // The user approves every request
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        let approver = tokio::spawn(async move {
+            let mut asked = Vec::new();
+            while let Some(event) = rx.recv().await {
+                if let AgentEvent::ApprovalRequest(request) = event {
+                    asked.push(request.tool.clone());
+                    request.respond(true);
+                }
+            }
+            asked
+        });
+        agent
+            .process_message_with_events(&incoming("go"), Some(&tx), false)
+            .await
+            .unwrap();
+        drop(tx);
+        assert_eq!(approver.await.unwrap(), vec!["list_files"]);
+
+        let tool_results: Vec<String> = mock.requests()[1]
+            .iter()
+            .filter(|m| m.role == "tool")
+            .map(|m| m.content.clone().unwrap())
+            .collect();
+        assert_eq!(tool_results[0], "Directory is empty");
+        assert!(tool_results[1].contains("disabled by the approval policy"));
+
+        // Without an event channel nobody can approve, so the call is refused
+        agent.process_message(&incoming("again")).await.unwrap();
+        let last = mock.requests()[3].last().unwrap().clone();
+        assert_eq!(last.role, "tool");
+        assert!(last
+            .content
+            .unwrap()
+            .contains("requires the user's approval"));
+    }
+
+    #[tokio::test]
+    async fn test_daily_budget_blocks_further_calls() {
+        let (mut agent, mock, _sandbox) = test_agent(vec![
+            MockProvider::text("First answer."),
+            MockProvider::text("Never sent."),
+        ])
+        .await;
+        agent.config.usage.default_budget.daily_token_budget = Some(1);
+
+        let response = agent.process_message(&incoming("hello")).await.unwrap();
+        assert_eq!(response, "First answer.");
+        let today = agent.memory.usage_today("telegram", "1").await.unwrap();
+        assert_eq!(today.calls, 1);
+        assert!(today.total_tokens() > 0);
+
+        // The estimated usage of the first call already exhausts the budget
+        let response = agent.process_message(&incoming("again")).await.unwrap();
+        assert!(response.contains("daily usage budget"));
+        assert_eq!(mock.requests().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_long_history_is_folded_into_summary() {
+        let (mut agent, mock, _sandbox) = test_agent(vec![
+            MockProvider::text("The user shared a lot of notes."),
+            MockProvider::text("Hi again!"),
+        ])
+        .await;
+        agent.config.openrouter.max_tokens = 100;
+        agent.config.agent.context_window_tokens =
+            context::estimate_tools_tokens(&agent.all_tool_definitions()) + 800;
+
+        let conv = agent
+            .memory
+            .get_or_create_conversation("telegram", "1")
+            .await
+            .unwrap();
+        let note = "x".repeat(800);
+        for role in [
+            "system",
+            "user",
+            "assistant",
+            "user",
+            "assistant",
+            "user",
+            "assistant",
+        ] {
+            let msg = ChatMessage::new(role, note.clone());
+            agent.memory.save_message(&conv, &msg).await.unwrap();
+        }
+
+        let response = agent.process_message(&incoming("hello")).await.unwrap();
+        assert_eq!(response, "Hi again!");
+
+        let requests = mock.requests();
+        assert_eq!(requests.len(), 2);
+        assert_eq!(
+            requests[0][0].content.as_deref(),
+            Some(context::SUMMARY_PROMPT)
+        );
+        let system = requests[1][0].content.as_deref().unwrap();
+        assert!(system.contains("The user shared a lot of notes."));
+        assert!(requests[1].len() < 8);
+        assert_eq!(
+            requests[1].last().unwrap().content.as_deref(),
+            Some("hello")
+        );
+
+        let state = agent.memory.load_conversation_summary(&conv).await.unwrap();
+        assert!(state.summarized_count > 0);
+    }
 
     #[test]
     fn test_parse_one_shot_delay_valid() {
@@ -1,11 +1,22 @@
 use anyhow::{Context, Result};
 use serde::Deserialize;
-use std::path::{Path, PathBuf};
+use std::path::{Component, Path, PathBuf};
 
 #[derive(Debug, Deserialize, Clone)]
 pub struct Config {
-    pub telegram: TelegramConfig,
+    /// Telegram bot; optional so `rustfox chat` and Discord/API-only setups need no token
+    #[serde(default)]
+    pub telegram: Option<TelegramConfig>,
+    /// Discord bot, run alongside Telegram when configured
+    #[serde(default)]
+    pub discord: Option<DiscordConfig>,
+    /// Local HTTP/WebSocket chat API
+    #[serde(default)]
+    pub api: Option<ApiConfig>,
+    #[serde(default = "default_openrouter_config")]
     pub openrouter: OpenRouterConfig,
+    #[serde(default)]
+    pub llm: Option<LlmConfig>,
     pub sandbox: SandboxConfig,
     #[serde(default)]
     pub mcp_servers: Vec<McpServerConfig>,
@@ -18,10 +29,33 @@ pub struct Config {
     #[serde(default = "default_agent_config")]
     pub agent: AgentConfig,
     pub embedding: Option<EmbeddingApiConfig>,
+    #[serde(default)]
+    pub usage: UsageConfig,
+    #[serde(default = "default_approval_config")]
+    pub approval: ApprovalConfig,
+    #[serde(default = "default_tool_output_config")]
+    pub tool_output: ToolOutputConfig,
+    #[serde(default = "default_media_config")]
+    pub media: MediaConfig,
+}
+
+/// Where embeddings are computed
+#[derive(Debug, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum EmbeddingProviderKind {
+    /// OpenAI-compatible `/embeddings` API
+    #[default]
+    Api,
+    /// Static embedding model in `model_path`, run on the CPU without network access
+    Local,
 }
 
 #[derive(Debug, Deserialize, Clone)]
 pub struct EmbeddingApiConfig {
+    #[serde(default)]
+    pub provider: EmbeddingProviderKind,
+    /// Required by the API provider
+    #[serde(default)]
     pub api_key: String,
     #[serde(default = "default_embedding_base_url")]
     pub base_url: String,
@@ -29,16 +63,90 @@ pub struct EmbeddingApiConfig {
     pub model: String,
     #[serde(default = "default_embedding_dimensions")]
     pub dimensions: usize,
+    /// Model directory of the local provider (`model.safetensors` and
+    /// `tokenizer.json`); the dimensions come from the model
+    #[serde(default)]
+    pub model_path: Option<PathBuf>,
+    /// Rate of the background re-embedding after a model or dimension change
+    /// (see `reembed_rate`)
+    #[serde(default)]
+    pub reembed_per_minute: Option<usize>,
+}
+
+impl EmbeddingApiConfig {
+    /// Texts re-embedded a minute: `reembed_per_minute`, or by default 120
+    /// for the API and 6000 for a local model, which is not rate-limited
+    pub fn reembed_rate(&self) -> usize {
+        self.reembed_per_minute
+            .unwrap_or(match self.provider {
+                EmbeddingProviderKind::Api => 120,
+                EmbeddingProviderKind::Local => 6000,
+            })
+            .max(1)
+    }
+
+    /// Check that the settings the provider needs are there
+    pub fn validate(&self) -> Result<()> {
+        match self.provider {
+            EmbeddingProviderKind::Api => anyhow::ensure!(
+                !self.api_key.trim().is_empty(),
+                "[embedding] needs an api_key (any value for servers that need none, such as Ollama)"
+            ),
+            EmbeddingProviderKind::Local => anyhow::ensure!(
+                self.model_path.is_some(),
+                "[embedding] provider = \"local\" needs a model_path"
+            ),
+        }
+        Ok(())
+    }
 }
 
 #[derive(Debug, Deserialize, Clone)]
 pub struct TelegramConfig {
     pub bot_token: String,
     pub allowed_user_ids: Vec<u64>,
+    /// Group chats in which every member may talk to the bot (by mentioning it or
+    /// replying to it). Group conversations are shared by the whole chat.
+    #[serde(default)]
+    pub allowed_chat_ids: Vec<i64>,
+    /// In forum supergroups, keep a separate conversation per topic
+    #[serde(default)]
+    pub per_topic_conversations: bool,
+    /// Stream replies by editing a placeholder message as tokens arrive
+    #[serde(default)]
+    pub streaming: bool,
+    /// Minimum interval between placeholder edits while streaming (Telegram rate-limits edits)
+    #[serde(default = "default_stream_edit_interval_ms")]
+    pub stream_edit_interval_ms: u64,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct DiscordConfig {
+    pub bot_token: String,
+    /// Discord user IDs allowed to talk to the bot (in DMs and in servers)
+    pub allowed_user_ids: Vec<u64>,
+}
+
+/// Local chat API (`[api]`): an OpenAI-style `/v1/chat/completions` endpoint and a
+/// WebSocket endpoint. Every request must carry one of the bearer `tokens`.
+#[derive(Debug, Deserialize, Clone)]
+pub struct ApiConfig {
+    /// Address to listen on; keep it on loopback unless a proxy adds TLS
+    #[serde(default = "default_api_listen")]
+    pub listen: String,
+    pub tokens: Vec<ApiToken>,
+}
+
+/// A bearer token and the user it acts as (each user has its own conversation)
+#[derive(Debug, Deserialize, Clone)]
+pub struct ApiToken {
+    pub token: String,
+    pub user_id: String,
 }
 
 #[derive(Debug, Deserialize, Clone)]
 pub struct OpenRouterConfig {
+    #[serde(default)]
     pub api_key: String,
     #[serde(default = "default_model")]
     pub model: String,
@@ -48,11 +156,107 @@ pub struct OpenRouterConfig {
     pub max_tokens: u32,
     #[serde(default = "default_system_prompt")]
     pub system_prompt: String,
+    /// Models tried in order when the primary model keeps failing
+    #[serde(default)]
+    pub fallback_models: Vec<String>,
+    #[serde(default = "default_retry_config")]
+    pub retry: RetryConfig,
+}
+
+/// Retry policy for LLM requests (`[openrouter.retry]`)
+#[derive(Debug, Deserialize, Clone)]
+pub struct RetryConfig {
+    /// Retries per model for rate limits, server errors and timeouts
+    #[serde(default = "default_max_retries")]
+    pub max_retries: u32,
+    #[serde(default = "default_initial_backoff_ms")]
+    pub initial_backoff_ms: u64,
+    #[serde(default = "default_max_backoff_ms")]
+    pub max_backoff_ms: u64,
+    /// Longest wait for the first streamed delta (or the whole reply when not
+    /// streaming) and between deltas; a stalled attempt counts as a retryable
+    /// failure until it has streamed text
+    #[serde(default = "default_request_timeout_secs")]
+    pub request_timeout_secs: u64,
+}
+
+/// Which LLM backend the agent talks to
+#[derive(Debug, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum LlmProviderKind {
+    /// OpenAI-compatible `/chat/completions` (OpenRouter, OpenAI, LM Studio, ...)
+    #[default]
+    OpenAi,
+    /// Native Anthropic Messages API
+    Anthropic,
+    /// Ollama's native `/api/chat`
+    Ollama,
+}
+
+/// Optional `[llm]` section selecting the provider.
+/// Unset fields fall back to `[openrouter]` (OpenAI-compatible) or the provider's defaults.
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct LlmConfig {
+    #[serde(default)]
+    pub provider: LlmProviderKind,
+    #[serde(default)]
+    pub api_key: Option<String>,
+    #[serde(default)]
+    pub base_url: Option<String>,
+    #[serde(default)]
+    pub model: Option<String>,
+}
+
+/// Fully resolved connection settings for the selected LLM provider
+#[derive(Debug, Clone)]
+pub struct LlmSettings {
+    pub provider: LlmProviderKind,
+    pub api_key: String,
+    pub base_url: String,
+    pub model: String,
+    pub max_tokens: u32,
 }
 
 #[derive(Debug, Deserialize, Clone)]
 pub struct SandboxConfig {
     pub allowed_directory: PathBuf,
+    #[serde(default = "default_command_sandbox_config")]
+    pub command: CommandSandboxConfig,
+}
+
+/// How `execute_command` is confined
+#[derive(Debug, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum IsolationMode {
+    /// Use bubblewrap when it is installed, otherwise only resource limits apply
+    #[default]
+    Auto,
+    /// Always use bubblewrap; commands fail if it is not installed
+    Bubblewrap,
+    /// Run directly in the sandbox directory with resource limits only
+    None,
+}
+
+/// Isolation and resource limits for `execute_command` (`[sandbox.command]`)
+#[derive(Debug, Deserialize, Clone)]
+pub struct CommandSandboxConfig {
+    #[serde(default)]
+    pub isolation: IsolationMode,
+    /// Allow network access (denying it requires bubblewrap)
+    #[serde(default = "default_true")]
+    pub allow_network: bool,
+    /// Wall-clock limit; the whole process group is killed when it is exceeded
+    #[serde(default = "default_command_timeout_secs")]
+    pub timeout_secs: u64,
+    /// RLIMIT_CPU
+    #[serde(default)]
+    pub max_cpu_secs: Option<u64>,
+    /// RLIMIT_AS
+    #[serde(default)]
+    pub max_memory_mb: Option<u64>,
+    /// RLIMIT_FSIZE
+    #[serde(default)]
+    pub max_file_size_mb: Option<u64>,
 }
 
 #[derive(Debug, Deserialize, Clone)]
@@ -69,6 +273,103 @@ pub struct McpServerConfig {
 pub struct MemoryConfig {
     #[serde(default = "default_db_path")]
     pub database_path: PathBuf,
+    #[serde(default = "default_auto_recall_config")]
+    pub auto_recall: AutoRecallConfig,
+    #[serde(default = "default_fact_extraction_config")]
+    pub fact_extraction: FactExtractionConfig,
+    #[serde(default = "default_maintenance_config")]
+    pub maintenance: MaintenanceConfig,
+    /// Users who may create, change and delete shared knowledge, which every
+    /// user sees. Nobody may by default.
+    #[serde(default)]
+    pub admin_user_ids: Vec<String>,
+}
+
+impl MemoryConfig {
+    /// Whether `user_id` may change shared knowledge
+    pub fn is_admin(&self, user_id: &str) -> bool {
+        self.admin_user_ids.iter().any(|admin| admin == user_id)
+    }
+}
+
+/// Built-in database upkeep job (`[memory.maintenance]`, see scheduler::tasks)
+#[derive(Debug, Deserialize, Clone)]
+pub struct MaintenanceConfig {
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+    /// 6-field cron expression for the job
+    #[serde(default = "default_maintenance_schedule")]
+    pub schedule: String,
+    /// Default message retention
+    #[serde(flatten)]
+    pub default_retention: RetentionPolicy,
+    /// Per-user (or group chat) retention, replacing the default
+    #[serde(default)]
+    pub users: std::collections::HashMap<String, RetentionPolicy>,
+    /// Rebuild the database file after cleaning up, to return free space to the
+    /// disk. Off by default: the bot cannot use the database while it runs.
+    #[serde(default)]
+    pub vacuum: bool,
+    /// Most messages and knowledge entries without a vector embedded per run
+    #[serde(default = "default_maintenance_backfill_batch")]
+    pub backfill_batch: usize,
+}
+
+impl MaintenanceConfig {
+    /// Retention for the owner of a conversation: their `users` entry, else the default
+    pub fn retention(&self, user_id: &str) -> RetentionPolicy {
+        self.users
+            .get(user_id)
+            .copied()
+            .unwrap_or(self.default_retention)
+    }
+}
+
+/// How long messages are kept. Unset keeps them forever.
+#[derive(Debug, Deserialize, Clone, Copy, Default, PartialEq)]
+pub struct RetentionPolicy {
+    #[serde(default)]
+    pub retention_days: Option<u64>,
+}
+
+/// Background job storing durable facts from conversations in the knowledge
+/// base (`[memory.fact_extraction]`, see crate::facts)
+#[derive(Debug, Deserialize, Clone)]
+pub struct FactExtractionConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    /// 6-field cron expression for the job
+    #[serde(default = "default_fact_extraction_schedule")]
+    pub schedule: String,
+    /// Conversations are reviewed once they have been quiet this long
+    #[serde(default = "default_fact_extraction_idle_minutes")]
+    pub idle_minutes: u64,
+    /// Most conversations reviewed per run
+    #[serde(default = "default_fact_extraction_max_conversations")]
+    pub max_conversations: usize,
+}
+
+/// Relevant memories added to the prompt before each turn (`[memory.auto_recall]`).
+/// Relevance runs from 0 to 1: the cosine similarity of a memory's embedding
+/// to the message's, raised by the bm25 score of the words they share. Without
+/// embeddings it is the keyword score alone, which stays low for words found in
+/// most of the store.
+#[derive(Debug, Deserialize, Clone)]
+pub struct AutoRecallConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    /// Knowledge entries to consider
+    #[serde(default = "default_auto_recall_knowledge")]
+    pub max_knowledge: usize,
+    /// Messages from the user's other conversations to consider
+    #[serde(default = "default_auto_recall_messages")]
+    pub max_messages: usize,
+    /// Memories scoring below this are left out
+    #[serde(default = "default_auto_recall_min_relevance")]
+    pub min_relevance: f64,
+    /// Token budget for everything injected
+    #[serde(default = "default_auto_recall_max_tokens")]
+    pub max_tokens: usize,
 }
 
 #[derive(Debug, Deserialize, Clone)]
@@ -88,6 +389,178 @@ pub struct GeneralConfig {
 pub struct AgentConfig {
     #[serde(default = "default_max_iterations")]
     pub max_iterations: u32,
+    /// Token budget for each LLM request (system prompt, history, tools and reply).
+    /// Older turns beyond this budget are folded into a rolling summary.
+    #[serde(default = "default_context_window_tokens")]
+    pub context_window_tokens: usize,
+    /// How many tool calls from one assistant turn may run at once (1 = sequential)
+    #[serde(default = "default_max_parallel_tools")]
+    pub max_parallel_tools: usize,
+}
+
+/// Size caps for tool results (`[tool_output]`).
+/// Longer results are cut to a head/tail excerpt and saved in full under `spill_dir`.
+#[derive(Debug, Deserialize, Clone)]
+pub struct ToolOutputConfig {
+    /// Cap in bytes for tools not listed in `per_tool`
+    #[serde(default = "default_tool_output_max_bytes")]
+    pub max_bytes: usize,
+    /// Caps for individual tools, e.g. `execute_command = 8000`
+    #[serde(default)]
+    pub per_tool: std::collections::HashMap<String, usize>,
+    /// Directory for full outputs, relative to the sandbox
+    #[serde(default = "default_tool_output_spill_dir")]
+    pub spill_dir: String,
+}
+
+impl ToolOutputConfig {
+    pub fn limit_for(&self, tool: &str) -> usize {
+        self.per_tool
+            .get(tool)
+            .copied()
+            .unwrap_or(self.max_bytes)
+            .max(1)
+    }
+
+    /// Check that `spill_dir` stays inside the sandbox
+    pub fn validate(&self) -> Result<()> {
+        let spill_dir = Path::new(&self.spill_dir);
+        anyhow::ensure!(
+            !self.spill_dir.trim().is_empty()
+                && spill_dir
+                    .components()
+                    .all(|c| matches!(c, Component::Normal(_) | Component::CurDir)),
+            "[tool_output] spill_dir must be a relative path inside the sandbox, got '{}'",
+            self.spill_dir
+        );
+        Ok(())
+    }
+}
+
+/// Handling of photos, documents and voice notes sent to the bot (`[media]`).
+/// Files are downloaded to `<allowed_directory>/<uploads_dir>/<chat id>/`.
+#[derive(Debug, Deserialize, Clone)]
+pub struct MediaConfig {
+    /// Send photos to the model as image input. Disable for text-only models;
+    /// the model is then only told where the image was saved.
+    #[serde(default = "default_true")]
+    pub vision: bool,
+    /// Attachments larger than this are rejected
+    #[serde(default = "default_max_download_mb")]
+    pub max_download_mb: u64,
+    /// Extracted document text beyond this many characters is cut off
+    #[serde(default = "default_max_document_chars")]
+    pub max_document_chars: usize,
+    /// Directory for downloaded attachments, relative to the sandbox
+    #[serde(default = "default_uploads_dir")]
+    pub uploads_dir: String,
+    /// Speech-to-text for voice notes; without it voice notes are only saved
+    #[serde(default)]
+    pub transcription: Option<TranscriptionConfig>,
+}
+
+/// An OpenAI-compatible `/audio/transcriptions` endpoint
+#[derive(Debug, Deserialize, Clone)]
+pub struct TranscriptionConfig {
+    pub api_key: String,
+    #[serde(default = "default_transcription_base_url")]
+    pub base_url: String,
+    #[serde(default = "default_transcription_model")]
+    pub model: String,
+    /// ISO-639-1 language hint, e.g. "en"
+    #[serde(default)]
+    pub language: Option<String>,
+}
+
+/// What happens when the model calls a tool
+#[derive(Debug, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ToolPolicy {
+    #[default]
+    Allow,
+    Deny,
+    /// Ask the user to approve each call
+    Ask,
+}
+
+/// Per-tool approval policy (`[approval]`).
+/// Keys in `tools` are tool names; a trailing `*` matches a prefix (e.g. `"mcp_*"`).
+#[derive(Debug, Deserialize, Clone)]
+pub struct ApprovalConfig {
+    /// Policy for tools not listed in `tools`
+    #[serde(default)]
+    pub default: ToolPolicy,
+    #[serde(default)]
+    pub tools: std::collections::HashMap<String, ToolPolicy>,
+    /// How long to wait for the user's decision before treating it as denied
+    #[serde(default = "default_approval_timeout_secs")]
+    pub timeout_secs: u64,
+}
+
+impl ApprovalConfig {
+    /// Policy for `tool`: an exact entry, else the longest matching prefix pattern, else the default.
+    pub fn policy_for(&self, tool: &str) -> ToolPolicy {
+        if let Some(policy) = self.tools.get(tool) {
+            return *policy;
+        }
+        self.tools
+            .iter()
+            .filter_map(|(pattern, policy)| {
+                let prefix = pattern.strip_suffix('*')?;
+                tool.starts_with(prefix).then_some((prefix.len(), *policy))
+            })
+            .max_by_key(|(len, _)| *len)
+            .map(|(_, policy)| policy)
+            .unwrap_or(self.default)
+    }
+}
+
+/// Daily LLM spending limits (`[usage]`).
+/// The top-level limits apply to every user; `[usage.users."<id>"]` replaces them for one user.
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct UsageConfig {
+    #[serde(flatten)]
+    pub default_budget: DailyBudget,
+    #[serde(default)]
+    pub users: std::collections::HashMap<String, DailyBudget>,
+}
+
+/// Limits on one user's LLM usage per UTC day. Unset limits are not enforced.
+#[derive(Debug, Deserialize, Clone, Copy, Default, PartialEq)]
+pub struct DailyBudget {
+    /// Prompt + completion tokens
+    #[serde(default)]
+    pub daily_token_budget: Option<u64>,
+    /// Cost in USD, as reported by the provider
+    #[serde(default)]
+    pub daily_cost_budget: Option<f64>,
+}
+
+fn default_stream_edit_interval_ms() -> u64 {
+    1500
+}
+
+fn default_true() -> bool {
+    true
+}
+
+fn default_api_listen() -> String {
+    "127.0.0.1:8787".to_string()
+}
+
+fn default_command_timeout_secs() -> u64 {
+    120
+}
+
+fn default_command_sandbox_config() -> CommandSandboxConfig {
+    CommandSandboxConfig {
+        isolation: IsolationMode::Auto,
+        allow_network: true,
+        timeout_secs: default_command_timeout_secs(),
+        max_cpu_secs: None,
+        max_memory_mb: None,
+        max_file_size_mb: None,
+    }
 }
 
 fn default_model() -> String {
@@ -109,6 +582,51 @@ fn default_system_prompt() -> String {
         .to_string()
 }
 
+fn default_anthropic_base_url() -> String {
+    "https://api.anthropic.com/v1".to_string()
+}
+
+fn default_ollama_base_url() -> String {
+    "http://localhost:11434".to_string()
+}
+
+fn default_max_retries() -> u32 {
+    2
+}
+
+fn default_initial_backoff_ms() -> u64 {
+    1000
+}
+
+fn default_max_backoff_ms() -> u64 {
+    30_000
+}
+
+fn default_request_timeout_secs() -> u64 {
+    180
+}
+
+fn default_retry_config() -> RetryConfig {
+    RetryConfig {
+        max_retries: default_max_retries(),
+        initial_backoff_ms: default_initial_backoff_ms(),
+        max_backoff_ms: default_max_backoff_ms(),
+        request_timeout_secs: default_request_timeout_secs(),
+    }
+}
+
+fn default_openrouter_config() -> OpenRouterConfig {
+    OpenRouterConfig {
+        api_key: String::new(),
+        model: default_model(),
+        base_url: default_base_url(),
+        max_tokens: default_max_tokens(),
+        system_prompt: default_system_prompt(),
+        fallback_models: Vec::new(),
+        retry: default_retry_config(),
+    }
+}
+
 fn default_db_path() -> PathBuf {
     PathBuf::from("rustfox.db")
 }
@@ -132,6 +650,76 @@ fn default_embedding_dimensions() -> usize {
 fn default_memory_config() -> MemoryConfig {
     MemoryConfig {
         database_path: default_db_path(),
+        auto_recall: default_auto_recall_config(),
+        fact_extraction: default_fact_extraction_config(),
+        maintenance: default_maintenance_config(),
+        admin_user_ids: Vec::new(),
+    }
+}
+
+fn default_auto_recall_knowledge() -> usize {
+    5
+}
+
+fn default_auto_recall_messages() -> usize {
+    3
+}
+
+fn default_auto_recall_min_relevance() -> f64 {
+    0.6
+}
+
+fn default_auto_recall_max_tokens() -> usize {
+    600
+}
+
+fn default_fact_extraction_schedule() -> String {
+    "0 */15 * * * *".to_string()
+}
+
+fn default_fact_extraction_idle_minutes() -> u64 {
+    10
+}
+
+fn default_fact_extraction_max_conversations() -> usize {
+    10
+}
+
+fn default_fact_extraction_config() -> FactExtractionConfig {
+    FactExtractionConfig {
+        enabled: false,
+        schedule: default_fact_extraction_schedule(),
+        idle_minutes: default_fact_extraction_idle_minutes(),
+        max_conversations: default_fact_extraction_max_conversations(),
+    }
+}
+
+fn default_maintenance_schedule() -> String {
+    "0 30 3 * * *".to_string()
+}
+
+fn default_maintenance_backfill_batch() -> usize {
+    100
+}
+
+fn default_maintenance_config() -> MaintenanceConfig {
+    MaintenanceConfig {
+        enabled: true,
+        schedule: default_maintenance_schedule(),
+        default_retention: RetentionPolicy::default(),
+        users: std::collections::HashMap::new(),
+        vacuum: false,
+        backfill_batch: default_maintenance_backfill_batch(),
+    }
+}
+
+fn default_auto_recall_config() -> AutoRecallConfig {
+    AutoRecallConfig {
+        enabled: false,
+        max_knowledge: default_auto_recall_knowledge(),
+        max_messages: default_auto_recall_messages(),
+        min_relevance: default_auto_recall_min_relevance(),
+        max_tokens: default_auto_recall_max_tokens(),
     }
 }
 
@@ -145,9 +733,77 @@ fn default_max_iterations() -> u32 {
     25
 }
 
+fn default_context_window_tokens() -> usize {
+    64_000
+}
+
+fn default_max_parallel_tools() -> usize {
+    4
+}
+
+fn default_tool_output_max_bytes() -> usize {
+    16_000
+}
+
+fn default_tool_output_spill_dir() -> String {
+    ".tool_output".to_string()
+}
+
+fn default_tool_output_config() -> ToolOutputConfig {
+    ToolOutputConfig {
+        max_bytes: default_tool_output_max_bytes(),
+        per_tool: std::collections::HashMap::new(),
+        spill_dir: default_tool_output_spill_dir(),
+    }
+}
+
+fn default_max_download_mb() -> u64 {
+    20
+}
+
+fn default_max_document_chars() -> usize {
+    20_000
+}
+
+fn default_uploads_dir() -> String {
+    "uploads".to_string()
+}
+
+fn default_transcription_base_url() -> String {
+    "https://api.openai.com/v1".to_string()
+}
+
+fn default_transcription_model() -> String {
+    "whisper-1".to_string()
+}
+
+fn default_media_config() -> MediaConfig {
+    MediaConfig {
+        vision: true,
+        max_download_mb: default_max_download_mb(),
+        max_document_chars: default_max_document_chars(),
+        uploads_dir: default_uploads_dir(),
+        transcription: None,
+    }
+}
+
+fn default_approval_timeout_secs() -> u64 {
+    300
+}
+
+fn default_approval_config() -> ApprovalConfig {
+    ApprovalConfig {
+        default: ToolPolicy::Allow,
+        tools: std::collections::HashMap::new(),
+        timeout_secs: default_approval_timeout_secs(),
+    }
+}
+
 fn default_agent_config() -> AgentConfig {
     AgentConfig {
         max_iterations: default_max_iterations(),
+        context_window_tokens: default_context_window_tokens(),
+        max_parallel_tools: default_max_parallel_tools(),
     }
 }
 
@@ -162,11 +818,67 @@ impl Config {
         self.agent.max_iterations
     }
 
+    /// Token budget per LLM request (from [agent] context_window_tokens, default 64000).
+    pub fn context_window_tokens(&self) -> usize {
+        self.agent.context_window_tokens
+    }
+
+    /// Concurrent tool calls per assistant turn (from [agent] max_parallel_tools, default 4).
+    pub fn max_parallel_tools(&self) -> usize {
+        self.agent.max_parallel_tools.max(1)
+    }
+
+    /// Daily budget for a user: their `[usage.users]` entry, else the `[usage]` defaults.
+    pub fn daily_budget(&self, user_id: &str) -> DailyBudget {
+        self.usage
+            .users
+            .get(user_id)
+            .copied()
+            .unwrap_or(self.usage.default_budget)
+    }
+
+    /// Resolve the LLM provider settings from [llm], falling back to [openrouter].
+    pub fn llm_settings(&self) -> LlmSettings {
+        let llm = self.llm.clone().unwrap_or_default();
+        let (default_key, default_url, default_model) = match llm.provider {
+            LlmProviderKind::OpenAi => (
+                self.openrouter.api_key.clone(),
+                self.openrouter.base_url.clone(),
+                self.openrouter.model.clone(),
+            ),
+            LlmProviderKind::Anthropic => (
+                String::new(),
+                default_anthropic_base_url(),
+                self.openrouter.model.clone(),
+            ),
+            LlmProviderKind::Ollama => (
+                String::new(),
+                default_ollama_base_url(),
+                self.openrouter.model.clone(),
+            ),
+        };
+        LlmSettings {
+            provider: llm.provider,
+            api_key: llm.api_key.unwrap_or(default_key),
+            base_url: llm
+                .base_url
+                .unwrap_or(default_url)
+                .trim_end_matches('/')
+                .to_string(),
+            model: llm.model.unwrap_or(default_model),
+            max_tokens: self.openrouter.max_tokens,
+        }
+    }
+
     pub fn load(path: &Path) -> Result<Self> {
         let content = std::fs::read_to_string(path)
             .with_context(|| format!("Failed to read config file: {}", path.display()))?;
         let config: Config =
             toml::from_str(&content).with_context(|| "Failed to parse config file")?;
+        if let Some(embedding) = &config.embedding {
+            embedding.validate()?;
+        }
+        config.tool_output.validate()?;
 
         // Validate sandbox directory exists
         if !config.sandbox.allowed_directory.exists() {
@@ -181,3 +893,183 @@ impl Config {
         Ok(config)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BASE: &str = "[telegram]\nbot_token = \"t\"\nallowed_user_ids = [1]\n\n\
+                        [sandbox]\nallowed_directory = \"/tmp/rustfox-sandbox\"\n";
+
+    #[test]
+    fn test_llm_settings_default_to_openrouter() {
+        let config: Config = toml::from_str(&format!(
+            "{}\n[openrouter]\napi_key = \"or-key\"\nmodel = \"m\"\n",
+            BASE
+        ))
+        .unwrap();
+        let settings = config.llm_settings();
+        assert_eq!(settings.provider, LlmProviderKind::OpenAi);
+        assert_eq!(settings.api_key, "or-key");
+        assert_eq!(settings.model, "m");
+        assert_eq!(settings.base_url, "https://openrouter.ai/api/v1");
+    }
+
+    #[test]
+    fn test_telegram_section_is_optional() {
+        let config: Config = toml::from_str(BASE).unwrap();
+        assert_eq!(config.telegram.unwrap().allowed_user_ids, vec![1]);
+
+        let config: Config =
+            toml::from_str("[sandbox]\nallowed_directory = \"/tmp/rustfox-sandbox\"\n").unwrap();
+        assert!(config.telegram.is_none());
+    }
+
+    #[test]
+    fn test_llm_settings_anthropic_without_openrouter_section() {
+        let config: Config = toml::from_str(&format!(
+            "{}\n[llm]\nprovider = \"anthropic\"\napi_key = \"sk-ant\"\nmodel = \"claude\"\n",
+            BASE
+        ))
+        .unwrap();
+        let settings = config.llm_settings();
+        assert_eq!(settings.provider, LlmProviderKind::Anthropic);
+        assert_eq!(settings.api_key, "sk-ant");
+        assert_eq!(settings.base_url, "https://api.anthropic.com/v1");
+        assert_eq!(settings.max_tokens, 4096);
+    }
+
+    #[test]
+    fn test_llm_settings_ollama_trims_base_url() {
+        let config: Config = toml::from_str(&format!(
+            "{}\n[llm]\nprovider = \"ollama\"\nbase_url = \"http://gpu:11434/\"\nmodel = \"qwen3\"\n",
+            BASE
+        ))
+        .unwrap();
+        let settings = config.llm_settings();
+        assert_eq!(settings.provider, LlmProviderKind::Ollama);
+        assert_eq!(settings.base_url, "http://gpu:11434");
+        assert_eq!(settings.model, "qwen3");
+    }
+
+    #[test]
+    fn test_local_embedding_provider() {
+        let config: Config = toml::from_str(&format!(
+            "{}\n[embedding]\nprovider = \"local\"\nmodel_path = \"models/potion\"\n",
+            BASE
+        ))
+        .unwrap();
+        let embedding = config.embedding.unwrap();
+        assert_eq!(embedding.provider, EmbeddingProviderKind::Local);
+        assert_eq!(embedding.model_path, Some(PathBuf::from("models/potion")));
+        assert_eq!(embedding.reembed_rate(), 6000);
+        embedding.validate().unwrap();
+
+        let config: Config =
+            toml::from_str(&format!("{}\n[embedding]\napi_key = \"k\"\n", BASE)).unwrap();
+        let embedding = config.embedding.unwrap();
+        assert_eq!(embedding.provider, EmbeddingProviderKind::Api);
+        assert_eq!(embedding.reembed_rate(), 120);
+        embedding.validate().unwrap();
+    }
+
+    #[test]
+    fn test_spill_dir_stays_in_the_sandbox() {
+        let config: Config = toml::from_str(BASE).unwrap();
+        config.tool_output.validate().unwrap();
+        for spill_dir in ["/tmp/out", "../out", "out/../../x", ""] {
+            let config: Config = toml::from_str(&format!(
+                "{}\n[tool_output]\nspill_dir = \"{}\"\n",
+                BASE, spill_dir
+            ))
+            .unwrap();
+            assert!(config.tool_output.validate().is_err(), "{}", spill_dir);
+        }
+    }
+
+    #[test]
+    fn test_embedding_provider_settings_are_required() {
+        for (settings, missing) in [
+            ("api_key = \" \"", "api_key"),
+            ("provider = \"api\"\nmodel_path = \"m\"", "api_key"),
+            ("provider = \"local\"\napi_key = \"k\"", "model_path"),
+        ] {
+            let config: Config =
+                toml::from_str(&format!("{}\n[embedding]\n{}\n", BASE, settings)).unwrap();
+            let error = config.embedding.unwrap().validate().unwrap_err();
+            assert!(error.to_string().contains(missing), "{}", error);
+        }
+    }
+
+    #[test]
+    fn test_approval_policy_lookup() {
+        let config: Config = toml::from_str(&format!(
+            "{}\n[approval]\ndefault = \"ask\"\n\n[approval.tools]\n\
+             read_file = \"allow\"\n\"mcp_*\" = \"deny\"\n\"mcp_git_*\" = \"allow\"\n",
+            BASE
+        ))
+        .unwrap();
+        let approval = &config.approval;
+        assert_eq!(approval.policy_for("read_file"), ToolPolicy::Allow);
+        assert_eq!(approval.policy_for("execute_command"), ToolPolicy::Ask);
+        assert_eq!(approval.policy_for("mcp_fetch_get"), ToolPolicy::Deny);
+        assert_eq!(approval.policy_for("mcp_git_status"), ToolPolicy::Allow);
+        assert_eq!(approval.timeout_secs, 300);
+
+        let config: Config = toml::from_str(BASE).unwrap();
+        assert_eq!(
+            config.approval.policy_for("execute_command"),
+            ToolPolicy::Allow
+        );
+    }
+
+    #[test]
+    fn test_daily_budget_user_override() {
+        let config: Config = toml::from_str(&format!(
+            "{}
+[usage]
+daily_token_budget = 1000
+
+[usage.users.\"42\"]
+daily_cost_budget = 0.5
+",
+            BASE
+        ))
+        .unwrap();
+        assert_eq!(config.daily_budget("1").daily_token_budget, Some(1000));
+        let user = config.daily_budget("42");
+        assert_eq!(user.daily_token_budget, None);
+        assert_eq!(user.daily_cost_budget, Some(0.5));
+    }
+
+    #[test]
+    fn test_retention_user_override() {
+        let config: Config = toml::from_str(&format!(
+            "{}
+[memory.maintenance]
+retention_days = 90
+vacuum = true
+
+[memory.maintenance.users.\"42\"]
+
+[memory.maintenance.users.\"chat:-100\"]
+retention_days = 7
+",
+            BASE
+        ))
+        .unwrap();
+        let maintenance = &config.memory.maintenance;
+        assert!(maintenance.enabled);
+        assert!(maintenance.vacuum);
+        assert_eq!(maintenance.retention("1").retention_days, Some(90));
+        assert_eq!(maintenance.retention("42").retention_days, None);
+        assert_eq!(maintenance.retention("chat:-100").retention_days, Some(7));
+
+        let config: Config = toml::from_str(BASE).unwrap();
+        assert_eq!(
+            config.memory.maintenance.retention("1").retention_days,
+            None
+        );
+        assert!(!config.memory.maintenance.vacuum);
+    }
+}
@@ -0,0 +1,171 @@
+//! Automatic extraction of durable facts from conversations.
+//!
+//! A background job (see `scheduler::tasks`) hands the turns of idle
+//! conversations that were not reviewed yet to the LLM, which lists the facts
+//! and preferences worth keeping. They are stored in the knowledge base of the
+//! conversation's owner with the conversation as their source: facts already
+//! known are skipped and changed ones update the existing entry.
+
+use anyhow::{Context, Result};
+use serde::Deserialize;
+
+use crate::memory::knowledge::KnowledgeEntry;
+
+/// Instructions for the fact extraction request.
+pub const FACT_EXTRACTION_PROMPT: &str = "You pick out durable facts worth remembering long-term \
+from a conversation between a user and an AI assistant: who the user is, their preferences, \
+relationships, projects, plans with dates, and standing instructions. Skip small talk, one-off \
+requests, things only true for the moment and facts about the assistant. Do not repeat facts that \
+are already known unless the conversation changes them; to update one, reuse its category and \
+key. Use short lowercase snake_case categories and keys. Reply with a JSON array only, for \
+example [{\"category\": \"preference\", \"key\": \"coffee\", \"value\": \"Takes coffee black\"}], \
+or [] if there is nothing worth keeping.";
+
+/// Most known facts listed in an extraction request.
+pub const MAX_KNOWN_FACTS: usize = 100;
+
+/// Most messages reviewed in one request; older unreviewed ones are skipped.
+pub const MAX_REVIEWED_MESSAGES: usize = 40;
+
+/// A fact the LLM found in a conversation
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct ExtractedFact {
+    pub category: String,
+    pub key: String,
+    pub value: String,
+}
+
+/// How an extracted fact relates to what is already known
+#[derive(Debug)]
+pub enum FactChange<'a> {
+    New,
+    /// Same category and key as this entry, different value
+    Changed(&'a KnowledgeEntry),
+    /// Already known, possibly under another key
+    Known,
+}
+
+/// The user message of the extraction request: the known facts, then the transcript.
+pub fn extraction_request(known: &[KnowledgeEntry], transcript: &str) -> String {
+    let mut request = String::from("Already known:\n");
+    if known.is_empty() {
+        request.push_str("(nothing yet)\n");
+    }
+    for entry in known.iter().take(MAX_KNOWN_FACTS) {
+        request.push_str(&format!(
+            "- {}/{}: {}\n",
+            entry.category, entry.key, entry.value
+        ));
+    }
+    request.push_str(&format!("\nConversation:\n{}", transcript));
+    request
+}
+
+/// Parse the facts out of the model's reply, tolerating code fences and
+/// text around the JSON array. Facts with an empty field are dropped.
+pub fn parse_facts(reply: &str) -> Result<Vec<ExtractedFact>> {
+    let start = reply.find('[').context("No JSON array in the reply")?;
+    let end = reply.rfind(']').context("No JSON array in the reply")?;
+    anyhow::ensure!(start < end, "No JSON array in the reply");
+    let facts: Vec<ExtractedFact> =
+        serde_json::from_str(&reply[start..=end]).context("Malformed facts JSON")?;
+    Ok(facts
+        .into_iter()
+        .map(|fact| ExtractedFact {
+            category: fact.category.trim().to_string(),
+            key: fact.key.trim().to_string(),
+            value: fact.value.trim().to_string(),
+        })
+        .filter(|fact| !fact.category.is_empty() && !fact.key.is_empty() && !fact.value.is_empty())
+        .collect())
+}
+
+/// Compare `fact` with the `known` entries. Keys and values are compared
+/// ignoring case, punctuation and spacing.
+pub fn classify<'a>(fact: &ExtractedFact, known: &'a [KnowledgeEntry]) -> FactChange<'a> {
+    let value = normalize(&fact.value);
+    let same_key = known.iter().find(|entry| {
+        normalize(&entry.category) == normalize(&fact.category)
+            && normalize(&entry.key) == normalize(&fact.key)
+    });
+    match same_key {
+        Some(entry) if normalize(&entry.value) == value => FactChange::Known,
+        Some(entry) => FactChange::Changed(entry),
+        None if known.iter().any(|entry| normalize(&entry.value) == value) => FactChange::Known,
+        None => FactChange::New,
+    }
+}
+
+/// The `source` of facts extracted from a conversation
+pub fn source(conversation_id: &str) -> String {
+    format!("conversation:{}", conversation_id)
+}
+
+fn normalize(text: &str) -> String {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(str::to_lowercase)
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(category: &str, key: &str, value: &str) -> KnowledgeEntry {
+        KnowledgeEntry {
+            id: String::new(),
+            category: category.to_string(),
+            key: key.to_string(),
+            value: value.to_string(),
+            source: None,
+            shared: false,
+        }
+    }
+
+    fn fact(category: &str, key: &str, value: &str) -> ExtractedFact {
+        ExtractedFact {
+            category: category.to_string(),
+            key: key.to_string(),
+            value: value.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_parse_facts_from_fenced_reply() {
+        let reply = "Here you go:\n```json\n[\n  {\"category\": \"pet\", \"key\": \"dog\", \
+                     \"value\": \" Rex, a beagle \"},\n  {\"category\": \"pet\", \"key\": \"\", \
+                     \"value\": \"x\"}\n]\n```";
+        assert_eq!(
+            parse_facts(reply).unwrap(),
+            vec![fact("pet", "dog", "Rex, a beagle")]
+        );
+        assert!(parse_facts("[]").unwrap().is_empty());
+        assert!(parse_facts("Nothing to add.").is_err());
+    }
+
+    #[test]
+    fn test_classify_against_known_facts() {
+        let known = vec![
+            entry("pet", "dog", "Rex, a beagle"),
+            entry("home", "city", "Porto"),
+        ];
+        assert!(matches!(
+            classify(&fact("Pet", "dog", "rex a beagle"), &known),
+            FactChange::Known
+        ));
+        assert!(matches!(
+            classify(&fact("location", "home_city", "Porto."), &known),
+            FactChange::Known
+        ));
+        assert!(matches!(
+            classify(&fact("home", "city", "Lisbon"), &known),
+            FactChange::Changed(entry) if entry.key == "city"
+        ));
+        assert!(matches!(
+            classify(&fact("food", "allergy", "peanuts"), &known),
+            FactChange::New
+        ));
+    }
+}
@@ -1,137 +0,0 @@
-use anyhow::{Context, Result};
-use serde::{Deserialize, Serialize};
-use tracing::debug;
-
-use crate::config::OpenRouterConfig;
-
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct ChatMessage {
-    pub role: String,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub content: Option<String>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub tool_calls: Option<Vec<ToolCall>>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub tool_call_id: Option<String>,
-}
-
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct ToolCall {
-    pub id: String,
-    #[serde(rename = "type")]
-    pub call_type: String,
-    pub function: FunctionCall,
-}
-
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct FunctionCall {
-    pub name: String,
-    pub arguments: String,
-}
-
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct ToolDefinition {
-    #[serde(rename = "type")]
-    pub tool_type: String,
-    pub function: FunctionDefinition,
-}
-
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct FunctionDefinition {
-    pub name: String,
-    pub description: String,
-    pub parameters: serde_json::Value,
-}
-
-#[derive(Debug, Serialize)]
-struct ChatRequest {
-    model: String,
-    messages: Vec<ChatMessage>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    tools: Option<Vec<ToolDefinition>>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    tool_choice: Option<String>,
-    max_tokens: u32,
-}
-
-#[derive(Debug, Deserialize)]
-struct ChatResponse {
-    choices: Vec<Choice>,
-}
-
-#[derive(Debug, Deserialize)]
-struct Choice {
-    message: ChatMessage,
-}
-
-pub struct LlmClient {
-    client: reqwest::Client,
-    config: OpenRouterConfig,
-}
-
-impl LlmClient {
-    pub fn new(config: OpenRouterConfig) -> Self {
-        Self {
-            client: reqwest::Client::new(),
-            config,
-        }
-    }
-
-    pub async fn chat(
-        &self,
-        messages: &[ChatMessage],
-        tools: &[ToolDefinition],
-    ) -> Result<ChatMessage> {
-        let tools_param = if tools.is_empty() {
-            None
-        } else {
-            Some(tools.to_vec())
-        };
-
-        let tool_choice = if tools_param.is_some() {
-            Some("auto".to_string())
-        } else {
-            None
-        };
-
-        let request = ChatRequest {
-            model: self.config.model.clone(),
-            messages: messages.to_vec(),
-            tools: tools_param,
-            tool_choice,
-            max_tokens: self.config.max_tokens,
-        };
-
-        let url = format!("{}/chat/completions", self.config.base_url);
-
-        debug!("Sending request to OpenRouter: {}", url);
-
-        let response = self
-            .client
-            .post(&url)
-            .header("Authorization", format!("Bearer {}", self.config.api_key))
-            .header("Content-Type", "application/json")
-            .json(&request)
-            .send()
-            .await
-            .context("Failed to send request to OpenRouter")?;
-
-        let status = response.status();
-        if !status.is_success() {
-            let error_body = response.text().await.unwrap_or_default();
-            anyhow::bail!("OpenRouter API error ({}): {}", status, error_body);
-        }
-
-        let chat_response: ChatResponse = response
-            .json()
-            .await
-            .context("Failed to parse OpenRouter response")?;
-
-        chat_response
-            .choices
-            .into_iter()
-            .next()
-            .map(|c| c.message)
-            .context("No response from OpenRouter")
-    }
-}
@@ -0,0 +1,366 @@
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use tracing::debug;
+
+use super::{
+    ChatMessage, FunctionCall, LlmApiError, LlmProvider, LlmResponse, ToolCall, ToolDefinition,
+    Usage,
+};
+use crate::config::LlmSettings;
+
+const ANTHROPIC_VERSION: &str = "2023-06-01";
+
+#[derive(Debug, Deserialize)]
+struct MessagesResponse {
+    content: Vec<ContentBlock>,
+    #[serde(default)]
+    model: Option<String>,
+    #[serde(default)]
+    usage: Option<MessagesUsage>,
+}
+
+#[derive(Debug, Deserialize)]
+struct MessagesUsage {
+    #[serde(default)]
+    input_tokens: u64,
+    #[serde(default)]
+    output_tokens: u64,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum ContentBlock {
+    Text {
+        text: String,
+    },
+    ToolUse {
+        id: String,
+        name: String,
+        input: Value,
+    },
+    #[serde(other)]
+    Other,
+}
+
+/// Provider for the native Anthropic Messages API (`/v1/messages`)
+pub struct AnthropicProvider {
+    client: reqwest::Client,
+    config: LlmSettings,
+}
+
+impl AnthropicProvider {
+    pub fn new(config: LlmSettings) -> Self {
+        Self {
+            client: reqwest::Client::new(),
+            config,
+        }
+    }
+}
+
+#[async_trait]
+impl LlmProvider for AnthropicProvider {
+    fn model(&self) -> &str {
+        &self.config.model
+    }
+
+    async fn chat(
+        &self,
+        messages: &[ChatMessage],
+        tools: &[ToolDefinition],
+    ) -> Result<LlmResponse> {
+        let request = build_request(&self.config.model, self.config.max_tokens, messages, tools);
+        let url = format!("{}/messages", self.config.base_url);
+
+        debug!("Sending request to Anthropic: {}", url);
+
+        let response = self
+            .client
+            .post(&url)
+            .header("x-api-key", &self.config.api_key)
+            .header("anthropic-version", ANTHROPIC_VERSION)
+            .header("Content-Type", "application/json")
+            .json(&request)
+            .send()
+            .await
+            .context("Failed to send request to Anthropic")?;
+
+        let status = response.status();
+        if !status.is_success() {
+            return Err(LlmApiError::from_response("Anthropic", response)
+                .await
+                .into());
+        }
+
+        let body: MessagesResponse = response
+            .json()
+            .await
+            .context("Failed to parse Anthropic response")?;
+
+        let model = body
+            .model
+            .clone()
+            .unwrap_or_else(|| self.config.model.clone());
+        let usage = body.usage.as_ref().map(|u| Usage {
+            prompt_tokens: u.input_tokens,
+            completion_tokens: u.output_tokens,
+            cost: None,
+        });
+        Ok(LlmResponse {
+            message: parse_response(body),
+            model,
+            usage,
+        })
+    }
+}
+
+/// Placeholder user turn put in front of a window that starts with the assistant
+const CONTINUATION_STUB: &str = "(Continuing the conversation summarized above.)";
+
+/// Translate OpenAI-style messages into a Messages API request body.
+/// System messages move to the top-level `system` field, tool calls become
+/// `tool_use` blocks and tool results become `tool_result` blocks in a user turn.
+fn build_request(
+    model: &str,
+    max_tokens: u32,
+    messages: &[ChatMessage],
+    tools: &[ToolDefinition],
+) -> Value {
+    let mut system = Vec::new();
+    let mut turns: Vec<Value> = Vec::new();
+
+    for message in messages {
+        let text = message.content.as_deref().filter(|c| !c.is_empty());
+        let (role, blocks) = match message.role.as_str() {
+            "system" => {
+                if let Some(text) = text {
+                    system.push(text.to_string());
+                }
+                continue;
+            }
+            "tool" => (
+                "user",
+                vec![json!({
+                    "type": "tool_result",
+                    "tool_use_id": message.tool_call_id.clone().unwrap_or_default(),
+                    "content": message.content.clone().unwrap_or_default(),
+                })],
+            ),
+            "assistant" => {
+                let mut blocks = Vec::new();
+                if let Some(text) = text {
+                    blocks.push(json!({ "type": "text", "text": text }));
+                }
+                for call in message.tool_calls.iter().flatten() {
+                    let input: Value = serde_json::from_str(&call.function.arguments)
+                        .unwrap_or_else(|_| json!({}));
+                    blocks.push(json!({
+                        "type": "tool_use",
+                        "id": call.id,
+                        "name": call.function.name,
+                        "input": input,
+                    }));
+                }
+                ("assistant", blocks)
+            }
+            _ => {
+                let images = message.images.iter().map(|image| {
+                    json!({
+                        "type": "image",
+                        "source": {
+                            "type": "base64",
+                            "media_type": image.mime_type,
+                            "data": image.data,
+                        },
+                    })
+                });
+                let text = text.map(|t| json!({ "type": "text", "text": t }));
+                ("user", images.chain(text).collect())
+            }
+        };
+        if blocks.is_empty() {
+            continue;
+        }
+
+        // The API requires alternating roles, so merge consecutive same-role turns
+        // (e.g. several tool results answering one assistant message).
+        match turns.last_mut() {
+            Some(last) if last["role"] == role => {
+                if let Some(content) = last["content"].as_array_mut() {
+                    content.extend(blocks);
+                }
+            }
+            _ => turns.push(json!({ "role": role, "content": blocks })),
+        }
+    }
+
+    // The first turn must be the user's. Folding older messages into a summary
+    // can leave the window starting on an assistant tool call.
+    if turns
+        .first()
+        .is_some_and(|turn| turn["role"] == "assistant")
+    {
+        turns.insert(
+            0,
+            json!({
+                "role": "user",
+                "content": [{ "type": "text", "text": CONTINUATION_STUB }],
+            }),
+        );
+    }
+
+    let mut request = json!({
+        "model": model,
+        "max_tokens": max_tokens,
+        "messages": turns,
+    });
+    if !system.is_empty() {
+        request["system"] = json!(system.join("\n\n"));
+    }
+    if !tools.is_empty() {
+        request["tools"] = tools
+            .iter()
+            .map(|t| {
+                json!({
+                    "name": t.function.name,
+                    "description": t.function.description,
+                    "input_schema": t.function.parameters,
+                })
+            })
+            .collect();
+    }
+    request
+}
+
+/// Collapse response content blocks into a single assistant message.
+fn parse_response(body: MessagesResponse) -> ChatMessage {
+    let mut text = String::new();
+    let mut tool_calls = Vec::new();
+    for block in body.content {
+        match block {
+            ContentBlock::Text { text: t } => text.push_str(&t),
+            ContentBlock::ToolUse { id, name, input } => tool_calls.push(ToolCall {
+                id,
+                call_type: "function".to_string(),
+                function: FunctionCall {
+                    name,
+                    arguments: input.to_string(),
+                },
+            }),
+            ContentBlock::Other => {}
+        }
+    }
+    ChatMessage {
+        role: "assistant".to_string(),
+        content: (!text.is_empty()).then_some(text),
+        tool_calls: (!tool_calls.is_empty()).then_some(tool_calls),
+        tool_call_id: None,
+        images: Vec::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::llm::FunctionDefinition;
+
+    #[test]
+    fn test_build_request_separates_system_and_tool_blocks() {
+        let messages = vec![
+            ChatMessage::new("system", "be nice"),
+            ChatMessage::user("list files"),
+            ChatMessage {
+                role: "assistant".to_string(),
+                content: None,
+                tool_calls: Some(vec![ToolCall {
+                    id: "toolu_1".to_string(),
+                    call_type: "function".to_string(),
+                    function: FunctionCall {
+                        name: "list_files".to_string(),
+                        arguments: "{\"path\":\".\"}".to_string(),
+                    },
+                }]),
+                tool_call_id: None,
+                images: Vec::new(),
+            },
+            ChatMessage {
+                role: "tool".to_string(),
+                content: Some("[FILE] a.txt".to_string()),
+                tool_calls: None,
+                tool_call_id: Some("toolu_1".to_string()),
+                images: Vec::new(),
+            },
+        ];
+        let tools = vec![ToolDefinition {
+            tool_type: "function".to_string(),
+            function: FunctionDefinition {
+                name: "list_files".to_string(),
+                description: "List files".to_string(),
+                parameters: json!({ "type": "object", "properties": {} }),
+            },
+        }];
+
+        let request = build_request("claude", 1024, &messages, &tools);
+        assert_eq!(request["system"], "be nice");
+        let turns = request["messages"].as_array().unwrap();
+        assert_eq!(turns.len(), 3);
+        assert_eq!(turns[0]["role"], "user");
+        assert_eq!(turns[1]["content"][0]["type"], "tool_use");
+        assert_eq!(turns[1]["content"][0]["input"]["path"], ".");
+        assert_eq!(turns[2]["role"], "user");
+        assert_eq!(turns[2]["content"][0]["type"], "tool_result");
+        assert_eq!(turns[2]["content"][0]["tool_use_id"], "toolu_1");
+        assert_eq!(request["tools"][0]["input_schema"]["type"], "object");
+    }
+
+    #[test]
+    fn test_build_request_starts_with_a_user_turn() {
+        let messages = vec![
+            ChatMessage::new("system", "Summary of the conversation so far: ..."),
+            ChatMessage::new("assistant", "Here is what I found."),
+            ChatMessage::user("thanks"),
+        ];
+        let request = build_request("claude", 1024, &messages, &[]);
+        let turns = request["messages"].as_array().unwrap();
+        assert_eq!(turns.len(), 3);
+        assert_eq!(turns[0]["role"], "user");
+        assert_eq!(turns[0]["content"][0]["text"], CONTINUATION_STUB);
+        assert_eq!(turns[1]["role"], "assistant");
+
+        let request = build_request("claude", 1024, &messages[2..], &[]);
+        assert_eq!(request["messages"].as_array().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_build_request_sends_images_as_base64_blocks() {
+        let mut message = ChatMessage::user("what is this?");
+        message.images.push(crate::llm::ImageContent {
+            mime_type: "image/jpeg".to_string(),
+            data: "AAAA".to_string(),
+        });
+        let request = build_request("claude", 1024, &[message], &[]);
+        let content = &request["messages"][0]["content"];
+        assert_eq!(content[0]["type"], "image");
+        assert_eq!(content[0]["source"]["media_type"], "image/jpeg");
+        assert_eq!(content[0]["source"]["data"], "AAAA");
+        assert_eq!(content[1]["text"], "what is this?");
+    }
+
+    #[test]
+    fn test_parse_response_maps_tool_use() {
+        let body: MessagesResponse = serde_json::from_value(json!({
+            "content": [
+                { "type": "text", "text": "Checking." },
+                { "type": "tool_use", "id": "toolu_9", "name": "read_file", "input": { "path": "a" } },
+                { "type": "thinking", "thinking": "..." }
+            ]
+        }))
+        .unwrap();
+        let message = parse_response(body);
+        assert_eq!(message.content.as_deref(), Some("Checking."));
+        let calls = message.tool_calls.unwrap();
+        assert_eq!(calls[0].id, "toolu_9");
+        assert_eq!(calls[0].function.arguments, r#"{"path":"a"}"#);
+    }
+}
@@ -0,0 +1,95 @@
+//! Scripted provider for tests: returns queued replies in order and records
+//! every request it receives.
+
+use anyhow::Result;
+use async_trait::async_trait;
+use std::collections::VecDeque;
+use std::sync::Mutex;
+
+use super::{ChatMessage, FunctionCall, LlmProvider, LlmResponse, ToolCall, ToolDefinition};
+
+pub struct MockProvider {
+    model: String,
+    replies: Mutex<VecDeque<Result<ChatMessage>>>,
+    requests: Mutex<Vec<Vec<ChatMessage>>>,
+}
+
+impl MockProvider {
+    /// A provider named "mock" that answers with `replies` in order
+    pub fn new(replies: Vec<ChatMessage>) -> Self {
+        Self::scripted("mock", replies.into_iter().map(Ok).collect())
+    }
+
+    /// A provider for `model` whose calls succeed or fail as scripted
+    pub fn scripted(model: &str, replies: Vec<Result<ChatMessage>>) -> Self {
+        Self {
+            model: model.to_string(),
+            replies: Mutex::new(replies.into()),
+            requests: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// A plain assistant text reply
+    pub fn text(content: &str) -> ChatMessage {
+        ChatMessage::new("assistant", content)
+    }
+
+    /// An assistant reply requesting a single tool call
+    pub fn tool_call(id: &str, name: &str, arguments: serde_json::Value) -> ChatMessage {
+        Self::tool_calls(vec![(id, name, arguments)])
+    }
+
+    /// An assistant reply requesting several tool calls in one turn
+    pub fn tool_calls(calls: Vec<(&str, &str, serde_json::Value)>) -> ChatMessage {
+        ChatMessage {
+            role: "assistant".to_string(),
+            content: None,
+            tool_calls: Some(
+                calls
+                    .into_iter()
+                    .map(|(id, name, arguments)| ToolCall {
+                        id: id.to_string(),
+                        call_type: "function".to_string(),
+                        function: FunctionCall {
+                            name: name.to_string(),
+                            arguments: arguments.to_string(),
+                        },
+                    })
+                    .collect(),
+            ),
+            tool_call_id: None,
+            images: Vec::new(),
+        }
+    }
+
+    /// Message lists of every request received so far
+    pub fn requests(&self) -> Vec<Vec<ChatMessage>> {
+        self.requests.lock().unwrap().clone()
+    }
+}
+
+#[async_trait]
+impl LlmProvider for MockProvider {
+    fn model(&self) -> &str {
+        &self.model
+    }
+
+    async fn chat(
+        &self,
+        messages: &[ChatMessage],
+        _tools: &[ToolDefinition],
+    ) -> Result<LlmResponse> {
+        self.requests.lock().unwrap().push(messages.to_vec());
+        let message = self
+            .replies
+            .lock()
+            .unwrap()
+            .pop_front()
+            .ok_or_else(|| anyhow::anyhow!("MockProvider has no scripted replies left"))??;
+        Ok(LlmResponse {
+            message,
+            model: self.model.clone(),
+            usage: None,
+        })
+    }
+}
@@ -0,0 +1,222 @@
+pub mod anthropic;
+#[cfg(test)]
+pub mod mock;
+pub mod ollama;
+pub mod openai;
+pub mod retry;
+
+use anyhow::Result;
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::config::{Config, LlmProviderKind, LlmSettings};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChatMessage {
+    pub role: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub content: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tool_calls: Option<Vec<ToolCall>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tool_call_id: Option<String>,
+    /// Images sent with a user message to vision-capable models.
+    /// Only kept for the turn they arrive in; they are not persisted.
+    #[serde(skip)]
+    pub images: Vec<ImageContent>,
+}
+
+impl ChatMessage {
+    /// A plain text message from `role`
+    pub fn new(role: &str, content: impl Into<String>) -> Self {
+        Self {
+            role: role.to_string(),
+            content: Some(content.into()),
+            tool_calls: None,
+            tool_call_id: None,
+            images: Vec::new(),
+        }
+    }
+
+    /// A plain text message from the user
+    pub fn user(content: impl Into<String>) -> Self {
+        Self::new("user", content)
+    }
+}
+
+/// An inline image, base64-encoded
+#[derive(Debug, Clone, PartialEq)]
+pub struct ImageContent {
+    pub mime_type: String,
+    pub data: String,
+}
+
+impl ImageContent {
+    /// `data:` URL form used by OpenAI-compatible APIs
+    pub fn data_url(&self) -> String {
+        format!("data:{};base64,{}", self.mime_type, self.data)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ToolCall {
+    pub id: String,
+    #[serde(rename = "type")]
+    pub call_type: String,
+    pub function: FunctionCall,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FunctionCall {
+    pub name: String,
+    pub arguments: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ToolDefinition {
+    #[serde(rename = "type")]
+    pub tool_type: String,
+    pub function: FunctionDefinition,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FunctionDefinition {
+    pub name: String,
+    pub description: String,
+    pub parameters: serde_json::Value,
+}
+
+/// Callback receiving streamed text content as it is generated. Chunks without
+/// text (such as tool-call argument fragments) arrive as empty deltas, so
+/// callers can tell a slow stream from a stalled one.
+pub type DeltaCallback<'a> = dyn Fn(&str) + Send + Sync + 'a;
+
+/// Token counts (and cost, when the API reports it) for a single LLM call
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Usage {
+    pub prompt_tokens: u64,
+    pub completion_tokens: u64,
+    /// Cost in USD as reported by the provider (OpenRouter), if any
+    pub cost: Option<f64>,
+}
+
+/// The assistant's reply together with the model that actually produced it
+#[derive(Debug, Clone)]
+pub struct LlmResponse {
+    pub message: ChatMessage,
+    pub model: String,
+    /// None when the provider did not report usage
+    pub usage: Option<Usage>,
+}
+
+/// Non-success HTTP status from an LLM API.
+/// Kept as a typed error so the retry layer can inspect status and `Retry-After`.
+#[derive(Debug)]
+pub struct LlmApiError {
+    pub provider: &'static str,
+    pub status: reqwest::StatusCode,
+    pub retry_after: Option<Duration>,
+    pub body: String,
+}
+
+impl LlmApiError {
+    /// Consume a failed response, capturing its status, `Retry-After` and body.
+    pub async fn from_response(provider: &'static str, response: reqwest::Response) -> Self {
+        let status = response.status();
+        let retry_after = response
+            .headers()
+            .get(reqwest::header::RETRY_AFTER)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.trim().parse::<u64>().ok())
+            .map(Duration::from_secs);
+        let body = response.text().await.unwrap_or_default();
+        Self {
+            provider,
+            status,
+            retry_after,
+            body,
+        }
+    }
+
+    /// Rate limits, timeouts and server errors are worth retrying
+    pub fn is_retryable(&self) -> bool {
+        self.status == reqwest::StatusCode::TOO_MANY_REQUESTS
+            || self.status == reqwest::StatusCode::REQUEST_TIMEOUT
+            || self.status.is_server_error()
+    }
+}
+
+impl std::fmt::Display for LlmApiError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} API error ({}): {}",
+            self.provider, self.status, self.body
+        )
+    }
+}
+
+impl std::error::Error for LlmApiError {}
+
+/// A chat-completion backend. Messages and tools use the OpenAI-style types
+/// above; each provider translates them to and from its own wire format.
+#[async_trait]
+pub trait LlmProvider: Send + Sync {
+    /// Model identifier requests are sent to
+    fn model(&self) -> &str;
+
+    /// Send the conversation and return the assistant's reply
+    async fn chat(&self, messages: &[ChatMessage], tools: &[ToolDefinition])
+        -> Result<LlmResponse>;
+
+    /// Like `chat`, but calls `on_delta` with text content as it is generated.
+    /// Providers without native streaming deliver the whole reply as one delta.
+    async fn chat_stream(
+        &self,
+        messages: &[ChatMessage],
+        tools: &[ToolDefinition],
+        on_delta: &DeltaCallback<'_>,
+    ) -> Result<LlmResponse> {
+        let response = self.chat(messages, tools).await?;
+        if let Some(content) = response
+            .message
+            .content
+            .as_deref()
+            .filter(|c| !c.is_empty())
+        {
+            on_delta(content);
+        }
+        Ok(response)
+    }
+}
+
+/// Build the provider selected by the `[llm]` config section.
+pub fn build_provider(settings: LlmSettings) -> Arc<dyn LlmProvider> {
+    match settings.provider {
+        LlmProviderKind::OpenAi => Arc::new(openai::OpenAiProvider::new(settings)),
+        LlmProviderKind::Anthropic => Arc::new(anthropic::AnthropicProvider::new(settings)),
+        LlmProviderKind::Ollama => Arc::new(ollama::OllamaProvider::new(settings)),
+    }
+}
+
+/// Build the configured provider for the primary model and each fallback model,
+/// wrapped in a chain that retries transient failures and falls back in order.
+pub fn build_from_config(config: &Config) -> Arc<dyn LlmProvider> {
+    let settings = config.llm_settings();
+    let models = std::iter::once(settings.model.clone())
+        .chain(config.openrouter.fallback_models.iter().cloned());
+    let providers = models
+        .map(|model| {
+            build_provider(LlmSettings {
+                model,
+                ..settings.clone()
+            })
+        })
+        .collect();
+    Arc::new(retry::FallbackChain::new(
+        providers,
+        config.openrouter.retry.clone(),
+    ))
+}
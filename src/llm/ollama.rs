@@ -0,0 +1,332 @@
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use tracing::debug;
+
+use super::{
+    ChatMessage, DeltaCallback, FunctionCall, LlmApiError, LlmProvider, LlmResponse, ToolCall,
+    ToolDefinition, Usage,
+};
+use crate::config::LlmSettings;
+
+/// One response object from `/api/chat` (the whole body, or one NDJSON line when streaming)
+#[derive(Debug, Deserialize)]
+struct ChatChunk {
+    #[serde(default)]
+    message: Option<OllamaMessage>,
+    #[serde(default)]
+    error: Option<String>,
+    /// Token counts, present on the final object
+    #[serde(default)]
+    prompt_eval_count: Option<u64>,
+    #[serde(default)]
+    eval_count: Option<u64>,
+}
+
+impl ChatChunk {
+    fn usage(&self) -> Option<Usage> {
+        if self.prompt_eval_count.is_none() && self.eval_count.is_none() {
+            return None;
+        }
+        Some(Usage {
+            prompt_tokens: self.prompt_eval_count.unwrap_or(0),
+            completion_tokens: self.eval_count.unwrap_or(0),
+            cost: None,
+        })
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct OllamaMessage {
+    #[serde(default)]
+    content: String,
+    #[serde(default)]
+    tool_calls: Vec<OllamaToolCall>,
+}
+
+#[derive(Debug, Deserialize)]
+struct OllamaToolCall {
+    function: OllamaFunction,
+}
+
+#[derive(Debug, Deserialize)]
+struct OllamaFunction {
+    name: String,
+    #[serde(default)]
+    arguments: Value,
+}
+
+/// Provider for Ollama's native `/api/chat` endpoint
+pub struct OllamaProvider {
+    client: reqwest::Client,
+    config: LlmSettings,
+}
+
+impl OllamaProvider {
+    pub fn new(config: LlmSettings) -> Self {
+        Self {
+            client: reqwest::Client::new(),
+            config,
+        }
+    }
+
+    async fn send(
+        &self,
+        messages: &[ChatMessage],
+        tools: &[ToolDefinition],
+        stream: bool,
+    ) -> Result<reqwest::Response> {
+        let request = build_request(
+            &self.config.model,
+            self.config.max_tokens,
+            messages,
+            tools,
+            stream,
+        );
+        let url = format!("{}/api/chat", self.config.base_url);
+
+        debug!("Sending request to Ollama: {}", url);
+
+        let response = self
+            .client
+            .post(&url)
+            .json(&request)
+            .send()
+            .await
+            .context("Failed to send request to Ollama")?;
+
+        let status = response.status();
+        if !status.is_success() {
+            return Err(LlmApiError::from_response("Ollama", response).await.into());
+        }
+        Ok(response)
+    }
+
+    fn response(
+        &self,
+        text: String,
+        tool_calls: Vec<ToolCall>,
+        usage: Option<Usage>,
+    ) -> LlmResponse {
+        LlmResponse {
+            message: assistant_message(text, tool_calls),
+            model: self.config.model.clone(),
+            usage,
+        }
+    }
+}
+
+#[async_trait]
+impl LlmProvider for OllamaProvider {
+    fn model(&self) -> &str {
+        &self.config.model
+    }
+
+    async fn chat(
+        &self,
+        messages: &[ChatMessage],
+        tools: &[ToolDefinition],
+    ) -> Result<LlmResponse> {
+        let response = self.send(messages, tools, false).await?;
+        let chunk: ChatChunk = response
+            .json()
+            .await
+            .context("Failed to parse Ollama response")?;
+        if let Some(error) = chunk.error {
+            anyhow::bail!("Ollama error: {}", error);
+        }
+        let usage = chunk.usage();
+        let message = chunk.message.context("No message in Ollama response")?;
+
+        let mut text = String::new();
+        let mut tool_calls = Vec::new();
+        merge_message(message, &mut text, &mut tool_calls);
+        Ok(self.response(text, tool_calls, usage))
+    }
+
+    /// Ollama streams newline-delimited JSON objects rather than SSE.
+    async fn chat_stream(
+        &self,
+        messages: &[ChatMessage],
+        tools: &[ToolDefinition],
+        on_delta: &DeltaCallback<'_>,
+    ) -> Result<LlmResponse> {
+        let mut response = self.send(messages, tools, true).await?;
+
+        let mut text = String::new();
+        let mut tool_calls = Vec::new();
+        let mut usage = None;
+        let mut buffer: Vec<u8> = Vec::new();
+
+        while let Some(bytes) = response
+            .chunk()
+            .await
+            .context("Failed to read Ollama stream")?
+        {
+            buffer.extend_from_slice(&bytes);
+            while let Some(pos) = buffer.iter().position(|&b| b == b'\n') {
+                let line: Vec<u8> = buffer.drain(..=pos).collect();
+                let line = String::from_utf8_lossy(&line);
+                let line = line.trim();
+                if line.is_empty() {
+                    continue;
+                }
+                let chunk: ChatChunk = serde_json::from_str(line)
+                    .with_context(|| format!("Failed to parse Ollama stream line: {}", line))?;
+                if let Some(error) = chunk.error {
+                    anyhow::bail!("Ollama error: {}", error);
+                }
+                if let Some(u) = chunk.usage() {
+                    usage = Some(u);
+                }
+                match chunk.message {
+                    Some(message) => {
+                        on_delta(&message.content);
+                        merge_message(message, &mut text, &mut tool_calls);
+                    }
+                    // An empty delta still shows the stream is alive
+                    None => on_delta(""),
+                }
+            }
+        }
+
+        Ok(self.response(text, tool_calls, usage))
+    }
+}
+
+/// Translate OpenAI-style messages into an `/api/chat` request body.
+/// Ollama expects tool arguments as JSON objects and identifies tool results by name.
+fn build_request(
+    model: &str,
+    max_tokens: u32,
+    messages: &[ChatMessage],
+    tools: &[ToolDefinition],
+    stream: bool,
+) -> Value {
+    let mut call_names: HashMap<&str, &str> = HashMap::new();
+    let mut wire = Vec::with_capacity(messages.len());
+
+    for message in messages {
+        let mut entry = json!({
+            "role": message.role,
+            "content": message.content.clone().unwrap_or_default(),
+        });
+        if !message.images.is_empty() {
+            entry["images"] = message.images.iter().map(|i| json!(i.data)).collect();
+        }
+        if let Some(calls) = &message.tool_calls {
+            entry["tool_calls"] = calls
+                .iter()
+                .map(|call| {
+                    call_names.insert(&call.id, &call.function.name);
+                    let arguments: Value = serde_json::from_str(&call.function.arguments)
+                        .unwrap_or_else(|_| json!({}));
+                    json!({ "function": { "name": call.function.name, "arguments": arguments } })
+                })
+                .collect();
+        }
+        if let Some(name) = message
+            .tool_call_id
+            .as_deref()
+            .and_then(|id| call_names.get(id))
+        {
+            entry["tool_name"] = json!(name);
+        }
+        wire.push(entry);
+    }
+
+    let mut request = json!({
+        "model": model,
+        "messages": wire,
+        "stream": stream,
+        "options": { "num_predict": max_tokens },
+    });
+    if !tools.is_empty() {
+        request["tools"] = json!(tools);
+    }
+    request
+}
+
+fn merge_message(message: OllamaMessage, text: &mut String, tool_calls: &mut Vec<ToolCall>) {
+    text.push_str(&message.content);
+    for call in message.tool_calls {
+        tool_calls.push(ToolCall {
+            // Ollama does not assign call IDs; synthesize one so results can be matched
+            id: format!("call_{}", uuid::Uuid::new_v4().simple()),
+            call_type: "function".to_string(),
+            function: FunctionCall {
+                name: call.function.name,
+                arguments: call.function.arguments.to_string(),
+            },
+        });
+    }
+}
+
+fn assistant_message(text: String, tool_calls: Vec<ToolCall>) -> ChatMessage {
+    ChatMessage {
+        role: "assistant".to_string(),
+        content: (!text.is_empty()).then_some(text),
+        tool_calls: (!tool_calls.is_empty()).then_some(tool_calls),
+        tool_call_id: None,
+        images: Vec::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_build_request_names_tool_results() {
+        let messages = vec![
+            ChatMessage {
+                role: "assistant".to_string(),
+                content: None,
+                tool_calls: Some(vec![ToolCall {
+                    id: "call_1".to_string(),
+                    call_type: "function".to_string(),
+                    function: FunctionCall {
+                        name: "read_file".to_string(),
+                        arguments: "{\"path\":\"a.txt\"}".to_string(),
+                    },
+                }]),
+                tool_call_id: None,
+                images: Vec::new(),
+            },
+            ChatMessage {
+                role: "tool".to_string(),
+                content: Some("hello".to_string()),
+                tool_calls: None,
+                tool_call_id: Some("call_1".to_string()),
+                images: Vec::new(),
+            },
+        ];
+        let request = build_request("llama3.1", 512, &messages, &[], false);
+        let wire = request["messages"].as_array().unwrap();
+        assert_eq!(
+            wire[0]["tool_calls"][0]["function"]["arguments"]["path"],
+            "a.txt"
+        );
+        assert_eq!(wire[1]["tool_name"], "read_file");
+        assert_eq!(request["options"]["num_predict"], 512);
+        assert!(request.get("tools").is_none());
+    }
+
+    #[test]
+    fn test_merge_message_assigns_call_ids() {
+        let message: OllamaMessage = serde_json::from_value(json!({
+            "role": "assistant",
+            "content": "",
+            "tool_calls": [{ "function": { "name": "list_files", "arguments": {} } }]
+        }))
+        .unwrap();
+        let mut text = String::new();
+        let mut calls = Vec::new();
+        merge_message(message, &mut text, &mut calls);
+        assert_eq!(calls.len(), 1);
+        assert!(calls[0].id.starts_with("call_"));
+        assert_eq!(calls[0].function.arguments, "{}");
+    }
+}
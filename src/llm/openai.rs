@@ -0,0 +1,488 @@
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use tracing::debug;
+
+use super::{
+    ChatMessage, DeltaCallback, FunctionCall, LlmApiError, LlmProvider, LlmResponse, ToolCall,
+    ToolDefinition, Usage,
+};
+use crate::config::LlmSettings;
+
+#[derive(Debug, Serialize)]
+struct ChatRequest {
+    model: String,
+    messages: Vec<RequestMessage>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tools: Option<Vec<ToolDefinition>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tool_choice: Option<String>,
+    max_tokens: u32,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    stream: bool,
+    /// Ask for a final usage chunk when streaming
+    #[serde(skip_serializing_if = "Option::is_none")]
+    stream_options: Option<serde_json::Value>,
+    /// OpenRouter usage accounting (adds `cost` to the usage object)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    usage: Option<serde_json::Value>,
+}
+
+/// A message as sent to the API. Content becomes an array of text and
+/// `image_url` parts when images are attached.
+#[derive(Debug, Serialize)]
+struct RequestMessage {
+    role: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    content: Option<RequestContent>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tool_calls: Option<Vec<ToolCall>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tool_call_id: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(untagged)]
+enum RequestContent {
+    Text(String),
+    Parts(Vec<serde_json::Value>),
+}
+
+impl From<&ChatMessage> for RequestMessage {
+    fn from(message: &ChatMessage) -> Self {
+        let content = if message.images.is_empty() {
+            message.content.clone().map(RequestContent::Text)
+        } else {
+            let text = message
+                .content
+                .iter()
+                .map(|text| serde_json::json!({ "type": "text", "text": text }));
+            let images = message.images.iter().map(|image| {
+                serde_json::json!({ "type": "image_url", "image_url": { "url": image.data_url() } })
+            });
+            Some(RequestContent::Parts(text.chain(images).collect()))
+        };
+        RequestMessage {
+            role: message.role.clone(),
+            content,
+            tool_calls: message.tool_calls.clone(),
+            tool_call_id: message.tool_call_id.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct ChatResponse {
+    choices: Vec<Choice>,
+    /// The model that actually answered (OpenRouter may route to a different one)
+    #[serde(default)]
+    model: Option<String>,
+    #[serde(default)]
+    usage: Option<WireUsage>,
+}
+
+#[derive(Debug, Deserialize)]
+struct WireUsage {
+    #[serde(default)]
+    prompt_tokens: u64,
+    #[serde(default)]
+    completion_tokens: u64,
+    #[serde(default)]
+    cost: Option<f64>,
+}
+
+impl From<WireUsage> for Usage {
+    fn from(usage: WireUsage) -> Self {
+        Usage {
+            prompt_tokens: usage.prompt_tokens,
+            completion_tokens: usage.completion_tokens,
+            cost: usage.cost,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct Choice {
+    message: ChatMessage,
+}
+
+/// One `data:` payload of a streamed (SSE) chat completion
+#[derive(Debug, Deserialize)]
+struct StreamChunk {
+    #[serde(default)]
+    choices: Vec<StreamChoice>,
+    #[serde(default)]
+    error: Option<serde_json::Value>,
+    #[serde(default)]
+    model: Option<String>,
+    #[serde(default)]
+    usage: Option<WireUsage>,
+}
+
+#[derive(Debug, Deserialize)]
+struct StreamChoice {
+    #[serde(default)]
+    delta: StreamDelta,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct StreamDelta {
+    #[serde(default)]
+    content: Option<String>,
+    #[serde(default)]
+    tool_calls: Option<Vec<ToolCallDelta>>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ToolCallDelta {
+    #[serde(default)]
+    index: usize,
+    #[serde(default)]
+    id: Option<String>,
+    #[serde(default)]
+    function: Option<FunctionCallDelta>,
+}
+
+#[derive(Debug, Deserialize)]
+struct FunctionCallDelta {
+    #[serde(default)]
+    name: Option<String>,
+    #[serde(default)]
+    arguments: Option<String>,
+}
+
+/// Reassembles a streamed completion into a single assistant message.
+/// Tool calls arrive as fragments keyed by `index`; names and argument
+/// JSON are concatenated across chunks.
+#[derive(Debug, Default)]
+struct StreamAccumulator {
+    content: String,
+    tool_calls: Vec<ToolCall>,
+    model: Option<String>,
+    usage: Option<Usage>,
+}
+
+impl StreamAccumulator {
+    /// Merge one chunk, returning any new text content.
+    fn apply(&mut self, chunk: StreamChunk) -> Option<String> {
+        let mut text = String::new();
+        if self.model.is_none() {
+            self.model = chunk.model;
+        }
+        if let Some(usage) = chunk.usage {
+            self.usage = Some(usage.into());
+        }
+        for choice in chunk.choices {
+            if let Some(content) = choice.delta.content {
+                text.push_str(&content);
+            }
+            for delta in choice.delta.tool_calls.into_iter().flatten() {
+                while self.tool_calls.len() <= delta.index {
+                    self.tool_calls.push(ToolCall {
+                        id: String::new(),
+                        call_type: "function".to_string(),
+                        function: FunctionCall {
+                            name: String::new(),
+                            arguments: String::new(),
+                        },
+                    });
+                }
+                let call = &mut self.tool_calls[delta.index];
+                if let Some(id) = delta.id {
+                    call.id = id;
+                }
+                if let Some(function) = delta.function {
+                    if let Some(name) = function.name {
+                        call.function.name.push_str(&name);
+                    }
+                    if let Some(arguments) = function.arguments {
+                        call.function.arguments.push_str(&arguments);
+                    }
+                }
+            }
+        }
+        self.content.push_str(&text);
+        (!text.is_empty()).then_some(text)
+    }
+
+    fn finish(self) -> ChatMessage {
+        ChatMessage {
+            role: "assistant".to_string(),
+            content: (!self.content.is_empty()).then_some(self.content),
+            tool_calls: (!self.tool_calls.is_empty()).then_some(self.tool_calls),
+            tool_call_id: None,
+            images: Vec::new(),
+        }
+    }
+}
+
+/// Extract the payload of an SSE `data:` line. Comments and other fields yield None.
+fn sse_data(line: &str) -> Option<&str> {
+    line.strip_prefix("data:").map(str::trim)
+}
+
+/// Provider for OpenAI-compatible `/chat/completions` endpoints (OpenRouter by default)
+pub struct OpenAiProvider {
+    client: reqwest::Client,
+    config: LlmSettings,
+}
+
+impl OpenAiProvider {
+    pub fn new(config: LlmSettings) -> Self {
+        Self {
+            client: reqwest::Client::new(),
+            config,
+        }
+    }
+
+    fn build_request(
+        &self,
+        messages: &[ChatMessage],
+        tools: &[ToolDefinition],
+        stream: bool,
+    ) -> ChatRequest {
+        let tools_param = if tools.is_empty() {
+            None
+        } else {
+            Some(tools.to_vec())
+        };
+
+        let tool_choice = if tools_param.is_some() {
+            Some("auto".to_string())
+        } else {
+            None
+        };
+
+        let is_openrouter = self.config.base_url.contains("openrouter.ai");
+
+        ChatRequest {
+            model: self.config.model.clone(),
+            messages: messages.iter().map(RequestMessage::from).collect(),
+            tools: tools_param,
+            tool_choice,
+            max_tokens: self.config.max_tokens,
+            stream,
+            stream_options: stream.then(|| serde_json::json!({ "include_usage": true })),
+            usage: is_openrouter.then(|| serde_json::json!({ "include": true })),
+        }
+    }
+
+    /// POST a request to `/chat/completions`, failing on non-success status.
+    async fn send(&self, request: &ChatRequest) -> Result<reqwest::Response> {
+        let url = format!("{}/chat/completions", self.config.base_url);
+
+        debug!("Sending request to OpenRouter: {}", url);
+
+        let response = self
+            .client
+            .post(&url)
+            .header("Authorization", format!("Bearer {}", self.config.api_key))
+            .header("Content-Type", "application/json")
+            .json(request)
+            .send()
+            .await
+            .context("Failed to send request to OpenRouter")?;
+
+        let status = response.status();
+        if !status.is_success() {
+            return Err(LlmApiError::from_response("OpenRouter", response)
+                .await
+                .into());
+        }
+
+        Ok(response)
+    }
+}
+
+#[async_trait]
+impl LlmProvider for OpenAiProvider {
+    fn model(&self) -> &str {
+        &self.config.model
+    }
+
+    async fn chat(
+        &self,
+        messages: &[ChatMessage],
+        tools: &[ToolDefinition],
+    ) -> Result<LlmResponse> {
+        let request = self.build_request(messages, tools, false);
+        let response = self.send(&request).await?;
+
+        let chat_response: ChatResponse = response
+            .json()
+            .await
+            .context("Failed to parse OpenRouter response")?;
+
+        let message = chat_response
+            .choices
+            .into_iter()
+            .next()
+            .map(|c| c.message)
+            .context("No response from OpenRouter")?;
+        Ok(LlmResponse {
+            message,
+            model: chat_response
+                .model
+                .unwrap_or_else(|| self.config.model.clone()),
+            usage: chat_response.usage.map(Usage::from),
+        })
+    }
+
+    /// Requests a server-sent event stream. Streamed tool-call fragments are
+    /// reassembled, so the returned message is equivalent to what `chat` would produce.
+    async fn chat_stream(
+        &self,
+        messages: &[ChatMessage],
+        tools: &[ToolDefinition],
+        on_delta: &DeltaCallback<'_>,
+    ) -> Result<LlmResponse> {
+        let request = self.build_request(messages, tools, true);
+        let mut response = self.send(&request).await?;
+
+        let mut accumulator = StreamAccumulator::default();
+        let mut buffer: Vec<u8> = Vec::new();
+
+        'read: while let Some(bytes) = response
+            .chunk()
+            .await
+            .context("Failed to read OpenRouter stream")?
+        {
+            buffer.extend_from_slice(&bytes);
+            while let Some(pos) = buffer.iter().position(|&b| b == b'\n') {
+                let line: Vec<u8> = buffer.drain(..=pos).collect();
+                let line = String::from_utf8_lossy(&line);
+                let Some(data) = sse_data(line.trim()) else {
+                    continue;
+                };
+                if data == "[DONE]" {
+                    break 'read;
+                }
+                if data.is_empty() {
+                    continue;
+                }
+                let chunk: StreamChunk = serde_json::from_str(data)
+                    .with_context(|| format!("Failed to parse stream chunk: {}", data))?;
+                if let Some(error) = &chunk.error {
+                    anyhow::bail!("OpenRouter stream error: {}", error);
+                }
+                // An empty delta still shows the stream is alive
+                on_delta(&accumulator.apply(chunk).unwrap_or_default());
+            }
+        }
+
+        let model = accumulator
+            .model
+            .take()
+            .unwrap_or_else(|| self.config.model.clone());
+        let usage = accumulator.usage.take();
+        Ok(LlmResponse {
+            message: accumulator.finish(),
+            model,
+            usage,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chunk(json: &str) -> StreamChunk {
+        serde_json::from_str(json).unwrap()
+    }
+
+    #[test]
+    fn test_request_message_with_images_uses_content_parts() {
+        let mut message = ChatMessage::user("what is this?");
+        let plain = serde_json::to_value(RequestMessage::from(&message)).unwrap();
+        assert_eq!(plain["content"], "what is this?");
+
+        message.images.push(crate::llm::ImageContent {
+            mime_type: "image/png".to_string(),
+            data: "AAAA".to_string(),
+        });
+        let wire = serde_json::to_value(RequestMessage::from(&message)).unwrap();
+        assert_eq!(wire["content"][0]["type"], "text");
+        assert_eq!(wire["content"][1]["type"], "image_url");
+        assert_eq!(
+            wire["content"][1]["image_url"]["url"],
+            "data:image/png;base64,AAAA"
+        );
+    }
+
+    #[test]
+    fn test_sse_data_extracts_payload() {
+        assert_eq!(sse_data("data: {\"a\":1}"), Some("{\"a\":1}"));
+        assert_eq!(sse_data("data:[DONE]"), Some("[DONE]"));
+        assert_eq!(sse_data(": OPENROUTER PROCESSING"), None);
+        assert_eq!(sse_data("event: message"), None);
+    }
+
+    #[test]
+    fn test_accumulator_concatenates_text() {
+        let mut acc = StreamAccumulator::default();
+        assert_eq!(
+            acc.apply(chunk(
+                r#"{"choices":[{"delta":{"role":"assistant","content":"Hel"}}]}"#
+            )),
+            Some("Hel".to_string())
+        );
+        assert_eq!(
+            acc.apply(chunk(r#"{"choices":[{"delta":{"content":"lo"}}]}"#)),
+            Some("lo".to_string())
+        );
+        assert_eq!(acc.apply(chunk(r#"{"choices":[{"delta":{}}]}"#)), None);
+
+        let message = acc.finish();
+        assert_eq!(message.role, "assistant");
+        assert_eq!(message.content.as_deref(), Some("Hello"));
+        assert!(message.tool_calls.is_none());
+    }
+
+    #[test]
+    fn test_accumulator_reassembles_tool_call_fragments() {
+        let mut acc = StreamAccumulator::default();
+        acc.apply(chunk(
+            r#"{"choices":[{"delta":{"tool_calls":[{"index":0,"id":"call_1","type":"function","function":{"name":"read_file","arguments":""}}]}}]}"#,
+        ));
+        acc.apply(chunk(
+            r#"{"choices":[{"delta":{"tool_calls":[{"index":0,"function":{"arguments":"{\"path\":"}}]}}]}"#,
+        ));
+        acc.apply(chunk(
+            r#"{"choices":[{"delta":{"tool_calls":[{"index":1,"id":"call_2","function":{"name":"list_files","arguments":"{}"}}]}}]}"#,
+        ));
+        acc.apply(chunk(
+            r#"{"choices":[{"delta":{"tool_calls":[{"index":0,"function":{"arguments":"\"a.txt\"}"}}]}}]}"#,
+        ));
+
+        let message = acc.finish();
+        assert!(message.content.is_none());
+        let calls = message.tool_calls.unwrap();
+        assert_eq!(calls.len(), 2);
+        assert_eq!(calls[0].id, "call_1");
+        assert_eq!(calls[0].function.name, "read_file");
+        assert_eq!(calls[0].function.arguments, r#"{"path":"a.txt"}"#);
+        assert_eq!(calls[1].id, "call_2");
+        assert_eq!(calls[1].function.name, "list_files");
+    }
+
+    #[test]
+    fn test_accumulator_captures_final_usage_chunk() {
+        let mut acc = StreamAccumulator::default();
+        acc.apply(chunk(
+            r#"{"model":"openai/gpt-4o","choices":[{"delta":{"content":"Hi"}}]}"#,
+        ));
+        acc.apply(chunk(
+            r#"{"choices":[],"usage":{"prompt_tokens":12,"completion_tokens":3,"cost":0.0004}}"#,
+        ));
+        assert_eq!(acc.model.as_deref(), Some("openai/gpt-4o"));
+        assert_eq!(
+            acc.usage,
+            Some(Usage {
+                prompt_tokens: 12,
+                completion_tokens: 3,
+                cost: Some(0.0004),
+            })
+        );
+    }
+}
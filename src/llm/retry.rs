@@ -0,0 +1,416 @@
+//! Retry and model-fallback wrapper around concrete providers.
+//!
+//! Each model is tried with exponential backoff (with jitter, honouring
+//! `Retry-After`) for transient failures; once it is exhausted, or fails
+//! with a non-retryable error, the next model in the chain takes over.
+
+use anyhow::Result;
+use async_trait::async_trait;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::time::Instant;
+use tracing::warn;
+
+use super::{ChatMessage, DeltaCallback, LlmApiError, LlmProvider, LlmResponse, ToolDefinition};
+use crate::config::RetryConfig;
+
+/// Tries the primary provider first and then each fallback, in order
+pub struct FallbackChain {
+    providers: Vec<Arc<dyn LlmProvider>>,
+    policy: RetryConfig,
+}
+
+impl FallbackChain {
+    pub fn new(providers: Vec<Arc<dyn LlmProvider>>, policy: RetryConfig) -> Self {
+        assert!(
+            !providers.is_empty(),
+            "FallbackChain needs at least one provider"
+        );
+        Self { providers, policy }
+    }
+
+    async fn call(
+        &self,
+        messages: &[ChatMessage],
+        tools: &[ToolDefinition],
+        on_delta: Option<&DeltaCallback<'_>>,
+    ) -> Result<LlmResponse> {
+        let timeout = Duration::from_secs(self.policy.request_timeout_secs);
+        let mut last_error = None;
+
+        for (index, provider) in self.providers.iter().enumerate() {
+            if index > 0 {
+                warn!(
+                    "Falling back to model '{}' after '{}' failed",
+                    provider.model(),
+                    self.providers[index - 1].model()
+                );
+            }
+
+            let mut attempt = 0;
+            loop {
+                // Deltas already shown cannot be taken back, so once one was
+                // forwarded the attempt is neither retried nor replaced
+                let streamed = AtomicBool::new(false);
+                let last_activity = Mutex::new(Instant::now());
+                let forward = |delta: &str| {
+                    // Every chunk counts as activity, but only text is shown
+                    *last_activity.lock().unwrap() = Instant::now();
+                    if delta.is_empty() {
+                        return;
+                    }
+                    streamed.store(true, Ordering::SeqCst);
+                    if let Some(on_delta) = on_delta {
+                        on_delta(delta);
+                    }
+                };
+                let call = async {
+                    match on_delta {
+                        Some(_) => provider.chat_stream(messages, tools, &forward).await,
+                        None => provider.chat(messages, tools).await,
+                    }
+                };
+                // The timeout covers the wait for the first delta and each gap
+                // between deltas, not the whole generation
+                let idle = async {
+                    loop {
+                        let deadline = *last_activity.lock().unwrap() + timeout;
+                        if Instant::now() >= deadline {
+                            break;
+                        }
+                        tokio::time::sleep_until(deadline).await;
+                    }
+                };
+                let outcome = tokio::select! {
+                    result = call => Some(result),
+                    _ = idle => None,
+                };
+                let (error, retryable, retry_after) = match outcome {
+                    Some(Ok(response)) => return Ok(response),
+                    Some(Err(e)) => {
+                        let (retryable, retry_after) = classify(&e);
+                        (e, retryable, retry_after)
+                    }
+                    None => (
+                        anyhow::anyhow!(
+                            "Request to model '{}' stalled for {:?}",
+                            provider.model(),
+                            timeout
+                        ),
+                        true,
+                        None,
+                    ),
+                };
+
+                if streamed.load(Ordering::SeqCst) {
+                    warn!(
+                        "Model '{}' failed after streaming part of its reply: {:#}",
+                        provider.model(),
+                        error
+                    );
+                    return Err(error);
+                }
+                if !retryable || attempt >= self.policy.max_retries {
+                    warn!("Model '{}' failed: {:#}", provider.model(), error);
+                    last_error = Some(error);
+                    break;
+                }
+
+                let delay = backoff_delay(&self.policy, attempt, retry_after);
+                warn!(
+                    "Model '{}' attempt {} failed ({:#}); retrying in {:?}",
+                    provider.model(),
+                    attempt + 1,
+                    error,
+                    delay
+                );
+                tokio::time::sleep(delay).await;
+                attempt += 1;
+            }
+        }
+
+        Err(last_error.unwrap_or_else(|| anyhow::anyhow!("No LLM providers configured")))
+    }
+}
+
+#[async_trait]
+impl LlmProvider for FallbackChain {
+    fn model(&self) -> &str {
+        self.providers[0].model()
+    }
+
+    async fn chat(
+        &self,
+        messages: &[ChatMessage],
+        tools: &[ToolDefinition],
+    ) -> Result<LlmResponse> {
+        self.call(messages, tools, None).await
+    }
+
+    async fn chat_stream(
+        &self,
+        messages: &[ChatMessage],
+        tools: &[ToolDefinition],
+        on_delta: &DeltaCallback<'_>,
+    ) -> Result<LlmResponse> {
+        self.call(messages, tools, Some(on_delta)).await
+    }
+}
+
+/// Whether an error is transient, and how long the server asked us to wait.
+fn classify(error: &anyhow::Error) -> (bool, Option<Duration>) {
+    if let Some(api) = error.chain().find_map(|e| e.downcast_ref::<LlmApiError>()) {
+        return (api.is_retryable(), api.retry_after);
+    }
+    if let Some(http) = error
+        .chain()
+        .find_map(|e| e.downcast_ref::<reqwest::Error>())
+    {
+        return (
+            http.is_timeout() || http.is_connect() || http.is_request() || http.is_body(),
+            None,
+        );
+    }
+    (false, None)
+}
+
+/// Exponential backoff with jitter in [delay/2, delay], capped at `max_backoff_ms`.
+/// A server-provided `Retry-After` takes precedence (also capped).
+fn backoff_delay(policy: &RetryConfig, attempt: u32, retry_after: Option<Duration>) -> Duration {
+    let max = Duration::from_millis(policy.max_backoff_ms);
+    if let Some(retry_after) = retry_after {
+        return retry_after.min(max);
+    }
+    let exponential = policy
+        .initial_backoff_ms
+        .saturating_mul(1u64 << attempt.min(20))
+        .min(policy.max_backoff_ms);
+    let half = exponential / 2;
+    Duration::from_millis(half + fastrand::u64(0..=exponential - half))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::llm::mock::MockProvider;
+
+    fn policy(max_retries: u32) -> RetryConfig {
+        RetryConfig {
+            max_retries,
+            initial_backoff_ms: 1,
+            max_backoff_ms: 5,
+            request_timeout_secs: 5,
+        }
+    }
+
+    fn api_error(status: u16) -> anyhow::Error {
+        LlmApiError {
+            provider: "Mock",
+            status: reqwest::StatusCode::from_u16(status).unwrap(),
+            retry_after: None,
+            body: String::new(),
+        }
+        .into()
+    }
+
+    #[test]
+    fn test_backoff_delay_bounds() {
+        let policy = RetryConfig {
+            max_retries: 5,
+            initial_backoff_ms: 100,
+            max_backoff_ms: 1000,
+            request_timeout_secs: 5,
+        };
+        for attempt in 0..8 {
+            let delay = backoff_delay(&policy, attempt, None).as_millis() as u64;
+            let expected = (100u64 << attempt).min(1000);
+            assert!(delay >= expected / 2 && delay <= expected, "{}", delay);
+        }
+    }
+
+    #[test]
+    fn test_backoff_delay_honours_retry_after() {
+        let policy = policy(1);
+        let delay = backoff_delay(&policy, 0, Some(Duration::from_millis(3)));
+        assert_eq!(delay, Duration::from_millis(3));
+        // Capped at max_backoff_ms
+        let delay = backoff_delay(&policy, 0, Some(Duration::from_secs(60)));
+        assert_eq!(delay, Duration::from_millis(5));
+    }
+
+    #[test]
+    fn test_classify_statuses() {
+        assert!(classify(&api_error(429)).0);
+        assert!(classify(&api_error(503)).0);
+        assert!(!classify(&api_error(400)).0);
+        assert!(!classify(&api_error(401)).0);
+    }
+
+    #[tokio::test]
+    async fn test_retries_transient_error_on_same_model() {
+        let primary = Arc::new(MockProvider::scripted(
+            "primary",
+            vec![Err(api_error(429)), Ok(MockProvider::text("ok"))],
+        ));
+        let chain = FallbackChain::new(vec![primary.clone()], policy(2));
+
+        let response = chain.chat(&[], &[]).await.unwrap();
+        assert_eq!(response.message.content.as_deref(), Some("ok"));
+        assert_eq!(response.model, "primary");
+        assert_eq!(primary.requests().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_falls_back_after_retries_exhausted() {
+        let primary = Arc::new(MockProvider::scripted(
+            "primary",
+            vec![Err(api_error(503)), Err(api_error(503))],
+        ));
+        let fallback = Arc::new(MockProvider::scripted(
+            "fallback",
+            vec![Ok(MockProvider::text("from fallback"))],
+        ));
+        let chain = FallbackChain::new(vec![primary.clone(), fallback.clone()], policy(1));
+
+        let response = chain.chat(&[], &[]).await.unwrap();
+        assert_eq!(response.model, "fallback");
+        assert_eq!(primary.requests().len(), 2);
+        assert_eq!(fallback.requests().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_non_retryable_error_skips_to_fallback() {
+        let primary = Arc::new(MockProvider::scripted("primary", vec![Err(api_error(400))]));
+        let fallback = Arc::new(MockProvider::scripted(
+            "fallback",
+            vec![Ok(MockProvider::text("fine"))],
+        ));
+        let chain = FallbackChain::new(vec![primary.clone(), fallback], policy(3));
+
+        let response = chain.chat(&[], &[]).await.unwrap();
+        assert_eq!(response.model, "fallback");
+        assert_eq!(primary.requests().len(), 1);
+    }
+
+    /// Streams `deltas` with `gap` before each, then fails with a 503 or
+    /// returns the text
+    struct SlowStream {
+        deltas: Vec<&'static str>,
+        gap: Duration,
+        fail: bool,
+    }
+
+    #[async_trait]
+    impl LlmProvider for SlowStream {
+        fn model(&self) -> &str {
+            "slow"
+        }
+
+        async fn chat(
+            &self,
+            _messages: &[ChatMessage],
+            _tools: &[ToolDefinition],
+        ) -> Result<LlmResponse> {
+            anyhow::bail!("only streams")
+        }
+
+        async fn chat_stream(
+            &self,
+            _messages: &[ChatMessage],
+            _tools: &[ToolDefinition],
+            on_delta: &DeltaCallback<'_>,
+        ) -> Result<LlmResponse> {
+            for delta in &self.deltas {
+                tokio::time::sleep(self.gap).await;
+                on_delta(delta);
+            }
+            if self.fail {
+                return Err(api_error(503));
+            }
+            Ok(LlmResponse {
+                message: MockProvider::text(&self.deltas.concat()),
+                model: self.model().to_string(),
+                usage: None,
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_failure_after_streamed_text_is_not_retried() {
+        let primary = Arc::new(SlowStream {
+            deltas: vec!["Hello"],
+            gap: Duration::ZERO,
+            fail: true,
+        });
+        let fallback = Arc::new(MockProvider::scripted(
+            "fallback",
+            vec![Ok(MockProvider::text("Hello again"))],
+        ));
+        let chain = FallbackChain::new(vec![primary, fallback.clone()], policy(3));
+
+        let shown = Mutex::new(String::new());
+        let on_delta = |delta: &str| shown.lock().unwrap().push_str(delta);
+        let error = chain.chat_stream(&[], &[], &on_delta).await.unwrap_err();
+        assert!(error.to_string().contains("503"));
+        assert_eq!(*shown.lock().unwrap(), "Hello");
+        assert!(fallback.requests().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_timeout_applies_between_deltas() {
+        // Longer than the timeout in total, but never idle for that long
+        let steady = Arc::new(SlowStream {
+            deltas: vec!["a", "b", "c"],
+            gap: Duration::from_millis(500),
+            fail: false,
+        });
+        let mut policy = policy(0);
+        policy.request_timeout_secs = 1;
+        let chain = FallbackChain::new(vec![steady], policy.clone());
+        let response = chain.chat_stream(&[], &[], &|_| {}).await.unwrap();
+        assert_eq!(response.message.content.as_deref(), Some("abc"));
+
+        let stalled = Arc::new(SlowStream {
+            deltas: vec!["a"],
+            gap: Duration::from_millis(1500),
+            fail: false,
+        });
+        let chain = FallbackChain::new(vec![stalled], policy);
+        let error = chain.chat_stream(&[], &[], &|_| {}).await.unwrap_err();
+        assert!(error.to_string().contains("stalled"));
+    }
+
+    #[tokio::test]
+    async fn test_tool_call_fragments_count_as_activity() {
+        // A reply that is only a tool call streams empty deltas for its argument
+        // fragments: longer than the timeout in total, shown to nobody
+        let writing = Arc::new(SlowStream {
+            deltas: vec!["", "", "", ""],
+            gap: Duration::from_millis(500),
+            fail: false,
+        });
+        let fallback = Arc::new(MockProvider::scripted(
+            "fallback",
+            vec![Ok(MockProvider::text("from fallback"))],
+        ));
+        let mut policy = policy(1);
+        policy.request_timeout_secs = 1;
+        let chain = FallbackChain::new(vec![writing, fallback.clone()], policy);
+
+        let shown = Mutex::new(Vec::new());
+        let on_delta = |delta: &str| shown.lock().unwrap().push(delta.to_string());
+        let response = chain.chat_stream(&[], &[], &on_delta).await.unwrap();
+        assert_eq!(response.model, "slow");
+        assert!(shown.lock().unwrap().is_empty());
+        assert!(fallback.requests().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_returns_last_error_when_all_models_fail() {
+        let primary = Arc::new(MockProvider::scripted("primary", vec![Err(api_error(401))]));
+        let chain = FallbackChain::new(vec![primary], policy(3));
+        let error = chain.chat(&[], &[]).await.unwrap_err();
+        assert!(error.to_string().contains("401"));
+    }
+}
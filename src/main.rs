@@ -1,72 +1,223 @@
 mod agent;
 mod config;
+mod context;
+mod facts;
 mod llm;
 mod mcp;
+mod media;
 mod memory;
 mod platform;
+mod sandbox;
 mod scheduler;
 mod skills;
 mod tools;
 
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 
 use anyhow::{Context, Result};
-use tracing::info;
+use tracing::{error, info};
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
 
 use crate::agent::Agent;
-use crate::config::Config;
+use crate::config::{Config, EmbeddingProviderKind};
 use crate::mcp::McpManager;
+use crate::memory::embeddings::{EmbeddingConfig, EmbeddingEngine};
+use crate::memory::export::{ConversationExport, ExportFormat};
 use crate::memory::MemoryStore;
+use crate::platform::Platforms;
 use crate::scheduler::tasks::register_builtin_tasks;
 use crate::scheduler::Scheduler;
 use crate::skills::loader::load_skills_from_dir;
 
+const USAGE: &str = "Usage:
+  rustfox [config.toml]                 Run the configured bots
+  rustfox chat [config.toml]            Chat in the terminal
+  rustfox export <conversation-id> [--format markdown|json] [--output FILE] [config.toml]
+  rustfox import <file.json> [--platform NAME] [--user ID] [config.toml]";
+
+/// What to run, from the command line
+enum Mode {
+    /// Run the configured platforms (Telegram, Discord, chat API)
+    Bots,
+    /// Interactive chat on stdin/stdout
+    Chat,
+    /// Write a conversation (id or unique prefix) to a file or stdout
+    Export {
+        conversation: String,
+        format: ExportFormat,
+        output: Option<PathBuf>,
+    },
+    /// Restore a conversation from a JSON export, optionally for another user
+    Import {
+        file: PathBuf,
+        platform: Option<String>,
+        user_id: Option<String>,
+    },
+}
+
+/// Parse the command line into a mode and the config path
+fn parse_args(args: Vec<String>) -> Result<(Mode, PathBuf)> {
+    let mut args = args.into_iter().peekable();
+    let subcommand = match args.peek().map(String::as_str) {
+        Some(name @ ("chat" | "export" | "import")) => {
+            let name = name.to_string();
+            args.next();
+            name
+        }
+        _ => String::new(),
+    };
+
+    let mut positional = Vec::new();
+    let mut format = ExportFormat::Markdown;
+    let mut output = None;
+    let mut platform = None;
+    let mut user_id = None;
+    while let Some(arg) = args.next() {
+        let mut value = |flag: &str| {
+            args.next()
+                .ok_or_else(|| anyhow::anyhow!("{} needs a value\n\n{}", flag, USAGE))
+        };
+        match arg.as_str() {
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                std::process::exit(0);
+            }
+            "--format" => {
+                let name = value("--format")?;
+                format = ExportFormat::parse(&name)
+                    .ok_or_else(|| anyhow::anyhow!("Unknown export format '{}'", name))?;
+            }
+            "--output" => output = Some(PathBuf::from(value("--output")?)),
+            "--platform" => platform = Some(value("--platform")?),
+            "--user" => user_id = Some(value("--user")?),
+            flag if flag.starts_with("--") => anyhow::bail!("Unknown option {}\n\n{}", flag, USAGE),
+            _ => positional.push(arg),
+        }
+    }
+
+    let mut positional = positional.into_iter();
+    let mode = match subcommand.as_str() {
+        "chat" => Mode::Chat,
+        "export" => Mode::Export {
+            conversation: positional
+                .next()
+                .ok_or_else(|| anyhow::anyhow!("export needs a conversation id\n\n{}", USAGE))?,
+            format,
+            output,
+        },
+        "import" => Mode::Import {
+            file: positional
+                .next()
+                .map(PathBuf::from)
+                .ok_or_else(|| anyhow::anyhow!("import needs a file\n\n{}", USAGE))?,
+            platform,
+            user_id,
+        },
+        _ => Mode::Bots,
+    };
+    let config_path = positional
+        .next()
+        .map(PathBuf::from)
+        .unwrap_or_else(|| PathBuf::from("config.toml"));
+    Ok((mode, config_path))
+}
+
 #[tokio::main]
 async fn main() -> Result<()> {
-    // Initialize logging
+    let (mode, config_path) = parse_args(std::env::args().skip(1).collect())?;
+    let chat_mode = matches!(mode, Mode::Chat);
+
+    // Initialize logging. Only the bots log progress; the terminal modes log
+    // warnings only, on stderr, so their output stays readable.
+    let default_filter = if matches!(mode, Mode::Bots) {
+        "info,rustfox=debug"
+    } else {
+        "warn"
+    };
     tracing_subscriber::registry()
         .with(
             tracing_subscriber::EnvFilter::try_from_default_env()
-                .unwrap_or_else(|_| "info,rustfox=debug".into()),
+                .unwrap_or_else(|_| default_filter.into()),
         )
-        .with(tracing_subscriber::fmt::layer())
+        .with(tracing_subscriber::fmt::layer().with_writer(std::io::stderr))
         .init();
 
-    // Load configuration
-    let config_path = std::env::args()
-        .nth(1)
-        .map(PathBuf::from)
-        .unwrap_or_else(|| PathBuf::from("config.toml"));
-
     info!("Loading configuration from: {}", config_path.display());
     let config = Config::load(&config_path)
         .with_context(|| format!("Failed to load config from {}", config_path.display()))?;
 
+    if matches!(mode, Mode::Bots)
+        && config.telegram.is_none()
+        && config.discord.is_none()
+        && config.api.is_none()
+    {
+        anyhow::bail!(
+            "No platform configured: add a [telegram], [discord] or [api] section, \
+             or run `rustfox chat` for a terminal chat"
+        );
+    }
+
     info!("Configuration loaded successfully");
-    info!("  Model: {}", config.openrouter.model);
+    let llm_settings = config.llm_settings();
+    info!(
+        "  LLM: {:?} {} ({})",
+        llm_settings.provider, llm_settings.model, llm_settings.base_url
+    );
+    if !config.openrouter.fallback_models.is_empty() {
+        info!("  Fallback models: {:?}", config.openrouter.fallback_models);
+    }
     info!("  Sandbox: {}", config.sandbox.allowed_directory.display());
-    info!("  Allowed users: {:?}", config.telegram.allowed_user_ids);
+    if let Some(telegram) = &config.telegram {
+        info!("  Allowed users: {:?}", telegram.allowed_user_ids);
+    }
+    if let Some(discord) = &config.discord {
+        info!("  Discord allowed users: {:?}", discord.allowed_user_ids);
+    }
+    if let Some(api) = &config.api {
+        info!("  Chat API: {} ({} tokens)", api.listen, api.tokens.len());
+    }
     info!("  MCP servers: {}", config.mcp_servers.len());
 
-    // Build embedding config if configured
-    let embedding_config =
-        config
-            .embedding
-            .as_ref()
-            .map(|cfg| crate::memory::embeddings::EmbeddingConfig {
+    // Build the embedding engine if configured
+    let embeddings = match &config.embedding {
+        None => EmbeddingEngine::new(None),
+        Some(cfg) => match cfg.provider {
+            EmbeddingProviderKind::Api => EmbeddingEngine::new(Some(EmbeddingConfig {
                 api_key: cfg.api_key.clone(),
                 base_url: cfg.base_url.clone(),
                 model: cfg.model.clone(),
                 dimensions: cfg.dimensions,
-            });
+            })),
+            EmbeddingProviderKind::Local => {
+                let model_path = cfg
+                    .model_path
+                    .as_deref()
+                    .context("[embedding] provider = \"local\" needs a model_path")?;
+                EmbeddingEngine::local(model_path)?
+            }
+        },
+    };
 
     // Initialize memory store (SQLite + vector embeddings)
-    let memory = MemoryStore::open(&config.memory.database_path, embedding_config)
+    let memory = MemoryStore::open(&config.memory.database_path, embeddings)
         .context("Failed to initialize memory store")?;
     info!("  Database: {}", config.memory.database_path.display());
 
+    match mode {
+        Mode::Export {
+            conversation,
+            format,
+            output,
+        } => return export_conversation(&memory, &conversation, format, output).await,
+        Mode::Import {
+            file,
+            platform,
+            user_id,
+        } => return import_conversation(&memory, &file, platform, user_id).await,
+        Mode::Bots | Mode::Chat => {}
+    }
+
     // Initialize MCP connections
     let mut mcp_manager = McpManager::new();
     mcp_manager.connect_all(&config.mcp_servers).await;
@@ -81,8 +232,32 @@ async fn main() -> Result<()> {
     // Create scheduler as Arc so Agent can hold it and closures can reference it
     let scheduler = Arc::new(Scheduler::new().await?);
 
-    // Create Bot early so it can be passed to Agent
-    let bot = Arc::new(teloxide::Bot::new(&config.telegram.bot_token));
+    // Outbound message sinks, used to deliver scheduled task replies to the
+    // platform each task was created on
+    let bot = config
+        .telegram
+        .as_ref()
+        .map(|telegram| teloxide::Bot::new(&telegram.bot_token));
+    let mut platforms = Platforms::default();
+    if chat_mode {
+        platforms.register("cli", Arc::new(platform::cli::CliSink));
+    }
+    if let Some(bot) = &bot {
+        platforms.register(
+            "telegram",
+            Arc::new(platform::telegram::TelegramSink::new(bot.clone())),
+        );
+    }
+    if let Some(discord) = &config.discord {
+        platforms.register(
+            "discord",
+            Arc::new(platform::discord::DiscordSink::new(discord)),
+        );
+    }
+    let api_clients = platform::api::ApiClients::default();
+    if config.api.is_some() {
+        platforms.register("api", Arc::new(api_clients.clone()));
+    }
 
     // Channel for dispatching scheduled job work from fire closures to background runner
     let (job_tx, mut job_rx) =
@@ -97,7 +272,6 @@ async fn main() -> Result<()> {
             skills,
             task_store.clone(),
             Arc::clone(&scheduler),
-            Arc::clone(&bot),
             weak.clone(),
             job_tx,
         )
@@ -106,14 +280,16 @@ async fn main() -> Result<()> {
     // Spawn background runner: receives ScheduledJobRequest, calls process_message, sends reply
     let agent_for_runner = Arc::clone(&agent);
     tokio::spawn(async move {
-        use teloxide::prelude::*;
         while let Some(req) = job_rx.recv().await {
             let agent = Arc::clone(&agent_for_runner);
             // Mark one-shot as completed (before running, so failure can override)
             if !req.is_recurring {
                 let _ = req.task_store.set_status(&req.task_id, "completed").await;
             }
-            let response = match agent.process_message(&req.incoming).await {
+            let response = match agent
+                .process_scheduled_message(&req.incoming, &req.task_id)
+                .await
+            {
                 Ok(r) => r,
                 Err(e) => {
                     tracing::error!("Scheduled task {} failed: {}", req.task_id, e);
@@ -123,44 +299,117 @@ async fn main() -> Result<()> {
                     continue;
                 }
             };
-            let chat_id_val: i64 = match req.incoming.chat_id.parse() {
-                Ok(v) => v,
-                Err(_) => {
-                    tracing::error!(
-                        "Unparseable chat_id '{}' for task {}",
-                        req.incoming.chat_id,
-                        req.task_id
-                    );
-                    continue;
-                }
-            };
-            let chat = teloxide::types::ChatId(chat_id_val);
-            for chunk in crate::agent::split_response_chunks(&response, 4000) {
-                if chunk.is_empty() {
-                    continue;
-                }
-                if let Err(e) = req.bot.send_message(chat, &chunk).await {
-                    tracing::error!("Failed to send scheduled response: {}", e);
-                }
+            if let Err(e) = platforms
+                .send_message(
+                    &req.incoming.platform,
+                    &req.incoming.chat_id,
+                    req.incoming.thread_id.as_deref(),
+                    &response,
+                )
+                .await
+            {
+                tracing::error!(
+                    "Failed to send response of scheduled task {}: {:#}",
+                    req.task_id,
+                    e
+                );
             }
         }
     });
 
     // Register built-in background tasks and start scheduler
-    register_builtin_tasks(&scheduler, memory).await?;
+    register_builtin_tasks(&scheduler, memory, Arc::downgrade(&agent)).await?;
     scheduler.start().await?;
     info!("  Scheduler: active");
     agent.restore_scheduled_tasks().await;
     info!("  Scheduled tasks: restored from DB");
 
-    // Run the Telegram platform
+    if chat_mode {
+        return platform::cli::run(agent).await;
+    }
+
+    // Run every configured platform: Telegram, Discord and the chat API
     info!("Bot is starting...");
-    platform::telegram::run(
-        agent,
-        config.telegram.allowed_user_ids.clone(),
-        Arc::clone(&bot),
-    )
-    .await?;
+    let telegram = async {
+        match (config.telegram.clone(), bot) {
+            (Some(telegram), Some(bot)) => {
+                platform::telegram::run(Arc::clone(&agent), telegram, bot).await
+            }
+            _ => Ok(()),
+        }
+    };
+    let discord = async {
+        match config.discord.clone() {
+            Some(discord) => platform::discord::run(Arc::clone(&agent), discord).await,
+            None => Ok(()),
+        }
+    };
+    let api = async {
+        match config.api.clone() {
+            Some(api) => platform::api::run(Arc::clone(&agent), api, api_clients).await,
+            None => Ok(()),
+        }
+    };
+    // A platform that fails is logged and leaves the others running
+    let (telegram, discord, api) = tokio::join!(telegram, discord, api);
+    let mut failed = 0;
+    for (name, result) in [
+        ("Telegram", telegram),
+        ("Discord", discord),
+        ("Chat API", api),
+    ] {
+        if let Err(e) = result {
+            error!("{} stopped with an error: {:#}", name, e);
+            failed += 1;
+        }
+    }
+    anyhow::ensure!(failed == 0, "{} platform(s) failed", failed);
+
+    Ok(())
+}
+
+/// `rustfox export`: write a conversation to `output`, or stdout
+async fn export_conversation(
+    memory: &MemoryStore,
+    conversation: &str,
+    format: ExportFormat,
+    output: Option<PathBuf>,
+) -> Result<()> {
+    let id = memory.find_conversation(conversation).await?;
+    let rendered = memory.export_conversation(&id).await?.render(format)?;
+    match output {
+        Some(path) => {
+            std::fs::write(&path, rendered)
+                .with_context(|| format!("Failed to write {}", path.display()))?;
+            eprintln!("Exported conversation {} to {}", id, path.display());
+        }
+        None => println!("{}", rendered),
+    }
+    Ok(())
+}
 
+/// `rustfox import`: restore a JSON export, for its original owner unless overridden
+async fn import_conversation(
+    memory: &MemoryStore,
+    file: &Path,
+    platform: Option<String>,
+    user_id: Option<String>,
+) -> Result<()> {
+    let raw = std::fs::read_to_string(file)
+        .with_context(|| format!("Failed to read {}", file.display()))?;
+    let export: ConversationExport = serde_json::from_str(&raw)
+        .with_context(|| format!("{} is not a JSON conversation export", file.display()))?;
+    let platform = platform.unwrap_or_else(|| export.platform.clone());
+    let user_id = user_id.unwrap_or_else(|| export.user_id.clone());
+    let id = memory
+        .import_conversation(&export, &platform, &user_id)
+        .await?;
+    eprintln!(
+        "Imported {} messages as conversation {} for {} user {} (use /list and /switch to open it)",
+        export.messages.len(),
+        id,
+        platform,
+        user_id
+    );
     Ok(())
 }
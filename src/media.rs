@@ -0,0 +1,383 @@
+//! Turning message attachments into model input.
+//!
+//! Images become image content parts (for vision-capable models), documents are
+//! converted to text, and voice notes are transcribed through an OpenAI-compatible
+//! `/audio/transcriptions` endpoint. Every attachment is also described by a short
+//! note in the message text, so the model knows where the file was saved and the
+//! conversation history keeps a record of it.
+
+use anyhow::{Context, Result};
+use base64::Engine;
+use std::path::Path;
+use tracing::{info, warn};
+
+use crate::config::{MediaConfig, TranscriptionConfig};
+use crate::llm::{ChatMessage, ImageContent};
+use crate::platform::{Attachment, AttachmentKind, IncomingMessage};
+
+/// Build the user message for `incoming`, including its attachments.
+/// In group chats the text is prefixed with the speaker's name.
+pub async fn user_message(
+    config: &MediaConfig,
+    sandbox_dir: &Path,
+    incoming: &IncomingMessage,
+) -> ChatMessage {
+    let mut parts = Vec::new();
+    if !incoming.text.is_empty() {
+        parts.push(incoming.text.clone());
+    }
+    let mut images = Vec::new();
+
+    for attachment in &incoming.attachments {
+        let shown = display_path(sandbox_dir, &attachment.path);
+        let note = match attachment.kind {
+            AttachmentKind::Image => {
+                if config.vision {
+                    match load_image(attachment).await {
+                        Ok(image) => images.push(image),
+                        Err(e) => warn!("Failed to load image {}: {:#}", shown, e),
+                    }
+                }
+                format!("[Image saved at {}]", shown)
+            }
+            AttachmentKind::Document => document_note(config, attachment, &shown).await,
+            AttachmentKind::Audio => audio_note(config, attachment, &shown).await,
+        };
+        parts.push(note);
+    }
+
+    let mut content = parts.join("\n\n");
+    if incoming.is_group && !incoming.user_name.is_empty() {
+        // Several people share a group conversation; tell the model who is speaking
+        // (scheduled task prompts have no speaker)
+        content = format!("[{}] {}", incoming.user_name, content);
+    }
+
+    ChatMessage {
+        images,
+        ..ChatMessage::user(content)
+    }
+}
+
+/// Path relative to the sandbox when possible, which is what the file tools expect
+fn display_path(sandbox_dir: &Path, path: &Path) -> String {
+    let relative = path
+        .strip_prefix(sandbox_dir)
+        .ok()
+        .map(Path::to_path_buf)
+        .or_else(|| {
+            let sandbox = sandbox_dir.canonicalize().ok()?;
+            let path = path.canonicalize().ok()?;
+            path.strip_prefix(&sandbox).ok().map(Path::to_path_buf)
+        });
+    match relative {
+        Some(relative) => relative.display().to_string(),
+        None => path.display().to_string(),
+    }
+}
+
+async fn load_image(attachment: &Attachment) -> Result<ImageContent> {
+    let bytes = tokio::fs::read(&attachment.path)
+        .await
+        .with_context(|| format!("Failed to read {}", attachment.path.display()))?;
+    let mime_type = attachment
+        .mime_type
+        .clone()
+        .filter(|m| m.starts_with("image/"))
+        .unwrap_or_else(|| image_mime_from_extension(&attachment.path).to_string());
+    Ok(ImageContent {
+        mime_type,
+        data: base64::engine::general_purpose::STANDARD.encode(bytes),
+    })
+}
+
+fn image_mime_from_extension(path: &Path) -> &'static str {
+    let ext = path
+        .extension()
+        .and_then(|e| e.to_str())
+        .unwrap_or_default()
+        .to_ascii_lowercase();
+    match ext.as_str() {
+        "png" => "image/png",
+        "gif" => "image/gif",
+        "webp" => "image/webp",
+        _ => "image/jpeg",
+    }
+}
+
+async fn document_note(config: &MediaConfig, attachment: &Attachment, shown: &str) -> String {
+    let name = attachment.file_name.as_deref().unwrap_or(shown);
+    let text = match extract_document_text(attachment).await {
+        Ok(Some(text)) if !text.trim().is_empty() => text,
+        Ok(_) => {
+            return format!(
+                "[Document {} saved at {}. Its text could not be extracted; use the tools to inspect it.]",
+                name, shown
+            )
+        }
+        Err(e) => {
+            warn!("Failed to extract text from {}: {:#}", shown, e);
+            return format!(
+                "[Document {} saved at {}. Text extraction failed: {}]",
+                name, shown, e
+            );
+        }
+    };
+
+    let total = text.chars().count();
+    let mut note = format!("[Document {} saved at {}]\n", name, shown);
+    if total > config.max_document_chars {
+        note.extend(text.chars().take(config.max_document_chars));
+        note.push_str(&format!(
+            "\n[Truncated: showing {} of {} characters; use read_file for the rest]",
+            config.max_document_chars, total
+        ));
+    } else {
+        note.push_str(&text);
+        note.push_str("\n[End of document]");
+    }
+    note
+}
+
+/// Text content of a document: PDFs go through `pdftotext` (poppler) when it is
+/// installed, other files are used as-is if they are valid UTF-8 text.
+/// Returns None for binary formats we cannot read.
+async fn extract_document_text(attachment: &Attachment) -> Result<Option<String>> {
+    let is_pdf = attachment.mime_type.as_deref() == Some("application/pdf")
+        || attachment
+            .path
+            .extension()
+            .is_some_and(|e| e.eq_ignore_ascii_case("pdf"));
+    if is_pdf {
+        let output = tokio::process::Command::new("pdftotext")
+            .arg("-layout")
+            .arg(&attachment.path)
+            .arg("-")
+            .output()
+            .await;
+        return match output {
+            Ok(output) if output.status.success() => {
+                Ok(Some(String::from_utf8_lossy(&output.stdout).into_owned()))
+            }
+            Ok(output) => anyhow::bail!(
+                "pdftotext failed: {}",
+                String::from_utf8_lossy(&output.stderr).trim()
+            ),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                warn!("pdftotext not found; PDF text is not extracted");
+                Ok(None)
+            }
+            Err(e) => Err(e).context("Failed to run pdftotext"),
+        };
+    }
+
+    let bytes = tokio::fs::read(&attachment.path)
+        .await
+        .with_context(|| format!("Failed to read {}", attachment.path.display()))?;
+    if bytes.contains(&0) {
+        return Ok(None);
+    }
+    Ok(String::from_utf8(bytes).ok())
+}
+
+async fn audio_note(config: &MediaConfig, attachment: &Attachment, shown: &str) -> String {
+    let Some(transcription) = &config.transcription else {
+        return format!(
+            "[Voice note saved at {}; transcription is not configured]",
+            shown
+        );
+    };
+    match transcribe(transcription, attachment).await {
+        Ok(text) => {
+            info!("Transcribed {} ({} chars)", shown, text.len());
+            format!(
+                "[Voice note saved at {}. Transcription:]\n{}",
+                shown,
+                text.trim()
+            )
+        }
+        Err(e) => {
+            warn!("Failed to transcribe {}: {:#}", shown, e);
+            format!(
+                "[Voice note saved at {}; transcription failed: {}]",
+                shown, e
+            )
+        }
+    }
+}
+
+#[derive(serde::Deserialize)]
+struct TranscriptionResponse {
+    text: String,
+}
+
+/// Send an audio file to `<base_url>/audio/transcriptions` and return the text
+async fn transcribe(config: &TranscriptionConfig, attachment: &Attachment) -> Result<String> {
+    let bytes = tokio::fs::read(&attachment.path)
+        .await
+        .with_context(|| format!("Failed to read {}", attachment.path.display()))?;
+    let file_name = attachment
+        .path
+        .file_name()
+        .and_then(|n| n.to_str())
+        .unwrap_or("audio.ogg")
+        .to_string();
+    let mut part = reqwest::multipart::Part::bytes(bytes).file_name(file_name);
+    if let Some(mime) = &attachment.mime_type {
+        part = part.mime_str(mime).context("Invalid audio MIME type")?;
+    }
+    let mut form = reqwest::multipart::Form::new()
+        .text("model", config.model.clone())
+        .part("file", part);
+    if let Some(language) = &config.language {
+        form = form.text("language", language.clone());
+    }
+
+    let url = format!(
+        "{}/audio/transcriptions",
+        config.base_url.trim_end_matches('/')
+    );
+    let response = reqwest::Client::new()
+        .post(&url)
+        .bearer_auth(&config.api_key)
+        .multipart(form)
+        .send()
+        .await
+        .context("Failed to send transcription request")?;
+    let status = response.status();
+    if !status.is_success() {
+        let body = response.text().await.unwrap_or_default();
+        anyhow::bail!("Transcription API error ({}): {}", status, body);
+    }
+    let body: TranscriptionResponse = response
+        .json()
+        .await
+        .context("Failed to parse transcription response")?;
+    Ok(body.text)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    fn media_config() -> MediaConfig {
+        MediaConfig {
+            vision: true,
+            max_download_mb: 20,
+            max_document_chars: 20,
+            uploads_dir: "uploads".to_string(),
+            transcription: None,
+        }
+    }
+
+    fn incoming(text: &str, attachments: Vec<Attachment>) -> IncomingMessage {
+        IncomingMessage {
+            platform: "telegram".to_string(),
+            user_id: "1".to_string(),
+            chat_id: "1".to_string(),
+            user_name: "Tester".to_string(),
+            text: text.to_string(),
+            attachments,
+            is_group: false,
+            thread_id: None,
+        }
+    }
+
+    fn attachment(kind: AttachmentKind, path: PathBuf, mime: Option<&str>) -> Attachment {
+        Attachment {
+            kind,
+            file_name: path.file_name().map(|n| n.to_string_lossy().into_owned()),
+            path,
+            mime_type: mime.map(str::to_string),
+        }
+    }
+
+    /// A sandbox with an upload directory, removed when dropped
+    fn tempdir() -> tempfile::TempDir {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir_all(dir.path().join("uploads/1")).unwrap();
+        dir
+    }
+
+    #[tokio::test]
+    async fn test_image_becomes_content_part_when_vision_enabled() {
+        let dir = tempdir();
+        let path = dir.path().join("uploads/1/photo.png");
+        std::fs::write(&path, [0x89, b'P', b'N', b'G']).unwrap();
+        let msg = incoming(
+            "what is this?",
+            vec![attachment(AttachmentKind::Image, path, None)],
+        );
+
+        let mut config = media_config();
+        let message = user_message(&config, dir.path(), &msg).await;
+        assert_eq!(
+            message.content.as_deref(),
+            Some("what is this?\n\n[Image saved at uploads/1/photo.png]")
+        );
+        assert_eq!(message.images.len(), 1);
+        assert_eq!(message.images[0].mime_type, "image/png");
+        assert_eq!(message.images[0].data, "iVBORw==");
+
+        config.vision = false;
+        let message = user_message(&config, dir.path(), &msg).await;
+        assert!(message.images.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_text_document_is_inlined_and_truncated() {
+        let dir = tempdir();
+        let short = dir.path().join("uploads/1/notes.md");
+        std::fs::write(&short, "# Notes\nbuy milk").unwrap();
+        let long = dir.path().join("uploads/1/long.txt");
+        std::fs::write(&long, "x".repeat(50)).unwrap();
+        let binary = dir.path().join("uploads/1/blob.bin");
+        std::fs::write(&binary, [0u8, 1, 2, 3]).unwrap();
+
+        let msg = incoming(
+            "",
+            vec![
+                attachment(AttachmentKind::Document, short, Some("text/markdown")),
+                attachment(AttachmentKind::Document, long, None),
+                attachment(AttachmentKind::Document, binary, None),
+            ],
+        );
+        let content = user_message(&media_config(), dir.path(), &msg)
+            .await
+            .content
+            .unwrap();
+        assert!(content.starts_with(
+            "[Document notes.md saved at uploads/1/notes.md]\n# Notes\nbuy milk\n[End of document]"
+        ));
+        assert!(content.contains("showing 20 of 50 characters"));
+        assert!(content.contains("blob.bin. Its text could not be extracted"));
+    }
+
+    #[tokio::test]
+    async fn test_group_messages_name_the_speaker() {
+        let mut msg = incoming("what's the plan?", Vec::new());
+        msg.is_group = true;
+        let message = user_message(&media_config(), tempdir().path(), &msg).await;
+        assert_eq!(
+            message.content.as_deref(),
+            Some("[Tester] what's the plan?")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_voice_note_without_transcription_is_only_noted() {
+        let dir = tempdir();
+        let path = dir.path().join("uploads/1/voice.ogg");
+        std::fs::write(&path, b"OggS").unwrap();
+        let msg = incoming(
+            "",
+            vec![attachment(AttachmentKind::Audio, path, Some("audio/ogg"))],
+        );
+        let message = user_message(&media_config(), dir.path(), &msg).await;
+        assert_eq!(
+            message.content.as_deref(),
+            Some("[Voice note saved at uploads/1/voice.ogg; transcription is not configured]")
+        );
+    }
+}
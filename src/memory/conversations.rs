@@ -1,9 +1,29 @@
 use anyhow::{Context, Result};
+use rusqlite::OptionalExtension;
 use uuid::Uuid;
 
-use super::MemoryStore;
+use super::{relevance, KeywordMatch, MemoryStore, VECTOR_OVERFETCH};
 use crate::llm::ChatMessage;
 
+/// Rolling summary state of a conversation
+#[derive(Debug, Clone, Default)]
+pub struct ConversationSummary {
+    /// Summary of all folded messages, if any were folded yet
+    pub summary: Option<String>,
+    /// Number of leading non-system messages covered by `summary`
+    pub summarized_count: usize,
+}
+
+/// A conversation with turns not yet reviewed by the fact extraction job
+#[derive(Debug, Clone)]
+pub struct PendingFactReview {
+    pub conversation_id: String,
+    pub platform: String,
+    pub user_id: String,
+    /// Number of leading non-system messages already reviewed
+    pub reviewed_count: usize,
+}
+
 /// Cast a &[f32] to &[u8] for SQLite blob storage
 pub(crate) fn f32_slice_to_bytes(floats: &[f32]) -> &[u8] {
     unsafe { std::slice::from_raw_parts(floats.as_ptr() as *const u8, floats.len() * 4) }
@@ -14,8 +34,24 @@ pub(crate) fn f32_vec_to_bytes(floats: &[f32]) -> Vec<u8> {
     f32_slice_to_bytes(floats).to_vec()
 }
 
+/// A conversation as shown by `/list`
+#[derive(Debug, Clone)]
+pub struct ConversationInfo {
+    pub id: String,
+    pub title: Option<String>,
+    /// Last activity, `YYYY-MM-DD HH:MM:SS` (UTC)
+    pub updated_at: String,
+    /// User and assistant messages with text
+    pub message_count: usize,
+    /// Whether this is the user's current conversation
+    pub active: bool,
+}
+
 impl MemoryStore {
-    /// Get or create a conversation for a platform user
+    /// The user's active conversation, created if there is none.
+    ///
+    /// Users without an active-conversation pointer (databases from before named
+    /// conversations) continue their most recently updated conversation.
     pub async fn get_or_create_conversation(
         &self,
         platform: &str,
@@ -23,37 +59,185 @@ impl MemoryStore {
     ) -> Result<String> {
         let conn = self.conn.lock().await;
 
-        // Try to find an existing active conversation
-        let existing: Option<String> = conn
+        let pointer: Option<Option<String>> = conn
             .query_row(
-                "SELECT id FROM conversations
-                 WHERE platform = ?1 AND user_id = ?2
-                 ORDER BY updated_at DESC LIMIT 1",
+                "SELECT conversation_id FROM active_conversations
+                 WHERE platform = ?1 AND user_id = ?2",
                 rusqlite::params![platform, user_id],
                 |row| row.get(0),
             )
-            .ok();
+            .optional()
+            .context("Failed to look up active conversation")?;
+
+        let existing: Option<String> = match &pointer {
+            // Continue the active conversation unless it has been archived since
+            Some(Some(id)) => conn
+                .query_row(
+                    "SELECT id FROM conversations WHERE id = ?1 AND archived_at IS NULL",
+                    rusqlite::params![id],
+                    |row| row.get(0),
+                )
+                .optional()?,
+            // Cleared: the next message starts a new conversation
+            Some(None) => None,
+            None => conn
+                .query_row(
+                    "SELECT id FROM conversations
+                     WHERE platform = ?1 AND user_id = ?2 AND archived_at IS NULL
+                     ORDER BY updated_at DESC LIMIT 1",
+                    rusqlite::params![platform, user_id],
+                    |row| row.get(0),
+                )
+                .optional()
+                .context("Failed to look up latest conversation")?,
+        };
+        let id = match existing {
+            Some(id) => id,
+            None => insert_conversation(&conn, platform, user_id, None)?,
+        };
+        if pointer.flatten().as_deref() != Some(id.as_str()) {
+            set_active(&conn, platform, user_id, Some(&id))?;
+        }
+        Ok(id)
+    }
 
-        if let Some(id) = existing {
-            return Ok(id);
+    /// Start a new conversation and make it the user's active one
+    pub async fn new_conversation(
+        &self,
+        platform: &str,
+        user_id: &str,
+        title: Option<&str>,
+    ) -> Result<String> {
+        let conn = self.conn.lock().await;
+        let id = insert_conversation(&conn, platform, user_id, title)?;
+        set_active(&conn, platform, user_id, Some(&id))?;
+        Ok(id)
+    }
+
+    /// The user's conversations that are not archived, most recently updated first
+    pub async fn list_conversations(
+        &self,
+        platform: &str,
+        user_id: &str,
+    ) -> Result<Vec<ConversationInfo>> {
+        let conn = self.conn.lock().await;
+        let mut stmt = conn.prepare(
+            "SELECT c.id, c.title, c.updated_at,
+                    (SELECT count(*) FROM messages m
+                     WHERE m.conversation_id = c.id AND m.role IN ('user', 'assistant')
+                       AND m.content IS NOT NULL AND m.content != ''),
+                    c.id IS (SELECT conversation_id FROM active_conversations
+                             WHERE platform = ?1 AND user_id = ?2)
+             FROM conversations c
+             WHERE c.platform = ?1 AND c.user_id = ?2 AND c.archived_at IS NULL
+             ORDER BY c.updated_at DESC, c.rowid DESC",
+        )?;
+        let conversations = stmt
+            .query_map(rusqlite::params![platform, user_id], |row| {
+                Ok(ConversationInfo {
+                    id: row.get(0)?,
+                    title: row.get(1)?,
+                    updated_at: row.get(2)?,
+                    message_count: row.get::<_, i64>(3)? as usize,
+                    active: row.get(4)?,
+                })
+            })?
+            .collect::<Result<Vec<_>, _>>()
+            .context("Failed to list conversations")?;
+        Ok(conversations)
+    }
+
+    /// Make `conversation_id` the user's active conversation.
+    /// Returns false if the user has no such (unarchived) conversation.
+    pub async fn switch_conversation(
+        &self,
+        platform: &str,
+        user_id: &str,
+        conversation_id: &str,
+    ) -> Result<bool> {
+        let conn = self.conn.lock().await;
+        let exists: bool = conn.query_row(
+            "SELECT count(*) > 0 FROM conversations
+             WHERE id = ?1 AND platform = ?2 AND user_id = ?3 AND archived_at IS NULL",
+            rusqlite::params![conversation_id, platform, user_id],
+            |row| row.get(0),
+        )?;
+        if exists {
+            set_active(&conn, platform, user_id, Some(conversation_id))?;
         }
+        Ok(exists)
+    }
 
-        // Create a new conversation
-        let id = Uuid::new_v4().to_string();
-        conn.execute(
-            "INSERT INTO conversations (id, platform, user_id) VALUES (?1, ?2, ?3)",
-            rusqlite::params![&id, platform, user_id],
-        )
-        .context("Failed to create conversation")?;
+    /// Title of a conversation, if it has one yet
+    pub async fn conversation_title(&self, conversation_id: &str) -> Result<Option<String>> {
+        let conn = self.conn.lock().await;
+        let title = conn
+            .query_row(
+                "SELECT title FROM conversations WHERE id = ?1",
+                rusqlite::params![conversation_id],
+                |row| row.get(0),
+            )
+            .optional()
+            .context("Failed to load conversation title")?;
+        Ok(title.flatten())
+    }
+
+    /// Set the title of a conversation. With `only_if_untitled`, an existing title
+    /// (e.g. one the user chose) is kept. Returns whether the title was changed.
+    pub async fn set_conversation_title(
+        &self,
+        conversation_id: &str,
+        title: &str,
+        only_if_untitled: bool,
+    ) -> Result<bool> {
+        let conn = self.conn.lock().await;
+        let sql = if only_if_untitled {
+            "UPDATE conversations SET title = ?1 WHERE id = ?2 AND title IS NULL"
+        } else {
+            "UPDATE conversations SET title = ?1 WHERE id = ?2"
+        };
+        let changed = conn
+            .execute(sql, rusqlite::params![title, conversation_id])
+            .context("Failed to set conversation title")?;
+        Ok(changed > 0)
+    }
 
+    /// The user's active conversation, without creating one
+    pub async fn active_conversation(
+        &self,
+        platform: &str,
+        user_id: &str,
+    ) -> Result<Option<String>> {
+        let conn = self.conn.lock().await;
+        let id = conn
+            .query_row(
+                "SELECT a.conversation_id FROM active_conversations a
+                 JOIN conversations c ON c.id = a.conversation_id AND c.archived_at IS NULL
+                 WHERE a.platform = ?1 AND a.user_id = ?2",
+                rusqlite::params![platform, user_id],
+                |row| row.get(0),
+            )
+            .optional()
+            .context("Failed to look up active conversation")?;
         Ok(id)
     }
 
-    /// Save a message to a conversation, with optional vector embedding
+    /// Save a message to a conversation; its vector embedding follows in the background
     pub async fn save_message(
         &self,
         conversation_id: &str,
         message: &ChatMessage,
+    ) -> Result<String> {
+        self.save_message_with_model(conversation_id, message, None)
+            .await
+    }
+
+    /// Save a message, recording which model produced it (assistant messages)
+    pub async fn save_message_with_model(
+        &self,
+        conversation_id: &str,
+        message: &ChatMessage,
+        model: Option<&str>,
     ) -> Result<String> {
         let id = Uuid::new_v4().to_string();
         let tool_calls_json = message
@@ -61,22 +245,11 @@ impl MemoryStore {
             .as_ref()
             .map(|tc| serde_json::to_string(tc).unwrap_or_default());
 
-        // Generate embedding before acquiring the DB lock (async HTTP call)
-        let embedding = if let Some(content) = &message.content {
-            if !content.is_empty() && message.role != "tool" {
-                self.embeddings.try_embed_one(content).await
-            } else {
-                None
-            }
-        } else {
-            None
-        };
-
         let conn = self.conn.lock().await;
 
         conn.execute(
-            "INSERT INTO messages (id, conversation_id, role, content, tool_calls, tool_call_id)
-             VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            "INSERT INTO messages (id, conversation_id, role, content, tool_calls, tool_call_id, model)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
             rusqlite::params![
                 &id,
                 conversation_id,
@@ -84,6 +257,7 @@ impl MemoryStore {
                 &message.content,
                 &tool_calls_json,
                 &message.tool_call_id,
+                model,
             ],
         )
         .context("Failed to save message")?;
@@ -96,13 +270,11 @@ impl MemoryStore {
             rusqlite::params![conversation_id],
         )?;
 
-        // Store vector embedding if available
-        if let Some(ref emb) = embedding {
-            let embedding_bytes = f32_slice_to_bytes(emb);
-            conn.execute(
-                "INSERT INTO message_embeddings (rowid, embedding) VALUES (?1, ?2)",
-                rusqlite::params![rowid, embedding_bytes],
-            )?;
+        // The vector is added in the background (see vectors::run_queue)
+        if let Some(content) = &message.content {
+            if !content.is_empty() && message.role != "tool" {
+                self.queue_embedding(rowid, content);
+            }
         }
 
         Ok(id)
@@ -115,7 +287,7 @@ impl MemoryStore {
             "SELECT role, content, tool_calls, tool_call_id
              FROM messages
              WHERE conversation_id = ?1
-             ORDER BY created_at ASC",
+             ORDER BY created_at ASC, rowid ASC",
         )?;
 
         let messages = stmt
@@ -128,6 +300,7 @@ impl MemoryStore {
                     content: row.get(1)?,
                     tool_calls,
                     tool_call_id: row.get(3)?,
+                    images: Vec::new(),
                 })
             })?
             .collect::<Result<Vec<_>, _>>()
@@ -136,65 +309,179 @@ impl MemoryStore {
         Ok(messages)
     }
 
-    /// Clear a conversation (delete all its messages and embeddings)
-    pub async fn clear_conversation(&self, platform: &str, user_id: &str) -> Result<()> {
+    /// Load the rolling summary of older turns for a conversation
+    pub async fn load_conversation_summary(
+        &self,
+        conversation_id: &str,
+    ) -> Result<ConversationSummary> {
         let conn = self.conn.lock().await;
+        let state = conn
+            .query_row(
+                "SELECT summary, summarized_count FROM conversations WHERE id = ?1",
+                rusqlite::params![conversation_id],
+                |row| {
+                    Ok(ConversationSummary {
+                        summary: row.get(0)?,
+                        summarized_count: row.get::<_, i64>(1)? as usize,
+                    })
+                },
+            )
+            .optional()
+            .context("Failed to load conversation summary")?;
+        Ok(state.unwrap_or_default())
+    }
 
-        // Delete embeddings for messages in this conversation
+    /// Persist the rolling summary and how many messages it covers
+    pub async fn save_conversation_summary(
+        &self,
+        conversation_id: &str,
+        summary: &str,
+        summarized_count: usize,
+    ) -> Result<()> {
+        let conn = self.conn.lock().await;
         conn.execute(
-            "DELETE FROM message_embeddings WHERE rowid IN (
-                SELECT m.rowid FROM messages m
-                JOIN conversations c ON m.conversation_id = c.id
-                WHERE c.platform = ?1 AND c.user_id = ?2
-            )",
-            rusqlite::params![platform, user_id],
+            "UPDATE conversations SET summary = ?1, summarized_count = ?2 WHERE id = ?3",
+            rusqlite::params![summary, summarized_count as i64, conversation_id],
+        )
+        .context("Failed to save conversation summary")?;
+        Ok(())
+    }
+
+    /// Conversations with unreviewed turns that have been idle for at least
+    /// `idle_minutes`, least recently updated first
+    pub async fn conversations_pending_fact_review(
+        &self,
+        idle_minutes: u64,
+        limit: usize,
+    ) -> Result<Vec<PendingFactReview>> {
+        let conn = self.conn.lock().await;
+        let mut stmt = conn.prepare(
+            "SELECT c.id, c.platform, c.user_id, c.facts_reviewed_count
+             FROM conversations c
+             WHERE c.updated_at <= datetime('now', ?1)
+               AND (SELECT COUNT(*) FROM messages m
+                    WHERE m.conversation_id = c.id AND m.role != 'system')
+                   > c.facts_reviewed_count
+             ORDER BY c.updated_at
+             LIMIT ?2",
         )?;
+        let pending = stmt
+            .query_map(
+                rusqlite::params![format!("-{} minutes", idle_minutes), limit as i64],
+                |row| {
+                    Ok(PendingFactReview {
+                        conversation_id: row.get(0)?,
+                        platform: row.get(1)?,
+                        user_id: row.get(2)?,
+                        reviewed_count: row.get::<_, i64>(3)? as usize,
+                    })
+                },
+            )?
+            .collect::<Result<Vec<_>, _>>()
+            .context("Failed to find conversations to review")?;
+        Ok(pending)
+    }
 
+    /// Record that the first `reviewed_count` non-system messages were reviewed for facts
+    pub async fn set_facts_reviewed(
+        &self,
+        conversation_id: &str,
+        reviewed_count: usize,
+    ) -> Result<()> {
+        let conn = self.conn.lock().await;
         conn.execute(
-            "DELETE FROM messages WHERE conversation_id IN (
-                SELECT id FROM conversations WHERE platform = ?1 AND user_id = ?2
-            )",
-            rusqlite::params![platform, user_id],
-        )?;
+            "UPDATE conversations SET facts_reviewed_count = ?1 WHERE id = ?2",
+            rusqlite::params![reviewed_count as i64, conversation_id],
+        )
+        .context("Failed to save fact review progress")?;
+        Ok(())
+    }
 
+    /// Archive the user's active conversation: it is kept (and stays searchable)
+    /// but no longer listed, and the next message starts a new conversation.
+    pub async fn clear_conversation(&self, platform: &str, user_id: &str) -> Result<()> {
+        let conn = self.conn.lock().await;
         conn.execute(
-            "DELETE FROM conversations WHERE platform = ?1 AND user_id = ?2",
+            "UPDATE conversations SET archived_at = datetime('now')
+             WHERE id = (SELECT conversation_id FROM active_conversations
+                         WHERE platform = ?1 AND user_id = ?2)",
             rusqlite::params![platform, user_id],
-        )?;
-
+        )
+        .context("Failed to archive conversation")?;
+        set_active(&conn, platform, user_id, None)?;
         Ok(())
     }
 
-    /// Hybrid search across messages using Reciprocal Rank Fusion (vector + FTS5).
+    /// Hybrid search across the messages of one user's (or group chat's)
+    /// conversations using Reciprocal Rank Fusion (vector + FTS5).
     /// Falls back to FTS5-only if embeddings are not available.
-    pub async fn search_messages(&self, query: &str, limit: usize) -> Result<Vec<ChatMessage>> {
+    pub async fn search_messages(
+        &self,
+        platform: &str,
+        user_id: &str,
+        query: &str,
+        limit: usize,
+    ) -> Result<Vec<ChatMessage>> {
+        let scored = self
+            .search_messages_scored(platform, user_id, query, KeywordMatch::Query, limit, None)
+            .await?;
+        Ok(scored.into_iter().map(|(message, _)| message).collect())
+    }
+
+    /// `search_messages` with the relevance (0 to 1) of each message, leaving
+    /// out the messages of `exclude_conversation`
+    pub async fn search_messages_scored(
+        &self,
+        platform: &str,
+        user_id: &str,
+        query: &str,
+        matching: KeywordMatch,
+        limit: usize,
+        exclude_conversation: Option<&str>,
+    ) -> Result<Vec<(ChatMessage, f64)>> {
         // Try to get query embedding for vector search
-        let query_embedding = self.embeddings.try_embed_one(query).await;
+        let query_embedding = self.try_embed(query).await;
+        let keywords = matching.fts_query(query);
 
         let conn = self.conn.lock().await;
 
         if let Some(ref qe) = query_embedding {
-            // Hybrid search with Reciprocal Rank Fusion
+            // Hybrid search with Reciprocal Rank Fusion. The vector index holds
+            // every user's messages, so it is asked for more neighbours than
+            // needed and filtered to the user's conversations afterwards.
             let query_bytes = f32_vec_to_bytes(qe);
             let sql = "
                 WITH vec_matches AS (
-                    SELECT rowid, distance,
-                           row_number() OVER (ORDER BY distance) as rank_number
-                    FROM message_embeddings
-                    WHERE embedding MATCH ?1
-                    ORDER BY distance
+                    SELECT v.rowid, v.similarity,
+                           row_number() OVER (ORDER BY v.distance) as rank_number
+                    FROM (
+                        SELECT rowid, distance,
+                               1.0 - vec_distance_cosine(embedding, ?1) as similarity
+                        FROM message_embeddings
+                        WHERE embedding MATCH ?1
+                        ORDER BY distance
+                        LIMIT ?4
+                    ) v
+                    JOIN messages m ON m.rowid = v.rowid
+                    JOIN conversations c ON c.id = m.conversation_id
+                    WHERE c.platform = ?5 AND c.user_id = ?6
+                      AND (?7 IS NULL OR c.id != ?7)
                     LIMIT ?2
                 ),
                 fts_matches AS (
-                    SELECT rowid,
-                           row_number() OVER (ORDER BY rank) as rank_number
-                    FROM messages_fts
-                    WHERE messages_fts MATCH ?3
+                    SELECT fts.rowid, fts.rank as bm25,
+                           row_number() OVER (ORDER BY fts.rank) as rank_number
+                    FROM messages_fts fts
+                    JOIN messages m ON m.rowid = fts.rowid
+                    JOIN conversations c ON c.id = m.conversation_id
+                    WHERE messages_fts MATCH ?3 AND c.platform = ?5 AND c.user_id = ?6
+                      AND (?7 IS NULL OR c.id != ?7)
                     LIMIT ?2
                 )
                 SELECT m.role, m.content, m.tool_calls, m.tool_call_id,
                        coalesce(1.0 / (60 + fts.rank_number), 0.0) * 0.5
-                       + coalesce(1.0 / (60 + vec.rank_number), 0.0) * 0.5 as combined_rank
+                       + coalesce(1.0 / (60 + vec.rank_number), 0.0) * 0.5 as combined_rank,
+                       vec.similarity, fts.bm25
                 FROM messages m
                 LEFT JOIN vec_matches vec ON m.rowid = vec.rowid
                 LEFT JOIN fts_matches fts ON m.rowid = fts.rowid
@@ -206,9 +493,21 @@ impl MemoryStore {
             let search_limit = (limit * 3) as i64;
             let mut stmt = conn.prepare(sql)?;
             let messages = stmt
-                .query_map(rusqlite::params![query_bytes, search_limit, query], |row| {
-                    parse_message_row(row)
-                })?
+                .query_map(
+                    rusqlite::params![
+                        query_bytes,
+                        search_limit,
+                        keywords,
+                        search_limit * VECTOR_OVERFETCH,
+                        platform,
+                        user_id,
+                        exclude_conversation
+                    ],
+                    |row| {
+                        let relevance = relevance(row.get(5)?, row.get(6)?);
+                        Ok((parse_message_row(row)?, relevance))
+                    },
+                )?
                 .collect::<Result<Vec<_>, _>>()
                 .context("Failed to hybrid-search messages")?;
 
@@ -216,18 +515,27 @@ impl MemoryStore {
         } else {
             // FTS5-only fallback
             let sql = "
-                SELECT m.role, m.content, m.tool_calls, m.tool_call_id
+                SELECT m.role, m.content, m.tool_calls, m.tool_call_id, fts.rank
                 FROM messages m
                 JOIN messages_fts fts ON m.rowid = fts.rowid
-                WHERE messages_fts MATCH ?1
+                JOIN conversations c ON c.id = m.conversation_id
+                WHERE messages_fts MATCH ?1 AND c.platform = ?3 AND c.user_id = ?4
+                  AND (?5 IS NULL OR c.id != ?5)
                 ORDER BY fts.rank
                 LIMIT ?2
             ";
             let mut stmt = conn.prepare(sql)?;
             let messages = stmt
-                .query_map(rusqlite::params![query, limit as i64], |row| {
-                    parse_message_row(row)
-                })?
+                .query_map(
+                    rusqlite::params![
+                        keywords,
+                        limit as i64,
+                        platform,
+                        user_id,
+                        exclude_conversation
+                    ],
+                    |row| Ok((parse_message_row(row)?, relevance(None, row.get(4)?))),
+                )?
                 .collect::<Result<Vec<_>, _>>()
                 .context("Failed to FTS-search messages")?;
 
@@ -236,6 +544,38 @@ impl MemoryStore {
     }
 }
 
+fn insert_conversation(
+    conn: &rusqlite::Connection,
+    platform: &str,
+    user_id: &str,
+    title: Option<&str>,
+) -> Result<String> {
+    let id = Uuid::new_v4().to_string();
+    conn.execute(
+        "INSERT INTO conversations (id, platform, user_id, title) VALUES (?1, ?2, ?3, ?4)",
+        rusqlite::params![&id, platform, user_id, title],
+    )
+    .context("Failed to create conversation")?;
+    Ok(id)
+}
+
+/// Point the user's active conversation at `conversation_id` (None: start a new one)
+fn set_active(
+    conn: &rusqlite::Connection,
+    platform: &str,
+    user_id: &str,
+    conversation_id: Option<&str>,
+) -> Result<()> {
+    conn.execute(
+        "INSERT INTO active_conversations (platform, user_id, conversation_id)
+         VALUES (?1, ?2, ?3)
+         ON CONFLICT (platform, user_id) DO UPDATE SET conversation_id = excluded.conversation_id",
+        rusqlite::params![platform, user_id, conversation_id],
+    )
+    .context("Failed to set active conversation")?;
+    Ok(())
+}
+
 fn parse_message_row(row: &rusqlite::Row) -> rusqlite::Result<ChatMessage> {
     let tool_calls_json: Option<String> = row.get(2)?;
     let tool_calls = tool_calls_json.and_then(|json| serde_json::from_str(&json).ok());
@@ -245,5 +585,184 @@ fn parse_message_row(row: &rusqlite::Row) -> rusqlite::Result<ChatMessage> {
         content: row.get(1)?,
         tool_calls,
         tool_call_id: row.get(3)?,
+        images: Vec::new(),
     })
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_new_switch_and_list_conversations() {
+        let memory = MemoryStore::open_in_memory().unwrap();
+        let first = memory
+            .get_or_create_conversation("telegram", "1")
+            .await
+            .unwrap();
+        memory
+            .save_message(&first, &ChatMessage::user("hello"))
+            .await
+            .unwrap();
+        assert_eq!(
+            memory
+                .get_or_create_conversation("telegram", "1")
+                .await
+                .unwrap(),
+            first
+        );
+
+        let second = memory
+            .new_conversation("telegram", "1", Some("Project X"))
+            .await
+            .unwrap();
+        assert_ne!(second, first);
+        assert_eq!(
+            memory
+                .get_or_create_conversation("telegram", "1")
+                .await
+                .unwrap(),
+            second
+        );
+
+        let list = memory.list_conversations("telegram", "1").await.unwrap();
+        assert_eq!(list.len(), 2);
+        let active: Vec<_> = list.iter().filter(|c| c.active).collect();
+        assert_eq!(active.len(), 1);
+        assert_eq!(active[0].id, second);
+        assert_eq!(active[0].title.as_deref(), Some("Project X"));
+        let old = list.iter().find(|c| c.id == first).unwrap();
+        assert_eq!(old.message_count, 1);
+
+        assert!(memory
+            .switch_conversation("telegram", "1", &first)
+            .await
+            .unwrap());
+        assert_eq!(
+            memory
+                .get_or_create_conversation("telegram", "1")
+                .await
+                .unwrap(),
+            first
+        );
+
+        // Conversations of other users can't be switched to
+        assert!(!memory
+            .switch_conversation("telegram", "2", &first)
+            .await
+            .unwrap());
+        assert!(memory
+            .list_conversations("telegram", "2")
+            .await
+            .unwrap()
+            .is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_message_search_is_scoped_to_the_owner() {
+        let memory = MemoryStore::open_in_memory().unwrap();
+        for (user, text) in [
+            ("1", "my locker code is 4412"),
+            ("2", "my locker is broken"),
+        ] {
+            let id = memory
+                .get_or_create_conversation("telegram", user)
+                .await
+                .unwrap();
+            memory
+                .save_message(&id, &ChatMessage::user(text))
+                .await
+                .unwrap();
+        }
+
+        let found = memory
+            .search_messages("telegram", "2", "locker", 5)
+            .await
+            .unwrap();
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].content.as_deref(), Some("my locker is broken"));
+        assert!(memory
+            .search_messages("telegram", "2", "4412", 5)
+            .await
+            .unwrap()
+            .is_empty());
+        assert!(memory
+            .search_messages("discord", "1", "locker", 5)
+            .await
+            .unwrap()
+            .is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_clear_archives_instead_of_deleting() {
+        let memory = MemoryStore::open_in_memory().unwrap();
+        let id = memory
+            .get_or_create_conversation("telegram", "1")
+            .await
+            .unwrap();
+        memory
+            .save_message(&id, &ChatMessage::user("remember the blue folder"))
+            .await
+            .unwrap();
+
+        memory.clear_conversation("telegram", "1").await.unwrap();
+        assert!(memory
+            .active_conversation("telegram", "1")
+            .await
+            .unwrap()
+            .is_none());
+        assert!(memory
+            .list_conversations("telegram", "1")
+            .await
+            .unwrap()
+            .is_empty());
+        // Messages are kept and still searchable
+        assert_eq!(memory.load_messages(&id).await.unwrap().len(), 1);
+        assert_eq!(
+            memory
+                .search_messages("telegram", "1", "folder", 5)
+                .await
+                .unwrap()
+                .len(),
+            1
+        );
+
+        let next = memory
+            .get_or_create_conversation("telegram", "1")
+            .await
+            .unwrap();
+        assert_ne!(next, id);
+        assert!(!memory
+            .switch_conversation("telegram", "1", &id)
+            .await
+            .unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_conversation_titles() {
+        let memory = MemoryStore::open_in_memory().unwrap();
+        let id = memory
+            .get_or_create_conversation("telegram", "1")
+            .await
+            .unwrap();
+        assert!(memory.conversation_title(&id).await.unwrap().is_none());
+
+        assert!(memory
+            .set_conversation_title(&id, "Lisbon trip", true)
+            .await
+            .unwrap());
+        // An automatic title never replaces an existing one, a rename does
+        assert!(!memory
+            .set_conversation_title(&id, "Other", true)
+            .await
+            .unwrap());
+        memory
+            .set_conversation_title(&id, "Portugal trip", false)
+            .await
+            .unwrap();
+        assert_eq!(
+            memory.conversation_title(&id).await.unwrap().as_deref(),
+            Some("Portugal trip")
+        );
+    }
+}
@@ -1,12 +1,27 @@
+use std::path::Path;
+use std::sync::Arc;
+
 use anyhow::{Context, Result};
 use serde::{Deserialize, Serialize};
-use tracing::{info, warn};
+use tracing::info;
+
+use crate::memory::local_embeddings::StaticModel;
 
-/// Embedding engine that calls an OpenAI-compatible /v1/embeddings API.
-/// Works with OpenRouter, OpenAI, Ollama, or any compatible provider.
+/// Embedding engine that calls an OpenAI-compatible /v1/embeddings API
+/// (OpenRouter, OpenAI, Ollama, or any compatible provider) or runs a local
+/// static model on the CPU.
 pub struct EmbeddingEngine {
     client: reqwest::Client,
-    config: Option<EmbeddingConfig>,
+    backend: Option<Backend>,
+}
+
+enum Backend {
+    Api(EmbeddingConfig),
+    Local {
+        /// Recorded as the model name, so switching models re-embeds
+        name: String,
+        model: Arc<StaticModel>,
+    },
 }
 
 /// Configuration for the embedding API
@@ -32,8 +47,14 @@ struct EmbeddingResponse {
 #[derive(Deserialize)]
 struct EmbeddingData {
     embedding: Vec<f32>,
+    /// Position of the input; providers may return the data out of order
+    #[serde(default)]
+    index: usize,
 }
 
+/// Most texts sent in one embeddings request
+const MAX_BATCH_SIZE: usize = 64;
+
 impl EmbeddingEngine {
     /// Create an embedding engine with API configuration.
     /// If config is None, embedding features are disabled (FTS5-only fallback).
@@ -48,73 +69,112 @@ impl EmbeddingEngine {
         }
         Self {
             client: reqwest::Client::new(),
-            config,
+            backend: config.map(Backend::Api),
         }
     }
 
+    /// Create an embedding engine running the static model in `model_dir`
+    pub fn local(model_dir: &Path) -> Result<Self> {
+        let model = StaticModel::load(model_dir).with_context(|| {
+            format!(
+                "Failed to load the local embedding model in {}",
+                model_dir.display()
+            )
+        })?;
+        info!(
+            "Embedding engine configured: local model {}, dims={}",
+            model_dir.display(),
+            model.dimensions()
+        );
+        Ok(Self {
+            client: reqwest::Client::new(),
+            backend: Some(Backend::Local {
+                name: format!("local:{}", &model.fingerprint()[..16]),
+                model: Arc::new(model),
+            }),
+        })
+    }
+
     /// Whether vector embeddings are available
     pub fn is_available(&self) -> bool {
-        self.config.is_some()
+        self.backend.is_some()
+    }
+
+    /// Name of the embedding model, if embeddings are configured
+    pub fn model(&self) -> Option<&str> {
+        match self.backend.as_ref()? {
+            Backend::Api(config) => Some(&config.model),
+            Backend::Local { name, .. } => Some(name),
+        }
     }
 
     /// Embedding dimensions (default 384)
     pub fn dimensions(&self) -> usize {
-        self.config.as_ref().map(|c| c.dimensions).unwrap_or(384)
+        match &self.backend {
+            Some(Backend::Api(config)) => config.dimensions,
+            Some(Backend::Local { model, .. }) => model.dimensions(),
+            None => 384,
+        }
     }
 
-    /// Generate a single embedding for one text via API
-    pub async fn embed_one(&self, text: &str) -> Result<Vec<f32>> {
-        let config = self
-            .config
+    /// Generate embeddings for `texts`, in order. The API gets one request per
+    /// `MAX_BATCH_SIZE` texts.
+    pub async fn embed_batch(&self, texts: &[String]) -> Result<Vec<Vec<f32>>> {
+        let config = match self
+            .backend
             .as_ref()
-            .context("Embedding engine not configured")?;
-
-        let url = format!("{}/embeddings", config.base_url);
-
-        let request = EmbeddingRequest {
-            model: config.model.clone(),
-            input: vec![text.to_string()],
+            .context("Embedding engine not configured")?
+        {
+            Backend::Api(config) => config,
+            Backend::Local { model, .. } => {
+                // Cheap per text, but a backfill embeds thousands
+                let model = Arc::clone(model);
+                let texts = texts.to_vec();
+                return tokio::task::spawn_blocking(move || {
+                    texts.iter().map(|text| model.embed(text)).collect()
+                })
+                .await
+                .context("Local embedding task failed");
+            }
         };
 
-        let response = self
-            .client
-            .post(&url)
-            .header("Authorization", format!("Bearer {}", config.api_key))
-            .header("Content-Type", "application/json")
-            .json(&request)
-            .send()
-            .await
-            .context("Failed to call embedding API")?;
-
-        let status = response.status();
-        if !status.is_success() {
-            let body = response.text().await.unwrap_or_default();
-            anyhow::bail!("Embedding API error ({}): {}", status, body);
-        }
-
-        let resp: EmbeddingResponse = response
-            .json()
-            .await
-            .context("Failed to parse embedding response")?;
-
-        resp.data
-            .into_iter()
-            .next()
-            .map(|d| d.embedding)
-            .context("No embedding returned from API")
-    }
-
-    /// Try to generate an embedding, returning None if not available or on error
-    pub async fn try_embed_one(&self, text: &str) -> Option<Vec<f32>> {
-        if !self.is_available() {
-            return None;
-        }
-        match self.embed_one(text).await {
-            Ok(embedding) => Some(embedding),
-            Err(e) => {
-                warn!("Embedding generation failed: {}", e);
-                None
+        let url = format!("{}/embeddings", config.base_url);
+        let mut embeddings = Vec::with_capacity(texts.len());
+        for chunk in texts.chunks(MAX_BATCH_SIZE) {
+            let request = EmbeddingRequest {
+                model: config.model.clone(),
+                input: chunk.to_vec(),
+            };
+
+            let response = self
+                .client
+                .post(&url)
+                .header("Authorization", format!("Bearer {}", config.api_key))
+                .header("Content-Type", "application/json")
+                .json(&request)
+                .send()
+                .await
+                .context("Failed to call embedding API")?;
+
+            let status = response.status();
+            if !status.is_success() {
+                let body = response.text().await.unwrap_or_default();
+                anyhow::bail!("Embedding API error ({}): {}", status, body);
             }
+
+            let mut resp: EmbeddingResponse = response
+                .json()
+                .await
+                .context("Failed to parse embedding response")?;
+            anyhow::ensure!(
+                resp.data.len() == chunk.len(),
+                "Embedding API returned {} embeddings for {} texts",
+                resp.data.len(),
+                chunk.len()
+            );
+            resp.data.sort_by_key(|d| d.index);
+            embeddings.extend(resp.data.into_iter().map(|d| d.embedding));
         }
+        Ok(embeddings)
     }
 }
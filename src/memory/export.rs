@@ -0,0 +1,466 @@
+//! Conversation export and import.
+//!
+//! A conversation is exported with every stored message (including the system
+//! prompt, tool calls and tool results) as versioned JSON, which can be imported
+//! again, or rendered as Markdown for reading.
+
+use anyhow::{Context, Result};
+use chrono::Utc;
+use rusqlite::OptionalExtension;
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+use uuid::Uuid;
+
+use super::conversations::f32_slice_to_bytes;
+use super::MemoryStore;
+use crate::llm::ToolCall;
+
+/// Value of `format` in exported JSON files
+pub const EXPORT_FORMAT: &str = "rustfox-conversation";
+/// Current version of the JSON export format
+pub const EXPORT_VERSION: u32 = 1;
+
+/// A conversation with all of its messages, as written to and read from JSON
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ConversationExport {
+    pub format: String,
+    pub version: u32,
+    /// Id in the database it was exported from (a new id is assigned on import)
+    pub id: String,
+    pub platform: String,
+    pub user_id: String,
+    #[serde(default)]
+    pub title: Option<String>,
+    pub started_at: String,
+    pub updated_at: String,
+    /// Rolling summary of older turns (see crate::context)
+    #[serde(default)]
+    pub summary: Option<String>,
+    #[serde(default)]
+    pub summarized_count: usize,
+    pub messages: Vec<ExportedMessage>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExportedMessage {
+    pub role: String,
+    #[serde(default)]
+    pub content: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tool_calls: Option<Vec<ToolCall>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tool_call_id: Option<String>,
+    /// Model that produced an assistant message
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+    pub created_at: String,
+}
+
+/// Output format of an export
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    Json,
+    Markdown,
+}
+
+impl ExportFormat {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "json" => Some(Self::Json),
+            "md" | "markdown" => Some(Self::Markdown),
+            _ => None,
+        }
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            Self::Json => "json",
+            Self::Markdown => "md",
+        }
+    }
+}
+
+impl ConversationExport {
+    /// Render in `format`
+    pub fn render(&self, format: ExportFormat) -> Result<String> {
+        match format {
+            ExportFormat::Json => {
+                serde_json::to_string_pretty(self).context("Failed to serialize conversation")
+            }
+            ExportFormat::Markdown => Ok(self.to_markdown()),
+        }
+    }
+
+    /// File name for the export, e.g. `conversation-3f2a9c1b.md`
+    pub fn file_name(&self, format: ExportFormat) -> String {
+        format!(
+            "conversation-{}.{}",
+            self.id.get(..8).unwrap_or(&self.id),
+            format.extension()
+        )
+    }
+
+    /// Readable transcript. The system prompt is left out.
+    pub fn to_markdown(&self) -> String {
+        let mut out = format!(
+            "# {}\n\n- Conversation: `{}`\n- Platform: {} (user {})\n- Started: {} UTC\n- Last updated: {} UTC\n",
+            self.title.as_deref().unwrap_or("Conversation"),
+            self.id,
+            self.platform,
+            self.user_id,
+            self.started_at,
+            self.updated_at
+        );
+        if let Some(summary) = &self.summary {
+            out.push_str(&format!("\n## Summary of earlier turns\n\n{}\n", summary));
+        }
+
+        for message in &self.messages {
+            let content = message.content.as_deref().unwrap_or("");
+            match message.role.as_str() {
+                "system" => continue,
+                "tool" => {
+                    out.push_str(&format!("\n### Tool result · {}\n\n", message.created_at));
+                    out.push_str(&fenced(content, ""));
+                }
+                role => {
+                    let heading = match role {
+                        "user" => "User".to_string(),
+                        "assistant" => match &message.model {
+                            Some(model) => format!("Assistant ({})", model),
+                            None => "Assistant".to_string(),
+                        },
+                        other => other.to_string(),
+                    };
+                    out.push_str(&format!("\n### {} · {}\n\n", heading, message.created_at));
+                    if !content.is_empty() {
+                        out.push_str(content.trim_end());
+                        out.push('\n');
+                    }
+                    for call in message.tool_calls.iter().flatten() {
+                        out.push_str(&format!("\nCalled `{}`:\n\n", call.function.name));
+                        out.push_str(&fenced(&pretty_json(&call.function.arguments), "json"));
+                    }
+                }
+            }
+        }
+        out
+    }
+}
+
+/// `text` in a code fence longer than any backtick run inside it
+fn fenced(text: &str, lang: &str) -> String {
+    let mut longest = 0;
+    let mut run = 0;
+    for c in text.chars() {
+        run = if c == '`' { run + 1 } else { 0 };
+        longest = longest.max(run);
+    }
+    let fence = "`".repeat((longest + 1).max(3));
+    format!("{}{}\n{}\n{}\n", fence, lang, text.trim_end(), fence)
+}
+
+fn pretty_json(raw: &str) -> String {
+    serde_json::from_str::<serde_json::Value>(raw)
+        .and_then(|value| serde_json::to_string_pretty(&value))
+        .unwrap_or_else(|_| raw.to_string())
+}
+
+impl MemoryStore {
+    /// Resolve a conversation id from a unique prefix (e.g. the short id in `/list`)
+    pub async fn find_conversation(&self, id_prefix: &str) -> Result<String> {
+        let conn = self.conn.lock().await;
+        let mut stmt = conn
+            .prepare("SELECT id FROM conversations WHERE substr(id, 1, length(?1)) = ?1 LIMIT 2")?;
+        let ids = stmt
+            .query_map([id_prefix], |row| row.get::<_, String>(0))?
+            .collect::<Result<Vec<_>, _>>()?;
+        match ids.as_slice() {
+            [id] => Ok(id.clone()),
+            [] => anyhow::bail!("No conversation with id '{}'", id_prefix),
+            _ => anyhow::bail!("'{}' matches several conversations", id_prefix),
+        }
+    }
+
+    /// Everything stored for a conversation
+    pub async fn export_conversation(&self, conversation_id: &str) -> Result<ConversationExport> {
+        let conn = self.conn.lock().await;
+        let header = conn
+            .query_row(
+                "SELECT platform, user_id, title, started_at, updated_at, summary, summarized_count
+                 FROM conversations WHERE id = ?1",
+                [conversation_id],
+                |row| {
+                    Ok(ConversationExport {
+                        format: EXPORT_FORMAT.to_string(),
+                        version: EXPORT_VERSION,
+                        id: conversation_id.to_string(),
+                        platform: row.get(0)?,
+                        user_id: row.get(1)?,
+                        title: row.get(2)?,
+                        started_at: row.get(3)?,
+                        updated_at: row.get(4)?,
+                        summary: row.get(5)?,
+                        summarized_count: row.get::<_, i64>(6)? as usize,
+                        messages: Vec::new(),
+                    })
+                },
+            )
+            .optional()
+            .context("Failed to load conversation")?;
+        let Some(mut export) = header else {
+            anyhow::bail!("No conversation with id '{}'", conversation_id);
+        };
+
+        let mut stmt = conn.prepare(
+            "SELECT role, content, tool_calls, tool_call_id, model, created_at
+             FROM messages
+             WHERE conversation_id = ?1
+             ORDER BY created_at ASC, rowid ASC",
+        )?;
+        export.messages = stmt
+            .query_map([conversation_id], |row| {
+                let tool_calls: Option<String> = row.get(2)?;
+                Ok(ExportedMessage {
+                    role: row.get(0)?,
+                    content: row.get(1)?,
+                    tool_calls: tool_calls.and_then(|json| serde_json::from_str(&json).ok()),
+                    tool_call_id: row.get(3)?,
+                    model: row.get(4)?,
+                    created_at: row.get(5)?,
+                })
+            })?
+            .collect::<Result<Vec<_>, _>>()
+            .context("Failed to load messages")?;
+        Ok(export)
+    }
+
+    /// Restore an exported conversation for `platform`/`user_id` under a new id,
+    /// keeping message timestamps. Messages are indexed for full-text search and,
+    /// when embeddings are configured, embedded like newly saved messages.
+    pub async fn import_conversation(
+        &self,
+        export: &ConversationExport,
+        platform: &str,
+        user_id: &str,
+    ) -> Result<String> {
+        if export.format != EXPORT_FORMAT {
+            anyhow::bail!("Not a RustFox conversation export");
+        }
+        if export.version > EXPORT_VERSION {
+            anyhow::bail!(
+                "Export format version {} is newer than this RustFox supports ({})",
+                export.version,
+                EXPORT_VERSION
+            );
+        }
+
+        // Embed before taking the DB lock, in batches. The background queue
+        // would not finish before a command-line import exits.
+        let embeddable = |message: &ExportedMessage| match &message.content {
+            Some(content) if !content.is_empty() && message.role != "tool" => Some(content.clone()),
+            _ => None,
+        };
+        let texts: Vec<String> = export.messages.iter().filter_map(embeddable).collect();
+        let mut vectors = Vec::new();
+        if self.embeddings.is_available() && !texts.is_empty() {
+            match self.embed_texts(&texts).await {
+                Ok(embedded) => vectors = embedded,
+                Err(e) => warn!(
+                    "Importing without embeddings, the maintenance backfill adds them: {:#}",
+                    e
+                ),
+            }
+        }
+        let mut vectors = vectors.into_iter();
+        let embeddings: Vec<Option<Vec<f32>>> = export
+            .messages
+            .iter()
+            .map(|message| embeddable(message).and_then(|_| vectors.next()))
+            .collect();
+
+        let mut conn = self.conn.lock().await;
+        let tx = conn.transaction()?;
+        let id = Uuid::new_v4().to_string();
+        let now = Utc::now().format("%Y-%m-%d %H:%M:%S").to_string();
+        tx.execute(
+            "INSERT INTO conversations
+                (id, platform, user_id, title, started_at, updated_at, summary, summarized_count)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+            rusqlite::params![
+                &id,
+                platform,
+                user_id,
+                &export.title,
+                if export.started_at.is_empty() {
+                    &now
+                } else {
+                    &export.started_at
+                },
+                if export.updated_at.is_empty() {
+                    &now
+                } else {
+                    &export.updated_at
+                },
+                &export.summary,
+                export.summarized_count as i64,
+            ],
+        )
+        .context("Failed to create conversation")?;
+
+        for (message, embedding) in export.messages.iter().zip(embeddings) {
+            let tool_calls = message
+                .tool_calls
+                .as_ref()
+                .map(|calls| serde_json::to_string(calls).unwrap_or_default());
+            let created_at = if message.created_at.is_empty() {
+                &now
+            } else {
+                &message.created_at
+            };
+            tx.execute(
+                "INSERT INTO messages
+                    (id, conversation_id, role, content, tool_calls, tool_call_id, model, created_at)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+                rusqlite::params![
+                    Uuid::new_v4().to_string(),
+                    &id,
+                    &message.role,
+                    &message.content,
+                    tool_calls,
+                    &message.tool_call_id,
+                    &message.model,
+                    created_at,
+                ],
+            )
+            .context("Failed to import message")?;
+            if let Some(embedding) = embedding {
+                tx.execute(
+                    "INSERT INTO message_embeddings (rowid, embedding) VALUES (?1, ?2)",
+                    rusqlite::params![tx.last_insert_rowid(), f32_slice_to_bytes(&embedding)],
+                )?;
+            }
+        }
+        tx.commit().context("Failed to import conversation")?;
+        Ok(id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::llm::{ChatMessage, FunctionCall};
+
+    async fn sample(memory: &MemoryStore) -> String {
+        let id = memory
+            .new_conversation("telegram", "1", Some("Weather"))
+            .await
+            .unwrap();
+        memory
+            .save_message(&id, &ChatMessage::new("system", "You are helpful."))
+            .await
+            .unwrap();
+        memory
+            .save_message(&id, &ChatMessage::user("Is it raining in Porto?"))
+            .await
+            .unwrap();
+        let mut call = ChatMessage::new("assistant", "");
+        call.content = None;
+        call.tool_calls = Some(vec![ToolCall {
+            id: "call_1".to_string(),
+            call_type: "function".to_string(),
+            function: FunctionCall {
+                name: "weather".to_string(),
+                arguments: r#"{"city":"Porto"}"#.to_string(),
+            },
+        }]);
+        memory.save_message(&id, &call).await.unwrap();
+        let mut result = ChatMessage::new("tool", "Light rain, 14°C");
+        result.tool_call_id = Some("call_1".to_string());
+        memory.save_message(&id, &result).await.unwrap();
+        memory
+            .save_message_with_model(
+                &id,
+                &ChatMessage::new("assistant", "Yes, light rain."),
+                Some("test-model"),
+            )
+            .await
+            .unwrap();
+        id
+    }
+
+    #[tokio::test]
+    async fn test_export_import_roundtrip() {
+        let memory = MemoryStore::open_in_memory().unwrap();
+        let id = sample(&memory).await;
+        assert_eq!(memory.find_conversation(&id[..8]).await.unwrap(), id);
+
+        let export = memory.export_conversation(&id).await.unwrap();
+        assert_eq!(export.messages.len(), 5);
+        assert_eq!(export.title.as_deref(), Some("Weather"));
+        let json = export.render(ExportFormat::Json).unwrap();
+
+        let other = MemoryStore::open_in_memory().unwrap();
+        let parsed: ConversationExport = serde_json::from_str(&json).unwrap();
+        let imported = other
+            .import_conversation(&parsed, "discord", "9")
+            .await
+            .unwrap();
+        assert_ne!(imported, id);
+
+        let restored = other.export_conversation(&imported).await.unwrap();
+        assert_eq!(restored.platform, "discord");
+        assert_eq!(restored.user_id, "9");
+        assert_eq!(restored.title.as_deref(), Some("Weather"));
+        let roles: Vec<_> = restored.messages.iter().map(|m| m.role.as_str()).collect();
+        assert_eq!(roles, ["system", "user", "assistant", "tool", "assistant"]);
+        assert_eq!(
+            restored.messages[2].tool_calls.as_ref().unwrap()[0]
+                .function
+                .name,
+            "weather"
+        );
+        assert_eq!(restored.messages[4].model.as_deref(), Some("test-model"));
+        assert_eq!(
+            restored.messages[1].created_at,
+            export.messages[1].created_at
+        );
+
+        // Imported messages are searchable
+        let found = other
+            .search_messages("discord", "9", "Porto", 5)
+            .await
+            .unwrap();
+        assert_eq!(found.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_markdown_export() {
+        let memory = MemoryStore::open_in_memory().unwrap();
+        let id = sample(&memory).await;
+        let markdown = memory.export_conversation(&id).await.unwrap().to_markdown();
+        assert!(markdown.starts_with("# Weather\n"));
+        assert!(!markdown.contains("You are helpful."));
+        assert!(markdown.contains("### User · "));
+        assert!(markdown.contains("Called `weather`:\n\n```json\n{\n  \"city\": \"Porto\"\n}\n```"));
+        assert!(markdown.contains("```\nLight rain, 14°C\n```"));
+        assert!(markdown.contains("### Assistant (test-model) · "));
+    }
+
+    #[test]
+    fn test_fence_is_longer_than_content_backticks() {
+        assert_eq!(fenced("a ```b``` c", ""), "````\na ```b``` c\n````\n");
+    }
+
+    #[tokio::test]
+    async fn test_import_rejects_other_files() {
+        let memory = MemoryStore::open_in_memory().unwrap();
+        let id = sample(&memory).await;
+        let mut export = memory.export_conversation(&id).await.unwrap();
+        export.format = "something-else".to_string();
+        assert!(memory
+            .import_conversation(&export, "telegram", "1")
+            .await
+            .is_err());
+    }
+}
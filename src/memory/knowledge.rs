@@ -1,9 +1,51 @@
 use anyhow::{Context, Result};
+use rusqlite::OptionalExtension;
 use uuid::Uuid;
 
-use super::MemoryStore;
+use super::{relevance, KeywordMatch, MemoryStore, VECTOR_OVERFETCH};
 use crate::memory::conversations::{f32_slice_to_bytes, f32_vec_to_bytes};
 
+/// Whose knowledge an entry is: one user (or group chat) on one platform, or
+/// everyone. Reads in a user scope also see the shared entries; an entry is only
+/// shared when it is stored in `KnowledgeScope::SHARED` explicitly.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KnowledgeScope<'a> {
+    pub platform: &'a str,
+    pub user_id: &'a str,
+}
+
+impl KnowledgeScope<'static> {
+    /// Entries visible to every user
+    pub const SHARED: Self = Self {
+        platform: "",
+        user_id: "",
+    };
+
+    /// Entries of a database from before per-user memory whose owner could
+    /// not be told. Nobody reads them until an admin claims them.
+    pub const QUARANTINE: Self = Self {
+        platform: "legacy",
+        user_id: "",
+    };
+}
+
+impl<'a> KnowledgeScope<'a> {
+    pub fn user(platform: &'a str, user_id: &'a str) -> Self {
+        Self { platform, user_id }
+    }
+}
+
+/// SQL condition selecting the rows a scope can read, with the scope's
+/// platform and user id bound to parameters `?p` and `?u`
+fn visible_to(alias: &str, p: usize, u: usize) -> String {
+    format!(
+        "(({a}.platform = ?{p} AND {a}.user_id = ?{u}) OR ({a}.platform = '' AND {a}.user_id = ''))",
+        a = alias,
+        p = p,
+        u = u
+    )
+}
+
 /// A knowledge entry the agent has learned
 #[derive(Debug, Clone)]
 #[allow(dead_code)]
@@ -13,12 +55,30 @@ pub struct KnowledgeEntry {
     pub key: String,
     pub value: String,
     pub source: Option<String>,
+    /// Stored in the shared scope rather than the user's own
+    pub shared: bool,
 }
 
+impl KnowledgeEntry {
+    /// The scope the entry is stored in, given the scope it was read from
+    pub fn scope<'a>(&self, reader: KnowledgeScope<'a>) -> KnowledgeScope<'a> {
+        if self.shared {
+            KnowledgeScope::SHARED
+        } else {
+            reader
+        }
+    }
+}
+
+/// Columns read by `parse_knowledge_row`, for a table aliased `k`
+const ENTRY_COLUMNS: &str =
+    "k.id, k.category, k.key, k.value, k.source, (k.platform = '' AND k.user_id = '')";
+
 impl MemoryStore {
     /// Store or update a knowledge entry with vector embedding
     pub async fn remember(
         &self,
+        scope: KnowledgeScope<'_>,
         category: &str,
         key: &str,
         value: &str,
@@ -28,15 +88,16 @@ impl MemoryStore {
 
         // Generate embedding before DB lock (async HTTP call)
         let embed_text = format!("{}: {}", key, value);
-        let embedding = self.embeddings.try_embed_one(&embed_text).await;
+        let embedding = self.try_embed(&embed_text).await;
 
         let conn = self.conn.lock().await;
 
         // Check if entry exists (for update case — need to remove old embedding)
         let old_rowid: Option<i64> = conn
             .query_row(
-                "SELECT rowid FROM knowledge WHERE category = ?1 AND key = ?2",
-                rusqlite::params![category, key],
+                "SELECT rowid FROM knowledge
+                 WHERE platform = ?1 AND user_id = ?2 AND category = ?3 AND key = ?4",
+                rusqlite::params![scope.platform, scope.user_id, category, key],
                 |row| row.get(0),
             )
             .ok();
@@ -49,20 +110,29 @@ impl MemoryStore {
         }
 
         conn.execute(
-            "INSERT INTO knowledge (id, category, key, value, source)
-             VALUES (?1, ?2, ?3, ?4, ?5)
-             ON CONFLICT(category, key) DO UPDATE SET
+            "INSERT INTO knowledge (id, platform, user_id, category, key, value, source)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)
+             ON CONFLICT(platform, user_id, category, key) DO UPDATE SET
                 value = excluded.value,
                 source = excluded.source,
                 updated_at = datetime('now')",
-            rusqlite::params![&id, category, key, value, source],
+            rusqlite::params![
+                &id,
+                scope.platform,
+                scope.user_id,
+                category,
+                key,
+                value,
+                source
+            ],
         )
         .context("Failed to store knowledge")?;
 
         // Get the rowid for embedding
         let rowid: i64 = conn.query_row(
-            "SELECT rowid FROM knowledge WHERE category = ?1 AND key = ?2",
-            rusqlite::params![category, key],
+            "SELECT rowid FROM knowledge
+             WHERE platform = ?1 AND user_id = ?2 AND category = ?3 AND key = ?4",
+            rusqlite::params![scope.platform, scope.user_id, category, key],
             |row| row.get(0),
         )?;
 
@@ -78,82 +148,133 @@ impl MemoryStore {
         Ok(())
     }
 
-    /// Recall a specific knowledge entry by exact key
-    pub async fn recall(&self, category: &str, key: &str) -> Result<Option<String>> {
-        let conn = self.conn.lock().await;
-        let result = conn
-            .query_row(
-                "SELECT value FROM knowledge WHERE category = ?1 AND key = ?2",
-                rusqlite::params![category, key],
-                |row| row.get(0),
-            )
-            .ok();
-
-        Ok(result)
+    /// Recall a specific knowledge entry by exact key. The user's own entry wins
+    /// over a shared one with the same key.
+    pub async fn recall(
+        &self,
+        scope: KnowledgeScope<'_>,
+        category: &str,
+        key: &str,
+    ) -> Result<Option<String>> {
+        Ok(self
+            .knowledge_entry(scope, category, key)
+            .await?
+            .map(|entry| entry.value))
     }
 
     /// Hybrid search across knowledge using Reciprocal Rank Fusion (vector + FTS5).
     /// Falls back to FTS5-only if embeddings are not available.
-    pub async fn search_knowledge(&self, query: &str, limit: usize) -> Result<Vec<KnowledgeEntry>> {
-        let query_embedding = self.embeddings.try_embed_one(query).await;
+    pub async fn search_knowledge(
+        &self,
+        scope: KnowledgeScope<'_>,
+        query: &str,
+        limit: usize,
+    ) -> Result<Vec<KnowledgeEntry>> {
+        let scored = self
+            .search_knowledge_scored(scope, query, KeywordMatch::Query, limit)
+            .await?;
+        Ok(scored.into_iter().map(|(entry, _)| entry).collect())
+    }
+
+    /// `search_knowledge` with the relevance (0 to 1) of each entry
+    pub async fn search_knowledge_scored(
+        &self,
+        scope: KnowledgeScope<'_>,
+        query: &str,
+        matching: KeywordMatch,
+        limit: usize,
+    ) -> Result<Vec<(KnowledgeEntry, f64)>> {
+        let query_embedding = self.try_embed(query).await;
+        let keywords = matching.fts_query(query);
 
         let conn = self.conn.lock().await;
 
         if let Some(ref qe) = query_embedding {
-            // Hybrid search with Reciprocal Rank Fusion
+            // Hybrid search with Reciprocal Rank Fusion. The vector index holds
+            // every user's entries, so it is asked for more neighbours than
+            // needed and filtered to the scope afterwards.
             let query_bytes = f32_vec_to_bytes(qe);
-            let sql = "
+            let sql = format!(
+                "
                 WITH vec_matches AS (
-                    SELECT rowid, distance,
-                           row_number() OVER (ORDER BY distance) as rank_number
-                    FROM knowledge_embeddings
-                    WHERE embedding MATCH ?1
-                    ORDER BY distance
+                    SELECT v.rowid, v.similarity,
+                           row_number() OVER (ORDER BY v.distance) as rank_number
+                    FROM (
+                        SELECT rowid, distance,
+                               1.0 - vec_distance_cosine(embedding, ?1) as similarity
+                        FROM knowledge_embeddings
+                        WHERE embedding MATCH ?1
+                        ORDER BY distance
+                        LIMIT ?4
+                    ) v
+                    JOIN knowledge k ON k.rowid = v.rowid
+                    WHERE {visible}
                     LIMIT ?2
                 ),
                 fts_matches AS (
-                    SELECT rowid,
-                           row_number() OVER (ORDER BY rank) as rank_number
-                    FROM knowledge_fts
-                    WHERE knowledge_fts MATCH ?3
+                    SELECT fts.rowid, fts.rank as bm25,
+                           row_number() OVER (ORDER BY fts.rank) as rank_number
+                    FROM knowledge_fts fts
+                    JOIN knowledge k ON k.rowid = fts.rowid
+                    WHERE knowledge_fts MATCH ?3 AND {visible}
                     LIMIT ?2
                 )
-                SELECT k.id, k.category, k.key, k.value, k.source,
+                SELECT {columns},
                        coalesce(1.0 / (60 + fts.rank_number), 0.0) * 0.5
-                       + coalesce(1.0 / (60 + vec.rank_number), 0.0) * 0.5 as combined_rank
+                       + coalesce(1.0 / (60 + vec.rank_number), 0.0) * 0.5 as combined_rank,
+                       vec.similarity, fts.bm25
                 FROM knowledge k
                 LEFT JOIN vec_matches vec ON k.rowid = vec.rowid
                 LEFT JOIN fts_matches fts ON k.rowid = fts.rowid
                 WHERE vec.rowid IS NOT NULL OR fts.rowid IS NOT NULL
                 ORDER BY combined_rank DESC
                 LIMIT ?2
-            ";
+            ",
+                visible = visible_to("k", 5, 6),
+                columns = ENTRY_COLUMNS
+            );
 
             let search_limit = (limit * 3) as i64;
-            let mut stmt = conn.prepare(sql)?;
+            let mut stmt = conn.prepare(&sql)?;
             let entries = stmt
-                .query_map(rusqlite::params![query_bytes, search_limit, query], |row| {
-                    parse_knowledge_row(row)
-                })?
+                .query_map(
+                    rusqlite::params![
+                        query_bytes,
+                        search_limit,
+                        keywords,
+                        search_limit * VECTOR_OVERFETCH,
+                        scope.platform,
+                        scope.user_id
+                    ],
+                    |row| {
+                        let relevance = relevance(row.get(7)?, row.get(8)?);
+                        Ok((parse_knowledge_row(row)?, relevance))
+                    },
+                )?
                 .collect::<Result<Vec<_>, _>>()
                 .context("Failed to hybrid-search knowledge")?;
 
             Ok(entries.into_iter().take(limit).collect())
         } else {
             // FTS5-only fallback
-            let sql = "
-                SELECT k.id, k.category, k.key, k.value, k.source
+            let sql = format!(
+                "
+                SELECT {columns}, fts.rank
                 FROM knowledge k
                 JOIN knowledge_fts fts ON k.rowid = fts.rowid
-                WHERE knowledge_fts MATCH ?1
+                WHERE knowledge_fts MATCH ?1 AND {visible}
                 ORDER BY fts.rank
                 LIMIT ?2
-            ";
-            let mut stmt = conn.prepare(sql)?;
+            ",
+                visible = visible_to("k", 3, 4),
+                columns = ENTRY_COLUMNS
+            );
+            let mut stmt = conn.prepare(&sql)?;
             let entries = stmt
-                .query_map(rusqlite::params![query, limit as i64], |row| {
-                    parse_knowledge_row(row)
-                })?
+                .query_map(
+                    rusqlite::params![keywords, limit as i64, scope.platform, scope.user_id],
+                    |row| Ok((parse_knowledge_row(row)?, relevance(None, row.get(6)?))),
+                )?
                 .collect::<Result<Vec<_>, _>>()
                 .context("Failed to FTS-search knowledge")?;
 
@@ -161,50 +282,161 @@ impl MemoryStore {
         }
     }
 
-    /// List all knowledge in a category
-    #[allow(dead_code)]
-    pub async fn list_knowledge(&self, category: &str) -> Result<Vec<KnowledgeEntry>> {
+    /// Knowledge categories visible to `scope` with their number of entries, alphabetically
+    pub async fn knowledge_categories(
+        &self,
+        scope: KnowledgeScope<'_>,
+    ) -> Result<Vec<(String, usize)>> {
         let conn = self.conn.lock().await;
-        let mut stmt = conn.prepare(
-            "SELECT id, category, key, value, source
-             FROM knowledge
-             WHERE category = ?1
-             ORDER BY key",
-        )?;
+        let mut stmt = conn.prepare(&format!(
+            "SELECT category, COUNT(*) FROM knowledge k
+             WHERE {}
+             GROUP BY category ORDER BY category",
+            visible_to("k", 1, 2)
+        ))?;
+        let categories = stmt
+            .query_map(rusqlite::params![scope.platform, scope.user_id], |row| {
+                Ok((row.get::<_, String>(0)?, row.get::<_, i64>(1)? as usize))
+            })?
+            .collect::<Result<Vec<_>, _>>()
+            .context("Failed to list knowledge categories")?;
+        Ok(categories)
+    }
+
+    /// A knowledge entry by exact key, with its source. The user's own entry
+    /// wins over a shared one with the same key.
+    pub async fn knowledge_entry(
+        &self,
+        scope: KnowledgeScope<'_>,
+        category: &str,
+        key: &str,
+    ) -> Result<Option<KnowledgeEntry>> {
+        let conn = self.conn.lock().await;
+        let entry = conn
+            .query_row(
+                &format!(
+                    "SELECT {}
+                     FROM knowledge k
+                     WHERE {} AND k.category = ?3 AND k.key = ?4
+                     ORDER BY k.platform = '' AND k.user_id = ''
+                     LIMIT 1",
+                    ENTRY_COLUMNS,
+                    visible_to("k", 1, 2)
+                ),
+                rusqlite::params![scope.platform, scope.user_id, category, key],
+                parse_knowledge_row,
+            )
+            .optional()
+            .context("Failed to read knowledge entry")?;
+        Ok(entry)
+    }
+
+    /// List all knowledge in a category visible to `scope`, the user's own
+    /// entries before shared ones with the same key
+    pub async fn list_knowledge(
+        &self,
+        scope: KnowledgeScope<'_>,
+        category: &str,
+    ) -> Result<Vec<KnowledgeEntry>> {
+        let conn = self.conn.lock().await;
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {}
+             FROM knowledge k
+             WHERE {} AND k.category = ?3
+             ORDER BY k.key, k.platform = '' AND k.user_id = ''",
+            ENTRY_COLUMNS,
+            visible_to("k", 1, 2)
+        ))?;
 
         let entries = stmt
-            .query_map(rusqlite::params![category], parse_knowledge_row)?
+            .query_map(
+                rusqlite::params![scope.platform, scope.user_id, category],
+                parse_knowledge_row,
+            )?
             .collect::<Result<Vec<_>, _>>()
             .context("Failed to list knowledge")?;
 
         Ok(entries)
     }
 
-    /// Forget a knowledge entry
-    #[allow(dead_code)]
-    pub async fn forget(&self, category: &str, key: &str) -> Result<bool> {
+    /// Forget a knowledge entry stored in exactly `scope`
+    pub async fn forget(
+        &self,
+        scope: KnowledgeScope<'_>,
+        category: &str,
+        key: &str,
+    ) -> Result<bool> {
         let conn = self.conn.lock().await;
 
         let rowid: Option<i64> = conn
             .query_row(
-                "SELECT rowid FROM knowledge WHERE category = ?1 AND key = ?2",
-                rusqlite::params![category, key],
+                "SELECT rowid FROM knowledge
+                 WHERE platform = ?1 AND user_id = ?2 AND category = ?3 AND key = ?4",
+                rusqlite::params![scope.platform, scope.user_id, category, key],
                 |row| row.get(0),
             )
-            .ok();
-
-        if let Some(rowid) = rowid {
-            conn.execute(
-                "DELETE FROM knowledge_embeddings WHERE rowid = ?1",
-                rusqlite::params![rowid],
-            )?;
-        }
+            .optional()?;
 
-        let rows = conn.execute(
-            "DELETE FROM knowledge WHERE category = ?1 AND key = ?2",
-            rusqlite::params![category, key],
+        let Some(rowid) = rowid else {
+            return Ok(false);
+        };
+        conn.execute(
+            "DELETE FROM knowledge_embeddings WHERE rowid = ?1",
+            rusqlite::params![rowid],
         )?;
-        Ok(rows > 0)
+        conn.execute(
+            "DELETE FROM knowledge WHERE rowid = ?1",
+            rusqlite::params![rowid],
+        )?;
+        Ok(true)
+    }
+
+    /// Move every entry stored in exactly `from` to `to`, except those whose
+    /// category and key `to` already uses. Returns the number moved.
+    pub async fn move_knowledge(
+        &self,
+        from: KnowledgeScope<'_>,
+        to: KnowledgeScope<'_>,
+    ) -> Result<usize> {
+        let conn = self.conn.lock().await;
+        conn.execute(
+            "UPDATE OR IGNORE knowledge
+             SET platform = ?3, user_id = ?4, updated_at = datetime('now')
+             WHERE platform = ?1 AND user_id = ?2",
+            rusqlite::params![from.platform, from.user_id, to.platform, to.user_id],
+        )
+        .context("Failed to move knowledge")
+    }
+
+    /// Forget every entry stored in exactly `scope`, limited to `category` if
+    /// given. Shared entries are only deleted through `KnowledgeScope::SHARED`.
+    /// Returns the number of entries deleted.
+    pub async fn forget_all(
+        &self,
+        scope: KnowledgeScope<'_>,
+        category: Option<&str>,
+    ) -> Result<usize> {
+        let mut conn = self.conn.lock().await;
+        let tx = conn.transaction()?;
+        let filter = "platform = ?1 AND user_id = ?2 AND (?3 IS NULL OR category = ?3)";
+        let params = rusqlite::params![scope.platform, scope.user_id, category];
+        {
+            // vec0 tables are deleted from one rowid at a time
+            let mut select =
+                tx.prepare(&format!("SELECT rowid FROM knowledge WHERE {}", filter))?;
+            let rowids = select
+                .query_map(params, |row| row.get::<_, i64>(0))?
+                .collect::<Result<Vec<_>, _>>()?;
+            let mut delete = tx.prepare("DELETE FROM knowledge_embeddings WHERE rowid = ?1")?;
+            for rowid in rowids {
+                delete.execute(rusqlite::params![rowid])?;
+            }
+        }
+        let rows = tx
+            .execute(&format!("DELETE FROM knowledge WHERE {}", filter), params)
+            .context("Failed to forget knowledge")?;
+        tx.commit()?;
+        Ok(rows)
     }
 }
 
@@ -215,5 +447,182 @@ fn parse_knowledge_row(row: &rusqlite::Row) -> rusqlite::Result<KnowledgeEntry>
         key: row.get(2)?,
         value: row.get(3)?,
         source: row.get(4)?,
+        shared: row.get(5)?,
     })
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ANA: KnowledgeScope<'static> = KnowledgeScope {
+        platform: "telegram",
+        user_id: "1",
+    };
+    const BEN: KnowledgeScope<'static> = KnowledgeScope {
+        platform: "telegram",
+        user_id: "2",
+    };
+
+    #[tokio::test]
+    async fn test_categories_and_bulk_forget() {
+        let store = MemoryStore::open_in_memory().unwrap();
+        store
+            .remember(ANA, "food", "favourite", "ramen", None)
+            .await
+            .unwrap();
+        store
+            .remember(ANA, "food", "allergy", "peanuts", Some("chat"))
+            .await
+            .unwrap();
+        store
+            .remember(ANA, "work", "employer", "Acme", None)
+            .await
+            .unwrap();
+
+        assert_eq!(
+            store.knowledge_categories(ANA).await.unwrap(),
+            vec![("food".to_string(), 2), ("work".to_string(), 1)]
+        );
+        let entry = store
+            .knowledge_entry(ANA, "food", "allergy")
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(entry.value, "peanuts");
+        assert_eq!(entry.source.as_deref(), Some("chat"));
+        assert!(store
+            .knowledge_entry(ANA, "food", "drink")
+            .await
+            .unwrap()
+            .is_none());
+
+        assert_eq!(store.forget_all(ANA, Some("food")).await.unwrap(), 2);
+        assert!(store.list_knowledge(ANA, "food").await.unwrap().is_empty());
+        assert_eq!(store.list_knowledge(ANA, "work").await.unwrap().len(), 1);
+
+        assert_eq!(store.forget_all(ANA, None).await.unwrap(), 1);
+        assert!(store.knowledge_categories(ANA).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_users_do_not_see_each_others_knowledge() {
+        let store = MemoryStore::open_in_memory().unwrap();
+        store
+            .remember(ANA, "pet", "name", "Biscuit", None)
+            .await
+            .unwrap();
+        store
+            .remember(BEN, "pet", "name", "Rex", None)
+            .await
+            .unwrap();
+
+        // Same category and key, two separate entries
+        assert_eq!(
+            store.recall(ANA, "pet", "name").await.unwrap().as_deref(),
+            Some("Biscuit")
+        );
+        assert_eq!(
+            store.recall(BEN, "pet", "name").await.unwrap().as_deref(),
+            Some("Rex")
+        );
+        let found = store.search_knowledge(BEN, "Biscuit", 5).await.unwrap();
+        assert!(found.is_empty(), "{:?}", found);
+        let other_platform = KnowledgeScope::user("discord", "1");
+        assert!(store
+            .knowledge_categories(other_platform)
+            .await
+            .unwrap()
+            .is_empty());
+
+        // Forgetting and bulk deletes stay within the user's scope
+        assert!(store.forget(BEN, "pet", "name").await.unwrap());
+        assert!(store.recall(BEN, "pet", "name").await.unwrap().is_none());
+        assert_eq!(store.forget_all(BEN, None).await.unwrap(), 0);
+        assert_eq!(
+            store.recall(ANA, "pet", "name").await.unwrap().as_deref(),
+            Some("Biscuit")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_shared_knowledge_is_visible_to_everyone() {
+        let store = MemoryStore::open_in_memory().unwrap();
+        store
+            .remember(KnowledgeScope::SHARED, "office", "wifi", "guest-net", None)
+            .await
+            .unwrap();
+        store
+            .remember(ANA, "office", "desk", "by the window", None)
+            .await
+            .unwrap();
+
+        for scope in [ANA, BEN] {
+            let found = store.search_knowledge(scope, "wifi", 5).await.unwrap();
+            assert_eq!(found.len(), 1);
+            assert!(found[0].shared);
+        }
+        assert_eq!(store.list_knowledge(ANA, "office").await.unwrap().len(), 2);
+        assert_eq!(store.list_knowledge(BEN, "office").await.unwrap().len(), 1);
+
+        // An own entry wins over a shared one with the same key
+        store
+            .remember(BEN, "office", "wifi", "home-net", None)
+            .await
+            .unwrap();
+        assert_eq!(
+            store
+                .recall(BEN, "office", "wifi")
+                .await
+                .unwrap()
+                .as_deref(),
+            Some("home-net")
+        );
+        assert_eq!(
+            store
+                .recall(ANA, "office", "wifi")
+                .await
+                .unwrap()
+                .as_deref(),
+            Some("guest-net")
+        );
+
+        // Bulk deletes leave shared entries alone
+        store.forget_all(BEN, None).await.unwrap();
+        assert_eq!(
+            store
+                .recall(BEN, "office", "wifi")
+                .await
+                .unwrap()
+                .as_deref(),
+            Some("guest-net")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_search_tool_matches_all_words() {
+        let store = MemoryStore::open_in_memory().unwrap();
+        store
+            .remember(ANA, "food", "allergy", "peanuts", None)
+            .await
+            .unwrap();
+        store
+            .remember(ANA, "food", "avoid", "peanuts and shellfish", None)
+            .await
+            .unwrap();
+
+        let found = store
+            .search_knowledge(ANA, "peanuts shellfish", 5)
+            .await
+            .unwrap();
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].key, "avoid");
+
+        // Auto-recall matches any word of the message
+        let found = store
+            .search_knowledge_scored(ANA, "Peanuts or shellfish?", KeywordMatch::AnyWord, 5)
+            .await
+            .unwrap();
+        assert_eq!(found.len(), 2);
+    }
+}
@@ -0,0 +1,541 @@
+//! In-process embeddings from a static (Model2Vec-style) model, for
+//! deployments without an embedding API. Each vocabulary token has a
+//! precomputed vector and a text's embedding is the mean of its tokens'
+//! vectors, so embedding needs no network, no GPU and barely any CPU.
+//!
+//! A model directory holds:
+//! - `model.safetensors` with an `embeddings` tensor (vocabulary size ×
+//!   dimensions, F32 or F16) and optionally per-token `weights`
+//! - `tokenizer.json`, a Hugging Face WordPiece tokenizer
+//! - optionally `config.json`, whose `normalize` says whether embeddings are
+//!   scaled to unit length (the default)
+
+use std::collections::HashMap;
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use unicode_normalization::char::is_combining_mark;
+use unicode_normalization::UnicodeNormalization;
+
+/// Tokens of a text that are embedded; the rest is ignored
+const MAX_TOKENS: usize = 512;
+
+/// A static embedding model loaded in memory
+pub struct StaticModel {
+    tokenizer: WordPiece,
+    /// Row-major, one row of `dims` values per token id
+    embeddings: Vec<f32>,
+    weights: Option<Vec<f32>>,
+    dims: usize,
+    normalize: bool,
+    /// SHA-256 of the tokenizer, the weights and the normalization setting
+    fingerprint: String,
+}
+
+impl StaticModel {
+    /// Load the model in `dir`
+    pub fn load(dir: &Path) -> Result<Self> {
+        let tokenizer_path = dir.join("tokenizer.json");
+        let tokenizer_json = std::fs::read(&tokenizer_path)
+            .with_context(|| format!("Failed to read {}", tokenizer_path.display()))?;
+        let tokenizer = WordPiece::from_json(&tokenizer_json)
+            .with_context(|| format!("Unsupported tokenizer in {}", tokenizer_path.display()))?;
+
+        let weights_path = dir.join("model.safetensors");
+        let file = std::fs::read(&weights_path)
+            .with_context(|| format!("Failed to read {}", weights_path.display()))?;
+        let tensors = SafeTensors::parse(&file)
+            .with_context(|| format!("Malformed {}", weights_path.display()))?;
+        anyhow::ensure!(
+            !tensors.contains("mapping"),
+            "Vocabulary-quantized models (with a `mapping` tensor) are not supported"
+        );
+        let (shape, embeddings) = tensors
+            .read("embeddings")
+            .with_context(|| format!("No usable embeddings in {}", weights_path.display()))?;
+        let [vocab_size, dims] = shape[..] else {
+            anyhow::bail!(
+                "The embeddings tensor has shape {:?}, expected 2 dimensions",
+                shape
+            );
+        };
+        anyhow::ensure!(dims > 0, "The embeddings tensor is empty");
+        let weights = if tensors.contains("weights") {
+            let (_, weights) = tensors.read("weights")?;
+            anyhow::ensure!(
+                weights.len() == vocab_size,
+                "{} token weights for {} tokens",
+                weights.len(),
+                vocab_size
+            );
+            Some(weights)
+        } else {
+            None
+        };
+        if let Some(id) = tokenizer
+            .vocab
+            .values()
+            .find(|&&id| id as usize >= vocab_size)
+        {
+            anyhow::bail!(
+                "Token id {} is out of range for {} embeddings",
+                id,
+                vocab_size
+            );
+        }
+
+        let config_path = dir.join("config.json");
+        let config: ModelConfig = match std::fs::read(&config_path) {
+            Ok(config) => serde_json::from_slice(&config)
+                .with_context(|| format!("Malformed {}", config_path.display()))?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => ModelConfig::default(),
+            Err(e) => {
+                return Err(e).with_context(|| format!("Failed to read {}", config_path.display()))
+            }
+        };
+
+        let fingerprint = Sha256::new()
+            .chain_update(&tokenizer_json)
+            .chain_update(&file)
+            .chain_update([config.normalize as u8])
+            .finalize();
+
+        Ok(Self {
+            tokenizer,
+            embeddings,
+            weights,
+            dims,
+            normalize: config.normalize,
+            fingerprint: format!("{:x}", fingerprint),
+        })
+    }
+
+    pub fn dimensions(&self) -> usize {
+        self.dims
+    }
+
+    /// Identifies the model by content, wherever it is installed
+    pub fn fingerprint(&self) -> &str {
+        &self.fingerprint
+    }
+
+    /// The embedding of `text`: the (weighted) mean of its token vectors.
+    /// Texts without known tokens get a zero vector.
+    pub fn embed(&self, text: &str) -> Vec<f32> {
+        let mut embedding = vec![0.0; self.dims];
+        let tokens = self.tokenizer.tokenize(text);
+        for &id in tokens.iter().take(MAX_TOKENS) {
+            let id = id as usize;
+            let weight = self.weights.as_ref().map_or(1.0, |weights| weights[id]);
+            let row = &self.embeddings[id * self.dims..(id + 1) * self.dims];
+            for (sum, value) in embedding.iter_mut().zip(row) {
+                *sum += weight * value;
+            }
+        }
+        let count = tokens.len().min(MAX_TOKENS);
+        if count == 0 {
+            return embedding;
+        }
+        let scale = if self.normalize {
+            let norm = embedding.iter().map(|v| v * v).sum::<f32>().sqrt();
+            if norm > 0.0 {
+                1.0 / norm
+            } else {
+                0.0
+            }
+        } else {
+            1.0 / count as f32
+        };
+        embedding.iter_mut().for_each(|v| *v *= scale);
+        embedding
+    }
+}
+
+#[derive(Deserialize)]
+struct ModelConfig {
+    #[serde(default = "default_normalize")]
+    normalize: bool,
+}
+
+impl Default for ModelConfig {
+    fn default() -> Self {
+        Self {
+            normalize: default_normalize(),
+        }
+    }
+}
+
+fn default_normalize() -> bool {
+    true
+}
+
+/// BERT-style WordPiece tokenization as described by a `tokenizer.json`.
+/// Unknown words are dropped rather than mapped to `[UNK]`, as Model2Vec does.
+struct WordPiece {
+    vocab: HashMap<String, u32>,
+    continuing_prefix: String,
+    max_word_chars: usize,
+    lowercase: bool,
+    strip_accents: bool,
+}
+
+#[derive(Deserialize)]
+struct TokenizerFile {
+    #[serde(default)]
+    normalizer: Option<serde_json::Value>,
+    model: serde_json::Value,
+}
+
+#[derive(Deserialize)]
+struct WordPieceSpec {
+    vocab: HashMap<String, u32>,
+    #[serde(default = "default_continuing_prefix")]
+    continuing_subword_prefix: String,
+    #[serde(default = "default_max_word_chars")]
+    max_input_chars_per_word: usize,
+}
+
+#[derive(Deserialize)]
+struct BertNormalizerSpec {
+    #[serde(default = "default_normalize")]
+    lowercase: bool,
+    /// Unset means "same as lowercase"
+    #[serde(default)]
+    strip_accents: Option<bool>,
+}
+
+fn default_continuing_prefix() -> String {
+    "##".to_string()
+}
+
+fn default_max_word_chars() -> usize {
+    100
+}
+
+impl WordPiece {
+    fn from_json(json: &[u8]) -> Result<Self> {
+        let file: TokenizerFile = serde_json::from_slice(json).context("Malformed tokenizer")?;
+        let kind = file.model["type"].as_str().unwrap_or_default();
+        anyhow::ensure!(
+            kind == "WordPiece",
+            "Only WordPiece tokenizers are supported, not '{}'",
+            kind
+        );
+        let model: WordPieceSpec =
+            serde_json::from_value(file.model).context("Malformed WordPiece model")?;
+        let (lowercase, strip_accents) = match file.normalizer {
+            Some(normalizer) if normalizer["type"] == "BertNormalizer" => {
+                let spec: BertNormalizerSpec =
+                    serde_json::from_value(normalizer).context("Malformed normalizer")?;
+                (spec.lowercase, spec.strip_accents.unwrap_or(spec.lowercase))
+            }
+            _ => (false, false),
+        };
+        Ok(Self {
+            vocab: model.vocab,
+            continuing_prefix: model.continuing_subword_prefix,
+            max_word_chars: model.max_input_chars_per_word,
+            lowercase,
+            strip_accents,
+        })
+    }
+
+    /// Token ids of the known (sub)words of `text`
+    fn tokenize(&self, text: &str) -> Vec<u32> {
+        let mut ids = Vec::new();
+        for word in self.words(&self.normalize(text)) {
+            self.word_pieces(&word, &mut ids);
+        }
+        ids
+    }
+
+    fn normalize(&self, text: &str) -> String {
+        let mut normalized = String::with_capacity(text.len());
+        for c in text.chars() {
+            if c.is_control() && !c.is_whitespace() {
+                continue;
+            }
+            // Chinese characters are words of their own
+            if is_cjk(c) {
+                normalized.push(' ');
+                normalized.push(c);
+                normalized.push(' ');
+            } else {
+                normalized.push(c);
+            }
+        }
+        if self.lowercase {
+            normalized = normalized.to_lowercase();
+        }
+        if self.strip_accents {
+            normalized = normalized
+                .nfd()
+                .filter(|&c| !is_combining_mark(c))
+                .collect();
+        }
+        normalized
+    }
+
+    /// Split on whitespace, with each punctuation character a word of its own
+    fn words(&self, text: &str) -> Vec<String> {
+        let mut words = Vec::new();
+        for chunk in text.split_whitespace() {
+            let mut word = String::new();
+            for c in chunk.chars() {
+                if c.is_alphanumeric() {
+                    word.push(c);
+                } else {
+                    if !word.is_empty() {
+                        words.push(std::mem::take(&mut word));
+                    }
+                    words.push(c.to_string());
+                }
+            }
+            if !word.is_empty() {
+                words.push(word);
+            }
+        }
+        words
+    }
+
+    /// Greedy longest-match-first split of `word` into vocabulary pieces. A
+    /// word that cannot be split completely adds nothing.
+    fn word_pieces(&self, word: &str, ids: &mut Vec<u32>) {
+        let chars: Vec<char> = word.chars().collect();
+        if chars.len() > self.max_word_chars {
+            return;
+        }
+        let mut pieces = Vec::new();
+        let mut start = 0;
+        while start < chars.len() {
+            let piece = (start + 1..=chars.len()).rev().find_map(|end| {
+                let mut piece: String = chars[start..end].iter().collect();
+                if start > 0 {
+                    piece.insert_str(0, &self.continuing_prefix);
+                }
+                self.vocab.get(&piece).map(|&id| (id, end))
+            });
+            let Some((id, end)) = piece else {
+                return;
+            };
+            pieces.push(id);
+            start = end;
+        }
+        ids.extend(pieces);
+    }
+}
+
+/// CJK ideographs, which BERT tokenizers treat as single-character words
+fn is_cjk(c: char) -> bool {
+    matches!(c as u32,
+        0x4E00..=0x9FFF
+        | 0x3400..=0x4DBF
+        | 0x20000..=0x2A6DF
+        | 0x2A700..=0x2B73F
+        | 0x2B740..=0x2B81F
+        | 0x2B820..=0x2CEAF
+        | 0xF900..=0xFAFF
+        | 0x2F800..=0x2FA1F)
+}
+
+/// The tensors of a `.safetensors` file: an 8-byte little-endian header
+/// length, a JSON header describing each tensor, then the raw data
+struct SafeTensors<'a> {
+    header: HashMap<String, serde_json::Value>,
+    data: &'a [u8],
+}
+
+#[derive(Deserialize)]
+struct TensorInfo {
+    dtype: String,
+    shape: Vec<usize>,
+    data_offsets: (usize, usize),
+}
+
+impl<'a> SafeTensors<'a> {
+    fn parse(file: &'a [u8]) -> Result<Self> {
+        let length = file
+            .get(..8)
+            .context("File too short")?
+            .try_into()
+            .map(u64::from_le_bytes)?;
+        let header_end = usize::try_from(length)
+            .ok()
+            .and_then(|length| length.checked_add(8))
+            .filter(|&end| end <= file.len())
+            .context("Header length out of range")?;
+        let header = serde_json::from_slice(&file[8..header_end]).context("Malformed header")?;
+        Ok(Self {
+            header,
+            data: &file[header_end..],
+        })
+    }
+
+    fn contains(&self, name: &str) -> bool {
+        self.header.contains_key(name)
+    }
+
+    /// Shape and values of tensor `name`, converted to f32
+    fn read(&self, name: &str) -> Result<(Vec<usize>, Vec<f32>)> {
+        let info = self
+            .header
+            .get(name)
+            .with_context(|| format!("No tensor named '{}'", name))?;
+        let info: TensorInfo = serde_json::from_value(info.clone())
+            .with_context(|| format!("Malformed description of tensor '{}'", name))?;
+        let (start, end) = info.data_offsets;
+        let bytes = self
+            .data
+            .get(start..end)
+            .with_context(|| format!("Data of tensor '{}' out of range", name))?;
+        let values: Vec<f32> = match info.dtype.as_str() {
+            "F32" => bytes
+                .chunks_exact(4)
+                .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+                .collect(),
+            "F16" => bytes
+                .chunks_exact(2)
+                .map(|b| f16_to_f32(u16::from_le_bytes([b[0], b[1]])))
+                .collect(),
+            other => anyhow::bail!("Tensor '{}' has unsupported type {}", name, other),
+        };
+        anyhow::ensure!(
+            values.len() == info.shape.iter().product::<usize>(),
+            "Tensor '{}' holds {} values for shape {:?}",
+            name,
+            values.len(),
+            info.shape
+        );
+        Ok((info.shape, values))
+    }
+}
+
+/// IEEE 754 half precision to single precision
+fn f16_to_f32(bits: u16) -> f32 {
+    let sign = u32::from(bits >> 15) << 31;
+    let exponent = u32::from((bits >> 10) & 0x1f);
+    let mantissa = u32::from(bits & 0x3ff);
+    let bits = match (exponent, mantissa) {
+        (0, 0) => sign,
+        // Subnormal: renormalize into the wider exponent range
+        (0, _) => {
+            let shift = mantissa.leading_zeros() - 21;
+            let mantissa = (mantissa << shift) & 0x3ff;
+            sign | ((113 - shift) << 23) | (mantissa << 13)
+        }
+        (0x1f, _) => sign | 0x7f80_0000 | (mantissa << 13),
+        _ => sign | ((exponent + 112) << 23) | (mantissa << 13),
+    };
+    f32::from_bits(bits)
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    /// Vocabulary of the test model and the vector of each token
+    const TOKENS: [(&str, [f32; 3]); 7] = [
+        ("[UNK]", [0.0, 0.0, 9.0]),
+        ("cafe", [1.0, 0.0, 0.0]),
+        ("coffee", [1.0, 0.2, 0.0]),
+        ("dog", [0.0, 1.0, 0.0]),
+        ("walk", [0.0, 1.0, 0.2]),
+        ("##s", [0.0, 0.0, 0.1]),
+        ("!", [0.0, 0.0, 1.0]),
+    ];
+
+    /// Write a tiny static model into a temporary directory
+    pub(crate) fn write_test_model() -> tempfile::TempDir {
+        let dir = tempfile::tempdir().unwrap();
+        let vocab: HashMap<&str, usize> = TOKENS
+            .iter()
+            .enumerate()
+            .map(|(id, (token, _))| (*token, id))
+            .collect();
+        let tokenizer = serde_json::json!({
+            "normalizer": {"type": "BertNormalizer", "lowercase": true, "strip_accents": null},
+            "model": {"type": "WordPiece", "unk_token": "[UNK]", "vocab": vocab},
+        });
+        std::fs::write(dir.path().join("tokenizer.json"), tokenizer.to_string()).unwrap();
+
+        let data: Vec<u8> = TOKENS
+            .iter()
+            .flat_map(|(_, vector)| vector.iter().flat_map(|v| v.to_le_bytes()))
+            .collect();
+        let header = serde_json::json!({
+            "__metadata__": {"format": "pt"},
+            "embeddings": {"dtype": "F32", "shape": [TOKENS.len(), 3], "data_offsets": [0, data.len()]},
+        })
+        .to_string();
+        let mut file = (header.len() as u64).to_le_bytes().to_vec();
+        file.extend(header.as_bytes());
+        file.extend(data);
+        std::fs::write(dir.path().join("model.safetensors"), file).unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_tokenize_and_embed() {
+        let dir = write_test_model();
+        let model = StaticModel::load(dir.path()).unwrap();
+        assert_eq!(model.dimensions(), 3);
+
+        // Accents and case are normalized, punctuation is split off, "walks"
+        // is "walk" + "##s" and unknown words are dropped
+        assert_eq!(
+            model.tokenizer.tokenize("Café, DOG walks!"),
+            [1, 3, 4, 5, 6]
+        );
+        assert!(model.tokenizer.tokenize("zebra").is_empty());
+
+        let embedding = model.embed("dog walk");
+        let norm = 0.2f32.hypot(2.0);
+        for (value, expected) in embedding.iter().zip([0.0, 2.0 / norm, 0.2 / norm]) {
+            assert!((value - expected).abs() < 1e-6);
+        }
+        assert_eq!(model.embed("zebra"), [0.0, 0.0, 0.0]);
+    }
+
+    #[test]
+    fn test_fingerprint_follows_content() {
+        let dir = write_test_model();
+        let copy = tempfile::tempdir().unwrap();
+        for file in ["tokenizer.json", "model.safetensors"] {
+            std::fs::copy(dir.path().join(file), copy.path().join(file)).unwrap();
+        }
+        let model = StaticModel::load(dir.path()).unwrap();
+        assert_eq!(
+            model.fingerprint(),
+            StaticModel::load(copy.path()).unwrap().fingerprint()
+        );
+
+        std::fs::write(copy.path().join("config.json"), r#"{"normalize": false}"#).unwrap();
+        assert_ne!(
+            model.fingerprint(),
+            StaticModel::load(copy.path()).unwrap().fingerprint()
+        );
+    }
+
+    #[test]
+    fn test_unsupported_models_are_rejected() {
+        let dir = write_test_model();
+        std::fs::write(
+            dir.path().join("tokenizer.json"),
+            r#"{"model": {"type": "Unigram", "vocab": [["a", 0.0]]}}"#,
+        )
+        .unwrap();
+        let error = StaticModel::load(dir.path()).err().unwrap();
+        assert!(format!("{:#}", error).contains("Only WordPiece"));
+    }
+
+    #[test]
+    fn test_f16_conversion() {
+        assert_eq!(f16_to_f32(0x3c00), 1.0);
+        assert_eq!(f16_to_f32(0xc000), -2.0);
+        assert_eq!(f16_to_f32(0x3555), 0.333_251_95);
+        assert_eq!(f16_to_f32(0x0001), 2f32.powi(-24));
+        assert!(f16_to_f32(0x7c00).is_infinite());
+    }
+}
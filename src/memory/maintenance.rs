@@ -0,0 +1,559 @@
+//! Database upkeep run by the maintenance job (see `scheduler::tasks`):
+//! message retention, vector cleanup, embedding backfill and SQLite housekeeping.
+
+use anyhow::{Context, Result};
+use rusqlite::Connection;
+use tracing::warn;
+
+use super::{meta, MemoryStore, META_REEMBED_PENDING};
+use crate::memory::vectors::{content_hash, store_vector, VectorTarget, EMBED_BATCH_SIZE};
+
+/// Failed embeddings in a row after which a backfill gives up
+const MAX_CONSECUTIVE_FAILURES: usize = 3;
+
+/// Days a row whose text failed to embed is left out of backfills
+const EMBED_FAILURE_COOLDOWN_DAYS: u64 = 7;
+
+/// Pages of full-text index merged per maintenance run
+const FTS_MERGE_PAGES: usize = 2000;
+
+/// Rows per index sampled when refreshing the query planner statistics
+const ANALYSIS_LIMIT: usize = 1000;
+
+impl MemoryStore {
+    /// Owners (platform, user_id) of stored conversations: users and group chats
+    pub async fn conversation_owners(&self) -> Result<Vec<(String, String)>> {
+        let conn = self.conn.lock().await;
+        let mut stmt = conn.prepare("SELECT DISTINCT platform, user_id FROM conversations")?;
+        let owners = stmt
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+            .collect::<Result<Vec<_>, _>>()
+            .context("Failed to list conversation owners")?;
+        Ok(owners)
+    }
+
+    /// Delete the owner's messages older than `retention_days`, along with their
+    /// vectors and cached embeddings. Turns are never cut in half: the rest of
+    /// the turn an expired message belongs to goes too. System prompts of
+    /// conversations that keep messages are kept; conversations left empty are
+    /// deleted. A rolling summary covering deleted messages is dropped, and the
+    /// kept messages are summarized again when needed. Facts learned from the
+    /// messages stay in the knowledge base.
+    /// Returns the number of messages deleted.
+    pub async fn prune_messages(
+        &self,
+        platform: &str,
+        user_id: &str,
+        retention_days: u64,
+    ) -> Result<usize> {
+        let mut conn = self.conn.lock().await;
+        let tx = conn.transaction()?;
+        let cutoff = format!("-{} days", retention_days);
+        let conversations = {
+            let mut stmt =
+                tx.prepare("SELECT id FROM conversations WHERE platform = ?1 AND user_id = ?2")?;
+            let ids = stmt
+                .query_map(rusqlite::params![platform, user_id], |row| {
+                    row.get::<_, String>(0)
+                })?
+                .collect::<Result<Vec<_>, _>>()?;
+            ids
+        };
+        let mut deleted = 0;
+        for conversation in conversations {
+            deleted += prune_conversation(&tx, &conversation, &cutoff)?;
+        }
+        tx.commit().context("Failed to prune messages")?;
+        Ok(deleted)
+    }
+
+    /// Delete vectors whose message or knowledge entry no longer exists.
+    /// Returns the number of vectors deleted.
+    pub async fn prune_orphan_embeddings(&self) -> Result<usize> {
+        let mut conn = self.conn.lock().await;
+        let tx = conn.transaction()?;
+        let mut deleted = 0;
+        for (vectors, rows) in [
+            ("message_embeddings", "messages"),
+            ("knowledge_embeddings", "knowledge"),
+        ] {
+            let orphans = {
+                let mut stmt = tx.prepare(&format!(
+                    "SELECT rowid FROM {} WHERE rowid NOT IN (SELECT rowid FROM {})",
+                    vectors, rows
+                ))?;
+                let rowids = stmt
+                    .query_map([], |row| row.get::<_, i64>(0))?
+                    .collect::<Result<Vec<_>, _>>()?;
+                rowids
+            };
+            deleted += delete_vectors(&tx, vectors, &orphans)?;
+            tx.execute(
+                &format!(
+                    "DELETE FROM embedding_failures WHERE content_table = ?1
+                     AND row_id NOT IN (SELECT rowid FROM {})",
+                    rows
+                ),
+                [rows],
+            )?;
+        }
+        tx.commit().context("Failed to prune orphaned embeddings")?;
+        Ok(deleted)
+    }
+
+    /// Drop cached embeddings not used for `days` days. Returns how many were dropped.
+    pub async fn prune_embedding_cache(&self, days: u64) -> Result<usize> {
+        let conn = self.conn.lock().await;
+        conn.execute(
+            "DELETE FROM embedding_cache WHERE used_at < datetime('now', ?1)",
+            [format!("-{} days", days)],
+        )
+        .context("Failed to prune the embedding cache")
+    }
+
+    /// Embed up to `limit` messages and knowledge entries that have no vector,
+    /// newest first: rows saved while the embedding API was down, or all rows
+    /// after a model change. A text that fails is recorded and left out of
+    /// backfills for `EMBED_FAILURE_COOLDOWN_DAYS`, so texts the API always
+    /// rejects do not hold up the rest; after `MAX_CONSECUTIVE_FAILURES` in a
+    /// row the backfill gives up so an unavailable API is not hammered.
+    /// Returns the number of vectors stored.
+    pub async fn backfill_embeddings(&self, limit: usize) -> Result<usize> {
+        if !self.embeddings.is_available() || limit == 0 {
+            return Ok(0);
+        }
+        let missing = {
+            let conn = self.conn.lock().await;
+            let mut missing = select_missing(&conn, VectorTarget::Message, limit)?;
+            missing.extend(select_missing(
+                &conn,
+                VectorTarget::Knowledge,
+                limit.saturating_sub(missing.len()),
+            )?);
+            missing
+        };
+
+        let mut stored = 0;
+        let mut failures = 0;
+        let mut stopped = None;
+        for chunk in missing.chunks(EMBED_BATCH_SIZE) {
+            let texts: Vec<String> = chunk.iter().map(|(_, _, text)| text.clone()).collect();
+            let vectors = match self.embed_texts(&texts).await {
+                Ok(vectors) => {
+                    failures = 0;
+                    vectors.into_iter().map(Some).collect()
+                }
+                // A single text the API rejects fails the whole batch
+                Err(_) => {
+                    let mut vectors = Vec::with_capacity(texts.len());
+                    for text in &texts {
+                        match self.embed_texts(std::slice::from_ref(text)).await {
+                            Ok(mut vector) => {
+                                failures = 0;
+                                vectors.push(vector.pop());
+                            }
+                            Err(e) if failures + 1 < MAX_CONSECUTIVE_FAILURES => {
+                                warn!("Embedding backfill skipped a text: {:#}", e);
+                                failures += 1;
+                                vectors.push(None);
+                            }
+                            Err(e) => {
+                                // More likely the API than the texts: those
+                                // failed in a row are not recorded
+                                let len = vectors.len().saturating_sub(failures);
+                                vectors.truncate(len);
+                                stopped = Some(e);
+                                break;
+                            }
+                        }
+                    }
+                    vectors
+                }
+            };
+            // Rows may have changed or gone while they were being embedded
+            let conn = self.conn.lock().await;
+            for ((target, rowid, text), vector) in chunk.iter().zip(&vectors) {
+                match vector {
+                    Some(vector) => stored += store_vector(&conn, *target, *rowid, text, vector)?,
+                    None => record_failure(&conn, *target, *rowid)?,
+                }
+            }
+            if let Some(e) = stopped {
+                return Err(e.context("Embedding backfill stopped"));
+            }
+        }
+        Ok(stored)
+    }
+
+    /// Number of messages and knowledge entries without a vector
+    pub async fn missing_embeddings(&self) -> Result<usize> {
+        let conn = self.conn.lock().await;
+        let count: i64 = conn
+            .query_row(
+                &format!(
+                    "SELECT (SELECT COUNT(*) {}) + (SELECT COUNT(*) {})",
+                    without_vector(VectorTarget::Message),
+                    without_vector(VectorTarget::Knowledge)
+                ),
+                [],
+                |row| row.get(0),
+            )
+            .context("Failed to count rows without embeddings")?;
+        Ok(count as usize)
+    }
+
+    /// Whether the vectors are being rebuilt after an embedding model change
+    /// (see `migrate_vector_tables`)
+    pub async fn reembedding_pending(&self) -> Result<bool> {
+        let conn = self.conn.lock().await;
+        Ok(meta(&conn, META_REEMBED_PENDING)?.is_some())
+    }
+
+    /// Record that every row has a vector of the current model
+    pub async fn finish_reembedding(&self) -> Result<()> {
+        let conn = self.conn.lock().await;
+        conn.execute(
+            "DELETE FROM schema_meta WHERE key = ?1",
+            [META_REEMBED_PENDING],
+        )
+        .context("Failed to update schema_meta")?;
+        Ok(())
+    }
+
+    /// Merge the full-text indexes, refresh the query planner statistics and,
+    /// if `vacuum` is set, rebuild the database file to reclaim free pages.
+    /// Each step is bounded and takes the connection on its own so messages
+    /// keep being served in between; only VACUUM holds it for a whole pass
+    /// over the file.
+    pub async fn optimize_database(&self, vacuum: bool) -> Result<()> {
+        for fts in ["messages_fts", "knowledge_fts"] {
+            let conn = self.conn.lock().await;
+            conn.execute(
+                &format!(
+                    "INSERT INTO {fts}({fts}, rank) VALUES('merge', {})",
+                    FTS_MERGE_PAGES
+                ),
+                [],
+            )
+            .with_context(|| format!("Failed to merge {}", fts))?;
+        }
+        {
+            // Samples each index instead of reading whole tables
+            let conn = self.conn.lock().await;
+            conn.pragma_update_and_check(None, "analysis_limit", ANALYSIS_LIMIT, |_| Ok(()))
+                .and_then(|()| conn.execute_batch("PRAGMA optimize = 0x10002;"))
+                .context("Failed to analyze the database")?;
+        }
+        if vacuum {
+            let conn = self.conn.lock().await;
+            conn.execute_batch("VACUUM;")
+                .context("Failed to vacuum the database")?;
+        }
+        Ok(())
+    }
+}
+
+/// Apply retention to one conversation; see `MemoryStore::prune_messages`
+fn prune_conversation(conn: &Connection, conversation_id: &str, cutoff: &str) -> Result<usize> {
+    let last_expired: Option<i64> = conn.query_row(
+        "SELECT MAX(rowid) FROM messages
+         WHERE conversation_id = ?1 AND role != 'system'
+           AND created_at < datetime('now', ?2)",
+        rusqlite::params![conversation_id, cutoff],
+        |row| row.get(0),
+    )?;
+    let Some(last_expired) = last_expired else {
+        return Ok(0);
+    };
+    // The next turn starts with a user message; without one nothing is kept
+    let next_turn: Option<i64> = conn.query_row(
+        "SELECT MIN(rowid) FROM messages
+         WHERE conversation_id = ?1 AND role = 'user' AND rowid > ?2",
+        rusqlite::params![conversation_id, last_expired],
+        |row| row.get(0),
+    )?;
+
+    let (expired, texts): (Vec<i64>, Vec<Option<String>>) = {
+        let mut stmt = conn.prepare(
+            "SELECT rowid, content FROM messages
+             WHERE conversation_id = ?1 AND (?2 IS NULL OR (role != 'system' AND rowid < ?2))",
+        )?;
+        let rows = stmt
+            .query_map(rusqlite::params![conversation_id, next_turn], |row| {
+                Ok((row.get::<_, i64>(0)?, row.get::<_, Option<String>>(1)?))
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        rows.into_iter().unzip()
+    };
+    delete_vectors(conn, "message_embeddings", &expired)?;
+    let mut delete = conn.prepare("DELETE FROM messages WHERE rowid = ?1")?;
+    for rowid in &expired {
+        delete.execute(rusqlite::params![rowid])?;
+    }
+    // Another row with the same text keeps its vector; it is only embedded
+    // again if it changes
+    let mut uncache = conn.prepare("DELETE FROM embedding_cache WHERE hash = ?1")?;
+    for text in texts.iter().flatten() {
+        uncache.execute([content_hash(text)])?;
+    }
+
+    if next_turn.is_none() {
+        conn.execute(
+            "DELETE FROM conversations WHERE id = ?1",
+            rusqlite::params![conversation_id],
+        )?;
+        return Ok(expired.len());
+    }
+    // Both counters count leading non-system messages, which are the ones
+    // deleted: any summary covers some of them and is dropped with them
+    conn.execute(
+        "UPDATE conversations SET
+             summary = NULL,
+             summarized_count = 0,
+             facts_reviewed_count = MAX(0, facts_reviewed_count - ?2)
+         WHERE id = ?1",
+        rusqlite::params![conversation_id, expired.len() as i64],
+    )?;
+    Ok(expired.len())
+}
+
+/// Delete `rowids` from a vec0 table, which is done one rowid at a time
+fn delete_vectors(conn: &Connection, table: &str, rowids: &[i64]) -> Result<usize> {
+    let mut delete = conn.prepare(&format!("DELETE FROM {} WHERE rowid = ?1", table))?;
+    let mut deleted = 0;
+    for rowid in rowids {
+        deleted += delete.execute(rusqlite::params![rowid])?;
+    }
+    Ok(deleted)
+}
+
+/// `FROM ... WHERE ...` of the rows of `target` that are embedded (as
+/// `save_message` and `remember` embed them) but have no vector, except those
+/// that recently failed to embed
+fn without_vector(target: VectorTarget) -> String {
+    let (vectors, rows, _) = target.tables();
+    let embedded = match target {
+        VectorTarget::Message => "content IS NOT NULL AND content != '' AND role != 'tool' AND ",
+        VectorTarget::Knowledge => "",
+    };
+    format!(
+        "FROM {rows} WHERE {embedded}rowid NOT IN (SELECT rowid FROM {vectors})
+         AND rowid NOT IN (
+             SELECT row_id FROM embedding_failures
+             WHERE content_table = '{rows}'
+               AND failed_at > datetime('now', '-{EMBED_FAILURE_COOLDOWN_DAYS} days'))"
+    )
+}
+
+/// Record that the text of a row could not be embedded (see `without_vector`)
+fn record_failure(conn: &Connection, target: VectorTarget, rowid: i64) -> Result<()> {
+    let (_, rows, _) = target.tables();
+    conn.execute(
+        "INSERT OR REPLACE INTO embedding_failures (content_table, row_id) VALUES (?1, ?2)",
+        rusqlite::params![rows, rowid],
+    )
+    .context("Failed to record an embedding failure")?;
+    Ok(())
+}
+
+/// Up to `limit` rows of `target` without a vector, newest first, with the text to embed
+fn select_missing(
+    conn: &Connection,
+    target: VectorTarget,
+    limit: usize,
+) -> Result<Vec<(VectorTarget, i64, String)>> {
+    if limit == 0 {
+        return Ok(Vec::new());
+    }
+    let (_, _, text) = target.tables();
+    let mut stmt = conn.prepare(&format!(
+        "SELECT rowid, {} {} ORDER BY rowid DESC LIMIT ?1",
+        text,
+        without_vector(target)
+    ))?;
+    let rows = stmt
+        .query_map(rusqlite::params![limit as i64], |row| {
+            Ok((target, row.get(0)?, row.get(1)?))
+        })?
+        .collect::<Result<Vec<_>, _>>()
+        .context("Failed to find rows without embeddings")?;
+    Ok(rows)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::llm::ChatMessage;
+    use crate::memory::conversations::f32_slice_to_bytes;
+    use crate::memory::knowledge::KnowledgeScope;
+
+    async fn age_messages(memory: &MemoryStore, conversation_id: &str, days: u64) {
+        let conn = memory.connection();
+        let conn = conn.lock().await;
+        conn.execute(
+            "UPDATE messages SET created_at = datetime('now', ?2) WHERE conversation_id = ?1",
+            rusqlite::params![conversation_id, format!("-{} days", days)],
+        )
+        .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_retention_deletes_old_turns_only() {
+        let memory = MemoryStore::open_in_memory().unwrap();
+        let old = memory
+            .new_conversation("telegram", "1", None)
+            .await
+            .unwrap();
+        for (role, text) in [("system", "prompt"), ("user", "hi"), ("assistant", "hello")] {
+            memory
+                .save_message(&old, &ChatMessage::new(role, text))
+                .await
+                .unwrap();
+        }
+        age_messages(&memory, &old, 40).await;
+
+        let mixed = memory
+            .new_conversation("telegram", "1", None)
+            .await
+            .unwrap();
+        for (role, text) in [
+            ("system", "prompt"),
+            ("user", "old question"),
+            ("assistant", "old answer"),
+        ] {
+            memory
+                .save_message(&mixed, &ChatMessage::new(role, text))
+                .await
+                .unwrap();
+        }
+        age_messages(&memory, &mixed, 40).await;
+        // A turn straddling the cutoff goes as a whole
+        memory
+            .save_message(&mixed, &ChatMessage::new("tool", "late tool result"))
+            .await
+            .unwrap();
+        memory
+            .save_message(&mixed, &ChatMessage::user("new question"))
+            .await
+            .unwrap();
+        memory
+            .save_conversation_summary(&mixed, "earlier", 2)
+            .await
+            .unwrap();
+        {
+            let conn = memory.connection();
+            let conn = conn.lock().await;
+            for text in ["old question", "new question"] {
+                conn.execute(
+                    "INSERT INTO embedding_cache (hash, embedding) VALUES (?1, x'00')",
+                    [content_hash(text)],
+                )
+                .unwrap();
+            }
+        }
+
+        let other = memory
+            .new_conversation("telegram", "2", None)
+            .await
+            .unwrap();
+        memory
+            .save_message(&other, &ChatMessage::user("hey"))
+            .await
+            .unwrap();
+        age_messages(&memory, &other, 40).await;
+
+        assert_eq!(memory.prune_messages("telegram", "1", 30).await.unwrap(), 6);
+        let list = memory.list_conversations("telegram", "1").await.unwrap();
+        assert_eq!(list.len(), 1);
+        let kept: Vec<_> = memory
+            .load_messages(&mixed)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|m| m.content.unwrap())
+            .collect();
+        assert_eq!(kept, ["prompt", "new question"]);
+        // The summary covered deleted messages
+        let summary = memory.load_conversation_summary(&mixed).await.unwrap();
+        assert_eq!(summary.summarized_count, 0);
+        assert!(summary.summary.is_none());
+        {
+            let conn = memory.connection();
+            let conn = conn.lock().await;
+            let cached: i64 = conn
+                .query_row("SELECT COUNT(*) FROM embedding_cache", [], |row| row.get(0))
+                .unwrap();
+            assert_eq!(cached, 1);
+            let kept: bool = conn
+                .query_row(
+                    "SELECT EXISTS(SELECT 1 FROM embedding_cache WHERE hash = ?1)",
+                    [content_hash("new question")],
+                    |row| row.get(0),
+                )
+                .unwrap();
+            assert!(kept);
+        }
+        // Other users are untouched
+        assert_eq!(memory.load_messages(&other).await.unwrap().len(), 1);
+        assert_eq!(memory.prune_messages("telegram", "1", 30).await.unwrap(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_prune_orphans_and_optimize() {
+        let memory = MemoryStore::open_in_memory().unwrap();
+        let conversation = memory
+            .new_conversation("telegram", "1", None)
+            .await
+            .unwrap();
+        memory
+            .save_message(&conversation, &ChatMessage::user("hello"))
+            .await
+            .unwrap();
+        memory
+            .remember(
+                KnowledgeScope::user("telegram", "1"),
+                "pet",
+                "dog",
+                "Rex",
+                None,
+            )
+            .await
+            .unwrap();
+        {
+            let conn = memory.connection();
+            let conn = conn.lock().await;
+            let vector = vec![0.0f32; memory.embeddings.dimensions()];
+            for (table, rowid) in [
+                ("message_embeddings", 1),
+                ("message_embeddings", 99),
+                ("knowledge_embeddings", 1),
+                ("knowledge_embeddings", 98),
+            ] {
+                conn.execute(
+                    &format!("INSERT INTO {} (rowid, embedding) VALUES (?1, ?2)", table),
+                    rusqlite::params![rowid, f32_slice_to_bytes(&vector)],
+                )
+                .unwrap();
+            }
+        }
+
+        assert_eq!(memory.prune_orphan_embeddings().await.unwrap(), 2);
+        assert_eq!(memory.prune_orphan_embeddings().await.unwrap(), 0);
+        memory.optimize_database(true).await.unwrap();
+        {
+            let conn = memory.connection();
+            let conn = conn.lock().await;
+            let analyzed: i64 = conn
+                .query_row("SELECT COUNT(*) FROM sqlite_stat1", [], |row| row.get(0))
+                .unwrap();
+            assert!(analyzed > 0);
+        }
+        let found = memory
+            .search_messages("telegram", "1", "hello", 5)
+            .await
+            .unwrap();
+        assert_eq!(found.len(), 1);
+        // Nothing to backfill without an embedding API
+        assert_eq!(memory.backfill_embeddings(10).await.unwrap(), 0);
+    }
+}
@@ -1,28 +1,107 @@
 pub mod conversations;
 pub mod embeddings;
+pub mod export;
 pub mod knowledge;
+pub mod local_embeddings;
+pub mod maintenance;
+pub mod usage;
+pub mod vectors;
 
 use anyhow::{Context, Result};
 use rusqlite::{Connection, OptionalExtension};
 use std::path::Path;
-use std::sync::Arc;
+use std::sync::{Arc, OnceLock};
 use tokio::sync::Mutex;
-use tracing::info;
+use tracing::{info, warn};
+
+use crate::memory::embeddings::EmbeddingEngine;
+use crate::memory::knowledge::KnowledgeScope;
+use crate::memory::vectors::EmbeddingQueue;
+
+/// The vector index is not partitioned by user, so scoped searches ask it for
+/// this many times the neighbours they need before filtering
+pub(crate) const VECTOR_OVERFETCH: i64 = 10;
+
+/// Relevance (0 to 1) of a search hit from the cosine similarity of its
+/// embedding to the query's and from its bm25 keyword score, for whichever of
+/// the two searches found it. A hit found by both scores higher than by either.
+fn relevance(similarity: Option<f64>, bm25: Option<f64>) -> f64 {
+    let vector = similarity.unwrap_or(0.0).clamp(0.0, 1.0);
+    let keyword = bm25.map(keyword_relevance).unwrap_or(0.0);
+    1.0 - (1.0 - vector) * (1.0 - keyword)
+}
+
+/// A bm25 score (FTS5 ranks: more negative is better) squashed into 0 to 1.
+/// A rare word of the query in a short text scores about 0.7; words found in
+/// most of the index say little and score near 0.
+fn keyword_relevance(bm25: f64) -> f64 {
+    let score = (-bm25).max(0.0);
+    score / (score + 1.0)
+}
 
-use crate::memory::embeddings::{EmbeddingConfig, EmbeddingEngine};
+/// How the text given to a search is matched against the full-text index
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeywordMatch {
+    /// The text is an FTS5 query, used as is: all of its words must match
+    Query,
+    /// Any of the words of free text, such as a whole user message
+    AnyWord,
+}
+
+impl KeywordMatch {
+    fn fts_query(self, text: &str) -> String {
+        match self {
+            KeywordMatch::Query => text.to_string(),
+            KeywordMatch::AnyWord => fts_query(text),
+        }
+    }
+}
+
+/// Words too common to be worth matching on
+const STOPWORDS: &[&str] = &[
+    "a", "an", "and", "are", "can", "do", "for", "how", "in", "is", "it", "me", "my", "of", "on",
+    "or", "the", "to", "was", "what", "with", "you", "your",
+];
+
+/// An FTS5 query matching any of the words of free text. Raw text cannot be
+/// used as is: quotes, `?`, `-` or `AND` would be read as query syntax.
+/// Matches nothing if the text has no searchable words.
+fn fts_query(text: &str) -> String {
+    let mut words: Vec<String> = Vec::new();
+    for word in text.split(|c: char| !c.is_alphanumeric()) {
+        let word = word.to_lowercase();
+        if word.chars().count() >= 2
+            && !STOPWORDS.contains(&word.as_str())
+            && !words.contains(&word)
+        {
+            words.push(word);
+        }
+    }
+    if words.is_empty() {
+        return "\"\"".to_string();
+    }
+    words
+        .iter()
+        .take(32)
+        .map(|word| format!("\"{}\"", word))
+        .collect::<Vec<_>>()
+        .join(" OR ")
+}
 
 /// Thread-safe SQLite memory store with hybrid vector+FTS5 search
 #[derive(Clone)]
 pub struct MemoryStore {
     conn: Arc<Mutex<Connection>>,
     pub embeddings: Arc<EmbeddingEngine>,
+    /// Saved messages waiting for their vector (see `vectors`)
+    embedding_queue: Arc<OnceLock<EmbeddingQueue>>,
 }
 
 impl MemoryStore {
     /// Open or create the SQLite database at the given path.
-    /// If `embedding_config` is provided, vector search is enabled alongside FTS5.
-    /// If None, falls back to FTS5-only search.
-    pub fn open(path: &Path, embedding_config: Option<EmbeddingConfig>) -> Result<Self> {
+    /// If `embeddings` is available, vector search is enabled alongside FTS5;
+    /// otherwise search falls back to FTS5 only.
+    pub fn open(path: &Path, embeddings: EmbeddingEngine) -> Result<Self> {
         // Register sqlite-vec extension before opening any connection
         unsafe {
             type VecInitFn = unsafe extern "C" fn(
@@ -45,15 +124,14 @@ impl MemoryStore {
         let _: String = conn.query_row("PRAGMA journal_mode=WAL", [], |row| row.get(0))?;
         conn.execute_batch("PRAGMA foreign_keys=ON;")?;
 
-        let embeddings = EmbeddingEngine::new(embedding_config);
-
         // Run migrations on the raw connection before wrapping in Mutex.
         // This avoids blocking_lock() panic when called from async context.
-        Self::run_migrations(&conn, embeddings.dimensions())?;
+        Self::run_migrations(&conn, embeddings.model(), embeddings.dimensions())?;
 
         let store = Self {
             conn: Arc::new(Mutex::new(conn)),
             embeddings: Arc::new(embeddings),
+            embedding_queue: Arc::new(OnceLock::new()),
         };
 
         info!("Memory store initialized at: {}", path.display());
@@ -63,6 +141,12 @@ impl MemoryStore {
     /// Open an in-memory database (for testing)
     #[allow(dead_code)]
     pub fn open_in_memory() -> Result<Self> {
+        Self::open_in_memory_with(EmbeddingEngine::new(None))
+    }
+
+    /// Open an in-memory database using `embeddings` (for testing)
+    #[allow(dead_code)]
+    pub fn open_in_memory_with(embeddings: EmbeddingEngine) -> Result<Self> {
         unsafe {
             type VecInitFn = unsafe extern "C" fn(
                 *mut rusqlite::ffi::sqlite3,
@@ -79,13 +163,12 @@ impl MemoryStore {
         let conn = Connection::open_in_memory()?;
         conn.execute_batch("PRAGMA foreign_keys=ON;")?;
 
-        let embeddings = EmbeddingEngine::new(None);
-
-        Self::run_migrations(&conn, embeddings.dimensions())?;
+        Self::run_migrations(&conn, embeddings.model(), embeddings.dimensions())?;
 
         let store = Self {
             conn: Arc::new(Mutex::new(conn)),
             embeddings: Arc::new(embeddings),
+            embedding_queue: Arc::new(OnceLock::new()),
         };
         Ok(store)
     }
@@ -96,7 +179,7 @@ impl MemoryStore {
         Arc::clone(&self.conn)
     }
 
-    fn run_migrations(conn: &Connection, dims: usize) -> Result<()> {
+    fn run_migrations(conn: &Connection, model: Option<&str>, dims: usize) -> Result<()> {
         conn.execute_batch(
             "
             -- Conversations table
@@ -126,9 +209,19 @@ impl MemoryStore {
             CREATE INDEX IF NOT EXISTS idx_conversations_user
                 ON conversations(platform, user_id, updated_at);
 
-            -- Knowledge table
+            -- The conversation each user is currently talking in (NULL: start a new one)
+            CREATE TABLE IF NOT EXISTS active_conversations (
+                platform        TEXT NOT NULL,
+                user_id         TEXT NOT NULL,
+                conversation_id TEXT,
+                PRIMARY KEY (platform, user_id)
+            );
+
+            -- Knowledge table; platform and user_id are '' for shared entries
             CREATE TABLE IF NOT EXISTS knowledge (
                 id TEXT PRIMARY KEY,
+                platform TEXT NOT NULL DEFAULT '',
+                user_id TEXT NOT NULL DEFAULT '',
                 category TEXT NOT NULL,
                 key TEXT NOT NULL,
                 value TEXT NOT NULL,
@@ -137,9 +230,6 @@ impl MemoryStore {
                 updated_at TEXT NOT NULL DEFAULT (datetime('now'))
             );
 
-            CREATE UNIQUE INDEX IF NOT EXISTS idx_knowledge_key
-                ON knowledge(category, key);
-
             -- FTS5 virtual tables for full-text search
             CREATE VIRTUAL TABLE IF NOT EXISTS messages_fts USING fts5(
                 content,
@@ -183,12 +273,27 @@ impl MemoryStore {
                     VALUES (NEW.rowid, NEW.key, NEW.value);
             END;
 
-            -- Schema metadata (e.g. embedding dimension for vec tables)
+            -- Schema metadata (e.g. embedding model and dimension of the vec tables)
             CREATE TABLE IF NOT EXISTS schema_meta (
                 key TEXT PRIMARY KEY,
                 value TEXT NOT NULL
             );
 
+            -- Vectors by SHA-256 of the embedded text, so no text is embedded twice
+            CREATE TABLE IF NOT EXISTS embedding_cache (
+                hash TEXT PRIMARY KEY,
+                embedding BLOB NOT NULL,
+                used_at TEXT NOT NULL DEFAULT (datetime('now'))
+            );
+
+            -- Rows whose text could not be embedded, left out of backfills for a while
+            CREATE TABLE IF NOT EXISTS embedding_failures (
+                content_table TEXT NOT NULL,
+                row_id INTEGER NOT NULL,
+                failed_at TEXT NOT NULL DEFAULT (datetime('now')),
+                PRIMARY KEY (content_table, row_id)
+            );
+
             -- Scheduled tasks for user-registered reminders / recurring jobs
             CREATE TABLE IF NOT EXISTS scheduled_tasks (
                 id               TEXT PRIMARY KEY,
@@ -207,94 +312,269 @@ impl MemoryStore {
 
             CREATE INDEX IF NOT EXISTS idx_scheduled_tasks_user
                 ON scheduled_tasks(user_id, status);
-            ",
-        )?;
-
-        // Stored embedding dimension (None if legacy DB without schema_meta row)
-        let raw: Option<String> = conn
-            .query_row(
-                "SELECT value FROM schema_meta WHERE key = 'embedding_dims'",
-                [],
-                |row| row.get(0),
-            )
-            .optional()
-            .context("schema_meta query")?;
-        let stored_dims: Option<usize> = raw.and_then(|s| s.parse().ok());
 
-        let need_migrate = !matches!(stored_dims, Some(s) if s == dims);
+            -- Token usage and cost of every LLM call
+            CREATE TABLE IF NOT EXISTS llm_usage (
+                id                INTEGER PRIMARY KEY AUTOINCREMENT,
+                platform          TEXT NOT NULL,
+                user_id           TEXT NOT NULL,
+                conversation_id   TEXT,
+                task_id           TEXT,
+                model             TEXT NOT NULL,
+                prompt_tokens     INTEGER NOT NULL,
+                completion_tokens INTEGER NOT NULL,
+                cost              REAL,
+                created_at        TEXT NOT NULL DEFAULT (datetime('now'))
+            );
 
-        let table_exists = |conn: &Connection, name: &str| -> bool {
-            conn.query_row(
-                &format!(
-                    "SELECT count(*) > 0 FROM sqlite_master WHERE type='table' AND name='{}'",
-                    name
-                ),
-                [],
-                |row| row.get(0),
-            )
-            .unwrap_or(false)
-        };
+            CREATE INDEX IF NOT EXISTS idx_llm_usage_user
+                ON llm_usage(platform, user_id, created_at);
+            ",
+        )?;
 
-        if need_migrate {
-            // Drop vec tables so we can recreate with new dimension
-            if table_exists(conn, "message_embeddings") {
-                conn.execute_batch("DROP TABLE message_embeddings;")?;
-            }
-            if table_exists(conn, "knowledge_embeddings") {
-                conn.execute_batch("DROP TABLE knowledge_embeddings;")?;
-            }
-            conn.execute_batch(&format!(
-                "CREATE VIRTUAL TABLE message_embeddings USING vec0(embedding float[{}]);",
-                dims
-            ))?;
-            conn.execute_batch(&format!(
-                "CREATE VIRTUAL TABLE knowledge_embeddings USING vec0(embedding float[{}]);",
-                dims
-            ))?;
-            conn.execute(
-                "INSERT OR REPLACE INTO schema_meta (key, value) VALUES ('embedding_dims', ?1)",
-                [dims.to_string()],
+        // Rolling summary of older turns (see crate::context)
+        add_column_if_missing(conn, "conversations", "summary", "TEXT")?;
+        add_column_if_missing(
+            conn,
+            "conversations",
+            "summarized_count",
+            "INTEGER NOT NULL DEFAULT 0",
+        )?;
+        // Model that produced an assistant message (may be a fallback model)
+        add_column_if_missing(conn, "messages", "model", "TEXT")?;
+        // Named conversations; archived ones are kept but no longer listed
+        add_column_if_missing(conn, "conversations", "title", "TEXT")?;
+        add_column_if_missing(conn, "conversations", "archived_at", "TEXT")?;
+        // Turns already reviewed by the fact extraction job (see crate::facts).
+        // History from before the job existed is not reviewed.
+        if add_column_if_missing(
+            conn,
+            "conversations",
+            "facts_reviewed_count",
+            "INTEGER NOT NULL DEFAULT 0",
+        )? {
+            conn.execute_batch(
+                "UPDATE conversations SET facts_reviewed_count = (
+                     SELECT COUNT(*) FROM messages m
+                     WHERE m.conversation_id = conversations.id AND m.role != 'system'
+                 );",
             )?;
-            if let Some(prev_dims) = stored_dims {
-                info!(
-                    "Embedding dimension changed from {} to {}; vector tables recreated.",
-                    prev_dims, dims
-                );
-            }
-        } else {
-            // Create vec tables only if they don't exist (same dimension)
-            if !table_exists(conn, "message_embeddings") {
-                conn.execute_batch(&format!(
-                    "CREATE VIRTUAL TABLE message_embeddings USING vec0(embedding float[{}]);",
-                    dims
-                ))?;
-                conn.execute(
-                    "INSERT OR REPLACE INTO schema_meta (key, value) VALUES ('embedding_dims', ?1)",
-                    [dims.to_string()],
-                )?;
-            }
-            if !table_exists(conn, "knowledge_embeddings") {
-                conn.execute_batch(&format!(
-                    "CREATE VIRTUAL TABLE knowledge_embeddings USING vec0(embedding float[{}]);",
-                    dims
-                ))?;
-                if stored_dims.is_none() {
-                    conn.execute(
-                        "INSERT OR REPLACE INTO schema_meta (key, value) VALUES ('embedding_dims', ?1)",
-                        [dims.to_string()],
-                    )?;
-                }
-            }
         }
+        // Tasks created in group chats run in the group's conversation
+        add_column_if_missing(
+            conn,
+            "scheduled_tasks",
+            "is_group",
+            "INTEGER NOT NULL DEFAULT 0",
+        )?;
+        add_column_if_missing(conn, "scheduled_tasks", "thread_id", "TEXT")?;
+        // Knowledge belongs to a user (or group chat); see knowledge::KnowledgeScope
+        let owned =
+            add_column_if_missing(conn, "knowledge", "platform", "TEXT NOT NULL DEFAULT ''")?;
+        add_column_if_missing(conn, "knowledge", "user_id", "TEXT NOT NULL DEFAULT ''")?;
+        if owned {
+            assign_legacy_knowledge(conn)?;
+        }
+        conn.execute_batch(
+            "DROP INDEX IF EXISTS idx_knowledge_key;
+             CREATE UNIQUE INDEX IF NOT EXISTS idx_knowledge_owner_key
+                 ON knowledge(platform, user_id, category, key);",
+        )?;
+
+        migrate_vector_tables(conn, model, dims)?;
 
         Ok(())
     }
 }
 
+/// Keys in `schema_meta`
+const META_EMBEDDING_DIMS: &str = "embedding_dims";
+const META_EMBEDDING_MODEL: &str = "embedding_model";
+/// Set while rows may lack a vector of the current model; cleared by the
+/// re-embedding job once they all have one
+pub(crate) const META_REEMBED_PENDING: &str = "reembed_pending";
+
+/// Create the vec0 tables for `model`. Vectors of another model (or of unknown
+/// dimensions) cannot be compared with the new model's, so the tables are then
+/// recreated empty and flagged for re-embedding from `messages` and `knowledge`.
+/// Without an embedding model the tables are kept as they are, for when
+/// embeddings are turned back on.
+fn migrate_vector_tables(conn: &Connection, model: Option<&str>, dims: usize) -> Result<()> {
+    // Both None on databases from before schema_meta
+    let stored_dims: Option<usize> = meta(conn, META_EMBEDDING_DIMS)?.and_then(|s| s.parse().ok());
+    let stored_model = meta(conn, META_EMBEDDING_MODEL)?;
+
+    let Some(model) = model else {
+        let dims = stored_dims.unwrap_or(dims);
+        create_vector_tables(conn, dims)?;
+        return set_meta(conn, META_EMBEDDING_DIMS, &dims.to_string());
+    };
+
+    let outdated = match (stored_dims, stored_model.as_deref()) {
+        (Some(stored), _) if stored != dims => true,
+        (Some(_), Some(stored)) => stored != model,
+        // Recorded before models were tracked: assume it is the same one
+        (Some(_), None) => false,
+        (None, _) => table_exists(conn, "message_embeddings")?,
+    };
+    if outdated {
+        conn.execute_batch(
+            "DROP TABLE IF EXISTS message_embeddings;
+             DROP TABLE IF EXISTS knowledge_embeddings;
+             DELETE FROM embedding_cache;
+             DELETE FROM embedding_failures;",
+        )?;
+        warn!(
+            "Embedding model changed from {} ({} dimensions) to {} ({} dimensions); \
+             stored vectors were dropped and are rebuilt in the background",
+            stored_model.as_deref().unwrap_or("unknown"),
+            stored_dims.map_or("unknown".to_string(), |d| d.to_string()),
+            model,
+            dims
+        );
+    }
+    create_vector_tables(conn, dims)?;
+    // A model seen for the first time may find rows without vectors too
+    if outdated || stored_model.as_deref() != Some(model) {
+        set_meta(conn, META_REEMBED_PENDING, "1")?;
+    }
+    set_meta(conn, META_EMBEDDING_DIMS, &dims.to_string())?;
+    set_meta(conn, META_EMBEDDING_MODEL, model)
+}
+
+fn create_vector_tables(conn: &Connection, dims: usize) -> Result<()> {
+    conn.execute_batch(&format!(
+        "CREATE VIRTUAL TABLE IF NOT EXISTS message_embeddings USING vec0(embedding float[{dims}]);
+         CREATE VIRTUAL TABLE IF NOT EXISTS knowledge_embeddings USING vec0(embedding float[{dims}]);"
+    ))
+    .context("Failed to create vector tables")
+}
+
+fn table_exists(conn: &Connection, name: &str) -> Result<bool> {
+    conn.query_row(
+        "SELECT count(*) > 0 FROM sqlite_master WHERE type = 'table' AND name = ?1",
+        [name],
+        |row| row.get(0),
+    )
+    .context("Failed to look up table")
+}
+
+pub(crate) fn meta(conn: &Connection, key: &str) -> Result<Option<String>> {
+    conn.query_row(
+        "SELECT value FROM schema_meta WHERE key = ?1",
+        [key],
+        |row| row.get(0),
+    )
+    .optional()
+    .context("schema_meta query")
+}
+
+pub(crate) fn set_meta(conn: &Connection, key: &str, value: &str) -> Result<()> {
+    conn.execute(
+        "INSERT OR REPLACE INTO schema_meta (key, value) VALUES (?1, ?2)",
+        [key, value],
+    )
+    .context("Failed to update schema_meta")?;
+    Ok(())
+}
+
+/// Add a column to an existing table unless it is already present.
+/// Lets older databases pick up new columns without a full migration framework.
+/// Returns whether the column was added.
+fn add_column_if_missing(
+    conn: &Connection,
+    table: &str,
+    column: &str,
+    definition: &str,
+) -> Result<bool> {
+    let exists: bool = conn
+        .query_row(
+            &format!(
+                "SELECT count(*) > 0 FROM pragma_table_info('{}') WHERE name = ?1",
+                table
+            ),
+            [column],
+            |row| row.get(0),
+        )
+        .with_context(|| format!("Failed to inspect columns of {}", table))?;
+    if !exists {
+        conn.execute_batch(&format!(
+            "ALTER TABLE {} ADD COLUMN {} {};",
+            table, column, definition
+        ))
+        .with_context(|| format!("Failed to add column {}.{}", table, column))?;
+    }
+    Ok(!exists)
+}
+
+/// Knowledge stored before it had an owner was visible to everyone. With a
+/// single user (the usual setup) it is theirs; otherwise nobody can tell whose
+/// it is, so it is set aside in `KnowledgeScope::QUARANTINE` until an admin
+/// reviews it with `/memory legacy`.
+fn assign_legacy_knowledge(conn: &Connection) -> Result<()> {
+    let count: i64 = conn.query_row("SELECT COUNT(*) FROM knowledge", [], |row| row.get(0))?;
+    if count == 0 {
+        return Ok(());
+    }
+    let owners: Vec<(String, String)> = conn
+        .prepare(
+            "SELECT DISTINCT platform, user_id FROM conversations
+             WHERE user_id NOT LIKE 'chat:%'
+             LIMIT 2",
+        )?
+        .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+        .collect::<Result<_, _>>()?;
+    let (platform, user_id) = match owners.as_slice() {
+        [(platform, user_id)] => (platform.as_str(), user_id.as_str()),
+        _ => (
+            KnowledgeScope::QUARANTINE.platform,
+            KnowledgeScope::QUARANTINE.user_id,
+        ),
+    };
+    conn.execute(
+        "UPDATE knowledge SET platform = ?1, user_id = ?2",
+        rusqlite::params![platform, user_id],
+    )
+    .context("Failed to assign knowledge to its user")?;
+    if owners.len() == 1 {
+        info!(
+            "Assigned {} existing knowledge entries to {} user {}",
+            count, platform, user_id
+        );
+    } else {
+        warn!(
+            "{} existing knowledge entries predate per-user memory and were set \
+             aside; an admin can review them with /memory legacy",
+            count
+        );
+    }
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_fts_query_quotes_words() {
+        assert_eq!(
+            fts_query("What's the plan for \"Porto\" trip?"),
+            "\"plan\" OR \"porto\" OR \"trip\""
+        );
+        assert_eq!(fts_query("is it?"), "\"\"");
+    }
+
+    #[test]
+    fn test_relevance_from_scores() {
+        // A close neighbour is relevant without any keyword in common
+        assert!(relevance(Some(0.7), None) >= 0.6);
+        assert_eq!(relevance(Some(-0.2), None), 0.0);
+        assert!(keyword_relevance(-2.5) > keyword_relevance(-0.5));
+        assert_eq!(keyword_relevance(0.0), 0.0);
+        // Found by both searches beats either alone
+        assert!(relevance(Some(0.5), Some(-1.0)) > 0.5);
+    }
+
     #[test]
     fn test_scheduled_tasks_table_exists() {
         let memory = MemoryStore::open_in_memory().unwrap();
@@ -318,4 +598,162 @@ mod tests {
         let n: i64 = conn.query_row("SELECT 42", [], |row| row.get(0)).unwrap();
         assert_eq!(n, 42);
     }
+
+    /// A database from before knowledge had an owner, with conversations of `users`
+    fn legacy_database(users: &[&str]) -> Connection {
+        // Registers sqlite-vec for the connection below
+        MemoryStore::open_in_memory().unwrap();
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(
+            "CREATE TABLE conversations (
+                 id TEXT PRIMARY KEY,
+                 platform TEXT NOT NULL,
+                 user_id TEXT NOT NULL,
+                 started_at TEXT NOT NULL DEFAULT (datetime('now')),
+                 updated_at TEXT NOT NULL DEFAULT (datetime('now'))
+             );
+             CREATE TABLE knowledge (
+                 id TEXT PRIMARY KEY,
+                 category TEXT NOT NULL,
+                 key TEXT NOT NULL,
+                 value TEXT NOT NULL,
+                 source TEXT,
+                 created_at TEXT NOT NULL DEFAULT (datetime('now')),
+                 updated_at TEXT NOT NULL DEFAULT (datetime('now'))
+             );
+             CREATE UNIQUE INDEX idx_knowledge_key ON knowledge(category, key);
+             CREATE VIRTUAL TABLE knowledge_fts USING fts5(
+                 key, value, content=knowledge, content_rowid=rowid
+             );
+             CREATE TRIGGER knowledge_fts_insert AFTER INSERT ON knowledge BEGIN
+                 INSERT INTO knowledge_fts(rowid, key, value)
+                     VALUES (NEW.rowid, NEW.key, NEW.value);
+             END;
+             INSERT INTO knowledge (id, category, key, value) VALUES ('k1', 'pet', 'name', 'Rex');",
+        )
+        .unwrap();
+        for (n, user) in users.iter().enumerate() {
+            conn.execute(
+                "INSERT INTO conversations (id, platform, user_id) VALUES (?1, 'telegram', ?2)",
+                rusqlite::params![n.to_string(), user],
+            )
+            .unwrap();
+        }
+        conn
+    }
+
+    fn knowledge_owner(conn: &Connection) -> (String, String) {
+        conn.query_row("SELECT platform, user_id FROM knowledge", [], |row| {
+            Ok((row.get(0)?, row.get(1)?))
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn test_migration_assigns_legacy_knowledge() {
+        let dims = EmbeddingEngine::new(None).dimensions();
+
+        // A single user owns everything that was remembered
+        let conn = legacy_database(&["7", "chat:-100"]);
+        MemoryStore::run_migrations(&conn, None, dims).unwrap();
+        assert_eq!(
+            knowledge_owner(&conn),
+            ("telegram".to_string(), "7".to_string())
+        );
+        // The old (category, key) uniqueness no longer applies across users
+        conn.execute(
+            "INSERT INTO knowledge (id, platform, user_id, category, key, value)
+             VALUES ('k2', 'telegram', '8', 'pet', 'name', 'Biscuit')",
+            [],
+        )
+        .unwrap();
+
+        // With several users nobody can tell whose it was: it is set aside,
+        // not shared
+        let quarantine = ("legacy".to_string(), String::new());
+        let conn = legacy_database(&["7", "8"]);
+        MemoryStore::run_migrations(&conn, None, dims).unwrap();
+        assert_eq!(knowledge_owner(&conn), quarantine);
+
+        // Running the migrations again leaves it alone
+        MemoryStore::run_migrations(&conn, None, dims).unwrap();
+        assert_eq!(knowledge_owner(&conn), quarantine);
+    }
+
+    fn vector_count(conn: &Connection) -> i64 {
+        conn.query_row("SELECT COUNT(*) FROM message_embeddings", [], |row| {
+            row.get(0)
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn test_embedding_model_change_flags_reembedding() {
+        let conn = legacy_database(&[]);
+        let pending = |conn: &Connection| meta(conn, META_REEMBED_PENDING).unwrap().is_some();
+        MemoryStore::run_migrations(&conn, Some("model-a"), 4).unwrap();
+        // A model seen for the first time backfills whatever is missing
+        assert!(pending(&conn));
+        conn.execute(
+            "DELETE FROM schema_meta WHERE key = ?1",
+            [META_REEMBED_PENDING],
+        )
+        .unwrap();
+        conn.execute(
+            "INSERT INTO message_embeddings (rowid, embedding) VALUES (1, ?1)",
+            [conversations::f32_vec_to_bytes(&[0.1; 4])],
+        )
+        .unwrap();
+
+        // Same model: vectors kept
+        MemoryStore::run_migrations(&conn, Some("model-a"), 4).unwrap();
+        assert_eq!(vector_count(&conn), 1);
+        assert!(!pending(&conn));
+
+        // Embeddings turned off: vectors kept for later
+        MemoryStore::run_migrations(&conn, None, 384).unwrap();
+        assert_eq!(vector_count(&conn), 1);
+        assert_eq!(
+            meta(&conn, META_EMBEDDING_DIMS).unwrap().as_deref(),
+            Some("4")
+        );
+
+        // Another model of the same size: vectors rebuilt
+        MemoryStore::run_migrations(&conn, Some("model-b"), 4).unwrap();
+        assert_eq!(vector_count(&conn), 0);
+        assert!(pending(&conn));
+        assert_eq!(
+            meta(&conn, META_EMBEDDING_MODEL).unwrap().as_deref(),
+            Some("model-b")
+        );
+
+        // Another size: the tables take the new dimensions
+        MemoryStore::run_migrations(&conn, Some("model-b"), 8).unwrap();
+        conn.execute(
+            "INSERT INTO message_embeddings (rowid, embedding) VALUES (1, ?1)",
+            [conversations::f32_vec_to_bytes(&[0.1; 8])],
+        )
+        .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_conversation_summary_roundtrip() {
+        let memory = MemoryStore::open_in_memory().unwrap();
+        let conv = memory
+            .get_or_create_conversation("telegram", "user-1")
+            .await
+            .unwrap();
+
+        let state = memory.load_conversation_summary(&conv).await.unwrap();
+        assert!(state.summary.is_none());
+        assert_eq!(state.summarized_count, 0);
+
+        memory
+            .save_conversation_summary(&conv, "User likes tea.", 6)
+            .await
+            .unwrap();
+        let state = memory.load_conversation_summary(&conv).await.unwrap();
+        assert_eq!(state.summary.as_deref(), Some("User likes tea."));
+        assert_eq!(state.summarized_count, 6);
+    }
 }
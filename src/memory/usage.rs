@@ -0,0 +1,274 @@
+use anyhow::{Context, Result};
+
+use super::MemoryStore;
+use crate::config::DailyBudget;
+use crate::llm::Usage;
+
+/// Who an LLM call is attributed to
+#[derive(Debug, Clone, Default)]
+pub struct UsageScope {
+    pub platform: String,
+    pub user_id: String,
+    pub conversation_id: Option<String>,
+    /// Set when the call was made on behalf of a scheduled task
+    pub task_id: Option<String>,
+}
+
+/// Aggregated usage over some set of calls
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct UsageTotals {
+    pub calls: u64,
+    pub prompt_tokens: u64,
+    pub completion_tokens: u64,
+    /// Sum of reported costs in USD (calls without a reported cost count as 0)
+    pub cost: f64,
+}
+
+impl UsageTotals {
+    pub fn total_tokens(&self) -> u64 {
+        self.prompt_tokens + self.completion_tokens
+    }
+
+    /// Whether these totals have reached any limit of `budget`
+    pub fn exceeds(&self, budget: &DailyBudget) -> bool {
+        budget
+            .daily_token_budget
+            .is_some_and(|limit| self.total_tokens() >= limit)
+            || budget
+                .daily_cost_budget
+                .is_some_and(|limit| self.cost >= limit)
+    }
+}
+
+/// Bucket size for usage breakdowns
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UsagePeriod {
+    Day,
+    Month,
+}
+
+impl UsagePeriod {
+    fn strftime(self) -> &'static str {
+        match self {
+            UsagePeriod::Day => "%Y-%m-%d",
+            UsagePeriod::Month => "%Y-%m",
+        }
+    }
+}
+
+const TOTALS_COLUMNS: &str = "count(*), coalesce(sum(prompt_tokens), 0),
+     coalesce(sum(completion_tokens), 0), coalesce(sum(cost), 0.0)";
+
+fn totals_from_row(row: &rusqlite::Row, offset: usize) -> rusqlite::Result<UsageTotals> {
+    Ok(UsageTotals {
+        calls: row.get::<_, i64>(offset)? as u64,
+        prompt_tokens: row.get::<_, i64>(offset + 1)? as u64,
+        completion_tokens: row.get::<_, i64>(offset + 2)? as u64,
+        cost: row.get(offset + 3)?,
+    })
+}
+
+impl MemoryStore {
+    /// Persist the token counts and cost of one LLM call
+    pub async fn record_usage(&self, scope: &UsageScope, model: &str, usage: &Usage) -> Result<()> {
+        let conn = self.conn.lock().await;
+        conn.execute(
+            "INSERT INTO llm_usage
+             (platform, user_id, conversation_id, task_id, model,
+              prompt_tokens, completion_tokens, cost)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+            rusqlite::params![
+                scope.platform,
+                scope.user_id,
+                scope.conversation_id,
+                scope.task_id,
+                model,
+                usage.prompt_tokens as i64,
+                usage.completion_tokens as i64,
+                usage.cost,
+            ],
+        )
+        .context("Failed to record LLM usage")?;
+        Ok(())
+    }
+
+    /// A user's usage since midnight UTC
+    pub async fn usage_today(&self, platform: &str, user_id: &str) -> Result<UsageTotals> {
+        let conn = self.conn.lock().await;
+        conn.query_row(
+            &format!(
+                "SELECT {} FROM llm_usage
+                 WHERE platform = ?1 AND user_id = ?2 AND created_at >= date('now')",
+                TOTALS_COLUMNS
+            ),
+            rusqlite::params![platform, user_id],
+            |row| totals_from_row(row, 0),
+        )
+        .context("Failed to query today's usage")
+    }
+
+    /// A user's usage grouped by day or month, most recent first
+    pub async fn usage_by_period(
+        &self,
+        platform: &str,
+        user_id: &str,
+        period: UsagePeriod,
+        limit: usize,
+    ) -> Result<Vec<(String, UsageTotals)>> {
+        let conn = self.conn.lock().await;
+        let mut stmt = conn.prepare(&format!(
+            "SELECT strftime('{}', created_at) AS bucket, {}
+             FROM llm_usage
+             WHERE platform = ?1 AND user_id = ?2
+             GROUP BY bucket
+             ORDER BY bucket DESC
+             LIMIT ?3",
+            period.strftime(),
+            TOTALS_COLUMNS
+        ))?;
+        let rows = stmt
+            .query_map(rusqlite::params![platform, user_id, limit as i64], |row| {
+                Ok((row.get(0)?, totals_from_row(row, 1)?))
+            })?
+            .collect::<rusqlite::Result<Vec<_>>>()
+            .context("Failed to query usage breakdown")?;
+        Ok(rows)
+    }
+
+    /// A user's usage in the current UTC month, grouped by model (highest token count first)
+    pub async fn usage_by_model_this_month(
+        &self,
+        platform: &str,
+        user_id: &str,
+    ) -> Result<Vec<(String, UsageTotals)>> {
+        let conn = self.conn.lock().await;
+        let mut stmt = conn.prepare(&format!(
+            "SELECT model, {}
+             FROM llm_usage
+             WHERE platform = ?1 AND user_id = ?2
+               AND created_at >= date('now', 'start of month')
+             GROUP BY model
+             ORDER BY sum(prompt_tokens + completion_tokens) DESC",
+            TOTALS_COLUMNS
+        ))?;
+        let rows = stmt
+            .query_map(rusqlite::params![platform, user_id], |row| {
+                Ok((row.get(0)?, totals_from_row(row, 1)?))
+            })?
+            .collect::<rusqlite::Result<Vec<_>>>()
+            .context("Failed to query usage by model")?;
+        Ok(rows)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scope(user_id: &str) -> UsageScope {
+        UsageScope {
+            platform: "telegram".to_string(),
+            user_id: user_id.to_string(),
+            conversation_id: Some("conv-1".to_string()),
+            task_id: None,
+        }
+    }
+
+    fn usage(prompt_tokens: u64, completion_tokens: u64, cost: Option<f64>) -> Usage {
+        Usage {
+            prompt_tokens,
+            completion_tokens,
+            cost,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_usage_today_sums_only_that_user() {
+        let memory = MemoryStore::open_in_memory().unwrap();
+        memory
+            .record_usage(&scope("1"), "a", &usage(100, 20, Some(0.01)))
+            .await
+            .unwrap();
+        memory
+            .record_usage(&scope("1"), "b", &usage(50, 5, None))
+            .await
+            .unwrap();
+        memory
+            .record_usage(&scope("2"), "a", &usage(999, 999, Some(9.0)))
+            .await
+            .unwrap();
+
+        let today = memory.usage_today("telegram", "1").await.unwrap();
+        assert_eq!(today.calls, 2);
+        assert_eq!(today.total_tokens(), 175);
+        assert!((today.cost - 0.01).abs() < 1e-9);
+    }
+
+    #[tokio::test]
+    async fn test_usage_breakdowns() {
+        let memory = MemoryStore::open_in_memory().unwrap();
+        memory
+            .record_usage(&scope("1"), "small", &usage(10, 10, None))
+            .await
+            .unwrap();
+        memory
+            .record_usage(&scope("1"), "large", &usage(300, 100, Some(0.2)))
+            .await
+            .unwrap();
+        {
+            // Backdate one call to last year
+            let conn = memory.connection();
+            let conn = conn.lock().await;
+            conn.execute(
+                "UPDATE llm_usage SET created_at = datetime('now', '-1 year') WHERE model = 'small'",
+                [],
+            )
+            .unwrap();
+        }
+
+        let daily = memory
+            .usage_by_period("telegram", "1", UsagePeriod::Day, 30)
+            .await
+            .unwrap();
+        assert_eq!(daily.len(), 2);
+        assert_eq!(daily[0].1.total_tokens(), 400);
+        assert_eq!(daily[1].1.total_tokens(), 20);
+
+        let monthly = memory
+            .usage_by_period("telegram", "1", UsagePeriod::Month, 1)
+            .await
+            .unwrap();
+        assert_eq!(monthly.len(), 1);
+        assert_eq!(monthly[0].0, chrono::Utc::now().format("%Y-%m").to_string());
+
+        let by_model = memory
+            .usage_by_model_this_month("telegram", "1")
+            .await
+            .unwrap();
+        assert_eq!(by_model.len(), 1);
+        assert_eq!(by_model[0].0, "large");
+    }
+
+    #[test]
+    fn test_totals_exceed_budget() {
+        let totals = UsageTotals {
+            calls: 3,
+            prompt_tokens: 800,
+            completion_tokens: 200,
+            cost: 0.05,
+        };
+        assert!(!totals.exceeds(&DailyBudget::default()));
+        assert!(totals.exceeds(&DailyBudget {
+            daily_token_budget: Some(1000),
+            daily_cost_budget: None,
+        }));
+        assert!(!totals.exceeds(&DailyBudget {
+            daily_token_budget: Some(1001),
+            daily_cost_budget: Some(0.10),
+        }));
+        assert!(totals.exceeds(&DailyBudget {
+            daily_token_budget: None,
+            daily_cost_budget: Some(0.05),
+        }));
+    }
+}
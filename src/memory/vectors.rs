@@ -0,0 +1,419 @@
+//! Vectors of messages and knowledge: the embedding cache, which keeps the
+//! same text from being embedded twice, and the queue that embeds saved
+//! messages in the background instead of making `save_message` wait.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use anyhow::{Context, Result};
+use rusqlite::{Connection, OptionalExtension};
+use sha2::{Digest, Sha256};
+use tokio::sync::{mpsc, Mutex};
+use tracing::{debug, warn};
+
+use super::MemoryStore;
+use crate::memory::conversations::f32_slice_to_bytes;
+use crate::memory::embeddings::EmbeddingEngine;
+
+/// Most texts embedded together by the queue and the backfill
+pub(crate) const EMBED_BATCH_SIZE: usize = 32;
+
+/// Rows that have a vector
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum VectorTarget {
+    Message,
+    Knowledge,
+}
+
+impl VectorTarget {
+    /// The vec0 table, the content table and the SQL expression of the text
+    /// that is embedded (as `save_message` and `remember` embed it)
+    pub(crate) fn tables(self) -> (&'static str, &'static str, &'static str) {
+        match self {
+            Self::Message => ("message_embeddings", "messages", "content"),
+            Self::Knowledge => ("knowledge_embeddings", "knowledge", "key || ': ' || value"),
+        }
+    }
+}
+
+/// A saved message waiting for its vector
+pub(crate) struct PendingEmbedding {
+    rowid: i64,
+    text: String,
+}
+
+pub(crate) type EmbeddingQueue = mpsc::UnboundedSender<PendingEmbedding>;
+
+impl MemoryStore {
+    /// Vectors of `texts`, in order. Cached ones are reused; the others are
+    /// embedded in one batch and cached.
+    pub async fn embed_texts(&self, texts: &[String]) -> Result<Vec<Vec<f32>>> {
+        embed_with_cache(&self.conn, &self.embeddings, texts).await
+    }
+
+    /// Vector of one text through the cache, or None if embeddings are not
+    /// available or fail
+    pub async fn try_embed(&self, text: &str) -> Option<Vec<f32>> {
+        if !self.embeddings.is_available() {
+            return None;
+        }
+        match self.embed_texts(&[text.to_string()]).await {
+            Ok(mut vectors) => vectors.pop(),
+            Err(e) => {
+                warn!("Embedding generation failed: {:#}", e);
+                None
+            }
+        }
+    }
+
+    /// Embed a saved message in the background. The queue's worker is started
+    /// with the first message.
+    pub(crate) fn queue_embedding(&self, rowid: i64, text: &str) {
+        if !self.embeddings.is_available() {
+            return;
+        }
+        let queue = self.embedding_queue.get_or_init(|| {
+            let (queue, pending) = mpsc::unbounded_channel();
+            tokio::spawn(run_queue(
+                Arc::clone(&self.conn),
+                Arc::clone(&self.embeddings),
+                pending,
+            ));
+            queue
+        });
+        let pending = PendingEmbedding {
+            rowid,
+            text: text.to_string(),
+        };
+        if queue.send(pending).is_err() {
+            warn!(
+                "Embedding queue stopped; message {} is left to the backfill",
+                rowid
+            );
+        }
+    }
+}
+
+/// Embed queued messages until the store is dropped. Messages queued while a
+/// request is running go out together in the next one. Failed ones are left
+/// without a vector for the maintenance backfill.
+async fn run_queue(
+    conn: Arc<Mutex<Connection>>,
+    embeddings: Arc<EmbeddingEngine>,
+    mut pending: mpsc::UnboundedReceiver<PendingEmbedding>,
+) {
+    while let Some(first) = pending.recv().await {
+        let mut batch = vec![first];
+        while batch.len() < EMBED_BATCH_SIZE {
+            match pending.try_recv() {
+                Ok(next) => batch.push(next),
+                Err(_) => break,
+            }
+        }
+        let texts: Vec<String> = batch.iter().map(|p| p.text.clone()).collect();
+        let vectors = match embed_with_cache(&conn, &embeddings, &texts).await {
+            Ok(vectors) => vectors,
+            Err(e) => {
+                warn!("Embedding {} saved message(s) failed: {:#}", batch.len(), e);
+                continue;
+            }
+        };
+        let conn = conn.lock().await;
+        for (message, vector) in batch.iter().zip(vectors) {
+            if let Err(e) = store_vector(
+                &conn,
+                VectorTarget::Message,
+                message.rowid,
+                &message.text,
+                &vector,
+            ) {
+                warn!(
+                    "Failed to store vector of message {}: {:#}",
+                    message.rowid, e
+                );
+            }
+        }
+        debug!("Embedded {} saved message(s)", batch.len());
+    }
+}
+
+/// See `MemoryStore::embed_texts`
+async fn embed_with_cache(
+    conn: &Mutex<Connection>,
+    embeddings: &EmbeddingEngine,
+    texts: &[String],
+) -> Result<Vec<Vec<f32>>> {
+    let hashes: Vec<String> = texts.iter().map(|text| content_hash(text)).collect();
+    let mut vectors: HashMap<&str, Vec<f32>> = HashMap::new();
+    {
+        let conn = conn.lock().await;
+        for hash in &hashes {
+            if let Some(vector) = cached_vector(&conn, hash, embeddings.dimensions())? {
+                vectors.insert(hash, vector);
+            }
+        }
+    }
+
+    // Each uncached text is embedded once, however often it appears
+    let mut missing: Vec<(&str, String)> = Vec::new();
+    for (hash, text) in hashes.iter().zip(texts) {
+        if !vectors.contains_key(hash.as_str()) && !missing.iter().any(|(h, _)| h == hash) {
+            missing.push((hash, text.clone()));
+        }
+    }
+    if !missing.is_empty() {
+        let inputs: Vec<String> = missing.iter().map(|(_, text)| text.clone()).collect();
+        let embedded = embeddings.embed_batch(&inputs).await?;
+        let conn = conn.lock().await;
+        for ((hash, _), vector) in missing.into_iter().zip(embedded) {
+            conn.execute(
+                "INSERT OR REPLACE INTO embedding_cache (hash, embedding) VALUES (?1, ?2)",
+                rusqlite::params![hash, f32_slice_to_bytes(&vector)],
+            )
+            .context("Failed to cache embedding")?;
+            vectors.insert(hash, vector);
+        }
+    }
+    Ok(hashes
+        .iter()
+        .map(|hash| vectors.get(hash.as_str()).cloned().unwrap_or_default())
+        .collect())
+}
+
+/// SHA-256 of `text`, the key of the embedding cache
+pub(crate) fn content_hash(text: &str) -> String {
+    format!("{:x}", Sha256::digest(text.as_bytes()))
+}
+
+/// The cached vector for `hash`, marked as used. Vectors of another size
+/// (left by a previous model) are ignored.
+fn cached_vector(conn: &Connection, hash: &str, dims: usize) -> Result<Option<Vec<f32>>> {
+    let blob: Option<Vec<u8>> = conn
+        .query_row(
+            "SELECT embedding FROM embedding_cache WHERE hash = ?1",
+            [hash],
+            |row| row.get(0),
+        )
+        .optional()
+        .context("Failed to read the embedding cache")?;
+    let Some(blob) = blob.filter(|blob| blob.len() == dims * 4) else {
+        return Ok(None);
+    };
+    conn.execute(
+        "UPDATE embedding_cache SET used_at = datetime('now') WHERE hash = ?1",
+        [hash],
+    )?;
+    Ok(Some(
+        blob.chunks_exact(4)
+            .map(|bytes| f32::from_ne_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+            .collect(),
+    ))
+}
+
+/// Store the vector of a row unless the row already has one, or changed or
+/// went away since `text` was read from it. Returns the number of vectors stored.
+pub(crate) fn store_vector(
+    conn: &Connection,
+    target: VectorTarget,
+    rowid: i64,
+    text: &str,
+    vector: &[f32],
+) -> Result<usize> {
+    let (vectors, rows, current_text) = target.tables();
+    let stored = conn
+        .execute(
+            &format!(
+                "INSERT INTO {vectors} (rowid, embedding)
+                 SELECT ?1, ?2
+                 WHERE EXISTS (SELECT 1 FROM {rows} WHERE rowid = ?1 AND {current_text} = ?3)
+                   AND NOT EXISTS (SELECT 1 FROM {vectors} WHERE rowid = ?1)"
+            ),
+            rusqlite::params![rowid, f32_slice_to_bytes(vector), text],
+        )
+        .context("Failed to store embedding")?;
+    if stored > 0 {
+        conn.execute(
+            "DELETE FROM embedding_failures WHERE content_table = ?1 AND row_id = ?2",
+            rusqlite::params![rows, rowid],
+        )
+        .context("Failed to store embedding")?;
+    }
+    Ok(stored)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::llm::ChatMessage;
+    use crate::memory::embeddings::EmbeddingConfig;
+    use crate::memory::knowledge::KnowledgeScope;
+    use crate::memory::KeywordMatch;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    /// An OpenAI-style embeddings endpoint that answers in reverse order and
+    /// counts the texts it embeds. The vector of a text is [length, 1, 0, 0];
+    /// requests with a text containing "REJECT" fail.
+    async fn embedding_server(embedded: Arc<AtomicUsize>) -> EmbeddingConfig {
+        let app = axum::Router::new().route(
+            "/embeddings",
+            axum::routing::post(move |axum::Json(body): axum::Json<serde_json::Value>| {
+                let embedded = Arc::clone(&embedded);
+                async move {
+                    let inputs = body["input"].as_array().cloned().unwrap_or_default();
+                    if inputs
+                        .iter()
+                        .any(|text| text.as_str().unwrap_or_default().contains("REJECT"))
+                    {
+                        return Err(axum::http::StatusCode::BAD_REQUEST);
+                    }
+                    embedded.fetch_add(inputs.len(), Ordering::SeqCst);
+                    let data: Vec<_> = inputs
+                        .iter()
+                        .enumerate()
+                        .rev()
+                        .map(|(index, text)| {
+                            let length = text.as_str().unwrap_or_default().len() as f32;
+                            serde_json::json!({"index": index, "embedding": [length, 1.0, 0.0, 0.0]})
+                        })
+                        .collect();
+                    Ok(axum::Json(serde_json::json!({ "data": data })))
+                }
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        EmbeddingConfig {
+            api_key: "test".to_string(),
+            base_url: format!("http://{}", address),
+            model: "test-embedding".to_string(),
+            dimensions: 4,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_embed_texts_batches_and_caches() {
+        let embedded = Arc::new(AtomicUsize::new(0));
+        let config = embedding_server(Arc::clone(&embedded)).await;
+        let memory = MemoryStore::open_in_memory_with(EmbeddingEngine::new(Some(config))).unwrap();
+
+        let texts = ["a", "bbb", "a"].map(String::from);
+        let vectors = memory.embed_texts(&texts).await.unwrap();
+        let lengths: Vec<f32> = vectors.iter().map(|v| v[0]).collect();
+        assert_eq!(lengths, [1.0, 3.0, 1.0]);
+        assert_eq!(embedded.load(Ordering::SeqCst), 2);
+
+        let vectors = memory
+            .embed_texts(&["bbb".to_string(), "cc".to_string()])
+            .await
+            .unwrap();
+        assert_eq!(vectors[0], [3.0, 1.0, 0.0, 0.0]);
+        assert_eq!(embedded.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn test_saved_messages_are_embedded_in_the_background() {
+        let embedded = Arc::new(AtomicUsize::new(0));
+        let config = embedding_server(Arc::clone(&embedded)).await;
+        let memory = MemoryStore::open_in_memory_with(EmbeddingEngine::new(Some(config))).unwrap();
+        let conversation = memory
+            .get_or_create_conversation("telegram", "1")
+            .await
+            .unwrap();
+        for text in ["hello there", "hello there", "general kenobi"] {
+            let message = ChatMessage::user(text);
+            memory.save_message(&conversation, &message).await.unwrap();
+        }
+
+        let mut vectors = 0;
+        for _ in 0..100 {
+            let conn = memory.connection();
+            let conn = conn.lock().await;
+            vectors = conn
+                .query_row("SELECT COUNT(*) FROM message_embeddings", [], |row| {
+                    row.get::<_, i64>(0)
+                })
+                .unwrap();
+            if vectors == 3 {
+                break;
+            }
+            drop(conn);
+            tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+        }
+        assert_eq!(vectors, 3);
+        // The repeated message was embedded once
+        assert_eq!(embedded.load(Ordering::SeqCst), 2);
+        assert_eq!(memory.missing_embeddings().await.unwrap(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_backfill_skips_texts_the_api_rejects() {
+        let embedded = Arc::new(AtomicUsize::new(0));
+        let config = embedding_server(Arc::clone(&embedded)).await;
+        let memory = MemoryStore::open_in_memory_with(EmbeddingEngine::new(Some(config))).unwrap();
+        let scope = KnowledgeScope::user("telegram", "1");
+        for (key, value) in [("a", "fine"), ("b", "REJECT this"), ("c", "also fine")] {
+            memory
+                .remember(scope, "test", key, value, None)
+                .await
+                .unwrap();
+        }
+        assert_eq!(memory.missing_embeddings().await.unwrap(), 1);
+
+        // The rejected text is recorded and no longer counted or retried
+        assert_eq!(memory.backfill_embeddings(10).await.unwrap(), 0);
+        assert_eq!(memory.missing_embeddings().await.unwrap(), 0);
+        let calls = embedded.load(Ordering::SeqCst);
+        assert_eq!(memory.backfill_embeddings(10).await.unwrap(), 0);
+        assert_eq!(embedded.load(Ordering::SeqCst), calls);
+
+        // It is tried again after the cool-down; a new value embeds as usual
+        {
+            let conn = memory.connection();
+            let conn = conn.lock().await;
+            conn.execute(
+                "UPDATE embedding_failures SET failed_at = datetime('now', '-8 days')",
+                [],
+            )
+            .unwrap();
+        }
+        assert_eq!(memory.missing_embeddings().await.unwrap(), 1);
+        memory
+            .remember(scope, "test", "b", "accepted now", None)
+            .await
+            .unwrap();
+        assert_eq!(memory.missing_embeddings().await.unwrap(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_hybrid_search_with_local_model() {
+        let dir = crate::memory::local_embeddings::tests::write_test_model();
+        let memory =
+            MemoryStore::open_in_memory_with(EmbeddingEngine::local(dir.path()).unwrap()).unwrap();
+        assert_eq!(memory.embeddings.dimensions(), 3);
+        let scope = KnowledgeScope::user("telegram", "1");
+        memory
+            .remember(scope, "pet", "dog", "Rex the dog", None)
+            .await
+            .unwrap();
+        memory
+            .remember(scope, "drink", "morning", "black coffee", None)
+            .await
+            .unwrap();
+
+        // No keyword matches "café"; the vectors find the coffee
+        let found = memory.search_knowledge(scope, "café", 1).await.unwrap();
+        assert_eq!(found[0].key, "morning");
+        assert_eq!(memory.missing_embeddings().await.unwrap(), 0);
+
+        // Relevance is the cosine similarity: the coffee alone clears the
+        // default auto-recall threshold of 0.6, the unrelated dog does not
+        let scored = memory
+            .search_knowledge_scored(scope, "Café?", KeywordMatch::AnyWord, 2)
+            .await
+            .unwrap();
+        let relevance = |key: &str| scored.iter().find(|(e, _)| e.key == key).unwrap().1;
+        assert!(relevance("morning") > 0.95, "{:?}", scored);
+        assert!(relevance("dog") < 0.1, "{:?}", scored);
+    }
+}
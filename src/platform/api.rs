@@ -0,0 +1,599 @@
+//! Local chat API for scripts and tools that cannot go through a chat app.
+//!
+//! - `POST /v1/chat/completions` accepts an OpenAI-style request. The agent keeps
+//!   the conversation history itself, so only the last user message is used.
+//!   With `"stream": true` the reply is sent as server-sent events.
+//! - `GET /v1/ws` upgrades to a WebSocket carrying JSON frames (see `ClientFrame`
+//!   and `ServerFrame`), including tool approval prompts and scheduled task replies.
+//!
+//! Requests authenticate with `Authorization: Bearer <token>` (WebSocket clients
+//! may pass `?token=` instead); each token maps to a `user_id` on platform "api".
+
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::sync::Arc;
+
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use axum::extract::ws::{Message as WsMessage, WebSocket, WebSocketUpgrade};
+use axum::extract::{Query, State};
+use axum::http::{header, HeaderMap, StatusCode};
+use axum::response::sse::{Event, KeepAlive, Sse};
+use axum::response::{IntoResponse, Response};
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use futures::{SinkExt, StreamExt};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use tokio::sync::mpsc;
+use tracing::{error, info, warn};
+
+use crate::agent::{Agent, AgentEvent, ApprovalRequest};
+use crate::config::{ApiConfig, ApiToken};
+use crate::platform::{IncomingMessage, MessageSink};
+
+const PLATFORM: &str = "api";
+
+/// Open WebSocket connections by user, used to push agent-initiated messages
+#[derive(Clone, Default)]
+pub struct ApiClients(
+    Arc<std::sync::Mutex<HashMap<String, Vec<mpsc::UnboundedSender<ServerFrame>>>>>,
+);
+
+impl ApiClients {
+    fn add(&self, user_id: &str, tx: mpsc::UnboundedSender<ServerFrame>) {
+        self.0
+            .lock()
+            .unwrap()
+            .entry(user_id.to_string())
+            .or_default()
+            .push(tx);
+    }
+
+    fn remove(&self, user_id: &str, tx: &mpsc::UnboundedSender<ServerFrame>) {
+        let mut clients = self.0.lock().unwrap();
+        if let Some(senders) = clients.get_mut(user_id) {
+            senders.retain(|s| !s.same_channel(tx));
+            if senders.is_empty() {
+                clients.remove(user_id);
+            }
+        }
+    }
+}
+
+/// Scheduled task replies reach API users through their open WebSockets
+#[async_trait]
+impl MessageSink for ApiClients {
+    async fn send_message(
+        &self,
+        chat_id: &str,
+        _thread_id: Option<&str>,
+        text: &str,
+    ) -> Result<()> {
+        let mut clients = self.0.lock().unwrap();
+        let senders = clients.get_mut(chat_id);
+        let mut delivered = false;
+        if let Some(senders) = senders {
+            senders.retain(|tx| {
+                let ok = tx
+                    .send(ServerFrame::Message {
+                        text: text.to_string(),
+                    })
+                    .is_ok();
+                delivered |= ok;
+                ok
+            });
+        }
+        if !delivered {
+            anyhow::bail!("No WebSocket client connected for API user '{}'", chat_id);
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone)]
+struct ApiState {
+    agent: Arc<Agent>,
+    tokens: Arc<Vec<ApiToken>>,
+    clients: ApiClients,
+}
+
+/// Run the HTTP/WebSocket API server
+pub async fn run(agent: Arc<Agent>, config: ApiConfig, clients: ApiClients) -> Result<()> {
+    if config.tokens.is_empty() {
+        anyhow::bail!("[api] is configured without any tokens");
+    }
+    let state = ApiState {
+        agent,
+        tokens: Arc::new(config.tokens),
+        clients,
+    };
+    let app = Router::new()
+        .route("/v1/chat/completions", post(chat_completions))
+        .route("/v1/ws", get(websocket))
+        .with_state(state);
+
+    let listener = tokio::net::TcpListener::bind(&config.listen)
+        .await
+        .with_context(|| format!("Failed to bind API server to {}", config.listen))?;
+    info!("Chat API listening on http://{}", config.listen);
+    axum::serve(listener, app)
+        .await
+        .context("API server failed")?;
+    Ok(())
+}
+
+/// An error response in the OpenAI format
+struct ApiError {
+    status: StatusCode,
+    message: String,
+}
+
+impl ApiError {
+    fn new(status: StatusCode, message: impl Into<String>) -> Self {
+        Self {
+            status,
+            message: message.into(),
+        }
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        let kind = if self.status == StatusCode::UNAUTHORIZED {
+            "authentication_error"
+        } else if self.status.is_client_error() {
+            "invalid_request_error"
+        } else {
+            "server_error"
+        };
+        let body = json!({ "error": { "message": self.message, "type": kind } });
+        (self.status, Json(body)).into_response()
+    }
+}
+
+/// The user a bearer token (from the header, else the query) belongs to
+fn authenticate(
+    tokens: &[ApiToken],
+    headers: &HeaderMap,
+    query_token: Option<&str>,
+) -> Result<String, ApiError> {
+    let presented = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+        .map(str::trim)
+        .or(query_token)
+        .ok_or_else(|| ApiError::new(StatusCode::UNAUTHORIZED, "Missing bearer token"))?;
+    tokens
+        .iter()
+        .find(|t| constant_time_eq(t.token.as_bytes(), presented.as_bytes()))
+        .map(|t| t.user_id.clone())
+        .ok_or_else(|| ApiError::new(StatusCode::UNAUTHORIZED, "Invalid bearer token"))
+}
+
+/// Compare secrets without short-circuiting on the first differing byte
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+fn incoming(user_id: &str, text: String) -> IncomingMessage {
+    IncomingMessage {
+        platform: PLATFORM.to_string(),
+        user_id: user_id.to_string(),
+        chat_id: user_id.to_string(),
+        user_name: user_id.to_string(),
+        text,
+        attachments: Vec::new(),
+        is_group: false,
+        thread_id: None,
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct CompletionRequest {
+    #[serde(default)]
+    messages: Vec<CompletionMessage>,
+    #[serde(default)]
+    stream: bool,
+}
+
+#[derive(Debug, Deserialize)]
+struct CompletionMessage {
+    role: String,
+    /// A string, or an array of content parts of which the text parts are used
+    #[serde(default)]
+    content: Value,
+}
+
+/// Text of the last user message in the request
+fn prompt_from(messages: &[CompletionMessage]) -> Option<String> {
+    let message = messages.iter().rev().find(|m| m.role == "user")?;
+    let text = match &message.content {
+        Value::String(text) => text.clone(),
+        Value::Array(parts) => parts
+            .iter()
+            .filter(|p| p["type"] == "text")
+            .filter_map(|p| p["text"].as_str())
+            .collect::<Vec<_>>()
+            .join("\n"),
+        _ => String::new(),
+    };
+    (!text.trim().is_empty()).then_some(text)
+}
+
+async fn chat_completions(
+    State(state): State<ApiState>,
+    headers: HeaderMap,
+    Json(request): Json<CompletionRequest>,
+) -> Result<Response, ApiError> {
+    let user_id = authenticate(&state.tokens, &headers, None)?;
+    let text = prompt_from(&request.messages).ok_or_else(|| {
+        ApiError::new(StatusCode::BAD_REQUEST, "No user message with text content")
+    })?;
+    info!("API request from {}: {}", user_id, text);
+
+    let id = format!("chatcmpl-{}", uuid::Uuid::new_v4().simple());
+    let model = state.agent.llm.model().to_string();
+    let created = chrono::Utc::now().timestamp();
+    let incoming = incoming(&user_id, text);
+
+    if !request.stream {
+        let reply = state.agent.process_message(&incoming).await.map_err(|e| {
+            error!("Error processing API message: {:#}", e);
+            ApiError::new(StatusCode::INTERNAL_SERVER_ERROR, e.to_string())
+        })?;
+        let body = json!({
+            "id": id,
+            "object": "chat.completion",
+            "created": created,
+            "model": model,
+            "choices": [{
+                "index": 0,
+                "message": { "role": "assistant", "content": reply },
+                "finish_reason": "stop",
+            }],
+        });
+        return Ok(Json(body).into_response());
+    }
+
+    // Streaming: forward text deltas as `chat.completion.chunk` events.
+    // There is no way to answer approval prompts here, so they are dismissed.
+    let (out_tx, out_rx) = mpsc::unbounded_channel::<Event>();
+    let chunk = move |delta: Value, finish_reason: Option<&str>| {
+        let body = json!({
+            "id": id,
+            "object": "chat.completion.chunk",
+            "created": created,
+            "model": model,
+            "choices": [{ "index": 0, "delta": delta, "finish_reason": finish_reason }],
+        });
+        Event::default().data(body.to_string())
+    };
+    tokio::spawn(async move {
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let forward = {
+            let out_tx = out_tx.clone();
+            let chunk = chunk.clone();
+            tokio::spawn(async move {
+                while let Some(event) = rx.recv().await {
+                    if let AgentEvent::TextDelta(text) = event {
+                        let _ = out_tx.send(chunk(json!({ "content": text }), None));
+                    }
+                }
+            })
+        };
+        let result = state
+            .agent
+            .process_message_with_events(&incoming, Some(&tx), true)
+            .await;
+        drop(tx);
+        forward.await.ok();
+        match result {
+            Ok(_) => {
+                let _ = out_tx.send(chunk(json!({}), Some("stop")));
+            }
+            Err(e) => {
+                error!("Error processing API message: {:#}", e);
+                let error =
+                    json!({ "error": { "message": e.to_string(), "type": "server_error" } });
+                let _ = out_tx.send(Event::default().data(error.to_string()));
+            }
+        }
+        let _ = out_tx.send(Event::default().data("[DONE]"));
+    });
+
+    let stream = futures::stream::unfold(out_rx, |mut rx| async move {
+        rx.recv()
+            .await
+            .map(|event| (Ok::<_, Infallible>(event), rx))
+    });
+    Ok(Sse::new(stream)
+        .keep_alive(KeepAlive::default())
+        .into_response())
+}
+
+/// Frames sent by WebSocket clients. Plain (non-JSON) text is treated as a message.
+#[derive(Debug, Deserialize, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum ClientFrame {
+    Message {
+        text: String,
+    },
+    /// Answer to an `approval_request`
+    Approval {
+        id: String,
+        approved: bool,
+    },
+}
+
+impl ClientFrame {
+    fn parse(raw: &str) -> Self {
+        serde_json::from_str(raw).unwrap_or_else(|_| ClientFrame::Message {
+            text: raw.to_string(),
+        })
+    }
+}
+
+/// Frames sent to WebSocket clients
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ServerFrame {
+    /// Streamed reply text
+    Delta {
+        text: String,
+    },
+    ToolCall {
+        name: String,
+        arguments: String,
+    },
+    ToolResult {
+        name: String,
+        output: String,
+    },
+    ApprovalRequest {
+        id: String,
+        tool: String,
+        arguments: String,
+    },
+    /// The complete reply to the last message
+    Response {
+        text: String,
+    },
+    /// A message the agent sent on its own (e.g. a scheduled task)
+    Message {
+        text: String,
+    },
+    Error {
+        message: String,
+    },
+}
+
+#[derive(Debug, Deserialize)]
+struct WsQuery {
+    token: Option<String>,
+}
+
+async fn websocket(
+    State(state): State<ApiState>,
+    headers: HeaderMap,
+    Query(query): Query<WsQuery>,
+    ws: WebSocketUpgrade,
+) -> Result<Response, ApiError> {
+    let user_id = authenticate(&state.tokens, &headers, query.token.as_deref())?;
+    Ok(ws.on_upgrade(move |socket| handle_socket(socket, state, user_id)))
+}
+
+type PendingApprovals = Arc<std::sync::Mutex<HashMap<String, ApprovalRequest>>>;
+
+async fn handle_socket(socket: WebSocket, state: ApiState, user_id: String) {
+    info!("API WebSocket connected: {}", user_id);
+    let (mut sink, mut stream) = socket.split();
+    let (out_tx, mut out_rx) = mpsc::unbounded_channel::<ServerFrame>();
+    state.clients.add(&user_id, out_tx.clone());
+
+    let writer = tokio::spawn(async move {
+        while let Some(frame) = out_rx.recv().await {
+            let text = serde_json::to_string(&frame).unwrap_or_default();
+            if sink.send(WsMessage::Text(text.into())).await.is_err() {
+                break;
+            }
+        }
+    });
+
+    let pending: PendingApprovals = Arc::default();
+    let mut running: Option<tokio::task::JoinHandle<()>> = None;
+    while let Some(Ok(message)) = stream.next().await {
+        let raw = match message {
+            WsMessage::Text(text) => text.to_string(),
+            WsMessage::Close(_) => break,
+            _ => continue,
+        };
+        match ClientFrame::parse(&raw) {
+            ClientFrame::Message { text } => {
+                if running.as_ref().is_some_and(|task| !task.is_finished()) {
+                    let _ = out_tx.send(ServerFrame::Error {
+                        message: "Still working on the previous message".to_string(),
+                    });
+                    continue;
+                }
+                info!("API WebSocket message from {}: {}", user_id, text);
+                running = Some(tokio::spawn(respond(
+                    Arc::clone(&state.agent),
+                    incoming(&user_id, text),
+                    out_tx.clone(),
+                    Arc::clone(&pending),
+                )));
+            }
+            ClientFrame::Approval { id, approved } => match pending.lock().unwrap().remove(&id) {
+                Some(request) => request.respond(approved),
+                None => warn!("Approval for unknown request {}", id),
+            },
+        }
+    }
+
+    info!("API WebSocket disconnected: {}", user_id);
+    state.clients.remove(&user_id, &out_tx);
+    // A message still being processed finishes (and is saved) but its reply is dropped
+    drop(out_tx);
+    writer.abort();
+}
+
+/// Process one WebSocket message, streaming progress frames to the client
+async fn respond(
+    agent: Arc<Agent>,
+    incoming: IncomingMessage,
+    out: mpsc::UnboundedSender<ServerFrame>,
+    pending: PendingApprovals,
+) {
+    let (tx, mut rx) = mpsc::unbounded_channel();
+    let forward = {
+        let out = out.clone();
+        let pending = Arc::clone(&pending);
+        tokio::spawn(async move {
+            while let Some(event) = rx.recv().await {
+                let frame = match event {
+                    AgentEvent::TextDelta(text) => ServerFrame::Delta { text },
+                    AgentEvent::ToolCall { name, arguments } => {
+                        ServerFrame::ToolCall { name, arguments }
+                    }
+                    AgentEvent::ToolResult { name, output } => {
+                        ServerFrame::ToolResult { name, output }
+                    }
+                    AgentEvent::ApprovalRequest(request) => {
+                        let frame = ServerFrame::ApprovalRequest {
+                            id: request.id.clone(),
+                            tool: request.tool.clone(),
+                            arguments: request.arguments.clone(),
+                        };
+                        pending.lock().unwrap().insert(request.id.clone(), request);
+                        frame
+                    }
+                };
+                let _ = out.send(frame);
+            }
+        })
+    };
+    let result = agent
+        .process_message_with_events(&incoming, Some(&tx), true)
+        .await;
+    drop(tx);
+    forward.await.ok();
+    pending.lock().unwrap().clear();
+
+    let frame = match result {
+        Ok(text) => ServerFrame::Response { text },
+        Err(e) => {
+            error!("Error processing API message: {:#}", e);
+            ServerFrame::Error {
+                message: e.to_string(),
+            }
+        }
+    };
+    let _ = out.send(frame);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tokens() -> Vec<ApiToken> {
+        vec![ApiToken {
+            token: "secret-1".to_string(),
+            user_id: "scripts".to_string(),
+        }]
+    }
+
+    #[test]
+    fn test_authenticate_maps_token_to_user() {
+        let mut headers = HeaderMap::new();
+        assert!(authenticate(&tokens(), &headers, None).is_err());
+        assert_eq!(
+            authenticate(&tokens(), &headers, Some("secret-1")).ok(),
+            Some("scripts".to_string())
+        );
+
+        headers.insert(header::AUTHORIZATION, "Bearer secret-2".parse().unwrap());
+        let err = authenticate(&tokens(), &headers, None).unwrap_err();
+        assert_eq!(err.status, StatusCode::UNAUTHORIZED);
+
+        headers.insert(header::AUTHORIZATION, "Bearer secret-1".parse().unwrap());
+        assert_eq!(
+            authenticate(&tokens(), &headers, None).ok(),
+            Some("scripts".to_string())
+        );
+    }
+
+    #[test]
+    fn test_prompt_is_last_user_message() {
+        let request: CompletionRequest = serde_json::from_value(json!({
+            "model": "ignored",
+            "messages": [
+                { "role": "system", "content": "be brief" },
+                { "role": "user", "content": "first" },
+                { "role": "assistant", "content": "ok" },
+                { "role": "user", "content": [
+                    { "type": "text", "text": "what's" },
+                    { "type": "image_url", "image_url": { "url": "data:," } },
+                    { "type": "text", "text": "new?" }
+                ] }
+            ]
+        }))
+        .unwrap();
+        assert_eq!(
+            prompt_from(&request.messages).as_deref(),
+            Some("what's\nnew?")
+        );
+        assert!(!request.stream);
+        assert_eq!(prompt_from(&request.messages[..1]), None);
+    }
+
+    #[test]
+    fn test_websocket_frames() {
+        assert_eq!(
+            ClientFrame::parse(r#"{"type":"approval","id":"a1","approved":true}"#),
+            ClientFrame::Approval {
+                id: "a1".to_string(),
+                approved: true
+            }
+        );
+        assert_eq!(
+            ClientFrame::parse("hello there"),
+            ClientFrame::Message {
+                text: "hello there".to_string()
+            }
+        );
+        let frame = serde_json::to_value(ServerFrame::ToolCall {
+            name: "read_file".to_string(),
+            arguments: "{}".to_string(),
+        })
+        .unwrap();
+        assert_eq!(
+            frame,
+            json!({ "type": "tool_call", "name": "read_file", "arguments": "{}" })
+        );
+    }
+
+    #[tokio::test]
+    async fn test_sink_pushes_to_connected_clients() {
+        let clients = ApiClients::default();
+        assert!(clients.send_message("scripts", None, "hi").await.is_err());
+
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        clients.add("scripts", tx.clone());
+        clients
+            .send_message("scripts", None, "reminder")
+            .await
+            .unwrap();
+        assert!(matches!(
+            rx.recv().await,
+            Some(ServerFrame::Message { text }) if text == "reminder"
+        ));
+
+        clients.remove("scripts", &tx);
+        assert!(clients
+            .send_message("scripts", None, "again")
+            .await
+            .is_err());
+    }
+}
@@ -0,0 +1,216 @@
+use std::io::Write;
+use std::sync::Arc;
+
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use tokio::io::{AsyncBufReadExt, BufReader, Lines, Stdin};
+use tracing::error;
+
+use crate::agent::{Agent, AgentEvent, ApprovalRequest};
+use crate::platform::{
+    export_command, handle_command, IncomingMessage, MessageSink, COMMANDS_HELP,
+};
+
+/// Chat id of the terminal session; scheduled tasks created from it reply here
+const CHAT_ID: &str = "cli";
+
+/// Tool results are shown truncated to this many lines / characters
+const PREVIEW_LINES: usize = 10;
+const PREVIEW_CHARS: usize = 800;
+
+type StdinLines = Lines<BufReader<Stdin>>;
+
+/// Prints agent-initiated messages (e.g. scheduled task replies) to the terminal
+pub struct CliSink;
+
+#[async_trait]
+impl MessageSink for CliSink {
+    async fn send_message(
+        &self,
+        _chat_id: &str,
+        _thread_id: Option<&str>,
+        text: &str,
+    ) -> Result<()> {
+        println!("\n[scheduled] {}", text);
+        prompt();
+        Ok(())
+    }
+}
+
+/// Run an interactive chat on stdin/stdout as the local user
+pub async fn run(agent: Arc<Agent>) -> Result<()> {
+    let user_id = std::env::var("USER").unwrap_or_else(|_| "local".to_string());
+    let mut lines = BufReader::new(tokio::io::stdin()).lines();
+
+    println!(
+        "RustFox chat as '{}'. Type /quit to leave.\n\n{}\n",
+        user_id, COMMANDS_HELP
+    );
+    loop {
+        prompt();
+        let Some(line) = lines.next_line().await.context("Failed to read stdin")? else {
+            println!();
+            break;
+        };
+        let text = line.trim();
+        if text.is_empty() {
+            continue;
+        }
+        if matches!(text, "/quit" | "/exit") {
+            break;
+        }
+        let incoming = IncomingMessage {
+            platform: "cli".to_string(),
+            user_id: user_id.clone(),
+            chat_id: CHAT_ID.to_string(),
+            user_name: user_id.clone(),
+            text: text.to_string(),
+            attachments: Vec::new(),
+            is_group: false,
+            thread_id: None,
+        };
+        if let Some(export) = export_command(&agent, &incoming).await {
+            match export.and_then(|(file_name, content)| {
+                std::fs::write(&file_name, content)
+                    .with_context(|| format!("Failed to write {}", file_name))?;
+                Ok(file_name)
+            }) {
+                Ok(file_name) => println!("Conversation exported to {}", file_name),
+                Err(e) => println!("Error: {:#}", e),
+            }
+            continue;
+        }
+        if let Some(reply) = handle_command(&agent, &incoming).await {
+            println!("{}", reply.trim_end());
+            continue;
+        }
+        respond(&agent, &incoming, &mut lines).await?;
+    }
+    Ok(())
+}
+
+fn prompt() {
+    print!("> ");
+    std::io::stdout().flush().ok();
+}
+
+/// Process one message, printing streamed text and tool activity as it happens
+async fn respond(agent: &Agent, incoming: &IncomingMessage, lines: &mut StdinLines) -> Result<()> {
+    let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+    let work = agent.process_message_with_events(incoming, Some(&tx), true);
+    tokio::pin!(work);
+
+    // Whether the reply text has been printed as it streamed in
+    let mut streamed = false;
+    let result = loop {
+        tokio::select! {
+            result = &mut work => break result,
+            Some(event) = rx.recv() => match event {
+                AgentEvent::ApprovalRequest(request) => ask_approval(request, lines).await?,
+                event => render(event, &mut streamed),
+            },
+        }
+    };
+    // Events sent just before the loop finished
+    while let Ok(event) = rx.try_recv() {
+        if let AgentEvent::ApprovalRequest(request) = event {
+            request.respond(false);
+        } else {
+            render(event, &mut streamed);
+        }
+    }
+
+    match result {
+        Ok(_) if streamed => println!(),
+        Ok(response) => println!("{}", response.trim_end()),
+        Err(e) => {
+            error!("Error processing message: {:#}", e);
+            println!("Error: {}", e);
+        }
+    }
+    Ok(())
+}
+
+fn render(event: AgentEvent, streamed: &mut bool) {
+    match event {
+        AgentEvent::TextDelta(delta) => {
+            print!("{}", delta);
+            *streamed = true;
+        }
+        AgentEvent::ToolCall { name, arguments } => {
+            // Text streamed so far was a preamble to the tool call
+            if *streamed {
+                println!();
+                *streamed = false;
+            }
+            println!("⚙ {} {}", name, arguments);
+        }
+        AgentEvent::ToolResult { name, output } => {
+            println!(
+                "  ↳ {}: {}",
+                name,
+                preview(&output, PREVIEW_LINES, PREVIEW_CHARS)
+            );
+        }
+        AgentEvent::ApprovalRequest(_) => {}
+    }
+    std::io::stdout().flush().ok();
+}
+
+/// Ask on the terminal whether a tool call may run. End of input counts as a denial.
+async fn ask_approval(request: ApprovalRequest, lines: &mut StdinLines) -> Result<()> {
+    println!(
+        "Approve tool call?\nTool: {}\nArguments:\n{}",
+        request.tool, request.arguments
+    );
+    print!("[y/N] ");
+    std::io::stdout().flush().ok();
+    let answer = lines.next_line().await.context("Failed to read stdin")?;
+    let approved = answer.is_some_and(|a| matches!(a.trim(), "y" | "Y" | "yes"));
+    request.respond(approved);
+    Ok(())
+}
+
+/// The start of a tool result, at most `max_lines` lines and `max_chars` characters
+fn preview(output: &str, max_lines: usize, max_chars: usize) -> String {
+    let joined = output
+        .lines()
+        .take(max_lines)
+        .collect::<Vec<_>>()
+        .join("\n    ");
+    let mut shown: String = joined.chars().take(max_chars).collect();
+    if output.lines().count() > max_lines || joined.chars().count() > max_chars {
+        shown.push_str(&format!(
+            "\n    … (truncated, {} chars in total)",
+            output.chars().count()
+        ));
+    }
+    shown
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_preview_truncates_long_output() {
+        assert_eq!(preview("ok", 10, 800), "ok");
+        assert_eq!(preview("a\nb", 10, 800), "a\n    b");
+
+        let many = (1..=20)
+            .map(|i| i.to_string())
+            .collect::<Vec<_>>()
+            .join("\n");
+        let shown = preview(&many, 3, 800);
+        assert_eq!(
+            shown,
+            "1\n    2\n    3\n    … (truncated, 50 chars in total)"
+        );
+
+        let wide = "x".repeat(50);
+        assert_eq!(
+            preview(&wide, 10, 10),
+            "xxxxxxxxxx\n    … (truncated, 50 chars in total)"
+        );
+    }
+}
@@ -0,0 +1,307 @@
+use std::sync::{Arc, OnceLock};
+
+use anyhow::{Context as _, Result};
+use async_trait::async_trait;
+use serenity::all::{
+    ChannelId, Client, Command, CommandInteraction, CommandOptionType, Context, CreateAttachment,
+    CreateCommand, CreateCommandOption, CreateInteractionResponse,
+    CreateInteractionResponseFollowup, CreateInteractionResponseMessage, EventHandler,
+    GatewayIntents, Http, Interaction, Message, Ready, UserId,
+};
+use tracing::{error, info, warn};
+
+use crate::agent::Agent;
+use crate::config::DiscordConfig;
+use crate::platform::{
+    export_command, handle_command, split_message, IncomingMessage, MessageSink,
+};
+
+/// Discord rejects messages longer than 2000 characters
+const MESSAGE_LIMIT: usize = 2000;
+
+/// Sends agent-initiated messages (e.g. scheduled task replies) to Discord channels
+pub struct DiscordSink {
+    http: Arc<Http>,
+}
+
+impl DiscordSink {
+    pub fn new(config: &DiscordConfig) -> Self {
+        Self {
+            http: Arc::new(Http::new(&config.bot_token)),
+        }
+    }
+}
+
+#[async_trait]
+impl MessageSink for DiscordSink {
+    async fn send_message(
+        &self,
+        chat_id: &str,
+        _thread_id: Option<&str>,
+        text: &str,
+    ) -> Result<()> {
+        let channel: u64 = chat_id
+            .parse()
+            .with_context(|| format!("Invalid Discord channel id '{}'", chat_id))?;
+        for chunk in split_message(text, MESSAGE_LIMIT) {
+            if chunk.trim().is_empty() {
+                continue;
+            }
+            ChannelId::new(channel)
+                .say(&self.http, chunk)
+                .await
+                .context("Failed to send Discord message")?;
+        }
+        Ok(())
+    }
+}
+
+struct Handler {
+    agent: Arc<Agent>,
+    allowed_user_ids: Vec<u64>,
+    /// Our own user ID, known once the gateway is ready; used to strip mentions
+    bot_id: OnceLock<UserId>,
+}
+
+/// Run the Discord bot platform
+pub async fn run(agent: Arc<Agent>, config: DiscordConfig) -> Result<()> {
+    info!("Starting Discord platform...");
+
+    // MESSAGE_CONTENT is a privileged intent; enable it in the developer portal
+    let intents = GatewayIntents::GUILD_MESSAGES
+        | GatewayIntents::DIRECT_MESSAGES
+        | GatewayIntents::MESSAGE_CONTENT;
+    let handler = Handler {
+        agent,
+        allowed_user_ids: config.allowed_user_ids,
+        bot_id: OnceLock::new(),
+    };
+    let mut client = Client::builder(&config.bot_token, intents)
+        .event_handler(handler)
+        .await
+        .context("Failed to create Discord client")?;
+    client
+        .start()
+        .await
+        .context("Discord gateway connection failed")?;
+    Ok(())
+}
+
+impl Handler {
+    fn is_allowed(&self, user_id: UserId) -> bool {
+        self.allowed_user_ids.contains(&user_id.get())
+    }
+
+    /// Whether `msg` mentions the bot or replies to one of its messages
+    fn is_addressed(&self, msg: &Message) -> bool {
+        let Some(&bot_id) = self.bot_id.get() else {
+            return false;
+        };
+        msg.mentions.iter().any(|user| user.id == bot_id)
+            || msg
+                .referenced_message
+                .as_ref()
+                .is_some_and(|reply| reply.author.id == bot_id)
+    }
+
+    /// Message text with mentions of the bot removed
+    fn strip_mention(&self, content: &str) -> String {
+        match self.bot_id.get() {
+            Some(id) => content
+                .replace(&format!("<@{}>", id), "")
+                .replace(&format!("<@!{}>", id), "")
+                .trim()
+                .to_string(),
+            None => content.trim().to_string(),
+        }
+    }
+
+    /// Answer a slash command, splitting long replies into follow-ups. Replies are
+    /// only shown to the user who ran the command, as they can list remembered
+    /// facts, conversation titles and spend.
+    async fn respond(&self, ctx: &Context, command: &CommandInteraction, text: String) {
+        let mut chunks = split_message(&text, MESSAGE_LIMIT).into_iter();
+        let first = chunks.next().unwrap_or_default();
+        let response = CreateInteractionResponse::Message(
+            CreateInteractionResponseMessage::new()
+                .content(first)
+                .ephemeral(true),
+        );
+        if let Err(e) = command.create_response(&ctx.http, response).await {
+            error!("Failed to answer Discord command: {}", e);
+            return;
+        }
+        for chunk in chunks {
+            let followup = CreateInteractionResponseFollowup::new()
+                .content(chunk)
+                .ephemeral(true);
+            if let Err(e) = command.create_followup(&ctx.http, followup).await {
+                error!("Failed to send Discord follow-up: {}", e);
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl EventHandler for Handler {
+    async fn ready(&self, ctx: Context, ready: Ready) {
+        info!("Discord connected as {}", ready.user.name);
+        let _ = self.bot_id.set(ready.user.id);
+
+        let text_option = |name: &str, description: &str, required: bool| {
+            CreateCommandOption::new(CommandOptionType::String, name, description)
+                .required(required)
+        };
+        let commands = vec![
+            CreateCommand::new("new")
+                .description("Start a new conversation")
+                .add_option(text_option("title", "Conversation title", false)),
+            CreateCommand::new("list").description("List your conversations"),
+            CreateCommand::new("switch")
+                .description("Continue another conversation")
+                .add_option(text_option(
+                    "conversation",
+                    "Number from /list or conversation id",
+                    true,
+                )),
+            CreateCommand::new("rename")
+                .description("Rename the current conversation")
+                .add_option(text_option("title", "New title", true)),
+            CreateCommand::new("export")
+                .description("Export the current conversation as a file")
+                .add_option(
+                    CreateCommandOption::new(CommandOptionType::String, "format", "File format")
+                        .add_string_choice("Markdown", "markdown")
+                        .add_string_choice("JSON", "json"),
+                ),
+            CreateCommand::new("clear")
+                .description("Archive the current conversation and start over"),
+            CreateCommand::new("tools").description("List available tools"),
+            CreateCommand::new("skills").description("List loaded skills"),
+            CreateCommand::new("memory")
+                .description("Browse, edit and delete what the bot remembers")
+                .add_option(text_option(
+                    "command",
+                    "e.g. list <category>, view <category> <n>, clear <category>",
+                    false,
+                )),
+            CreateCommand::new("usage").description("Show token usage and cost"),
+        ];
+        if let Err(e) = Command::set_global_commands(&ctx.http, commands).await {
+            error!("Failed to register Discord slash commands: {}", e);
+        }
+    }
+
+    async fn message(&self, ctx: Context, msg: Message) {
+        if msg.author.bot || !self.is_allowed(msg.author.id) {
+            return;
+        }
+        // In servers, only messages that mention the bot or reply to it are answered
+        let is_group = msg.guild_id.is_some();
+        if is_group && !self.is_addressed(&msg) {
+            return;
+        }
+        let text = self.strip_mention(&msg.content);
+        if text.is_empty() {
+            return;
+        }
+
+        let user_id = msg.author.id.get().to_string();
+        info!(
+            "Discord message from {} ({}): {}",
+            msg.author.name, user_id, text
+        );
+
+        let incoming = IncomingMessage {
+            platform: "discord".to_string(),
+            user_id,
+            chat_id: msg.channel_id.get().to_string(),
+            user_name: msg.author.name.clone(),
+            text,
+            attachments: Vec::new(),
+            // Server channels share one conversation per channel (threads are channels too)
+            is_group,
+            thread_id: None,
+        };
+
+        let typing = msg.channel_id.start_typing(&ctx.http);
+        let result = self.agent.process_message(&incoming).await;
+        typing.stop();
+
+        let reply = match result {
+            Ok(response) => response,
+            Err(e) => {
+                error!("Error processing message: {:#}", e);
+                format!("Error: {}", e)
+            }
+        };
+        for chunk in split_message(&reply, MESSAGE_LIMIT) {
+            if chunk.trim().is_empty() {
+                continue;
+            }
+            if let Err(e) = msg.channel_id.say(&ctx.http, chunk).await {
+                error!("Failed to send Discord message: {}", e);
+            }
+        }
+    }
+
+    async fn interaction_create(&self, ctx: Context, interaction: Interaction) {
+        let Interaction::Command(command) = interaction else {
+            return;
+        };
+        if !self.is_allowed(command.user.id) {
+            let response = CreateInteractionResponse::Message(
+                CreateInteractionResponseMessage::new()
+                    .content("You are not allowed to use this bot.")
+                    .ephemeral(true),
+            );
+            command.create_response(&ctx.http, response).await.ok();
+            return;
+        }
+
+        let user_id = command.user.id.get().to_string();
+        // Rebuild the command line, e.g. "/rename Trip planning"
+        let mut text = format!("/{}", command.data.name);
+        for option in &command.data.options {
+            if let Some(value) = option.value.as_str() {
+                text.push(' ');
+                text.push_str(value);
+            }
+        }
+        let incoming = IncomingMessage {
+            platform: "discord".to_string(),
+            user_id,
+            chat_id: command.channel_id.get().to_string(),
+            user_name: command.user.name.clone(),
+            text,
+            attachments: Vec::new(),
+            is_group: command.guild_id.is_some(),
+            thread_id: None,
+        };
+        if let Some(export) = export_command(&self.agent, &incoming).await {
+            match export {
+                Ok((file_name, content)) => {
+                    let file = CreateAttachment::bytes(content.into_bytes(), file_name);
+                    let response = CreateInteractionResponse::Message(
+                        CreateInteractionResponseMessage::new()
+                            .add_file(file)
+                            .ephemeral(true),
+                    );
+                    if let Err(e) = command.create_response(&ctx.http, response).await {
+                        error!("Failed to send Discord export: {}", e);
+                    }
+                }
+                Err(e) => self.respond(&ctx, &command, format!("Error: {}", e)).await,
+            }
+            return;
+        }
+        let reply = match handle_command(&self.agent, &incoming).await {
+            Some(reply) => reply,
+            None => {
+                warn!("Unknown Discord command: {}", command.data.name);
+                format!("Unknown command: /{}", command.data.name)
+            }
+        };
+        self.respond(&ctx, &command, reply).await;
+    }
+}
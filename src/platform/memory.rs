@@ -0,0 +1,478 @@
+//! The `/memory` command: browse and curate what the bot has learned
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+
+use crate::agent::Agent;
+use crate::memory::knowledge::{KnowledgeEntry, KnowledgeScope};
+
+const USAGE: &str = "Usage:\n\
+     /memory - List knowledge categories\n\
+     /memory list <category> - List the entries in a category\n\
+     /memory view <category> <number or key> - Show an entry in full\n\
+     /memory edit <category> <number or key> = <new value> - Change an entry\n\
+     /memory delete <category> <number or key> - Delete an entry\n\
+     /memory clear <category|all> - Delete a whole category, or everything (asks for confirmation)\n\
+     /memory legacy [claim] - (admins) Review, or take over, knowledge set aside when upgrading\n\
+     (shared entries are only changed by admins, one at a time)";
+
+/// Values longer than this are shortened in `/memory list`
+const LIST_VALUE_CHARS: usize = 80;
+
+/// How long the code given by `/memory clear` stays valid
+const CLEAR_CONFIRMATION_TTL: Duration = Duration::from_secs(300);
+
+/// `/memory clear` requests waiting for their confirmation, by knowledge owner
+#[derive(Default)]
+pub struct PendingMemoryClears(Mutex<HashMap<(String, String), PendingClear>>);
+
+struct PendingClear {
+    target: String,
+    code: String,
+    expires: Instant,
+}
+
+impl PendingMemoryClears {
+    /// Issue a new confirmation code for clearing `target`, replacing any
+    /// earlier one of the owner
+    fn issue(&self, scope: KnowledgeScope<'_>, target: &str) -> String {
+        let code = format!("{:04}", fastrand::u32(0..10_000));
+        self.0.lock().unwrap().insert(
+            owner(scope),
+            PendingClear {
+                target: target.to_string(),
+                code: code.clone(),
+                expires: Instant::now() + CLEAR_CONFIRMATION_TTL,
+            },
+        );
+        code
+    }
+
+    /// Whether `code` is the live code issued for clearing `target`. A
+    /// pending clear is used up by any confirmation attempt.
+    fn confirm(&self, scope: KnowledgeScope<'_>, target: &str, code: &str) -> bool {
+        let pending = self.0.lock().unwrap().remove(&owner(scope));
+        pending.is_some_and(|pending| {
+            pending.target == target && pending.code == code && pending.expires > Instant::now()
+        })
+    }
+}
+
+fn owner(scope: KnowledgeScope<'_>) -> (String, String) {
+    (scope.platform.to_string(), scope.user_id.to_string())
+}
+
+/// Reply to `/memory <args>` from `user_id` for the knowledge of `scope`
+pub(super) async fn memory_command(
+    agent: &Agent,
+    scope: KnowledgeScope<'_>,
+    user_id: &str,
+    args: &str,
+) -> Result<String> {
+    let (action, rest) = split_word(args);
+    match action {
+        "" => categories(agent, scope).await,
+        "list" => list(agent, scope, rest).await,
+        "view" | "edit" | "delete" => {
+            let (category, rest) = split_word(rest);
+            let (selector, value) = match action {
+                "edit" => match rest.split_once('=') {
+                    Some((selector, value)) => (selector.trim(), Some(value.trim())),
+                    None => return Ok(USAGE.to_string()),
+                },
+                _ => (rest, None),
+            };
+            if category.is_empty() || selector.is_empty() {
+                return Ok(USAGE.to_string());
+            }
+            let Some(entry) = find_entry(agent, scope, category, selector).await? else {
+                return Ok(format!(
+                    "No entry '{}' in '{}'. See /memory list {}.",
+                    selector, category, category
+                ));
+            };
+            if action != "view" && entry.shared && !agent.config.memory.is_admin(user_id) {
+                return Ok("Shared entries can only be changed by an admin.".to_string());
+            }
+            match (action, value) {
+                ("view", _) => Ok(view(&entry)),
+                ("edit", Some(value)) => edit(agent, entry.scope(scope), &entry, value).await,
+                _ => delete(agent, entry.scope(scope), &entry).await,
+            }
+        }
+        "clear" => clear(agent, scope, rest).await,
+        "legacy" if agent.config.memory.is_admin(user_id) => legacy(agent, scope, rest).await,
+        "legacy" => Ok("Only an admin can review set-aside knowledge.".to_string()),
+        "help" => Ok(USAGE.to_string()),
+        // `/memory <category>` is a shortcut for `/memory list <category>`
+        _ => list(agent, scope, args).await,
+    }
+}
+
+/// First whitespace-separated word of `text` and the trimmed rest
+fn split_word(text: &str) -> (&str, &str) {
+    let text = text.trim();
+    let (word, rest) = text.split_once(char::is_whitespace).unwrap_or((text, ""));
+    (word, rest.trim())
+}
+
+async fn categories(agent: &Agent, scope: KnowledgeScope<'_>) -> Result<String> {
+    let categories = agent.memory.knowledge_categories(scope).await?;
+    if categories.is_empty() {
+        return Ok("I haven't remembered anything yet.".to_string());
+    }
+    let mut reply = String::from("Knowledge categories:\n\n");
+    for (category, count) in &categories {
+        reply.push_str(&format!("  - {} ({} entries)\n", category, count));
+    }
+    reply.push_str("\nUse /memory list <category> to see the entries.");
+    Ok(reply)
+}
+
+async fn list(agent: &Agent, scope: KnowledgeScope<'_>, category: &str) -> Result<String> {
+    if category.is_empty() {
+        return Ok(USAGE.to_string());
+    }
+    let entries = agent.memory.list_knowledge(scope, category).await?;
+    if entries.is_empty() {
+        return Ok(format!("Nothing remembered in '{}'.", category));
+    }
+    let mut reply = format!("Knowledge in '{}':\n\n", category);
+    for (number, entry) in entries.iter().enumerate() {
+        reply.push_str(&format!(
+            "{}. {} = {}{}\n",
+            number + 1,
+            entry.key,
+            shorten(&entry.value, LIST_VALUE_CHARS),
+            if entry.shared { " (shared)" } else { "" }
+        ));
+    }
+    reply.push_str(&format!(
+        "\nUse /memory view {} <number> to see an entry in full.",
+        category
+    ));
+    Ok(reply)
+}
+
+/// An entry of `category` by its number in `/memory list` or its exact key
+async fn find_entry(
+    agent: &Agent,
+    scope: KnowledgeScope<'_>,
+    category: &str,
+    selector: &str,
+) -> Result<Option<KnowledgeEntry>> {
+    if let Some(index) = selector
+        .parse::<usize>()
+        .ok()
+        .and_then(|n| n.checked_sub(1))
+    {
+        let entries = agent.memory.list_knowledge(scope, category).await?;
+        if let Some(entry) = entries.into_iter().nth(index) {
+            return Ok(Some(entry));
+        }
+    }
+    agent
+        .memory
+        .knowledge_entry(scope, category, selector)
+        .await
+}
+
+fn view(entry: &KnowledgeEntry) -> String {
+    let mut reply = format!("[{}] {}\n\n{}", entry.category, entry.key, entry.value);
+    if let Some(source) = &entry.source {
+        reply.push_str(&format!("\n\nSource: {}", source));
+    }
+    if entry.shared {
+        reply.push_str("\n\nShared with all users.");
+    }
+    reply
+}
+
+/// Change `entry`, which is stored in `scope`
+async fn edit(
+    agent: &Agent,
+    scope: KnowledgeScope<'_>,
+    entry: &KnowledgeEntry,
+    value: &str,
+) -> Result<String> {
+    if value.is_empty() {
+        return Ok(format!(
+            "The new value is empty. To remove the entry use /memory delete {} {}",
+            entry.category, entry.key
+        ));
+    }
+    agent
+        .memory
+        .remember(
+            scope,
+            &entry.category,
+            &entry.key,
+            value,
+            entry.source.as_deref(),
+        )
+        .await?;
+    Ok(format!(
+        "Updated [{}] {} = {}",
+        entry.category, entry.key, value
+    ))
+}
+
+/// Delete `entry`, which is stored in `scope`
+async fn delete(
+    agent: &Agent,
+    scope: KnowledgeScope<'_>,
+    entry: &KnowledgeEntry,
+) -> Result<String> {
+    agent
+        .memory
+        .forget(scope, &entry.category, &entry.key)
+        .await?;
+    Ok(format!("Forgot [{}] {}", entry.category, entry.key))
+}
+
+/// `/memory clear <category|all> [confirm <code>]`: without a confirmation,
+/// only say what would be deleted and issue the code that confirms it. Shared
+/// entries are left alone.
+async fn clear(agent: &Agent, scope: KnowledgeScope<'_>, args: &str) -> Result<String> {
+    let (target, code) = match args.rsplit_once(" confirm ") {
+        Some((target, code)) => (target.trim(), Some(code.trim())),
+        None => (args.trim_end_matches(" confirm"), None),
+    };
+    if target.is_empty() || target == "confirm" {
+        return Ok(USAGE.to_string());
+    }
+    let category = (target != "all").then_some(target);
+
+    let Some(code) = code else {
+        let mut count = 0;
+        let mut categories = 0;
+        for (name, _) in agent.memory.knowledge_categories(scope).await? {
+            if category.is_none_or(|c| c == name) {
+                let entries = agent.memory.list_knowledge(scope, &name).await?;
+                let own = entries.iter().filter(|entry| !entry.shared).count();
+                count += own;
+                categories += usize::from(own > 0);
+            }
+        }
+        if count == 0 {
+            return Ok("Nothing to delete.".to_string());
+        }
+        let what = match category {
+            Some(category) => format!("all {} entries in '{}'", count, category),
+            None => format!(
+                "everything I remember ({} entries in {} categories)",
+                count, categories
+            ),
+        };
+        let code = agent.memory_clears.issue(scope, target);
+        return Ok(format!(
+            "This deletes {}. Send /memory clear {} confirm {} within {} minutes to go ahead.",
+            what,
+            target,
+            code,
+            CLEAR_CONFIRMATION_TTL.as_secs() / 60
+        ));
+    };
+
+    if !agent.memory_clears.confirm(scope, target, code) {
+        return Ok(format!(
+            "That confirmation is not valid or has expired. Send /memory clear {} to get a new one.",
+            target
+        ));
+    }
+    let deleted = agent.memory.forget_all(scope, category).await?;
+    Ok(format!("Deleted {} entries.", deleted))
+}
+
+/// `/memory legacy [claim]`: list the knowledge set aside when upgrading a
+/// database with several users, or move it into the admin's own memory
+async fn legacy(agent: &Agent, scope: KnowledgeScope<'_>, args: &str) -> Result<String> {
+    let quarantine = KnowledgeScope::QUARANTINE;
+    if args == "claim" {
+        let moved = agent.memory.move_knowledge(quarantine, scope).await?;
+        let mut reply = format!("Moved {} entries into your memory.", moved);
+        if !set_aside(agent).await?.is_empty() {
+            reply.push_str(" Entries whose key you already use were left; see /memory legacy.");
+        }
+        return Ok(reply);
+    }
+    let entries = set_aside(agent).await?;
+    if entries.is_empty() {
+        return Ok("No knowledge is set aside.".to_string());
+    }
+    let mut reply = format!(
+        "{} entries from before per-user memory are set aside:\n\n",
+        entries.len()
+    );
+    for entry in &entries {
+        reply.push_str(&format!(
+            "  - [{}] {} = {}\n",
+            entry.category,
+            entry.key,
+            shorten(&entry.value, LIST_VALUE_CHARS)
+        ));
+    }
+    reply.push_str("\nUse /memory legacy claim to move them into your own memory.");
+    Ok(reply)
+}
+
+/// The entries in `KnowledgeScope::QUARANTINE`
+async fn set_aside(agent: &Agent) -> Result<Vec<KnowledgeEntry>> {
+    let quarantine = KnowledgeScope::QUARANTINE;
+    let mut entries = Vec::new();
+    for (category, _) in agent.memory.knowledge_categories(quarantine).await? {
+        let listed = agent.memory.list_knowledge(quarantine, &category).await?;
+        entries.extend(listed.into_iter().filter(|entry| !entry.shared));
+    }
+    Ok(entries)
+}
+
+/// `text` cut to `max_chars` characters, with an ellipsis if anything was cut
+fn shorten(text: &str, max_chars: usize) -> String {
+    let single_line = text.replace('\n', " ");
+    if single_line.chars().count() <= max_chars {
+        return single_line;
+    }
+    let mut short: String = single_line.chars().take(max_chars).collect();
+    short.push('…');
+    short
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::agent::tests::test_agent;
+
+    /// The code in a `/memory clear` warning
+    fn confirmation_code(warning: &str) -> String {
+        warning
+            .split(" confirm ")
+            .nth(1)
+            .and_then(|rest| rest.split_whitespace().next())
+            .unwrap_or_else(|| panic!("No confirmation code in {}", warning))
+            .to_string()
+    }
+
+    #[tokio::test]
+    async fn test_memory_command_confirms_bulk_delete() {
+        let (agent, _mock, _sandbox) = test_agent(vec![]).await;
+        let scope = KnowledgeScope::user("telegram", "1");
+        for (key, value) in [("favourite", "ramen"), ("allergy", "peanuts")] {
+            agent
+                .memory
+                .remember(scope, "food", key, value, None)
+                .await
+                .unwrap();
+        }
+        let command = |args: &'static str| memory_command(&agent, scope, "1", args);
+        let entries = || agent.memory.list_knowledge(scope, "food");
+
+        let list = command("list food").await.unwrap();
+        assert!(list.contains("1. allergy = peanuts"), "{}", list);
+        assert!(command("view food 2").await.unwrap().contains("ramen"));
+        assert_eq!(
+            command("edit food allergy = shellfish").await.unwrap(),
+            "Updated [food] allergy = shellfish"
+        );
+
+        // Confirming without the issued code deletes nothing
+        for args in [
+            "clear food confirm",
+            "clear all confirm",
+            "clear food confirm 0",
+        ] {
+            let reply = command(args).await.unwrap();
+            assert!(!reply.starts_with("Deleted"), "{}", reply);
+        }
+        assert_eq!(entries().await.unwrap().len(), 2);
+
+        let warning = command("clear food").await.unwrap();
+        let code = confirmation_code(&warning);
+        assert_eq!(entries().await.unwrap().len(), 2);
+
+        // The code only confirms what it was issued for, and only once
+        let other = memory_command(&agent, scope, "1", &format!("clear all confirm {}", code))
+            .await
+            .unwrap();
+        assert!(other.contains("not valid"), "{}", other);
+        let warning = command("clear food").await.unwrap();
+        let code = confirmation_code(&warning);
+        let confirm = format!("clear food confirm {}", code);
+        assert_eq!(
+            memory_command(&agent, scope, "1", &confirm).await.unwrap(),
+            "Deleted 2 entries."
+        );
+        assert!(entries().await.unwrap().is_empty());
+        assert!(memory_command(&agent, scope, "1", &confirm)
+            .await
+            .unwrap()
+            .contains("not valid"));
+    }
+
+    #[tokio::test]
+    async fn test_shared_entries_are_changed_by_admins_only() {
+        let (mut agent, _mock, _sandbox) = test_agent(vec![]).await;
+        agent
+            .memory
+            .remember(KnowledgeScope::SHARED, "office", "wifi", "guest-net", None)
+            .await
+            .unwrap();
+        let scope = KnowledgeScope::user("telegram", "1");
+
+        for args in ["edit office wifi = evil-net", "delete office wifi"] {
+            let reply = memory_command(&agent, scope, "1", args).await.unwrap();
+            assert_eq!(reply, "Shared entries can only be changed by an admin.");
+        }
+        let view = memory_command(&agent, scope, "1", "view office wifi").await;
+        assert!(view.unwrap().contains("guest-net"));
+
+        agent.config.memory.admin_user_ids = vec!["1".to_string()];
+        let reply = memory_command(&agent, scope, "1", "delete office wifi").await;
+        assert_eq!(reply.unwrap(), "Forgot [office] wifi");
+    }
+
+    #[tokio::test]
+    async fn test_admin_claims_set_aside_knowledge() {
+        let (mut agent, _mock, _sandbox) = test_agent(vec![]).await;
+        let quarantine = KnowledgeScope::QUARANTINE;
+        for (key, value) in [("name", "Rex"), ("vet", "Dr. Lee")] {
+            agent
+                .memory
+                .remember(quarantine, "pet", key, value, None)
+                .await
+                .unwrap();
+        }
+        let scope = KnowledgeScope::user("telegram", "1");
+        agent
+            .memory
+            .remember(scope, "pet", "vet", "Dr. Ortiz", None)
+            .await
+            .unwrap();
+        // Set-aside entries are nobody's
+        assert_eq!(
+            agent.memory.recall(scope, "pet", "name").await.unwrap(),
+            None
+        );
+        let reply = memory_command(&agent, scope, "1", "legacy").await.unwrap();
+        assert!(reply.starts_with("Only an admin"), "{}", reply);
+
+        agent.config.memory.admin_user_ids = vec!["1".to_string()];
+        let reply = memory_command(&agent, scope, "1", "legacy").await.unwrap();
+        assert!(reply.contains("[pet] name = Rex"), "{}", reply);
+        let reply = memory_command(&agent, scope, "1", "legacy claim")
+            .await
+            .unwrap();
+        assert!(reply.starts_with("Moved 1 entries"), "{}", reply);
+        assert_eq!(
+            agent.memory.recall(scope, "pet", "name").await.unwrap(),
+            Some("Rex".to_string())
+        );
+        assert_eq!(
+            agent.memory.recall(scope, "pet", "vet").await.unwrap(),
+            Some("Dr. Ortiz".to_string())
+        );
+    }
+}
@@ -1,8 +1,61 @@
+pub mod api;
+pub mod cli;
+pub mod discord;
+mod memory;
 pub mod telegram;
 
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use anyhow::Result;
+use async_trait::async_trait;
+
+use tracing::error;
+
+use crate::agent::Agent;
+use crate::memory::export::ExportFormat;
+use crate::memory::knowledge::KnowledgeScope;
+
+pub use memory::PendingMemoryClears;
+
+/// Outbound side of a platform: delivers messages the agent produces on its own,
+/// such as scheduled task replies, to a chat identified by `IncomingMessage.chat_id`.
+#[async_trait]
+pub trait MessageSink: Send + Sync {
+    /// Send `text` to `chat_id` (in thread or forum topic `thread_id`, where the
+    /// platform has them), split as the platform's size limit requires
+    async fn send_message(&self, chat_id: &str, thread_id: Option<&str>, text: &str) -> Result<()>;
+}
+
+/// Message sinks of the running platforms, keyed by `IncomingMessage.platform`
+#[derive(Clone, Default)]
+pub struct Platforms {
+    sinks: HashMap<String, Arc<dyn MessageSink>>,
+}
+
+impl Platforms {
+    pub fn register(&mut self, platform: &str, sink: Arc<dyn MessageSink>) {
+        self.sinks.insert(platform.to_string(), sink);
+    }
+
+    /// Deliver `text` to a chat (or one of its threads) on `platform`
+    pub async fn send_message(
+        &self,
+        platform: &str,
+        chat_id: &str,
+        thread_id: Option<&str>,
+        text: &str,
+    ) -> Result<()> {
+        let sink = self
+            .sinks
+            .get(platform)
+            .ok_or_else(|| anyhow::anyhow!("No running platform named '{}'", platform))?;
+        sink.send_message(chat_id, thread_id, text).await
+    }
+}
+
 /// A message received from any platform
 #[derive(Debug, Clone)]
-#[allow(dead_code)]
 pub struct IncomingMessage {
     /// Platform identifier (e.g., "telegram", "discord")
     pub platform: String,
@@ -12,6 +65,401 @@ pub struct IncomingMessage {
     pub chat_id: String,
     /// Display name of the user
     pub user_name: String,
-    /// The message text
+    /// The message text (or the caption of an attachment)
     pub text: String,
+    /// Files sent with the message, already downloaded into the sandbox
+    pub attachments: Vec<Attachment>,
+    /// Sent in a group chat: the conversation belongs to the chat, not the speaker
+    pub is_group: bool,
+    /// Thread or forum topic, set when group conversations are kept per thread
+    pub thread_id: Option<String>,
+}
+
+impl IncomingMessage {
+    /// Whose conversation this message belongs to: the speaker in private chats,
+    /// the chat (or one of its threads) in group chats. Used in place of `user_id`
+    /// for conversation state, while usage and permissions stay with the speaker.
+    pub fn conversation_owner(&self) -> String {
+        match (self.is_group, &self.thread_id) {
+            (false, _) => self.user_id.clone(),
+            (true, None) => format!("chat:{}", self.chat_id),
+            (true, Some(thread)) => format!("chat:{}:{}", self.chat_id, thread),
+        }
+    }
+}
+
+/// Kind of file attached to a message
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AttachmentKind {
+    Image,
+    Document,
+    /// Voice notes and audio files
+    Audio,
+}
+
+/// A file received with a message
+#[derive(Debug, Clone)]
+pub struct Attachment {
+    pub kind: AttachmentKind,
+    /// Where the file was saved (inside the sandbox)
+    pub path: std::path::PathBuf,
+    /// Original file name, if the platform provided one
+    pub file_name: Option<String>,
+    pub mime_type: Option<String>,
+}
+
+/// Split long messages to fit a platform's message size limit (in bytes),
+/// preferring to break at newlines, then spaces.
+pub fn split_message(text: &str, max_len: usize) -> Vec<String> {
+    if text.len() <= max_len {
+        return vec![text.to_string()];
+    }
+
+    let mut chunks = Vec::new();
+    let mut start = 0;
+
+    while start < text.len() {
+        let mut end = (start + max_len).min(text.len());
+        // Walk back to a valid UTF-8 char boundary so slicing doesn't panic
+        while end > start && !text.is_char_boundary(end) {
+            end -= 1;
+        }
+        let actual_end = if end < text.len() {
+            text[start..end]
+                .rfind('\n')
+                .or_else(|| text[start..end].rfind(' '))
+                .map(|pos| start + pos + 1)
+                .unwrap_or(end)
+        } else {
+            end
+        };
+
+        chunks.push(text[start..actual_end].to_string());
+        start = actual_end;
+    }
+
+    chunks
+}
+
+/// Slash commands understood on every platform
+pub const COMMANDS_HELP: &str = "Commands:\n\
+     /new [title] - Start a new conversation\n\
+     /list - List your conversations\n\
+     /switch <number or id> - Continue another conversation\n\
+     /rename <title> - Rename the current conversation\n\
+     /export [markdown|json] - Export the current conversation as a file\n\
+     /clear - Archive the current conversation and start over\n\
+     /tools - List available tools\n\
+     /skills - List loaded skills\n\
+     /memory - Browse, edit and delete what I remember\n\
+     /usage - Show token usage and cost";
+
+/// Run a slash command shared by all platforms and return its reply, or None if
+/// the message is not one of them (it is then treated as a normal message).
+/// A trailing `@botname`, as Telegram adds in groups, is ignored.
+/// Conversation commands act on the conversation of the chat in group chats.
+pub async fn handle_command(agent: &Agent, incoming: &IncomingMessage) -> Option<String> {
+    let platform = incoming.platform.as_str();
+    let owner = incoming.conversation_owner();
+    let user_id = owner.as_str();
+    let (command, args) = parse_command(&incoming.text);
+    let result = match command {
+        "/help" => Ok(COMMANDS_HELP.to_string()),
+        "/new" => new_conversation(agent, platform, user_id, args).await,
+        "/list" => conversation_list(agent, platform, user_id).await,
+        "/switch" => switch_conversation(agent, platform, user_id, args).await,
+        "/rename" => rename_conversation(agent, platform, user_id, args).await,
+        "/clear" => agent
+            .clear_conversation(platform, user_id)
+            .await
+            .map(|()| "Conversation archived. Your next message starts a new one.".to_string()),
+        "/tools" => Ok(tools_overview(agent)),
+        "/skills" => Ok(skills_overview(agent).await),
+        "/memory" => {
+            let scope = KnowledgeScope::user(platform, user_id);
+            memory::memory_command(agent, scope, &incoming.user_id, args).await
+        }
+        "/usage" => agent.usage_report(platform, &incoming.user_id).await,
+        _ => return None,
+    };
+    Some(result.unwrap_or_else(|e| {
+        error!("Command {} failed: {:#}", command, e);
+        format!("Error: {}", e)
+    }))
+}
+
+/// Split a message into a slash command (without any `@botname` suffix) and its arguments
+fn parse_command(text: &str) -> (&str, &str) {
+    let text = text.trim();
+    let (command, args) = text.split_once(char::is_whitespace).unwrap_or((text, ""));
+    let command = command.split('@').next().unwrap_or_default();
+    (command, args.trim())
+}
+
+/// The `/export [markdown|json]` command: the current conversation as a file
+/// name and content, for platforms to send as a document. None if `incoming`
+/// is not an export command.
+pub async fn export_command(
+    agent: &Agent,
+    incoming: &IncomingMessage,
+) -> Option<Result<(String, String)>> {
+    let (command, args) = parse_command(&incoming.text);
+    if command != "/export" {
+        return None;
+    }
+    Some(export_conversation(agent, incoming, args).await)
+}
+
+async fn export_conversation(
+    agent: &Agent,
+    incoming: &IncomingMessage,
+    format: &str,
+) -> Result<(String, String)> {
+    let format = match format {
+        "" => ExportFormat::Markdown,
+        name => ExportFormat::parse(name).ok_or_else(|| {
+            anyhow::anyhow!("Unknown export format '{}' (use markdown or json)", name)
+        })?,
+    };
+    let Some(conversation_id) = agent
+        .memory
+        .active_conversation(&incoming.platform, &incoming.conversation_owner())
+        .await?
+    else {
+        anyhow::bail!("There is no current conversation to export.");
+    };
+    let export = agent.memory.export_conversation(&conversation_id).await?;
+    Ok((export.file_name(format), export.render(format)?))
+}
+
+async fn new_conversation(
+    agent: &Agent,
+    platform: &str,
+    user_id: &str,
+    title: &str,
+) -> Result<String> {
+    let title = (!title.is_empty()).then_some(title);
+    agent
+        .memory
+        .new_conversation(platform, user_id, title)
+        .await?;
+    Ok(match title {
+        Some(title) => format!("Started a new conversation: {}", title),
+        None => "Started a new conversation.".to_string(),
+    })
+}
+
+/// Reply to the `/list` command
+async fn conversation_list(agent: &Agent, platform: &str, user_id: &str) -> Result<String> {
+    let conversations = agent.memory.list_conversations(platform, user_id).await?;
+    if conversations.is_empty() {
+        return Ok("No conversations yet.".to_string());
+    }
+    let mut list = String::from("Conversations:\n\n");
+    for (number, conversation) in conversations.iter().enumerate() {
+        list.push_str(&format!(
+            "{} {}. {} ({} messages, {}) [{}]\n",
+            if conversation.active { "▶" } else { " " },
+            number + 1,
+            conversation.title.as_deref().unwrap_or("Untitled"),
+            conversation.message_count,
+            conversation
+                .updated_at
+                .get(..16)
+                .unwrap_or(&conversation.updated_at),
+            short_id(&conversation.id)
+        ));
+    }
+    list.push_str("\nUse /switch <number> to continue one.");
+    Ok(list)
+}
+
+/// Switch to a conversation by its number in `/list` or a prefix of its id
+async fn switch_conversation(
+    agent: &Agent,
+    platform: &str,
+    user_id: &str,
+    selector: &str,
+) -> Result<String> {
+    if selector.is_empty() {
+        return Ok("Usage: /switch <number or id> (see /list)".to_string());
+    }
+    let conversations = agent.memory.list_conversations(platform, user_id).await?;
+    let by_number = selector
+        .parse::<usize>()
+        .ok()
+        .and_then(|n| n.checked_sub(1))
+        .and_then(|i| conversations.get(i));
+    let matches: Vec<_> = match by_number {
+        Some(conversation) => vec![conversation],
+        None => conversations
+            .iter()
+            .filter(|c| c.id.starts_with(selector))
+            .collect(),
+    };
+    let conversation = match matches.as_slice() {
+        [conversation] => conversation,
+        [] => {
+            return Ok(format!(
+                "No conversation matches '{}'. See /list.",
+                selector
+            ))
+        }
+        _ => {
+            return Ok(format!(
+                "'{}' matches several conversations. See /list.",
+                selector
+            ))
+        }
+    };
+    agent
+        .memory
+        .switch_conversation(platform, user_id, &conversation.id)
+        .await?;
+    Ok(format!(
+        "Switched to: {}",
+        conversation.title.as_deref().unwrap_or("Untitled")
+    ))
+}
+
+async fn rename_conversation(
+    agent: &Agent,
+    platform: &str,
+    user_id: &str,
+    title: &str,
+) -> Result<String> {
+    if title.is_empty() {
+        return Ok("Usage: /rename <title>".to_string());
+    }
+    let Some(conversation_id) = agent.memory.active_conversation(platform, user_id).await? else {
+        return Ok("There is no current conversation to rename.".to_string());
+    };
+    agent
+        .memory
+        .set_conversation_title(&conversation_id, title, false)
+        .await?;
+    Ok(format!("Conversation renamed to: {}", title))
+}
+
+/// First eight characters of a conversation id, enough to tell them apart
+fn short_id(id: &str) -> &str {
+    id.get(..8).unwrap_or(id)
+}
+
+/// Reply to the `/tools` command
+pub fn tools_overview(agent: &Agent) -> String {
+    let mut tool_list = String::from("Available tools:\n\n");
+    for tool in &agent.all_tool_definitions() {
+        tool_list.push_str(&format!(
+            "  - {}: {}\n",
+            tool.function.name, tool.function.description
+        ));
+    }
+    tool_list
+}
+
+/// Reply to the `/skills` command
+pub async fn skills_overview(agent: &Agent) -> String {
+    let skills_guard = agent.skills.read().await;
+    let skills = skills_guard.list();
+    if skills.is_empty() {
+        return "No skills loaded.".to_string();
+    }
+    let mut skill_list = String::from("Loaded skills:\n\n");
+    for skill in &skills {
+        skill_list.push_str(&format!("  - {}: {}\n", skill.name, skill.description));
+    }
+    skill_list
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    #[derive(Default)]
+    struct RecordingSink {
+        sent: Mutex<Vec<(String, Option<String>, String)>>,
+    }
+
+    #[async_trait]
+    impl MessageSink for RecordingSink {
+        async fn send_message(
+            &self,
+            chat_id: &str,
+            thread_id: Option<&str>,
+            text: &str,
+        ) -> Result<()> {
+            self.sent.lock().unwrap().push((
+                chat_id.to_string(),
+                thread_id.map(str::to_string),
+                text.to_string(),
+            ));
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_platforms_route_by_name() {
+        let telegram = Arc::new(RecordingSink::default());
+        let discord = Arc::new(RecordingSink::default());
+        let mut platforms = Platforms::default();
+        platforms.register("telegram", telegram.clone());
+        platforms.register("discord", discord.clone());
+
+        platforms
+            .send_message("discord", "42", Some("7"), "reminder")
+            .await
+            .unwrap();
+        assert!(telegram.sent.lock().unwrap().is_empty());
+        assert_eq!(
+            *discord.sent.lock().unwrap(),
+            vec![(
+                "42".to_string(),
+                Some("7".to_string()),
+                "reminder".to_string()
+            )]
+        );
+
+        let err = platforms
+            .send_message("matrix", "1", None, "hi")
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("matrix"));
+    }
+
+    #[test]
+    fn test_group_conversations_belong_to_the_chat() {
+        let mut incoming = IncomingMessage {
+            platform: "telegram".to_string(),
+            user_id: "7".to_string(),
+            chat_id: "-100123".to_string(),
+            user_name: "Ana".to_string(),
+            text: "hi".to_string(),
+            attachments: Vec::new(),
+            is_group: false,
+            thread_id: None,
+        };
+        assert_eq!(incoming.conversation_owner(), "7");
+
+        incoming.is_group = true;
+        assert_eq!(incoming.conversation_owner(), "chat:-100123");
+
+        incoming.thread_id = Some("42".to_string());
+        assert_eq!(incoming.conversation_owner(), "chat:-100123:42");
+    }
+
+    #[test]
+    fn test_split_message_prefers_line_breaks() {
+        assert_eq!(split_message("short", 10), vec!["short"]);
+
+        let chunks = split_message("first line\nsecond line\nthird", 15);
+        assert_eq!(chunks, vec!["first line\n", "second line\n", "third"]);
+
+        // Multi-byte characters are never cut in half
+        let text = "é".repeat(10);
+        let chunks = split_message(&text, 5);
+        assert!(chunks.iter().all(|c| c.len() <= 5));
+        assert_eq!(chunks.concat(), text);
+    }
 }
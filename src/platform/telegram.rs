@@ -1,67 +1,125 @@
+use std::collections::HashMap;
+use std::ops::Range;
+use std::path::Path;
 use std::sync::Arc;
+use std::time::Duration;
 
-use anyhow::Result;
+use anyhow::{Context, Result};
+use teloxide::net::Download;
 use teloxide::prelude::*;
+use teloxide::types::{
+    FileMeta, InlineKeyboardButton, InlineKeyboardMarkup, InputFile, Me, MessageId, ThreadId,
+    UpdateKind,
+};
 use tracing::{error, info, warn};
 
-use crate::agent::Agent;
-use crate::platform::IncomingMessage;
+use crate::agent::{Agent, AgentEvent, ApprovalRequest};
+use crate::config::{MediaConfig, TelegramConfig};
+use crate::platform::{
+    export_command, handle_command, split_message, Attachment, AttachmentKind, IncomingMessage,
+    MessageSink, COMMANDS_HELP,
+};
 
-/// Split long messages for Telegram's 4096 char limit
-fn split_message(text: &str, max_len: usize) -> Vec<String> {
-    if text.len() <= max_len {
-        return vec![text.to_string()];
-    }
+/// An approval prompt posted in a chat, waiting for a button press
+struct PendingApproval {
+    request: ApprovalRequest,
+    /// Only the user whose message triggered the tool call may decide
+    user_id: u64,
+    chat_id: ChatId,
+    message_id: MessageId,
+    text: String,
+}
 
-    let mut chunks = Vec::new();
-    let mut start = 0;
+/// Approval prompts awaiting a decision, keyed by request id
+type PendingApprovals = Arc<std::sync::Mutex<HashMap<String, PendingApproval>>>;
 
-    while start < text.len() {
-        let mut end = (start + max_len).min(text.len());
-        // Walk back to a valid UTF-8 char boundary so slicing doesn't panic
-        while end > start && !text.is_char_boundary(end) {
-            end -= 1;
-        }
-        let actual_end = if end < text.len() {
-            text[start..end]
-                .rfind('\n')
-                .or_else(|| text[start..end].rfind(' '))
-                .map(|pos| start + pos + 1)
-                .unwrap_or(end)
-        } else {
-            end
-        };
+/// What an event consumer needs to post approval prompts for one message
+#[derive(Clone)]
+struct ApprovalContext {
+    bot: Bot,
+    chat_id: ChatId,
+    /// Forum topic to post prompts in
+    thread_id: Option<ThreadId>,
+    user_id: u64,
+    pending: PendingApprovals,
+    timeout: Duration,
+}
+
+/// Sends agent-initiated messages (e.g. scheduled task replies) to Telegram chats
+pub struct TelegramSink {
+    bot: Bot,
+}
 
-        chunks.push(text[start..actual_end].to_string());
-        start = actual_end;
+impl TelegramSink {
+    pub fn new(bot: Bot) -> Self {
+        Self { bot }
     }
+}
 
-    chunks
+#[async_trait::async_trait]
+impl MessageSink for TelegramSink {
+    async fn send_message(&self, chat_id: &str, thread_id: Option<&str>, text: &str) -> Result<()> {
+        let chat_id: i64 = chat_id
+            .parse()
+            .with_context(|| format!("Invalid Telegram chat id '{}'", chat_id))?;
+        let thread = thread_id
+            .map(|thread| {
+                thread
+                    .parse()
+                    .map(|id| ThreadId(MessageId(id)))
+                    .with_context(|| format!("Invalid Telegram topic id '{}'", thread))
+            })
+            .transpose()?;
+        for chunk in split_message(text, 4000) {
+            if chunk.trim().is_empty() {
+                continue;
+            }
+            let mut request = self.bot.send_message(ChatId(chat_id), chunk);
+            if let Some(thread) = thread {
+                request = request.message_thread_id(thread);
+            }
+            request.await.context("Failed to send Telegram message")?;
+        }
+        Ok(())
+    }
 }
 
 /// Run the Telegram bot platform
-pub async fn run(
-    agent: Arc<Agent>,
-    allowed_user_ids: Vec<u64>,
-    bot: Arc<teloxide::Bot>,
-) -> Result<()> {
-    let bot = (*bot).clone();
-
+pub async fn run(agent: Arc<Agent>, config: TelegramConfig, bot: Bot) -> Result<()> {
     info!("Starting Telegram platform...");
 
-    let handler = Update::filter_message()
-        .filter_map(move |msg: Message| {
-            let user = msg.from.as_ref()?;
-            if allowed_user_ids.contains(&user.id.0) {
-                Some(msg)
-            } else {
-                None
-            }
-        })
-        .endpoint(handle_message);
+    let pending: PendingApprovals = Arc::default();
+    let me = bot.get_me().await.context("Failed to get bot info")?;
+    let allowed_user_ids = config.allowed_user_ids.clone();
+    let allowed_chat_ids = config.allowed_chat_ids.clone();
+    let config = Arc::new(config);
+
+    let handler = dptree::entry()
+        .branch(
+            Update::filter_message()
+                .filter_map(move |msg: Message| {
+                    let user = msg.from.as_ref()?;
+                    // Allowed users anywhere, plus every member of an allowed group
+                    if allowed_user_ids.contains(&user.id.0)
+                        || allowed_chat_ids.contains(&msg.chat.id.0)
+                    {
+                        Some(msg)
+                    } else {
+                        None
+                    }
+                })
+                .endpoint(handle_message),
+        )
+        .branch(Update::filter_callback_query().endpoint(handle_callback));
 
     Dispatcher::builder(bot, handler)
-        .dependencies(dptree::deps![agent])
+        .dependencies(dptree::deps![agent, config, me, pending])
+        // Updates from one chat are handled in order, but button presses must get
+        // through while that chat's message is suspended waiting for approval.
+        .distribution_function(|update| match update.kind {
+            UpdateKind::CallbackQuery(_) => None,
+            _ => update.chat().map(|chat| chat.id),
+        })
         .default_handler(|upd| async move {
             warn!("Unhandled update: {:?}", upd.id);
         })
@@ -73,106 +131,629 @@ pub async fn run(
     Ok(())
 }
 
-async fn handle_message(bot: Bot, msg: Message, agent: Arc<Agent>) -> ResponseResult<()> {
+async fn handle_message(
+    bot: Bot,
+    msg: Message,
+    agent: Arc<Agent>,
+    config: Arc<TelegramConfig>,
+    me: Me,
+    pending: PendingApprovals,
+) -> ResponseResult<()> {
     let user = match msg.from.as_ref() {
         Some(user) => user,
         None => return Ok(()),
     };
 
     let user_id = user.id.0;
-    let text = match msg.text() {
-        Some(t) => t.to_string(),
-        None => return Ok(()),
-    };
+    // Attachments carry their text as a caption
+    let mut text = msg
+        .text()
+        .or_else(|| msg.caption())
+        .unwrap_or_default()
+        .to_string();
 
     let user_name = user.first_name.clone();
 
+    // In groups, only messages that mention the bot or reply to it are answered
+    // (plus our slash commands)
+    let is_group = msg.chat.is_group() || msg.chat.is_supergroup();
+    let mut addressed = true;
+    if is_group {
+        let replies_to_bot = msg
+            .reply_to_message()
+            .and_then(|reply| reply.from.as_ref())
+            .is_some_and(|from| from.id == me.id);
+        addressed = replies_to_bot || !mention_ranges(&text, me.username()).is_empty();
+        if !addressed && !text.starts_with('/') {
+            return Ok(());
+        }
+        if is_other_bots_command(&text, me.username()) {
+            return Ok(());
+        }
+        text = strip_mentions(&text, me.username());
+    }
+
     info!(
-        "Telegram message from {} ({}): {}",
-        user_name, user_id, text
+        "Telegram message from {} ({}) in {}: {}",
+        user_name, user_id, msg.chat.id, text
     );
 
+    let thread_id = forum_topic(&msg)
+        .filter(|_| is_group && config.per_topic_conversations)
+        .map(|thread| thread.0 .0.to_string());
+    let mut incoming = IncomingMessage {
+        platform: "telegram".to_string(),
+        user_id: user_id.to_string(),
+        chat_id: msg.chat.id.0.to_string(),
+        user_name,
+        text,
+        attachments: Vec::new(),
+        is_group,
+        thread_id,
+    };
+
     // Handle commands
-    if text == "/clear" {
-        if let Err(e) = agent
-            .clear_conversation("telegram", &user_id.to_string())
-            .await
-        {
-            error!("Failed to clear conversation: {}", e);
-        }
-        bot.send_message(msg.chat.id, "Conversation cleared.")
-            .await?;
+    if incoming.text.split('@').next() == Some("/start") {
+        reply_to(
+            &bot,
+            &msg,
+            format!(
+                "Hello! I'm your AI assistant. Send me a message and I'll help you. \
+                 You can also send photos, documents and voice notes.\n\n{}",
+                COMMANDS_HELP
+            ),
+        )
+        .await?;
         return Ok(());
     }
 
-    if text == "/start" {
-        bot.send_message(
-            msg.chat.id,
-            "Hello! I'm your AI assistant. Send me a message and I'll help you.\n\n\
-             Commands:\n\
-             /clear - Clear conversation history\n\
-             /tools - List available tools\n\
-             /skills - List loaded skills",
-        )
-        .await?;
+    if let Some(export) = export_command(&agent, &incoming).await {
+        match export {
+            Ok((file_name, content)) => {
+                let file = InputFile::memory(content.into_bytes()).file_name(file_name);
+                let mut request = bot.send_document(msg.chat.id, file);
+                if let Some(thread) = forum_topic(&msg) {
+                    request = request.message_thread_id(thread);
+                }
+                request.await?;
+            }
+            Err(e) => {
+                warn!("Export failed: {:#}", e);
+                reply_to(&bot, &msg, format!("Error: {}", e)).await?;
+            }
+        }
         return Ok(());
     }
 
-    if text == "/tools" {
-        let all_tools = agent.all_tool_definitions();
-        let mut tool_list = String::from("Available tools:\n\n");
-        for tool in &all_tools {
-            tool_list.push_str(&format!(
-                "  - {}: {}\n",
-                tool.function.name, tool.function.description
-            ));
+    if let Some(reply) = handle_command(&agent, &incoming).await {
+        for chunk in split_message(&reply, 4000) {
+            reply_to(&bot, &msg, chunk).await?;
         }
-        bot.send_message(msg.chat.id, tool_list).await?;
+        return Ok(());
+    }
+    if !addressed {
+        // Another bot's command
         return Ok(());
     }
 
-    if text == "/skills" {
-        let skills_guard = agent.skills.read().await;
-        let skills = skills_guard.list();
-        if skills.is_empty() {
-            bot.send_message(msg.chat.id, "No skills loaded.").await?;
-        } else {
-            let mut skill_list = String::from("Loaded skills:\n\n");
-            for skill in &skills {
-                skill_list.push_str(&format!("  - {}: {}\n", skill.name, skill.description));
-            }
-            bot.send_message(msg.chat.id, skill_list).await?;
+    incoming.attachments = match download_attachments(
+        &bot,
+        &msg,
+        &agent.config.media,
+        &agent.config.sandbox.allowed_directory,
+    )
+    .await
+    {
+        Ok(attachments) => attachments,
+        Err(e) => {
+            error!("Failed to download attachment: {:#}", e);
+            reply_to(&bot, &msg, format!("Could not receive the file: {}", e)).await?;
+            return Ok(());
         }
+    };
+    if incoming.text.is_empty() && incoming.attachments.is_empty() {
+        // Stickers, locations and other content we don't handle
         return Ok(());
     }
 
+    let approvals = ApprovalContext {
+        bot: bot.clone(),
+        chat_id: msg.chat.id,
+        thread_id: forum_topic(&msg),
+        user_id,
+        pending,
+        timeout: Duration::from_secs(agent.config.approval.timeout_secs),
+    };
+
+    if config.streaming {
+        let interval = Duration::from_millis(config.stream_edit_interval_ms);
+        return respond_streaming(&bot, &msg, &agent, &incoming, approvals, interval).await;
+    }
+
     // Send "typing" indicator
-    bot.send_chat_action(msg.chat.id, teloxide::types::ChatAction::Typing)
-        .await
-        .ok();
+    let mut typing = bot.send_chat_action(msg.chat.id, teloxide::types::ChatAction::Typing);
+    if let Some(thread) = forum_topic(&msg) {
+        typing = typing.message_thread_id(thread);
+    }
+    typing.await.ok();
 
-    // Build platform-agnostic message
-    let incoming = IncomingMessage {
-        platform: "telegram".to_string(),
-        user_id: user_id.to_string(),
-        chat_id: msg.chat.id.0.to_string(),
-        user_name,
-        text,
-    };
+    // Only approval requests need handling when not streaming
+    let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+    let prompts = tokio::spawn(async move {
+        while let Some(event) = rx.recv().await {
+            if let AgentEvent::ApprovalRequest(request) = event {
+                tokio::spawn(prompt_approval(approvals.clone(), request));
+            }
+        }
+    });
+    let result = agent
+        .process_message_with_events(&incoming, Some(&tx), false)
+        .await;
+    drop(tx);
+    prompts.await.ok();
 
     // Process through agent
-    match agent.process_message(&incoming).await {
+    match result {
         Ok(response) => {
             for chunk in split_message(&response, 4000) {
-                bot.send_message(msg.chat.id, chunk).await.ok();
+                reply_to(&bot, &msg, chunk).await.ok();
             }
         }
         Err(e) => {
             error!("Error processing message: {:#}", e);
-            bot.send_message(msg.chat.id, format!("Error: {}", e))
+            reply_to(&bot, &msg, format!("Error: {}", e)).await?;
+        }
+    }
+
+    Ok(())
+}
+
+/// The forum topic `msg` was posted in, if any
+fn forum_topic(msg: &Message) -> Option<ThreadId> {
+    msg.thread_id.filter(|_| msg.is_topic_message)
+}
+
+/// Send `text` to the chat of `msg`, in the same forum topic
+fn reply_to(bot: &Bot, msg: &Message, text: impl Into<String>) -> <Bot as Requester>::SendMessage {
+    let request = bot.send_message(msg.chat.id, text);
+    match forum_topic(msg) {
+        Some(thread) => request.message_thread_id(thread),
+        None => request,
+    }
+}
+
+/// Byte ranges of `@username` mentions in `text` (Telegram usernames are ASCII
+/// and case-insensitive)
+fn mention_ranges(text: &str, username: &str) -> Vec<Range<usize>> {
+    let needle = format!("@{}", username.to_ascii_lowercase());
+    let haystack = text.to_ascii_lowercase();
+    let mut ranges = Vec::new();
+    let mut from = 0;
+    while let Some(pos) = haystack[from..].find(&needle) {
+        let start = from + pos;
+        let end = start + needle.len();
+        // "@rustfox_bot" must not match "@rustfox_bot2"
+        let whole_word = haystack[end..]
+            .chars()
+            .next()
+            .is_none_or(|c| !(c.is_ascii_alphanumeric() || c == '_'));
+        if whole_word {
+            ranges.push(start..end);
+        }
+        from = end;
+    }
+    ranges
+}
+
+/// Whether `text` is a `/command@name` addressed to a bot other than `username`
+fn is_other_bots_command(text: &str, username: &str) -> bool {
+    let command = text.split_whitespace().next().unwrap_or_default();
+    if !command.starts_with('/') {
+        return false;
+    }
+    command
+        .split_once('@')
+        .is_some_and(|(_, name)| !name.eq_ignore_ascii_case(username))
+}
+
+/// `text` with mentions of the bot removed
+fn strip_mentions(text: &str, username: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut last = 0;
+    for range in mention_ranges(text, username) {
+        out.push_str(&text[last..range.start]);
+        last = range.end;
+    }
+    out.push_str(&text[last..]);
+    out.trim().to_string()
+}
+
+/// A file attached to a Telegram message, before download
+struct RemoteFile<'a> {
+    kind: AttachmentKind,
+    meta: &'a FileMeta,
+    /// Name to save the file under
+    save_as: String,
+    file_name: Option<String>,
+    mime_type: Option<String>,
+}
+
+/// Download the photo, document, voice note or audio file of `msg` into
+/// `<sandbox>/<uploads_dir>/<chat id>/`. Files over `max_download_mb` are refused.
+async fn download_attachments(
+    bot: &Bot,
+    msg: &Message,
+    config: &MediaConfig,
+    sandbox_dir: &Path,
+) -> Result<Vec<Attachment>> {
+    let mut files = Vec::new();
+    if let Some(photo) = msg.photo().and_then(|sizes| sizes.last()) {
+        files.push(RemoteFile {
+            kind: AttachmentKind::Image,
+            meta: &photo.file,
+            save_as: format!("photo-{}.jpg", photo.file.unique_id.0),
+            file_name: None,
+            mime_type: Some("image/jpeg".to_string()),
+        });
+    }
+    if let Some(doc) = msg.document() {
+        let mime_type = doc.mime_type.as_ref().map(|m| m.to_string());
+        let kind = if mime_type
+            .as_deref()
+            .is_some_and(|m| m.starts_with("image/"))
+        {
+            AttachmentKind::Image
+        } else {
+            AttachmentKind::Document
+        };
+        let name = doc.file_name.as_deref().unwrap_or("document");
+        files.push(RemoteFile {
+            kind,
+            meta: &doc.file,
+            save_as: format!("{}-{}", doc.file.unique_id.0, sanitize_file_name(name)),
+            file_name: doc.file_name.clone(),
+            mime_type,
+        });
+    }
+    if let Some(voice) = msg.voice() {
+        files.push(RemoteFile {
+            kind: AttachmentKind::Audio,
+            meta: &voice.file,
+            save_as: format!("voice-{}.ogg", voice.file.unique_id.0),
+            file_name: None,
+            mime_type: Some(
+                voice
+                    .mime_type
+                    .as_ref()
+                    .map_or("audio/ogg".to_string(), |m| m.to_string()),
+            ),
+        });
+    }
+    if let Some(audio) = msg.audio() {
+        let name = audio.file_name.as_deref().unwrap_or("audio");
+        files.push(RemoteFile {
+            kind: AttachmentKind::Audio,
+            meta: &audio.file,
+            save_as: format!("{}-{}", audio.file.unique_id.0, sanitize_file_name(name)),
+            file_name: audio.file_name.clone(),
+            mime_type: audio.mime_type.as_ref().map(|m| m.to_string()),
+        });
+    }
+    if files.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let dir = sandbox_dir
+        .join(&config.uploads_dir)
+        .join(msg.chat.id.0.to_string());
+    tokio::fs::create_dir_all(&dir)
+        .await
+        .with_context(|| format!("Failed to create {}", dir.display()))?;
+
+    let limit = config.max_download_mb.saturating_mul(1024 * 1024);
+    let mut attachments = Vec::with_capacity(files.len());
+    for remote in files {
+        if u64::from(remote.meta.size) > limit {
+            anyhow::bail!(
+                "{} is too large ({:.1} MB, limit {} MB)",
+                remote.file_name.as_deref().unwrap_or("the file"),
+                f64::from(remote.meta.size) / (1024.0 * 1024.0),
+                config.max_download_mb
+            );
+        }
+        let file = bot
+            .get_file(remote.meta.id.clone())
+            .await
+            .context("Failed to look up file")?;
+        let path = dir.join(remote.save_as);
+        let mut dst = tokio::fs::File::create(&path)
+            .await
+            .with_context(|| format!("Failed to create {}", path.display()))?;
+        bot.download_file(&file.path, &mut dst)
+            .await
+            .context("Failed to download file")?;
+        info!("Saved attachment to {}", path.display());
+        attachments.push(Attachment {
+            kind: remote.kind,
+            path,
+            file_name: remote.file_name,
+            mime_type: remote.mime_type,
+        });
+    }
+    Ok(attachments)
+}
+
+/// Keep a user-supplied file name safe to use as a single path component
+fn sanitize_file_name(name: &str) -> String {
+    name.chars()
+        .map(|c| {
+            if c.is_alphanumeric() || matches!(c, '.' | '-' | '_') {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect::<String>()
+        .trim_start_matches('.')
+        .to_string()
+}
+
+/// Process a message while progressively editing a placeholder reply.
+/// The final response replaces the placeholder (which is deleted if the response
+/// is empty); overflow goes into extra messages.
+async fn respond_streaming(
+    bot: &Bot,
+    msg: &Message,
+    agent: &Agent,
+    incoming: &IncomingMessage,
+    approvals: ApprovalContext,
+    interval: Duration,
+) -> ResponseResult<()> {
+    let placeholder = reply_to(bot, msg, "…").await?;
+
+    let (tx, rx) = tokio::sync::mpsc::unbounded_channel();
+    let editor = tokio::spawn(stream_edits(
+        bot.clone(),
+        msg.chat.id,
+        placeholder.id,
+        rx,
+        interval,
+        approvals,
+    ));
+
+    let result = agent
+        .process_message_with_events(incoming, Some(&tx), true)
+        .await;
+    drop(tx);
+    editor.await.ok();
+
+    match result {
+        Ok(response) => {
+            let mut chunks = split_message(&response, 4000).into_iter();
+            match chunks.next().filter(|c| !c.trim().is_empty()) {
+                Some(first) => {
+                    bot.edit_message_text(msg.chat.id, placeholder.id, first)
+                        .await
+                        .ok();
+                }
+                // Nothing to say: don't leave "…" or a stale tool status behind
+                None => {
+                    bot.delete_message(msg.chat.id, placeholder.id).await.ok();
+                }
+            }
+            for chunk in chunks {
+                reply_to(bot, msg, chunk).await.ok();
+            }
+        }
+        Err(e) => {
+            error!("Error processing message: {:#}", e);
+            bot.edit_message_text(msg.chat.id, placeholder.id, format!("Error: {}", e))
                 .await?;
         }
     }
 
     Ok(())
 }
+
+/// Apply streamed agent events to the placeholder message, at most once per `interval`.
+async fn stream_edits(
+    bot: Bot,
+    chat_id: ChatId,
+    message_id: MessageId,
+    mut rx: tokio::sync::mpsc::UnboundedReceiver<AgentEvent>,
+    interval: Duration,
+    approvals: ApprovalContext,
+) {
+    let mut text = String::new();
+    let mut showing_tool = false;
+    let mut dirty = false;
+    let mut ticker = tokio::time::interval(interval);
+    ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+
+    loop {
+        tokio::select! {
+            event = rx.recv() => match event {
+                Some(AgentEvent::TextDelta(delta)) => {
+                    if showing_tool {
+                        text.clear();
+                        showing_tool = false;
+                    }
+                    text.push_str(&delta);
+                    dirty = true;
+                }
+                Some(AgentEvent::ToolCall { name, .. }) => {
+                    // Any streamed text was a preamble to the tool call; show progress instead.
+                    text = format!("Using tool: {}…", name);
+                    showing_tool = true;
+                    dirty = true;
+                }
+                Some(AgentEvent::ToolResult { .. }) => {}
+                Some(AgentEvent::ApprovalRequest(request)) => {
+                    tokio::spawn(prompt_approval(approvals.clone(), request));
+                }
+                None => break,
+            },
+            _ = ticker.tick() => {
+                if dirty && !text.trim().is_empty() {
+                    let preview = split_message(&text, 4000).swap_remove(0);
+                    if let Err(e) = bot.edit_message_text(chat_id, message_id, preview).await {
+                        warn!("Failed to edit streaming message: {}", e);
+                    }
+                    dirty = false;
+                }
+            }
+        }
+    }
+}
+
+/// Post an Approve/Deny keyboard for a tool call and wait for a decision.
+/// If nobody answers within the timeout the prompt is marked as expired
+/// (the agent treats the missing answer as a denial).
+async fn prompt_approval(ctx: ApprovalContext, request: ApprovalRequest) {
+    let mut arguments = request.arguments.clone();
+    if arguments.len() > 3000 {
+        let mut end = 3000;
+        while !arguments.is_char_boundary(end) {
+            end -= 1;
+        }
+        arguments.truncate(end);
+        arguments.push_str("\n…");
+    }
+    let text = format!(
+        "Approve tool call?\n\nTool: {}\nArguments:\n{}",
+        request.tool, arguments
+    );
+    let keyboard = InlineKeyboardMarkup::new([[
+        InlineKeyboardButton::callback("✅ Approve", format!("approve:{}", request.id)),
+        InlineKeyboardButton::callback("❌ Deny", format!("deny:{}", request.id)),
+    ]]);
+
+    let mut prompt = ctx
+        .bot
+        .send_message(ctx.chat_id, &text)
+        .reply_markup(keyboard);
+    if let Some(thread) = ctx.thread_id {
+        prompt = prompt.message_thread_id(thread);
+    }
+    let sent = match prompt.await {
+        Ok(sent) => sent,
+        Err(e) => {
+            error!("Failed to send approval prompt: {}", e);
+            request.respond(false);
+            return;
+        }
+    };
+
+    let id = request.id.clone();
+    ctx.pending.lock().unwrap().insert(
+        id.clone(),
+        PendingApproval {
+            request,
+            user_id: ctx.user_id,
+            chat_id: ctx.chat_id,
+            message_id: sent.id,
+            text: text.clone(),
+        },
+    );
+
+    tokio::time::sleep(ctx.timeout).await;
+    let expired = ctx.pending.lock().unwrap().remove(&id);
+    if let Some(expired) = expired {
+        expired.request.respond(false);
+        ctx.bot
+            .edit_message_text(
+                expired.chat_id,
+                expired.message_id,
+                format!("{}\n\n⏱ No answer — not run.", expired.text),
+            )
+            .await
+            .ok();
+    }
+}
+
+/// Handle Approve/Deny button presses on approval prompts
+async fn handle_callback(
+    bot: Bot,
+    query: CallbackQuery,
+    pending: PendingApprovals,
+) -> ResponseResult<()> {
+    let Some((action, id)) = query.data.as_deref().and_then(|d| d.split_once(':')) else {
+        bot.answer_callback_query(query.id.clone()).await?;
+        return Ok(());
+    };
+
+    let entry = {
+        let mut pending = pending.lock().unwrap();
+        match pending.get(id) {
+            Some(entry) if entry.user_id == query.from.id.0 => pending.remove(id).ok_or(""),
+            Some(_) => Err("Only the user who sent the request can decide."),
+            None => Err("This request has expired."),
+        }
+    };
+    let entry = match entry {
+        Ok(entry) => entry,
+        Err(reason) => {
+            bot.answer_callback_query(query.id.clone())
+                .text(reason)
+                .await?;
+            return Ok(());
+        }
+    };
+
+    let approved = action == "approve";
+    entry.request.respond(approved);
+    info!(
+        "Tool '{}' {} by user {}",
+        entry.request.tool,
+        if approved { "approved" } else { "denied" },
+        entry.user_id
+    );
+
+    bot.answer_callback_query(query.id.clone()).await?;
+    let verdict = if approved {
+        "✅ Approved"
+    } else {
+        "❌ Denied"
+    };
+    bot.edit_message_text(
+        entry.chat_id,
+        entry.message_id,
+        format!("{}\n\n{}", entry.text, verdict),
+    )
+    .await
+    .ok();
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mentions_are_found_and_stripped() {
+        assert_eq!(
+            mention_ranges("hey @RustFox_Bot, help", "rustfox_bot"),
+            vec![4..16]
+        );
+        assert!(mention_ranges("ask @rustfox_bot2", "rustfox_bot").is_empty());
+        assert!(mention_ranges("no mention here", "rustfox_bot").is_empty());
+
+        assert_eq!(
+            strip_mentions("@rustfox_bot what's on\ntoday?", "rustfox_bot"),
+            "what's on\ntoday?"
+        );
+        assert_eq!(
+            strip_mentions("/clear@rustfox_bot", "rustfox_bot"),
+            "/clear"
+        );
+
+        assert!(is_other_bots_command("/clear@otherbot", "rustfox_bot"));
+        assert!(is_other_bots_command(
+            "/memory@other_bot clear @rustfox_bot",
+            "rustfox_bot"
+        ));
+        assert!(!is_other_bots_command("/clear@RustFox_Bot", "rustfox_bot"));
+        assert!(!is_other_bots_command("/clear", "rustfox_bot"));
+        assert!(!is_other_bots_command("ask @otherbot", "rustfox_bot"));
+    }
+}
@@ -0,0 +1,326 @@
+//! Process isolation for `execute_command`.
+//!
+//! On Linux, commands run under bubblewrap (`bwrap`): a private mount namespace in
+//! which system directories are read-only, `allowed_directory` is the only writable
+//! host path, and the network can be unshared. Resource limits are applied with
+//! rlimits, and the whole process group is killed when the wall-clock limit is hit.
+
+use anyhow::{Context, Result};
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::sync::OnceLock;
+use std::time::Duration;
+use tracing::{info, warn};
+
+use crate::config::{CommandSandboxConfig, IsolationMode};
+
+/// Result of a sandboxed command
+#[derive(Debug)]
+pub struct CommandOutput {
+    pub stdout: String,
+    pub stderr: String,
+    /// None if the process was killed by a signal
+    pub exit_code: Option<i32>,
+    pub timed_out: bool,
+}
+
+/// Host paths mounted read-only inside the bubblewrap sandbox (skipped if missing).
+/// Only the parts of `/etc` commands need: the rest may hold secrets such as a
+/// deployed config file with API keys.
+const READ_ONLY_MOUNTS: &[&str] = &[
+    "/usr",
+    "/bin",
+    "/sbin",
+    "/lib",
+    "/lib64",
+    "/etc/alternatives",
+    "/etc/ca-certificates",
+    "/etc/group",
+    "/etc/hosts",
+    "/etc/ld.so.cache",
+    "/etc/localtime",
+    "/etc/nsswitch.conf",
+    "/etc/passwd",
+    "/etc/resolv.conf",
+    "/etc/ssl",
+];
+
+/// Run `command` with `sh -c` inside `sandbox_dir`, confined according to `config`.
+pub async fn run_command(
+    config: &CommandSandboxConfig,
+    sandbox_dir: &Path,
+    command: &str,
+) -> Result<CommandOutput> {
+    let sandbox_dir = sandbox_dir
+        .canonicalize()
+        .with_context(|| format!("Sandbox directory not found: {}", sandbox_dir.display()))?;
+
+    let mut cmd = match isolation_binary(config)? {
+        Some(bwrap) => {
+            let mut cmd = tokio::process::Command::new(bwrap);
+            cmd.args(bwrap_args(config, &sandbox_dir, command));
+            cmd
+        }
+        None => {
+            let mut cmd = tokio::process::Command::new("sh");
+            cmd.arg("-c").arg(command);
+            cmd
+        }
+    };
+    cmd.current_dir(&sandbox_dir)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true);
+
+    #[cfg(unix)]
+    {
+        let limits = ResourceLimits::from(config);
+        // Own process group so a timeout can kill everything the command spawned
+        cmd.process_group(0);
+        // SAFETY: the closure only calls setrlimit, which is async-signal-safe.
+        unsafe {
+            cmd.pre_exec(move || limits.apply());
+        }
+    }
+
+    let child = cmd
+        .spawn()
+        .with_context(|| format!("Failed to execute command: {}", command))?;
+    let pid = child.id();
+
+    let timeout = Duration::from_secs(config.timeout_secs);
+    match tokio::time::timeout(timeout, child.wait_with_output()).await {
+        Ok(output) => {
+            let output = output.context("Failed to collect command output")?;
+            Ok(CommandOutput {
+                stdout: String::from_utf8_lossy(&output.stdout).into_owned(),
+                stderr: String::from_utf8_lossy(&output.stderr).into_owned(),
+                exit_code: output.status.code(),
+                timed_out: false,
+            })
+        }
+        Err(_) => {
+            warn!(
+                "Command timed out after {}s, killing it: {}",
+                config.timeout_secs, command
+            );
+            #[cfg(unix)]
+            if let Some(pid) = pid {
+                // SAFETY: plain syscall; a negative pid targets the process group.
+                unsafe {
+                    libc::kill(-(pid as i32), libc::SIGKILL);
+                }
+            }
+            #[cfg(not(unix))]
+            let _ = pid;
+            Ok(CommandOutput {
+                stdout: String::new(),
+                stderr: String::new(),
+                exit_code: None,
+                timed_out: true,
+            })
+        }
+    }
+}
+
+/// The bubblewrap binary to run under, or None to run the command directly.
+fn isolation_binary(config: &CommandSandboxConfig) -> Result<Option<&'static Path>> {
+    let bwrap = bwrap_path();
+    let use_bwrap = match config.isolation {
+        IsolationMode::None => false,
+        IsolationMode::Bubblewrap => {
+            if bwrap.is_none() {
+                anyhow::bail!(
+                    "Sandbox isolation is set to \"bubblewrap\" but bwrap is not installed"
+                );
+            }
+            true
+        }
+        IsolationMode::Auto => {
+            if bwrap.is_none() {
+                static WARNED: OnceLock<()> = OnceLock::new();
+                WARNED.get_or_init(|| {
+                    warn!("bwrap not found; execute_command runs without filesystem isolation")
+                });
+            }
+            bwrap.is_some()
+        }
+    };
+    if !use_bwrap && !config.allow_network {
+        anyhow::bail!("Denying network access to commands requires bubblewrap (bwrap)");
+    }
+    Ok(if use_bwrap { bwrap } else { None })
+}
+
+/// Location of `bwrap` on PATH (Linux only), looked up once.
+fn bwrap_path() -> Option<&'static Path> {
+    static BWRAP: OnceLock<Option<PathBuf>> = OnceLock::new();
+    BWRAP
+        .get_or_init(|| {
+            if !cfg!(target_os = "linux") {
+                return None;
+            }
+            let found = std::env::var_os("PATH").and_then(|paths| {
+                std::env::split_paths(&paths)
+                    .map(|dir| dir.join("bwrap"))
+                    .find(|candidate| candidate.is_file())
+            });
+            if let Some(path) = &found {
+                info!("Command isolation: using {}", path.display());
+            }
+            found
+        })
+        .as_deref()
+}
+
+/// Arguments for `bwrap`: a fresh set of namespaces with read-only system
+/// directories, private /tmp, /proc and /dev, and `sandbox_dir` as the only
+/// writable host path (and the working directory).
+fn bwrap_args(config: &CommandSandboxConfig, sandbox_dir: &Path, command: &str) -> Vec<String> {
+    let sandbox = sandbox_dir.display().to_string();
+    let mut args: Vec<String> = vec![
+        "--die-with-parent".into(),
+        "--new-session".into(),
+        "--unshare-all".into(),
+    ];
+    if config.allow_network {
+        args.push("--share-net".into());
+    }
+    for path in READ_ONLY_MOUNTS {
+        args.extend(["--ro-bind-try".into(), path.to_string(), path.to_string()]);
+    }
+    args.extend(
+        [
+            "--proc", "/proc", "--dev", "/dev", "--tmpfs", "/tmp", "--bind", &sandbox, &sandbox,
+            "--chdir", &sandbox, "--setenv", "HOME", &sandbox, "--", "sh", "-c", command,
+        ]
+        .map(String::from),
+    );
+    args
+}
+
+/// rlimits applied to the command in the child before exec
+#[cfg(unix)]
+#[derive(Debug, Clone, Copy)]
+struct ResourceLimits {
+    cpu_secs: Option<u64>,
+    memory_bytes: Option<u64>,
+    file_size_bytes: Option<u64>,
+}
+
+#[cfg(unix)]
+impl From<&CommandSandboxConfig> for ResourceLimits {
+    fn from(config: &CommandSandboxConfig) -> Self {
+        const MB: u64 = 1024 * 1024;
+        Self {
+            cpu_secs: config.max_cpu_secs,
+            memory_bytes: config.max_memory_mb.map(|mb| mb.saturating_mul(MB)),
+            file_size_bytes: config.max_file_size_mb.map(|mb| mb.saturating_mul(MB)),
+        }
+    }
+}
+
+#[cfg(unix)]
+impl ResourceLimits {
+    fn apply(&self) -> std::io::Result<()> {
+        let limits = [
+            (libc::RLIMIT_CPU, self.cpu_secs),
+            (libc::RLIMIT_AS, self.memory_bytes),
+            (libc::RLIMIT_FSIZE, self.file_size_bytes),
+        ];
+        for (resource, value) in limits {
+            let Some(value) = value else { continue };
+            let limit = libc::rlimit {
+                rlim_cur: value as libc::rlim_t,
+                rlim_max: value as libc::rlim_t,
+            };
+            // SAFETY: setrlimit only reads the struct we pass.
+            if unsafe { libc::setrlimit(resource, &limit) } != 0 {
+                return Err(std::io::Error::last_os_error());
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(isolation: IsolationMode) -> CommandSandboxConfig {
+        CommandSandboxConfig {
+            isolation,
+            allow_network: true,
+            timeout_secs: 10,
+            max_cpu_secs: None,
+            max_memory_mb: None,
+            max_file_size_mb: None,
+        }
+    }
+
+    #[test]
+    fn test_bwrap_args_confine_to_sandbox() {
+        let mut cfg = config(IsolationMode::Bubblewrap);
+        let args = bwrap_args(&cfg, Path::new("/srv/box"), "ls -la");
+        let joined = args.join(" ");
+        assert!(joined.contains("--unshare-all --share-net"));
+        assert!(joined.contains("--ro-bind-try /usr /usr"));
+        assert!(joined.contains("--ro-bind-try /etc/ssl /etc/ssl"));
+        assert!(!args.iter().any(|a| a == "/etc"));
+        assert!(joined.contains("--bind /srv/box /srv/box --chdir /srv/box"));
+        assert_eq!(&args[args.len() - 3..], ["sh", "-c", "ls -la"]);
+
+        cfg.allow_network = false;
+        let args = bwrap_args(&cfg, Path::new("/srv/box"), "true");
+        assert!(!args.iter().any(|a| a == "--share-net"));
+    }
+
+    #[tokio::test]
+    async fn test_run_command_without_isolation() {
+        let dir = tempfile::tempdir().unwrap();
+        let output = run_command(&config(IsolationMode::None), dir.path(), "echo hi; pwd")
+            .await
+            .unwrap();
+        assert_eq!(output.exit_code, Some(0));
+        assert!(output.stdout.starts_with("hi\n"));
+        assert!(output
+            .stdout
+            .trim_end()
+            .ends_with(dir.path().file_name().unwrap().to_str().unwrap()));
+    }
+
+    #[tokio::test]
+    async fn test_run_command_kills_on_timeout() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut cfg = config(IsolationMode::None);
+        cfg.timeout_secs = 1;
+        let started = std::time::Instant::now();
+        let output = run_command(&cfg, dir.path(), "sleep 30 & sleep 30")
+            .await
+            .unwrap();
+        assert!(output.timed_out);
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+
+    #[tokio::test]
+    async fn test_run_command_applies_file_size_limit() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut cfg = config(IsolationMode::None);
+        cfg.max_file_size_mb = Some(1);
+        let output = run_command(&cfg, dir.path(), "head -c 3000000 /dev/zero > big.bin")
+            .await
+            .unwrap();
+        assert_ne!(output.exit_code, Some(0));
+        assert!(std::fs::metadata(dir.path().join("big.bin")).unwrap().len() <= 1024 * 1024);
+    }
+
+    #[tokio::test]
+    async fn test_network_denial_requires_bubblewrap() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut cfg = config(IsolationMode::None);
+        cfg.allow_network = false;
+        let err = run_command(&cfg, dir.path(), "true").await.unwrap_err();
+        assert!(err.to_string().contains("requires bubblewrap"));
+    }
+}
@@ -18,6 +18,10 @@ pub struct ScheduledTask {
     pub status: String,
     pub created_at: String,
     pub next_run_at: Option<String>,
+    /// Created in a group chat: runs in the group's conversation
+    pub is_group: bool,
+    /// Group thread or forum topic the task was created in
+    pub thread_id: Option<String>,
 }
 
 #[derive(Clone)]
@@ -37,8 +41,9 @@ impl ScheduledTaskStore {
         conn.execute(
             "INSERT INTO scheduled_tasks
              (id, scheduler_job_id, user_id, chat_id, platform, trigger_type,
-              trigger_value, prompt, description, status, created_at, next_run_at)
-             VALUES (?1,?2,?3,?4,?5,?6,?7,?8,?9,?10,?11,?12)",
+              trigger_value, prompt, description, status, created_at, next_run_at,
+              is_group, thread_id)
+             VALUES (?1,?2,?3,?4,?5,?6,?7,?8,?9,?10,?11,?12,?13,?14)",
             rusqlite::params![
                 task.id,
                 task.scheduler_job_id,
@@ -52,6 +57,8 @@ impl ScheduledTaskStore {
                 task.status,
                 task.created_at,
                 task.next_run_at,
+                task.is_group,
+                task.thread_id,
             ],
         )
         .context("Failed to insert scheduled task")?;
@@ -97,7 +104,8 @@ impl ScheduledTaskStore {
         let mut stmt = conn
             .prepare(
                 "SELECT id, scheduler_job_id, user_id, chat_id, platform, trigger_type,
-                        trigger_value, prompt, description, status, created_at, next_run_at
+                        trigger_value, prompt, description, status, created_at, next_run_at,
+                        is_group, thread_id
                  FROM scheduled_tasks WHERE id = ?1",
             )
             .context("Failed to prepare get_by_id query")?;
@@ -116,6 +124,8 @@ impl ScheduledTaskStore {
                     status: row.get(9)?,
                     created_at: row.get(10)?,
                     next_run_at: row.get(11)?,
+                    is_group: row.get(12)?,
+                    thread_id: row.get(13)?,
                 })
             })
             .context("Failed to query task by id")?;
@@ -146,7 +156,8 @@ impl ScheduledTaskStore {
     ) -> Result<Vec<ScheduledTask>> {
         let sql = format!(
             "SELECT id, scheduler_job_id, user_id, chat_id, platform, trigger_type,
-                    trigger_value, prompt, description, status, created_at, next_run_at
+                    trigger_value, prompt, description, status, created_at, next_run_at,
+                    is_group, thread_id
              FROM scheduled_tasks {}
              ORDER BY created_at ASC",
             where_clause
@@ -167,6 +178,8 @@ impl ScheduledTaskStore {
                     status: row.get(9)?,
                     created_at: row.get(10)?,
                     next_run_at: row.get(11)?,
+                    is_group: row.get(12)?,
+                    thread_id: row.get(13)?,
                 })
             })
             .context("Failed to map rows")?
@@ -195,6 +208,8 @@ mod tests {
             status: "active".to_string(),
             created_at: "2026-01-01T00:00:00".to_string(),
             next_run_at: Some("2099-01-01T09:00:00".to_string()),
+            is_group: false,
+            thread_id: None,
         }
     }
 
@@ -211,6 +226,21 @@ mod tests {
         assert_eq!(tasks[0].id, "task-1");
     }
 
+    #[tokio::test]
+    async fn test_group_origin_roundtrip() {
+        let memory = MemoryStore::open_in_memory().unwrap();
+        let store = ScheduledTaskStore::new(memory.connection());
+
+        let mut task = make_task("task-g", "user-1", "recurring");
+        task.is_group = true;
+        task.thread_id = Some("42".to_string());
+        store.create(&task).await.unwrap();
+
+        let stored = store.get_by_id("task-g").await.unwrap().unwrap();
+        assert!(stored.is_group);
+        assert_eq!(stored.thread_id.as_deref(), Some("42"));
+    }
+
     #[tokio::test]
     async fn test_list_only_returns_active() {
         let memory = MemoryStore::open_in_memory().unwrap();
@@ -1,12 +1,23 @@
-use tracing::info;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Weak};
+use std::time::{Duration, Instant};
 
+use tracing::{debug, info, warn};
+
+use crate::agent::Agent;
+use crate::config::{FactExtractionConfig, MaintenanceConfig};
+use crate::facts::{self, FactChange};
+use crate::memory::conversations::PendingFactReview;
+use crate::memory::knowledge::{KnowledgeEntry, KnowledgeScope};
+use crate::memory::usage::UsageScope;
 use crate::memory::MemoryStore;
 use crate::scheduler::Scheduler;
 
 /// Register built-in background tasks
 pub async fn register_builtin_tasks(
     scheduler: &Scheduler,
-    _memory: MemoryStore,
+    memory: MemoryStore,
+    agent: Weak<Agent>,
 ) -> anyhow::Result<()> {
     // Heartbeat — log that the bot is alive every hour
     scheduler
@@ -17,5 +28,302 @@ pub async fn register_builtin_tasks(
         })
         .await?;
 
+    let Some((memory_config, embedding)) = agent
+        .upgrade()
+        .map(|agent| (agent.config.memory.clone(), agent.config.embedding.clone()))
+    else {
+        return Ok(());
+    };
+
+    // Re-embedding — rebuild the vectors after an embedding model change,
+    // resuming where the last run stopped
+    if let Some(embedding) = embedding {
+        if memory.reembedding_pending().await? {
+            tokio::spawn(reembed(memory.clone(), embedding.reembed_rate()));
+        }
+    }
+
+    // Maintenance — retention, vector cleanup, embedding backfill, housekeeping
+    let config = memory_config.maintenance;
+    if config.enabled {
+        let schedule = config.schedule.clone();
+        let memory = memory.clone();
+        let running = Arc::new(AtomicBool::new(false));
+        scheduler
+            .add_cron_job(&schedule, "memory_maintenance", move || {
+                let memory = memory.clone();
+                let config = config.clone();
+                let running = Arc::clone(&running);
+                Box::pin(async move {
+                    if running.swap(true, Ordering::SeqCst) {
+                        debug!("Memory maintenance still running; skipping this run");
+                        return;
+                    }
+                    run_maintenance(&memory, &config).await;
+                    running.store(false, Ordering::SeqCst);
+                })
+            })
+            .await?;
+    }
+
+    // Fact extraction — store what idle conversations revealed about their users
+    let config = memory_config.fact_extraction;
+    if config.enabled {
+        let schedule = config.schedule.clone();
+        // A slow run must not overlap with the next one
+        let running = Arc::new(AtomicBool::new(false));
+        scheduler
+            .add_cron_job(&schedule, "fact_extraction", move || {
+                let memory = memory.clone();
+                let agent = agent.clone();
+                let config = config.clone();
+                let running = Arc::clone(&running);
+                Box::pin(async move {
+                    if running.swap(true, Ordering::SeqCst) {
+                        debug!("Fact extraction still running; skipping this run");
+                        return;
+                    }
+                    if let Some(agent) = agent.upgrade() {
+                        extract_facts(&memory, &agent, &config).await;
+                    }
+                    running.store(false, Ordering::SeqCst);
+                })
+            })
+            .await?;
+    }
+
     Ok(())
 }
+
+/// Cached embeddings unused for this many days are dropped by the maintenance job
+const EMBEDDING_CACHE_DAYS: u64 = 30;
+
+/// Give up re-embedding after this many failed attempts in a row, until the next start
+const MAX_REEMBED_RETRIES: u32 = 5;
+
+/// Embed every row without a vector, at most `per_minute` a minute, then clear
+/// the re-embedding flag. Failures are retried with a growing delay; texts the
+/// API rejects are left to later maintenance backfills. Progress lives in the
+/// database, so a restart picks up where this stopped.
+async fn reembed(memory: MemoryStore, per_minute: usize) {
+    let total = match memory.missing_embeddings().await {
+        Ok(total) => total,
+        Err(e) => {
+            warn!("Re-embedding: {:#}", e);
+            return;
+        }
+    };
+    info!(
+        "Re-embedding {} messages and facts for the current embedding model ({} a minute)",
+        total, per_minute
+    );
+    let mut retries = 0;
+    loop {
+        let started = Instant::now();
+        let result = memory.backfill_embeddings(per_minute).await;
+        let remaining = match memory.missing_embeddings().await {
+            Ok(remaining) => remaining,
+            Err(e) => {
+                warn!("Re-embedding: {:#}", e);
+                return;
+            }
+        };
+        if remaining == 0 {
+            match memory.finish_reembedding().await {
+                Ok(()) => info!("Re-embedding finished"),
+                Err(e) => warn!("Re-embedding: {:#}", e),
+            }
+            return;
+        }
+        match result {
+            Ok(stored) if stored > 0 => {
+                retries = 0;
+                info!(
+                    "Re-embedding: {}/{} done",
+                    total.saturating_sub(remaining),
+                    total
+                );
+                tokio::time::sleep(Duration::from_secs(60).saturating_sub(started.elapsed())).await;
+            }
+            failed => {
+                retries += 1;
+                let reason = match failed {
+                    Err(e) => format!("{:#}", e),
+                    Ok(_) => "no rows could be embedded".to_string(),
+                };
+                if retries > MAX_REEMBED_RETRIES {
+                    warn!(
+                        "Re-embedding paused with {} rows left ({}); it resumes on the next start",
+                        remaining, reason
+                    );
+                    return;
+                }
+                let delay = 2u64.pow(retries);
+                warn!("Re-embedding: {}; retrying in {} minutes", reason, delay);
+                tokio::time::sleep(Duration::from_secs(60 * delay)).await;
+            }
+        }
+    }
+}
+
+/// One maintenance run. Each step is logged and skipped on failure so the
+/// others still run.
+async fn run_maintenance(memory: &MemoryStore, config: &MaintenanceConfig) {
+    let mut pruned = 0;
+    match memory.conversation_owners().await {
+        Ok(owners) => {
+            for (platform, user_id) in owners {
+                let Some(days) = config.retention(&user_id).retention_days else {
+                    continue;
+                };
+                match memory.prune_messages(&platform, &user_id, days).await {
+                    Ok(deleted) => pruned += deleted,
+                    Err(e) => warn!(
+                        "Maintenance: retention for {}:{} failed: {:#}",
+                        platform, user_id, e
+                    ),
+                }
+            }
+        }
+        Err(e) => warn!("Maintenance: retention failed: {:#}", e),
+    }
+    let orphans = memory.prune_orphan_embeddings().await.unwrap_or_else(|e| {
+        warn!("Maintenance: pruning orphaned embeddings failed: {:#}", e);
+        0
+    });
+    let uncached = memory
+        .prune_embedding_cache(EMBEDDING_CACHE_DAYS)
+        .await
+        .unwrap_or_else(|e| {
+            warn!("Maintenance: {:#}", e);
+            0
+        });
+    let backfilled = memory
+        .backfill_embeddings(config.backfill_batch)
+        .await
+        .unwrap_or_else(|e| {
+            warn!("Maintenance: {:#}", e);
+            0
+        });
+    if let Err(e) = memory.optimize_database(config.vacuum).await {
+        warn!("Maintenance: {:#}", e);
+    }
+    info!(
+        "Memory maintenance: deleted {} expired message(s), {} orphaned vector(s) and \
+         {} unused cached embedding(s), embedded {} row(s)",
+        pruned, orphans, uncached, backfilled
+    );
+}
+
+/// Review the new turns of idle conversations
+pub(crate) async fn extract_facts(
+    memory: &MemoryStore,
+    agent: &Agent,
+    config: &FactExtractionConfig,
+) {
+    let pending = match memory
+        .conversations_pending_fact_review(config.idle_minutes, config.max_conversations)
+        .await
+    {
+        Ok(pending) => pending,
+        Err(e) => {
+            warn!("Fact extraction: {:#}", e);
+            return;
+        }
+    };
+    for conversation in pending {
+        match review_conversation(memory, agent, &conversation).await {
+            Ok(0) => {}
+            Ok(stored) => info!(
+                "Fact extraction: stored {} fact(s) from conversation {}",
+                stored, conversation.conversation_id
+            ),
+            Err(e) => warn!(
+                "Fact extraction for conversation {} failed: {:#}",
+                conversation.conversation_id, e
+            ),
+        }
+    }
+}
+
+/// Extract the facts of one conversation's unreviewed turns into its owner's
+/// knowledge and return how many were stored. The turns count as reviewed
+/// unless the LLM could not be reached, so they are retried next run.
+async fn review_conversation(
+    memory: &MemoryStore,
+    agent: &Agent,
+    conversation: &PendingFactReview,
+) -> anyhow::Result<usize> {
+    let id = conversation.conversation_id.as_str();
+    let messages: Vec<_> = memory
+        .load_messages(id)
+        .await?
+        .into_iter()
+        .filter(|m| m.role != "system")
+        .collect();
+    let unreviewed = &messages[conversation.reviewed_count.min(messages.len())..];
+    let unreviewed = &unreviewed[unreviewed
+        .len()
+        .saturating_sub(facts::MAX_REVIEWED_MESSAGES)..];
+
+    let scope = KnowledgeScope::user(&conversation.platform, &conversation.user_id);
+    let mut known = known_facts(memory, scope).await?;
+    let usage = UsageScope {
+        platform: conversation.platform.clone(),
+        user_id: conversation.user_id.clone(),
+        conversation_id: Some(id.to_string()),
+        task_id: None,
+    };
+    let reply = agent.fact_extraction(&usage, &known, unreviewed).await?;
+
+    let extracted = match facts::parse_facts(&reply) {
+        Ok(extracted) => extracted,
+        Err(e) => {
+            warn!(
+                "Fact extraction for conversation {}: unreadable reply ({:#})",
+                id, e
+            );
+            Vec::new()
+        }
+    };
+    let source = facts::source(id);
+    let mut stored = 0;
+    for fact in extracted {
+        let (category, key) = match facts::classify(&fact, &known) {
+            FactChange::Known => {
+                debug!("Fact already known: {}/{}", fact.category, fact.key);
+                continue;
+            }
+            FactChange::Changed(entry) => (entry.category.clone(), entry.key.clone()),
+            FactChange::New => (fact.category.clone(), fact.key.clone()),
+        };
+        memory
+            .remember(scope, &category, &key, &fact.value, Some(&source))
+            .await?;
+        debug!("Remembered {}/{} = {}", category, key, fact.value);
+        known.retain(|entry| entry.shared || entry.category != category || entry.key != key);
+        known.push(KnowledgeEntry {
+            id: String::new(),
+            category,
+            key,
+            value: fact.value,
+            source: Some(source.clone()),
+            shared: false,
+        });
+        stored += 1;
+    }
+    memory.set_facts_reviewed(id, messages.len()).await?;
+    Ok(stored)
+}
+
+/// Facts visible to `scope`, for the LLM to build on and for deduplication
+async fn known_facts(
+    memory: &MemoryStore,
+    scope: KnowledgeScope<'_>,
+) -> anyhow::Result<Vec<KnowledgeEntry>> {
+    let mut known = Vec::new();
+    for (category, _) in memory.knowledge_categories(scope).await? {
+        known.extend(memory.list_knowledge(scope, &category).await?);
+    }
+    Ok(known)
+}
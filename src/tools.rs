@@ -3,6 +3,7 @@ use serde_json::{json, Value};
 use std::path::{Path, PathBuf};
 use tracing::info;
 
+use crate::config::SandboxConfig;
 use crate::llm::{FunctionDefinition, ToolDefinition};
 
 /// Validates that a path is within the allowed sandbox directory.
@@ -44,13 +45,229 @@ fn validate_sandbox_path(sandbox_dir: &Path, requested: &str) -> Result<PathBuf>
     Ok(check_path)
 }
 
+/// How a tool call may be scheduled alongside other calls from the same assistant turn
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ToolConcurrency {
+    /// Independent of every other call (reads, MCP fetches, searches)
+    Parallel,
+    /// Calls sharing a key touch the same resource and run in their original order
+    Keyed(String),
+    /// Runs alone: everything before it finishes first, everything after waits
+    Exclusive,
+}
+
+/// Classify a tool call for parallel execution.
+/// Calls on the same file, knowledge entry or skill are serialized; shell commands
+/// and skill reloads can touch anything, so they are exclusive.
+pub fn tool_concurrency(name: &str, arguments: &Value) -> ToolConcurrency {
+    let arg = |field: &str| arguments[field].as_str().unwrap_or("").to_string();
+    match name {
+        "read_file" | "write_file" => {
+            let path = arg("path");
+            ToolConcurrency::Keyed(format!(
+                "file:{}",
+                path.trim_start_matches("./").trim_end_matches('/')
+            ))
+        }
+        "remember" | "recall" | "forget" => ToolConcurrency::Keyed(format!(
+            "knowledge:{}/{}",
+            arguments["category"].as_str().unwrap_or("general"),
+            arg("key")
+        )),
+        "write_skill_file" => ToolConcurrency::Keyed(format!("skill:{}", arg("skill_name"))),
+        "execute_command" | "reload_skills" => ToolConcurrency::Exclusive,
+        _ => ToolConcurrency::Parallel,
+    }
+}
+
+/// Lines `offset..offset+limit` (1-based) of `content`, with a header saying where they are.
+fn line_range(content: &str, offset: usize, limit: Option<usize>) -> String {
+    let total = content.lines().count();
+    let start = offset.max(1);
+    if start > total {
+        return format!(
+            "[File has {} lines; offset {} is past the end]",
+            total, start
+        );
+    }
+    let lines: Vec<&str> = content
+        .lines()
+        .skip(start - 1)
+        .take(limit.unwrap_or(usize::MAX))
+        .collect();
+    let end = start + lines.len() - 1;
+    format!(
+        "[Lines {}-{} of {}]\n{}",
+        start,
+        end,
+        total,
+        lines.join("\n")
+    )
+}
+
+/// Characters `offset..offset+limit` (0-based) of `content`, with a header saying
+/// where they are. For files whose lines are too long to page through by line.
+fn char_range(content: &str, offset: usize, limit: Option<usize>) -> String {
+    let total = content.chars().count();
+    if offset >= total {
+        return format!(
+            "[File has {} characters; char_offset {} is past the end]",
+            total, offset
+        );
+    }
+    let chunk: String = content
+        .chars()
+        .skip(offset)
+        .take(limit.unwrap_or(usize::MAX))
+        .collect();
+    format!(
+        "[Characters {}-{} of {}]\n{}",
+        offset,
+        offset + chunk.chars().count(),
+        total,
+        chunk
+    )
+}
+
+/// Cap an oversized tool result to a head/tail excerpt of at most `limit` bytes.
+///
+/// The full output is written to `spill_dir` inside the sandbox so the model can
+/// page through it with ranged `read_file` calls. For `read_file` itself the
+/// source file is already on disk, so nothing is written.
+pub async fn cap_tool_output(
+    tool_name: &str,
+    call_id: &str,
+    arguments: &Value,
+    output: String,
+    limit: usize,
+    sandbox_dir: &Path,
+    spill_dir: &str,
+) -> String {
+    if output.len() <= limit {
+        return output;
+    }
+
+    let full_copy = if tool_name == "read_file" {
+        Some(arguments["path"].as_str().unwrap_or_default().to_string())
+    } else {
+        let safe_id: String = call_id
+            .chars()
+            .filter(|c| c.is_ascii_alphanumeric() || *c == '-' || *c == '_')
+            .collect();
+        let relative = format!(
+            "{}/{}-{}.txt",
+            spill_dir.trim_end_matches('/'),
+            tool_name,
+            if safe_id.is_empty() {
+                uuid::Uuid::new_v4().simple().to_string()
+            } else {
+                safe_id
+            }
+        );
+        match spill_output(sandbox_dir, &relative, &output).await {
+            Ok(()) => Some(relative),
+            Err(e) => {
+                tracing::warn!("Failed to save full output of '{}': {:#}", tool_name, e);
+                None
+            }
+        }
+    };
+
+    let head_len = floor_char_boundary(&output, limit * 2 / 3);
+    let tail_start = ceil_char_boundary(&output, output.len() - (limit - head_len));
+    let omitted = tail_start - head_len;
+
+    let notice = match &full_copy {
+        Some(path) => format!(
+            "[Output truncated: {} bytes, {} lines. Showing the first {} and last {} bytes. \
+             The full output is in '{}'; page through it with read_file offset/limit (lines) \
+             or char_offset/max_chars (characters).]",
+            output.len(),
+            output.lines().count(),
+            head_len,
+            output.len() - tail_start,
+            path
+        ),
+        None => format!(
+            "[Output truncated: {} bytes. Showing the first {} and last {} bytes.]",
+            output.len(),
+            head_len,
+            output.len() - tail_start
+        ),
+    };
+    format!(
+        "{}\n{}\n\n[... {} bytes omitted ...]\n\n{}",
+        notice,
+        &output[..head_len],
+        omitted,
+        &output[tail_start..]
+    )
+}
+
+/// Write the full `output` to `relative` in the sandbox. Commands can plant
+/// symlinks there, so neither the directories on the way nor the file itself
+/// may lead out of the sandbox.
+async fn spill_output(sandbox_dir: &Path, relative: &str, output: &str) -> Result<()> {
+    use tokio::io::AsyncWriteExt;
+
+    let sandbox_canonical = sandbox_dir
+        .canonicalize()
+        .with_context(|| format!("Sandbox directory not found: {}", sandbox_dir.display()))?;
+    let path = sandbox_dir.join(relative);
+    let parent = path.parent().context("Path has no parent directory")?;
+    let mut existing = parent;
+    while !existing.exists() {
+        existing = existing.parent().context("Path has no parent directory")?;
+    }
+    if !existing.canonicalize()?.starts_with(&sandbox_canonical) {
+        anyhow::bail!("'{}' leads outside the sandbox", existing.display());
+    }
+    tokio::fs::create_dir_all(parent)
+        .await
+        .with_context(|| format!("Failed to create {}", parent.display()))?;
+
+    let path = validate_sandbox_path(sandbox_dir, relative)?;
+    let mut file = tokio::fs::OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .custom_flags(libc::O_NOFOLLOW)
+        .open(&path)
+        .await
+        .with_context(|| format!("Failed to open {}", path.display()))?;
+    file.write_all(output.as_bytes())
+        .await
+        .with_context(|| format!("Failed to write {}", path.display()))?;
+    // tokio finishes file writes in the background; make sure the model can read it
+    file.flush()
+        .await
+        .with_context(|| format!("Failed to write {}", path.display()))
+}
+
+fn floor_char_boundary(text: &str, mut index: usize) -> usize {
+    index = index.min(text.len());
+    while !text.is_char_boundary(index) {
+        index -= 1;
+    }
+    index
+}
+
+fn ceil_char_boundary(text: &str, mut index: usize) -> usize {
+    while !text.is_char_boundary(index) {
+        index += 1;
+    }
+    index
+}
+
 pub fn builtin_tool_definitions() -> Vec<ToolDefinition> {
     vec![
         ToolDefinition {
             tool_type: "function".to_string(),
             function: FunctionDefinition {
                 name: "read_file".to_string(),
-                description: "Read the contents of a file within the sandbox directory"
+                description: "Read the contents of a file within the sandbox directory. \
+                              Use offset/limit to page through large files by line, or \
+                              char_offset/max_chars by character."
                     .to_string(),
                 parameters: json!({
                     "type": "object",
@@ -58,6 +275,23 @@ pub fn builtin_tool_definitions() -> Vec<ToolDefinition> {
                         "path": {
                             "type": "string",
                             "description": "The file path (relative to sandbox or absolute within sandbox)"
+                        },
+                        "offset": {
+                            "type": "integer",
+                            "description": "Line number to start reading from (1-based, optional)"
+                        },
+                        "limit": {
+                            "type": "integer",
+                            "description": "Maximum number of lines to return (optional)"
+                        },
+                        "char_offset": {
+                            "type": "integer",
+                            "description": "Character position to start reading from (0-based, optional). \
+                                            Use with max_chars instead of offset/limit for very long lines."
+                        },
+                        "max_chars": {
+                            "type": "integer",
+                            "description": "Maximum number of characters to return (optional)"
                         }
                     },
                     "required": ["path"]
@@ -128,8 +362,9 @@ pub fn builtin_tool_definitions() -> Vec<ToolDefinition> {
 pub async fn execute_builtin_tool(
     tool_name: &str,
     arguments: &Value,
-    sandbox_dir: &Path,
+    sandbox: &SandboxConfig,
 ) -> Result<String> {
+    let sandbox_dir = sandbox.allowed_directory.as_path();
     match tool_name {
         "read_file" => {
             let path = arguments["path"]
@@ -140,7 +375,25 @@ pub async fn execute_builtin_tool(
             let content = tokio::fs::read_to_string(&full_path)
                 .await
                 .with_context(|| format!("Failed to read file: {}", full_path.display()))?;
-            Ok(content)
+            let char_offset = arguments["char_offset"].as_u64();
+            let max_chars = arguments["max_chars"].as_u64();
+            if char_offset.is_some() || max_chars.is_some() {
+                return Ok(char_range(
+                    &content,
+                    char_offset.unwrap_or(0) as usize,
+                    max_chars.map(|l| l as usize),
+                ));
+            }
+            let offset = arguments["offset"].as_u64();
+            let limit = arguments["limit"].as_u64();
+            if offset.is_none() && limit.is_none() {
+                return Ok(content);
+            }
+            Ok(line_range(
+                &content,
+                offset.unwrap_or(1) as usize,
+                limit.map(|l| l as usize),
+            ))
         }
         "write_file" => {
             let path = arguments["path"]
@@ -208,30 +461,146 @@ pub async fn execute_builtin_tool(
 
             info!("Executing command in sandbox: {}", command);
 
-            let output = tokio::process::Command::new("sh")
-                .arg("-c")
-                .arg(command)
-                .current_dir(sandbox_dir)
-                .output()
-                .await
-                .with_context(|| format!("Failed to execute command: {}", command))?;
-
-            let stdout = String::from_utf8_lossy(&output.stdout);
-            let stderr = String::from_utf8_lossy(&output.stderr);
+            let output =
+                crate::sandbox::run_command(&sandbox.command, sandbox_dir, command).await?;
+            if output.timed_out {
+                return Ok(format!(
+                    "Command timed out after {} seconds and was killed.",
+                    sandbox.command.timeout_secs
+                ));
+            }
 
             let mut result = String::new();
-            if !stdout.is_empty() {
-                result.push_str(&format!("STDOUT:\n{}\n", stdout));
+            if !output.stdout.is_empty() {
+                result.push_str(&format!("STDOUT:\n{}\n", output.stdout));
             }
-            if !stderr.is_empty() {
-                result.push_str(&format!("STDERR:\n{}\n", stderr));
+            if !output.stderr.is_empty() {
+                result.push_str(&format!("STDERR:\n{}\n", output.stderr));
             }
-            result.push_str(&format!(
-                "Exit code: {}",
-                output.status.code().unwrap_or(-1)
-            ));
+            result.push_str(&format!("Exit code: {}", output.exit_code.unwrap_or(-1)));
             Ok(result)
         }
         _ => anyhow::bail!("Unknown built-in tool: {}", tool_name),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_line_range() {
+        let content = "a\nb\nc\nd";
+        assert_eq!(line_range(content, 2, Some(2)), "[Lines 2-3 of 4]\nb\nc");
+        assert_eq!(line_range(content, 3, None), "[Lines 3-4 of 4]\nc\nd");
+        assert!(line_range(content, 9, Some(1)).contains("past the end"));
+    }
+
+    #[test]
+    fn test_char_range() {
+        let content = "{\"a\":\"é\",\"b\":2}";
+        assert_eq!(
+            char_range(content, 5, Some(4)),
+            "[Characters 5-9 of 15]\n\"é\","
+        );
+        assert_eq!(
+            char_range(content, 12, None),
+            "[Characters 12-15 of 15]\n:2}"
+        );
+        assert!(char_range(content, 15, Some(1)).contains("past the end"));
+    }
+
+    #[tokio::test]
+    async fn test_cap_tool_output_spills_full_output() {
+        let dir = tempfile::tempdir().unwrap();
+        let output: String = (0..500).map(|i| format!("line {}\n", i)).collect();
+
+        let short = cap_tool_output(
+            "execute_command",
+            "call_1",
+            &json!({}),
+            "ok".into(),
+            100,
+            dir.path(),
+            ".out",
+        )
+        .await;
+        assert_eq!(short, "ok");
+
+        let capped = cap_tool_output(
+            "execute_command",
+            "call_1",
+            &json!({}),
+            output.clone(),
+            300,
+            dir.path(),
+            ".out",
+        )
+        .await;
+        assert!(capped.contains("'.out/execute_command-call_1.txt'"));
+        assert!(capped.contains("line 0\n"));
+        assert!(capped.contains("line 499"));
+        assert!(!capped.contains("line 250\n"));
+        let saved =
+            std::fs::read_to_string(dir.path().join(".out/execute_command-call_1.txt")).unwrap();
+        assert_eq!(saved, output);
+    }
+
+    #[tokio::test]
+    async fn test_spill_does_not_follow_symlinks_out_of_the_sandbox() {
+        let dir = tempfile::tempdir().unwrap();
+        let outside = tempfile::tempdir().unwrap();
+        std::os::unix::fs::symlink(outside.path(), dir.path().join(".out")).unwrap();
+        std::fs::create_dir(dir.path().join(".spill")).unwrap();
+        std::os::unix::fs::symlink(
+            outside.path().join("planted.txt"),
+            dir.path().join(".spill/execute_command-call_1.txt"),
+        )
+        .unwrap();
+
+        for spill_dir in [".out", ".spill"] {
+            let capped = cap_tool_output(
+                "execute_command",
+                "call_1",
+                &json!({}),
+                "x".repeat(1000),
+                100,
+                dir.path(),
+                spill_dir,
+            )
+            .await;
+            assert!(capped.starts_with("[Output truncated: 1000 bytes. Showing"));
+        }
+        assert_eq!(std::fs::read_dir(outside.path()).unwrap().count(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_cap_read_file_points_at_source() {
+        let dir = tempfile::tempdir().unwrap();
+        let capped = cap_tool_output(
+            "read_file",
+            "call_2",
+            &json!({ "path": "big.log" }),
+            "é".repeat(400),
+            101,
+            dir.path(),
+            ".out",
+        )
+        .await;
+        assert!(capped.contains("'big.log'"));
+        assert!(!dir.path().join(".out").exists());
+
+        // Paging through the file with offset/limit
+        std::fs::write(dir.path().join("big.log"), "one\ntwo\nthree\n").unwrap();
+        let config: crate::config::SandboxConfig =
+            toml::from_str(&format!("allowed_directory = \"{}\"", dir.path().display())).unwrap();
+        let page = execute_builtin_tool(
+            "read_file",
+            &json!({ "path": "big.log", "offset": 2, "limit": 1 }),
+            &config,
+        )
+        .await
+        .unwrap();
+        assert_eq!(page, "[Lines 2-2 of 3]\ntwo");
+    }
+}